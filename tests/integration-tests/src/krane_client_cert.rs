@@ -0,0 +1,167 @@
+use super::run_command;
+use krane_bundle::KRANE;
+use oci_cli_wrapper::{KRANE_CLIENT_CERT_ENV, KRANE_CLIENT_KEY_ENV};
+use std::ffi::OsStr;
+use std::fs::File;
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::thread::sleep;
+use std::time::Duration;
+use tempfile::TempDir;
+
+const CLIENT_CN: &str = "twoliter-test-client";
+
+/// A TLS server that requires a client certificate signed by its CA, logging the subject of each
+/// client certificate it verifies.
+struct MutualTlsServer {
+    child: Child,
+    port: u16,
+    log: PathBuf,
+}
+
+impl MutualTlsServer {
+    fn start(certs: &Path) -> Self {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let log = certs.join(format!("s_server-{port}.log"));
+        let log_file = File::create(&log).unwrap();
+        let child = Command::new("openssl")
+            .args(["s_server", "-www", "-accept"])
+            .arg(format!("127.0.0.1:{port}"))
+            .arg("-cert")
+            .arg(certs.join("server.crt"))
+            .arg("-key")
+            .arg(certs.join("server.key"))
+            .arg("-CAfile")
+            .arg(certs.join("ca.crt"))
+            .args(["-Verify", "1"])
+            .stdout(log_file.try_clone().unwrap())
+            .stderr(log_file)
+            .spawn()
+            .expect("failed to start openssl s_server");
+        for _ in 0..50 {
+            if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+                break;
+            }
+            sleep(Duration::from_millis(100));
+        }
+        Self { child, port, log }
+    }
+
+    /// Stops the server and returns everything it logged.
+    fn stop(mut self) -> String {
+        self.child.kill().unwrap();
+        self.child.wait().unwrap();
+        std::fs::read_to_string(&self.log).unwrap()
+    }
+}
+
+fn openssl(args: &[&str]) {
+    let output = run_command("openssl", args.iter().copied(), []);
+    assert!(output.status.success(), "openssl {args:?} failed");
+}
+
+/// Creates a CA, a server certificate for 127.0.0.1 and a client certificate, both signed by the
+/// CA.
+fn create_certs(dir: &Path) {
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+    openssl(&[
+        "req",
+        "-x509",
+        "-nodes",
+        "-days",
+        "1",
+        "-newkey",
+        "rsa:2048",
+        "-keyout",
+        &path("ca.key"),
+        "-out",
+        &path("ca.crt"),
+        "-subj",
+        "/CN=twoliter-test-ca",
+    ]);
+    std::fs::write(dir.join("server.ext"), "subjectAltName=IP:127.0.0.1\n").unwrap();
+    std::fs::write(dir.join("client.ext"), "extendedKeyUsage=clientAuth\n").unwrap();
+    for (name, cn) in [("server", "127.0.0.1"), ("client", CLIENT_CN)] {
+        openssl(&[
+            "req",
+            "-nodes",
+            "-newkey",
+            "rsa:2048",
+            "-keyout",
+            &path(&format!("{name}.key")),
+            "-out",
+            &path(&format!("{name}.csr")),
+            "-subj",
+            &format!("/CN={cn}"),
+        ]);
+        openssl(&[
+            "x509",
+            "-req",
+            "-days",
+            "1",
+            "-in",
+            &path(&format!("{name}.csr")),
+            "-CA",
+            &path("ca.crt"),
+            "-CAkey",
+            &path("ca.key"),
+            "-CAcreateserial",
+            "-extfile",
+            &path(&format!("{name}.ext")),
+            "-out",
+            &path(&format!("{name}.crt")),
+        ]);
+    }
+}
+
+/// Runs krane against the server, trusting its CA, with the given extra environment.
+fn krane_digest(certs: &Path, port: u16, envs: &[(&str, PathBuf)]) {
+    let uri = format!("127.0.0.1:{port}/kit:v1");
+    let ca = certs.join("ca.crt");
+    // The server is not a registry, so the command fails either way; only the handshake matters.
+    run_command(
+        KRANE.path().as_os_str(),
+        [OsStr::new("digest"), OsStr::new(&uri)],
+        envs.iter()
+            .map(|(key, value)| (OsStr::new(key), value.as_os_str()))
+            .chain([(OsStr::new("SSL_CERT_FILE"), ca.as_os_str())]),
+    );
+}
+
+#[test]
+#[ignore]
+/// Ensure that krane presents the client certificate named in KRANE_CLIENT_CERT and
+/// KRANE_CLIENT_KEY to a registry that requires mutual TLS, and no certificate without them.
+fn test_krane_presents_client_certificate() {
+    let temp_dir = TempDir::new().unwrap();
+    let certs = temp_dir.path();
+    create_certs(certs);
+
+    let server = MutualTlsServer::start(certs);
+    krane_digest(certs, server.port, &[]);
+    let log = server.stop();
+    assert!(
+        !log.contains(CLIENT_CN),
+        "server verified a client certificate that was not configured:\n{log}"
+    );
+
+    let server = MutualTlsServer::start(certs);
+    krane_digest(
+        certs,
+        server.port,
+        &[
+            (KRANE_CLIENT_CERT_ENV, certs.join("client.crt")),
+            (KRANE_CLIENT_KEY_ENV, certs.join("client.key")),
+        ],
+    );
+    let log = server.stop();
+    assert!(
+        log.contains(&format!("CN = {CLIENT_CN}")) || log.contains(&format!("CN={CLIENT_CN}")),
+        "server did not receive the client certificate:\n{log}"
+    );
+}
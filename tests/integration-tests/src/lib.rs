@@ -5,8 +5,13 @@ use std::path::PathBuf;
 use std::process::Command;
 use tempfile::TempDir;
 
+mod krane_client_cert;
 mod twoliter_build;
+mod twoliter_fetch;
 mod twoliter_update;
+mod twoliter_validate;
+mod twoliter_verify_release;
+mod twoliter_warm_cache;
 
 pub const TWOLITER_PATH: &'static str = env!("CARGO_BIN_FILE_TWOLITER");
 
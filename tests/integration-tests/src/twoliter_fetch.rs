@@ -0,0 +1,78 @@
+use super::twoliter_build::copy_project_to_temp_dir;
+use super::twoliter_update::LocalKit;
+use super::{run_command, test_projects_dir, KitRegistry, TWOLITER_PATH};
+
+const TWOLITER_TOML: &str = r#"
+schema-version = 1
+release-version = "1.0.0"
+
+[vendor.vendor-a]
+registry = "definitely-wont-resolve"
+
+[vendor.vendor-b]
+registry = "definitely-wont-resolve"
+
+[[kit]]
+name = "core-kit"
+version = "1.0.0"
+vendor = "vendor-a"
+
+[[kit]]
+name = "core-kit"
+version = "1.0.0"
+vendor = "vendor-b"
+"#;
+
+const TWOLITER_OVERRIDE: &str = r#"
+[vendor-a.core-kit]
+registry = "localhost:5000"
+name = "core-kit-overridden"
+
+[vendor-b.core-kit]
+registry = "localhost:5000"
+name = "core-kit-overridden"
+"#;
+
+#[test]
+#[ignore]
+/// Fetches several kits from the local registry at once
+fn test_twoliter_fetch_kits_concurrently() {
+    let registry = KitRegistry::new();
+    let _local_kit = LocalKit::build(&registry);
+
+    let project = copy_project_to_temp_dir(test_projects_dir().join("external-kit"));
+    let project_dir = project.path();
+    let twoliter_toml = project_dir.join("Twoliter.toml");
+    std::fs::write(&twoliter_toml, TWOLITER_TOML).unwrap();
+    std::fs::write(project_dir.join("Twoliter.override"), TWOLITER_OVERRIDE).unwrap();
+    let cert_file = registry.cert_file();
+    let env = [
+        ("TWOLITER_KIT_IMAGE_TOOL", "crane"),
+        ("SSL_CERT_FILE", cert_file.to_str().unwrap()),
+    ];
+
+    let output = run_command(
+        TWOLITER_PATH,
+        ["update", "--project-path", twoliter_toml.to_str().unwrap()],
+        env,
+    );
+    assert!(output.status.success());
+
+    let output = run_command(
+        TWOLITER_PATH,
+        [
+            "fetch",
+            "--project-path",
+            twoliter_toml.to_str().unwrap(),
+            "--jobs",
+            "2",
+        ],
+        env,
+    );
+    assert!(output.status.success());
+
+    for vendor in ["vendor-a", "vendor-b"] {
+        let kit_dir = project_dir.join(format!("build/external-kits/{vendor}/core-kit/x86_64"));
+        assert!(kit_dir.join("digest").is_file(), "{vendor} was not fetched");
+    }
+}
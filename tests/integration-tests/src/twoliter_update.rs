@@ -1,3 +1,4 @@
+use super::twoliter_build::copy_project_to_temp_dir;
 use super::{run_command, test_projects_dir, KitRegistry, TWOLITER_PATH};
 
 const INFRA_TOML: &str = r#"
@@ -23,7 +24,7 @@ fn test_twoliter_build_and_update() {
 
     // Build & push a local kit to the registry
     let registry = KitRegistry::new();
-    LocalKit::build(&registry);
+    let _local_kit = LocalKit::build(&registry);
 
     // Point twoliter to the local registry as an override
     std::fs::write(&override_file, TWOLITER_OVERRIDE).unwrap();
@@ -70,10 +71,122 @@ fn test_twoliter_build_and_update() {
     std::fs::remove_file(&override_file).ok();
 }
 
-struct LocalKit;
+const TWO_VENDOR_TWOLITER_TOML: &str = r#"
+schema-version = 1
+release-version = "1.0.0"
+
+[vendor.vendor-a]
+registry = "definitely-wont-resolve"
+
+[vendor.vendor-b]
+registry = "definitely-wont-resolve"
+
+[[kit]]
+name = "core-kit"
+version = "1.0.0"
+vendor = "vendor-a"
+
+[[kit]]
+name = "core-kit"
+version = "1.0.0"
+vendor = "vendor-b"
+"#;
+
+const TWO_VENDOR_TWOLITER_OVERRIDE: &str = r#"
+[vendor-a.core-kit]
+registry = "localhost:5000"
+name = "core-kit-overridden"
+
+[vendor-b.core-kit]
+registry = "localhost:5000"
+name = "core-kit-overridden"
+"#;
+
+/// The digest locked for each vendor's core kit in `lockfile`.
+fn locked_digests(lockfile: &std::path::Path) -> Vec<(String, String)> {
+    let parsed: toml::Value = toml::from_str(&std::fs::read_to_string(lockfile).unwrap()).unwrap();
+    let mut digests: Vec<_> = parsed["kit"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|kit| {
+            (
+                kit["vendor"].as_str().unwrap().to_string(),
+                kit["digest"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect();
+    digests.sort();
+    digests
+}
+
+#[test]
+#[ignore]
+/// Updates only the selected kit, leaving the other vendor's kit at its locked digest
+fn test_twoliter_update_only() {
+    let registry = KitRegistry::new();
+    let _local_kit = LocalKit::build(&registry);
+
+    let project = copy_project_to_temp_dir(test_projects_dir().join("external-kit"));
+    let project_dir = project.path();
+    let twoliter_toml = project_dir.join("Twoliter.toml");
+    let lockfile = project_dir.join("Twoliter.lock");
+    std::fs::remove_file(&lockfile).ok();
+    std::fs::write(&twoliter_toml, TWO_VENDOR_TWOLITER_TOML).unwrap();
+    std::fs::write(
+        project_dir.join("Twoliter.override"),
+        TWO_VENDOR_TWOLITER_OVERRIDE,
+    )
+    .unwrap();
+    let cert_file = registry.cert_file();
+    let env = [
+        ("TWOLITER_KIT_IMAGE_TOOL", "crane"),
+        ("SSL_CERT_FILE", cert_file.to_str().unwrap()),
+    ];
+
+    let output = run_command(
+        TWOLITER_PATH,
+        ["update", "--project-path", twoliter_toml.to_str().unwrap()],
+        env,
+    );
+    assert!(output.status.success());
+    let resolved = locked_digests(&lockfile);
+    assert_eq!(resolved.len(), 2);
+
+    // Make both locked digests stale, then update only vendor-a's kit.
+    let stale = "c3RhbGU=";
+    let lock_contents = std::fs::read_to_string(&lockfile).unwrap();
+    std::fs::write(&lockfile, lock_contents.replace(&resolved[0].1, stale)).unwrap();
+    let output = run_command(
+        TWOLITER_PATH,
+        [
+            "update",
+            "--project-path",
+            twoliter_toml.to_str().unwrap(),
+            "--only",
+            "core-kit@vendor-a",
+        ],
+        env,
+    );
+    assert!(output.status.success());
+
+    assert_eq!(
+        locked_digests(&lockfile),
+        [
+            ("vendor-a".to_string(), resolved[0].1.clone()),
+            ("vendor-b".to_string(), stale.to_string()),
+        ]
+    );
+}
+
+/// The `local-kit` project's core kit, built for the default architecture only and published to
+/// the local registry as `localhost:5000/core-kit-overridden:v1.0.0`. The files written into the
+/// `local-kit` project to publish it are removed when this is dropped.
+pub(crate) struct LocalKit;
 
 impl LocalKit {
-    fn build(registry: &KitRegistry) {
+    #[must_use]
+    pub(crate) fn build(registry: &KitRegistry) -> Self {
         let local_kit = test_projects_dir().join("local-kit");
 
         run_command(
@@ -122,6 +235,7 @@ impl LocalKit {
             ],
             [("SSL_CERT_FILE", registry.cert_file().to_str().unwrap())],
         );
+        Self
     }
 }
 
@@ -0,0 +1,73 @@
+use super::{run_command, test_projects_dir, TWOLITER_PATH};
+use tempfile::TempDir;
+
+#[test]
+/// Checks that `validate` accepts the `local-kit` project without contacting a registry
+fn test_twoliter_validate_local_kit() {
+    let project_path = test_projects_dir().join("local-kit").join("Twoliter.toml");
+    let output = run_command(
+        TWOLITER_PATH,
+        ["validate", "--project-path", project_path.to_str().unwrap()],
+        [],
+    );
+    assert!(output.status.success());
+}
+
+#[test]
+/// Checks that `validate` reports every problem in a project rather than only the first
+fn test_twoliter_validate_reports_all_problems() {
+    let temp_dir = TempDir::new().unwrap();
+    let project_path = temp_dir.path().join("Twoliter.toml");
+    std::fs::write(
+        &project_path,
+        r#"
+schema-version = 1
+release-version = "1.0.0"
+
+[vendor."bottle.rocket"]
+registry = "public.ecr.aws/bottlerocket"
+
+[sdk]
+name = "bottlerocket-sdk"
+vendor = "unknown"
+version = "0.50.0"
+
+[[kit]]
+name = "core-kit"
+vendor = "bottle.rocket"
+version = "not-a-version"
+"#,
+    )
+    .unwrap();
+    std::fs::write(
+        temp_dir.path().join("Twoliter.lock"),
+        r#"
+schema-version = 1
+
+[sdk]
+name = "bottlerocket-sdk"
+version = "0.50.0"
+vendor = "bottlerocket"
+source = "public.ecr.aws/bottlerocket/bottlerocket-sdk:v0.50.0"
+digest = "latest"
+"#,
+    )
+    .unwrap();
+
+    let output = run_command(
+        TWOLITER_PATH,
+        ["validate", "--project-path", project_path.to_str().unwrap()],
+        [],
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    for problem in [
+        "vendor 'bottle.rocket': invalid character '.'",
+        "sdk: vendor 'unknown' is not defined in Twoliter.toml",
+        "kit #1: 'version'",
+        "vendor 'bottlerocket' is not defined in Twoliter.toml",
+        "'latest' is not a sha256 digest",
+    ] {
+        assert!(stderr.contains(problem), "missing '{problem}' in {stderr}");
+    }
+}
@@ -0,0 +1,40 @@
+use super::twoliter_update::LocalKit;
+use super::{run_command, KitRegistry, TWOLITER_PATH};
+
+#[test]
+#[ignore]
+/// Checks that `verify-release` names the architecture a published kit is missing
+fn test_twoliter_verify_release_missing_arch() {
+    // The local kit is only built, and so only published, for x86_64
+    let registry = KitRegistry::new();
+    let _local_kit = LocalKit::build(&registry);
+    let cert_file = registry.cert_file();
+    let env = [
+        ("TWOLITER_KIT_IMAGE_TOOL", "crane"),
+        ("SSL_CERT_FILE", cert_file.to_str().unwrap()),
+    ];
+
+    let output = run_command(
+        TWOLITER_PATH,
+        [
+            "verify-release",
+            "localhost:5000/core-kit-overridden:v1.0.0",
+            "--arch",
+            "x86_64",
+        ],
+        env,
+    );
+    assert!(output.status.success());
+
+    let output = run_command(
+        TWOLITER_PATH,
+        [
+            "verify-release",
+            "localhost:5000/core-kit-overridden:v1.0.0",
+        ],
+        env,
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("has no image for aarch64"), "{stderr}");
+}
@@ -0,0 +1,88 @@
+use super::twoliter_build::copy_project_to_temp_dir;
+use super::twoliter_update::LocalKit;
+use super::{run_command, test_projects_dir, KitRegistry, TWOLITER_PATH};
+
+const TWOLITER_OVERRIDE: &str = r#"
+[custom-vendor.core-kit]
+registry = "localhost:5000"
+name = "core-kit-overridden"
+"#;
+
+const KIT_IMAGE: &str = "localhost:5000/core-kit-overridden:v1.0.0";
+
+/// The images listed by `twoliter warm-cache` as loaded and as already cached.
+fn warm_cache_report(stdout: &[u8]) -> (Vec<String>, Vec<String>) {
+    let stdout = String::from_utf8_lossy(stdout);
+    let image = |line: &str| line.split_whitespace().next().unwrap().to_string();
+    let loaded = stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("loaded"))
+        .map(image)
+        .collect();
+    let skipped = stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("skipped"))
+        .map(image)
+        .collect();
+    (loaded, skipped)
+}
+
+#[test]
+#[ignore]
+/// Loads the SDK and every kit of the `external-kit` project into the docker daemon
+fn test_twoliter_warm_cache_loads_every_image() {
+    let registry = KitRegistry::new();
+    let _local_kit = LocalKit::build(&registry);
+    run_command("docker", ["image", "rm", "--force", KIT_IMAGE], []);
+
+    let project = copy_project_to_temp_dir(test_projects_dir().join("external-kit"));
+    let project_dir = project.path();
+    let twoliter_toml = project_dir.join("Twoliter.toml");
+    std::fs::remove_file(project_dir.join("Twoliter.lock")).ok();
+    std::fs::write(project_dir.join("Twoliter.override"), TWOLITER_OVERRIDE).unwrap();
+    let cert_file = registry.cert_file();
+    let env = [
+        ("TWOLITER_KIT_IMAGE_TOOL", "crane"),
+        ("SSL_CERT_FILE", cert_file.to_str().unwrap()),
+    ];
+
+    let output = run_command(
+        TWOLITER_PATH,
+        ["update", "--project-path", twoliter_toml.to_str().unwrap()],
+        env,
+    );
+    assert!(output.status.success());
+
+    let warm_cache = || {
+        let output = run_command(
+            TWOLITER_PATH,
+            [
+                "warm-cache",
+                "--project-path",
+                twoliter_toml.to_str().unwrap(),
+            ],
+            env,
+        );
+        assert!(output.status.success());
+        warm_cache_report(&output.stdout)
+    };
+
+    // The SDK may already be cached from another test, but the kit was just removed.
+    let (loaded, skipped) = warm_cache();
+    assert!(loaded.iter().any(|image| image == KIT_IMAGE), "{loaded:?}");
+    assert_eq!(loaded.len() + skipped.len(), 2, "{loaded:?} {skipped:?}");
+    for image in loaded.iter().chain(&skipped) {
+        let output = run_command("docker", ["image", "inspect", image], []);
+        assert!(
+            output.status.success(),
+            "{image} is not in the docker daemon"
+        );
+    }
+
+    // Every image is cached now, so nothing is pulled again.
+    let (loaded, skipped) = warm_cache();
+    assert!(loaded.is_empty(), "{loaded:?}");
+    assert_eq!(skipped.len(), 2, "{skipped:?}");
+
+    run_command("docker", ["image", "rm", "--force", KIT_IMAGE], []);
+}
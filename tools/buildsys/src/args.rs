@@ -5,9 +5,11 @@ of its input arguments from environment variables.
 
 !*/
 
+use crate::builder::DockerBackend;
 use buildsys::manifest::SupportedArch;
 use buildsys::BuildType;
 use clap::{Parser, Subcommand};
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
 use url::Url;
 
@@ -15,8 +17,9 @@ use url::Url;
 /// variable changes. The build type is represented with bit flags so that we can easily list
 /// multiple build types for a single variable. See `[BuildType]` and `[rerun_for_envs]` below to
 /// see how this list is used.
-const REBUILD_VARS: [(&str, u8); 14] = [
+const REBUILD_VARS: [(&str, u8); 16] = [
     ("BUILDSYS_ARCH", PACKAGE | KIT | VARIANT),
+    ("BUILDSYS_ARCHES", PACKAGE | VARIANT),
     ("BUILDSYS_CACERTS_BUNDLE_OVERRIDE", VARIANT),
     ("BUILDSYS_KITS_DIR", KIT),
     ("BUILDSYS_EXTERNAL_KITS_DIR", PACKAGE | KIT | VARIANT),
@@ -26,6 +29,7 @@ const REBUILD_VARS: [(&str, u8); 14] = [
     ("BUILDSYS_PACKAGES_DIR", PACKAGE),
     ("BUILDSYS_PRETTY_NAME", VARIANT),
     ("BUILDSYS_ROOT_DIR", PACKAGE | KIT | VARIANT),
+    ("BUILDSYS_SOURCE_DATE_EPOCH", PACKAGE | KIT | VARIANT),
     ("BUILDSYS_STATE_DIR", PACKAGE | KIT | VARIANT),
     ("BUILDSYS_VERSION_BUILD", KIT | VARIANT),
     ("BUILDSYS_VERSION_IMAGE", KIT | VARIANT),
@@ -59,7 +63,7 @@ impl Command {
 }
 
 /// Arguments common to all subcommands.
-#[derive(Debug, Parser)]
+#[derive(Debug, Clone, Parser)]
 pub(crate) struct Common {
     #[arg(long, env = "BUILDSYS_ARCH")]
     pub(crate) arch: SupportedArch,
@@ -85,21 +89,49 @@ pub(crate) struct Common {
     #[arg(long, env = "TWOLITER_TOOLS_DIR")]
     pub(crate) tools_dir: PathBuf,
 
+    /// How docker builds images: `buildx`, `legacy` for `docker build`, or `auto` to use buildx
+    /// when the docker CLI has it.
+    #[arg(
+        long,
+        env = "BUILDSYS_DOCKER_BACKEND",
+        value_enum,
+        default_value_t = DockerBackend::Auto
+    )]
+    pub(crate) docker_backend: DockerBackend,
+
+    /// The `SOURCE_DATE_EPOCH` for the build, in seconds since the Unix epoch. This takes
+    /// precedence over `source-date-epoch` in the manifest, and over the manifest's modification
+    /// time which is used when neither is set.
+    #[arg(long, env = "BUILDSYS_SOURCE_DATE_EPOCH")]
+    pub(crate) source_date_epoch: Option<u64>,
+
     /// cicd_hack is used to suppress builds from running after all the cargo-related metadata is
     /// emitted. This allows cargo to create a fresh crate, and assumes that the corresponding
     /// build artifacts are already present. It is intended for use in a CI/CD scenario where some
     /// other process populates the build directory from a cache. Other uses may lead to unexpected
-    /// build failures that are difficult to troubleshoot.
+    /// build failures that are difficult to troubleshoot. Since no build runs, the build cache is
+    /// neither read from `BUILDSYS_CACHE_FROM` nor exported to `BUILDSYS_CACHE_TO`.
     #[arg(long, env = "BUILDSYS_CICD_HACK")]
     pub(crate) cicd_hack: bool,
 }
 
 /// Build RPMs from a spec file and sources.
-#[derive(Debug, Parser)]
+#[derive(Debug, Clone, Parser)]
 pub(crate) struct BuildPackageArgs {
     #[arg(long, env = "BUILDSYS_PACKAGES_DIR")]
     pub(crate) packages_dir: PathBuf,
 
+    /// Build the package for each of these architectures in turn instead of only for
+    /// `BUILDSYS_ARCH`. The manifest is parsed and external files are fetched once for all of them.
+    #[arg(long, env = "BUILDSYS_ARCHES", value_delimiter = ',')]
+    pub(crate) arches: Vec<SupportedArch>,
+
+    /// Images or, with buildx, cache specs to seed the build cache from, separated by spaces,
+    /// e.g. `registry.example.com/cache/hello:x86_64`. Nothing is read when
+    /// `BUILDSYS_CICD_HACK` skips the build.
+    #[arg(long, env = "BUILDSYS_CACHE_FROM", value_delimiter = ' ')]
+    pub(crate) cache_from: Vec<String>,
+
     /// version_build is used along with version_build_timestamp in setting the Release of a Package. The Release is
     /// set in the form "<timestamp of latest project commit>.<latest project commit short sha>.br1" in RPMs.
     /// The value defaults to the latest commit of a project.
@@ -121,6 +153,28 @@ pub(crate) struct BuildPackageArgs {
     #[arg(long, env = "BUILDSYS_UPSTREAM_SOURCE_FALLBACK")]
     pub(crate) upstream_source_fallback: String,
 
+    /// The most connections to open to any one host when fetching external files, for mirrors
+    /// that throttle or drop clients making too many parallel requests.
+    #[arg(
+        long,
+        env = "BUILDSYS_LOOKASIDE_CACHE_HOST_CONNECTIONS",
+        default_value = "4"
+    )]
+    pub(crate) lookaside_cache_host_connections: NonZeroUsize,
+
+    /// The most external files to fetch at once.
+    #[arg(
+        long,
+        env = "BUILDSYS_LOOKASIDE_CACHE_FETCH_WORKERS",
+        default_value = "4"
+    )]
+    pub(crate) lookaside_cache_fetch_workers: NonZeroUsize,
+
+    /// Fail instead of fetching anything over the network. External files must already be present
+    /// in the package directory, and Go modules must already be in the module cache.
+    #[arg(long, env = "BUILDSYS_OFFLINE")]
+    pub(crate) offline: bool,
+
     #[command(flatten)]
     pub(crate) common: Common,
 }
@@ -148,12 +202,23 @@ pub(crate) struct BuildKitArgs {
     #[arg(long, env = "BUILDSYS_VERSION_IMAGE")]
     pub(crate) version_image: String,
 
+    /// Images or, with buildx, cache specs to seed the build cache from, separated by spaces.
+    /// Nothing is read when `BUILDSYS_CICD_HACK` skips the build.
+    #[arg(long, env = "BUILDSYS_CACHE_FROM", value_delimiter = ' ')]
+    pub(crate) cache_from: Vec<String>,
+
+    /// A buildx cache spec to export the build cache to, e.g.
+    /// `type=registry,ref=registry.example.com/cache/core-kit,mode=max`. Nothing is exported
+    /// when `BUILDSYS_CICD_HACK` skips the build, so a cache populated that way is not refreshed.
+    #[arg(long, env = "BUILDSYS_CACHE_TO")]
+    pub(crate) cache_to: Option<String>,
+
     #[command(flatten)]
     pub(crate) common: Common,
 }
 
 /// Build filesystem and disk images from RPMs.
-#[derive(Debug, Parser)]
+#[derive(Debug, Clone, Parser)]
 pub(crate) struct BuildVariantArgs {
     #[arg(long, env = "BUILDSYS_NAME")]
     pub(crate) name: String,
@@ -170,6 +235,11 @@ pub(crate) struct BuildVariantArgs {
     #[arg(long, env = "BUILDSYS_IMAGES_DIR")]
     pub(crate) image_dir: PathBuf,
 
+    /// Build the variant for each of these architectures in turn instead of only for
+    /// `BUILDSYS_ARCH`. Architectures the variant does not support are skipped.
+    #[arg(long, env = "BUILDSYS_ARCHES", value_delimiter = ',')]
+    pub(crate) arches: Vec<SupportedArch>,
+
     #[command(flatten)]
     pub(crate) common: Common,
 }
@@ -9,13 +9,14 @@ pub(crate) mod error;
 use crate::args::{BuildKitArgs, BuildPackageArgs, BuildVariantArgs, RepackVariantArgs};
 use bottlerocket_variant::Variant;
 use buildsys::manifest::{
-    ExternalKitMetadataView, ImageFeature, ImageFormat, ImageLayout, Manifest, PartitionPlan,
-    SupportedArch,
+    ExternalKitMetadataView, ImageFeature, ImageFormat, ImageLayout, Manifest, ManifestInfo,
+    PartitionPlan, SupportedArch,
 };
 use buildsys::BuildType;
 use buildsys_config::EXTERNAL_KIT_METADATA;
 use duct::cmd;
 use error::Result;
+use filetime::FileTime;
 use lazy_static::lazy_static;
 use nonzero_ext::nonzero;
 use pipesys::server::Server as PipesysServer;
@@ -67,6 +68,16 @@ lazy_static! {
     .unwrap();
 }
 
+// A docker image reference, such as `registry.example.com:5000/cache/hello:x86_64`.
+lazy_static! {
+    static ref IMAGE_REFERENCE: Regex = Regex::new(concat!(
+        r"^[a-z0-9]+([._-][a-z0-9]+)*(:[0-9]+)?",
+        r"(/[a-z0-9]+([._-]+[a-z0-9]+)*)*",
+        r"(:\w[\w.-]{0,127})?(@sha256:[0-9a-f]{64})?$",
+    ))
+    .unwrap();
+}
+
 /*
 We also see sporadic CI failures with only this error message.
 We use (?m) for multi-line mode so we can match the message on a line of its own without splitting
@@ -104,13 +115,135 @@ enum OutputCleanup {
     None,
 }
 
+/// How `docker` builds images, chosen with `BUILDSYS_DOCKER_BACKEND`. Either way the build is
+/// driven by the same Dockerfile, targets and arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum DockerBackend {
+    /// Use buildx if the docker CLI has it, or else `docker build`
+    Auto,
+    /// Build with `docker buildx build`
+    Buildx,
+    /// Build with `docker build`
+    Legacy,
+}
+
+impl DockerBackend {
+    /// Settle `Auto` on whichever backend is available.
+    fn resolve(self) -> Result<Self> {
+        match self {
+            Self::Auto if buildx_available() => Ok(Self::Buildx),
+            Self::Auto => Ok(Self::Legacy),
+            Self::Buildx => {
+                ensure!(buildx_available(), error::BuildxUnavailableSnafu);
+                Ok(self)
+            }
+            Self::Legacy => Ok(self),
+        }
+    }
+
+    /// The `docker` arguments that start a build with this backend.
+    fn build_command(self) -> Vec<String> {
+        match self {
+            // Load the image into the local image store as `docker build` does, whichever driver
+            // the current builder uses, so that it can be removed after the build.
+            Self::Buildx => "buildx build --load",
+            Self::Auto | Self::Legacy => "build",
+        }
+        .split_string()
+    }
+}
+
+/// Whether the docker CLI has the buildx plugin.
+fn buildx_available() -> bool {
+    cmd("docker", ["buildx", "version"])
+        .stdout_null()
+        .stderr_null()
+        .unchecked()
+        .run()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// Where a build seeds its layer cache from, and where it exports the cache to.
+#[derive(Debug, Default)]
+struct BuildCache {
+    from: Vec<String>,
+    to: Option<String>,
+}
+
+impl BuildCache {
+    /// Each reference is either an image, such as `registry.example.com/cache/hello`, or with
+    /// buildx a cache spec such as `type=registry,ref=registry.example.com/cache/hello`.
+    fn new(from: Vec<String>, to: Option<String>) -> Result<Self> {
+        for cache_ref in from.iter().chain(&to) {
+            ensure!(
+                valid_cache_ref(cache_ref),
+                error::CacheRefSnafu {
+                    cache_ref,
+                    reason: "expected an image reference or a `type=...` cache spec",
+                }
+            );
+        }
+        Ok(Self { from, to })
+    }
+
+    /// The arguments for a build with `backend`, which must be resolved.
+    fn args(&self, backend: DockerBackend) -> Result<Vec<String>> {
+        let mut args = Vec::new();
+        for cache_ref in &self.from {
+            ensure!(
+                backend == DockerBackend::Buildx || !cache_ref.contains('='),
+                error::CacheRefSnafu {
+                    cache_ref,
+                    reason: "cache specs need BUILDSYS_DOCKER_BACKEND 'buildx' or 'auto'",
+                }
+            );
+            args.push("--cache-from".to_string());
+            args.push(cache_ref.clone());
+        }
+        if let Some(cache_ref) = &self.to {
+            // `docker build` can only read a cache from images, not export one.
+            ensure!(
+                backend == DockerBackend::Buildx,
+                error::CacheRefSnafu {
+                    cache_ref,
+                    reason: "exporting the cache needs BUILDSYS_DOCKER_BACKEND 'buildx' or 'auto'",
+                }
+            );
+            args.push("--cache-to".to_string());
+            args.push(cache_ref.clone());
+        }
+        Ok(args)
+    }
+}
+
+/// Whether `cache_ref` is an image reference, or a cache spec made of comma-separated `key=value`
+/// pairs that include the cache `type`.
+fn valid_cache_ref(cache_ref: &str) -> bool {
+    if !cache_ref.contains('=') {
+        return IMAGE_REFERENCE.is_match(cache_ref);
+    }
+    let pairs = cache_ref
+        .split(',')
+        .map(|pair| pair.split_once('='))
+        .collect::<Option<Vec<_>>>();
+    pairs.is_some_and(|pairs| {
+        !cache_ref.contains(char::is_whitespace)
+            && pairs
+                .iter()
+                .all(|(key, value)| !key.is_empty() && !value.is_empty())
+            && pairs.iter().any(|(key, _)| *key == "type")
+    })
+}
+
 struct CommonBuildArgs {
     arch: SupportedArch,
+    backend: DockerBackend,
     sdk: String,
     nocache: String,
     token: String,
     cleanup: OutputCleanup,
     output_socket: String,
+    source_date_epoch: u64,
 }
 
 impl CommonBuildArgs {
@@ -118,7 +251,9 @@ impl CommonBuildArgs {
         root: impl AsRef<Path>,
         sdk: String,
         arch: SupportedArch,
+        backend: DockerBackend,
         cleanup: OutputCleanup,
+        source_date_epoch: u64,
     ) -> Self {
         let token = token(&root);
 
@@ -131,11 +266,13 @@ impl CommonBuildArgs {
 
         Self {
             arch,
+            backend,
             sdk,
             nocache,
             token,
             cleanup,
             output_socket,
+            source_date_epoch,
         }
     }
 }
@@ -323,6 +460,7 @@ pub(crate) struct DockerBuild {
     common_build_args: CommonBuildArgs,
     target_build_args: TargetBuildArgs,
     secrets_args: Vec<String>,
+    cache: BuildCache,
 }
 
 impl DockerBuild {
@@ -331,6 +469,11 @@ impl DockerBuild {
         let package = manifest.info().package_name();
         let per_package_dir = format!("{}/{}", args.packages_dir.display(), package).into();
         let old_package_dir = format!("{}", args.packages_dir.display()).into();
+        let source_date_epoch = source_date_epoch(
+            args.common.source_date_epoch,
+            manifest.info(),
+            &args.common.cargo_manifest_dir,
+        )?;
 
         Ok(Self {
             dockerfile: args.common.tools_dir.join("build.Dockerfile"),
@@ -352,7 +495,9 @@ impl DockerBuild {
                 &args.common.root_dir,
                 args.common.sdk_image,
                 args.common.arch,
+                args.common.docker_backend,
                 OutputCleanup::BeforeBuild,
+                source_date_epoch,
             ),
             target_build_args: TargetBuildArgs::Package(PackageBuildArgs {
                 package: package.to_string(),
@@ -365,12 +510,18 @@ impl DockerBuild {
                 version_build_timestamp: args.version_build_timestamp,
             }),
             secrets_args: Vec::new(),
+            cache: BuildCache::new(args.cache_from, None)?,
         })
     }
 
     pub(crate) fn new_kit(args: BuildKitArgs, manifest: &Manifest) -> Result<Self> {
         let kit = manifest.info().kit_name();
         let per_kit_dir = args.kits_dir.join(kit);
+        let source_date_epoch = source_date_epoch(
+            args.common.source_date_epoch,
+            manifest.info(),
+            &args.common.cargo_manifest_dir,
+        )?;
 
         Ok(Self {
             dockerfile: args.common.tools_dir.join("build.Dockerfile"),
@@ -392,7 +543,9 @@ impl DockerBuild {
                 &args.common.root_dir,
                 args.common.sdk_image,
                 args.common.arch,
+                args.common.docker_backend,
                 OutputCleanup::BeforeBuild,
+                source_date_epoch,
             ),
             target_build_args: TargetBuildArgs::Kit(KitBuildArgs {
                 kit: kit.to_string(),
@@ -404,6 +557,7 @@ impl DockerBuild {
                 version_id: args.version_image,
             }),
             secrets_args: Vec::new(),
+            cache: BuildCache::new(args.cache_from, args.cache_to)?,
         })
     }
 
@@ -420,6 +574,11 @@ impl DockerBuild {
         let (os_image_publish_size_gib, data_image_publish_size_gib) =
             image_layout.publish_image_sizes_gib();
 
+        let source_date_epoch = source_date_epoch(
+            args.common.source_date_epoch,
+            manifest.info(),
+            &args.common.cargo_manifest_dir,
+        )?;
         let variant = filename(args.common.cargo_manifest_dir);
 
         let v = Variant::new(&variant).context(error::VariantParseSnafu)?;
@@ -446,7 +605,9 @@ impl DockerBuild {
                 &args.common.root_dir,
                 args.common.sdk_image,
                 args.common.arch,
+                args.common.docker_backend,
                 OutputCleanup::BeforeBuild,
+                source_date_epoch,
             ),
             target_build_args: TargetBuildArgs::Variant(VariantBuildArgs {
                 package_dependencies: manifest.package_dependencies().context(error::GraphSnafu)?,
@@ -493,6 +654,7 @@ impl DockerBuild {
                 version_image: args.version_image,
             }),
             secrets_args: secrets_args()?,
+            cache: BuildCache::default(),
         })
     }
 
@@ -509,6 +671,11 @@ impl DockerBuild {
         let (os_image_publish_size_gib, data_image_publish_size_gib) =
             image_layout.publish_image_sizes_gib();
 
+        let source_date_epoch = source_date_epoch(
+            args.common.source_date_epoch,
+            manifest.info(),
+            &args.common.cargo_manifest_dir,
+        )?;
         let variant = filename(args.common.cargo_manifest_dir);
 
         Ok(Self {
@@ -529,7 +696,9 @@ impl DockerBuild {
                 &args.common.root_dir,
                 args.common.sdk_image,
                 args.common.arch,
+                args.common.docker_backend,
                 OutputCleanup::None,
+                source_date_epoch,
             ),
             target_build_args: TargetBuildArgs::Repack(RepackVariantBuildArgs {
                 data_image_publish_size_gib,
@@ -554,6 +723,7 @@ impl DockerBuild {
                 version_image: args.version_image,
             }),
             secrets_args: secrets_args()?,
+            cache: BuildCache::default(),
         })
     }
 
@@ -578,8 +748,11 @@ impl DockerBuild {
             OutputCleanup::None => (),
         }
 
-        let mut build = format!(
-            "build {context} \
+        let backend = self.common_build_args.backend.resolve()?;
+        let mut build = backend.build_command();
+        build.extend(
+            format!(
+                "{context} \
             --target {target} \
             --tag {tag} \
             --network host \
@@ -592,11 +765,13 @@ impl DockerBuild {
             target = self.target,
             tag = self.tag,
             uid = *BUILDER_UID,
-        )
-        .split_string();
+            )
+            .split_string(),
+        );
 
         build.extend(self.build_args());
         build.extend(self.secrets_args.clone());
+        build.extend(self.cache.args(backend)?);
 
         // Run a container with the project's root as a read-only volume mount, so that pipesys can
         // serve a read-only file descriptor that's safe to pass into builds.
@@ -691,6 +866,10 @@ impl DockerBuild {
         args.build_arg("NOCACHE", &self.common_build_args.nocache);
         args.build_arg("TOKEN", &self.common_build_args.token);
         args.build_arg("OUTPUT_SOCKET", &self.common_build_args.output_socket);
+        args.build_arg(
+            "SOURCE_DATE_EPOCH",
+            self.common_build_args.source_date_epoch.to_string(),
+        );
 
         // Skip some build checks:
         // - InvalidDefaultArgInFrom warns about the SDK argument, which is always set
@@ -706,6 +885,25 @@ impl DockerBuild {
 
 // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
 
+/// Choose the `SOURCE_DATE_EPOCH` for a build. A value passed to buildsys wins over the manifest's
+/// `source-date-epoch`; without either, use the manifest's modification time, which is also the
+/// time that fetched external files are capped to.
+fn source_date_epoch(
+    requested: Option<u64>,
+    manifest_info: &ManifestInfo,
+    manifest_dir: &Path,
+) -> Result<u64> {
+    if let Some(epoch) = requested.or_else(|| manifest_info.source_date_epoch()) {
+        return Ok(epoch);
+    }
+    let path = manifest_dir.join("Cargo.toml");
+    let metadata = fs::metadata(&path).context(error::FileMetadataSnafu { path })?;
+    let mtime = FileTime::from_last_modification_time(&metadata);
+    Ok(u64::try_from(mtime.unix_seconds()).unwrap_or_default())
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
 /// Run `docker` with the specified arguments.
 fn docker(args: &[String], retry: Retry) -> Result<Output> {
     let mut max_attempts: u16 = 1;
@@ -1066,3 +1264,155 @@ fn filename(p: impl AsRef<Path>) -> String {
         .to_string_lossy()
         .to_string()
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use filetime::set_file_mtime;
+    use tempfile::TempDir;
+
+    const MANIFEST: &str = r#"
+[package]
+name = "hello"
+version = "0.1.0"
+
+[package.metadata.build-package]
+"#;
+
+    fn write_manifest(dir: &Path, extra: &str) -> ManifestInfo {
+        let path = dir.join("Cargo.toml");
+        fs::write(&path, format!("{MANIFEST}{extra}")).unwrap();
+        set_file_mtime(&path, FileTime::from_unix_time(1_700_000_000, 0)).unwrap();
+        ManifestInfo::new(path).unwrap()
+    }
+
+    fn package_build(root: &Path, source_date_epoch: u64) -> DockerBuild {
+        DockerBuild {
+            dockerfile: root.join("build.Dockerfile"),
+            context: root.to_path_buf(),
+            target: "package".to_string(),
+            tag: append_token("buildsys-pkg-hello-x86_64", root),
+            root_dir: root.to_path_buf(),
+            artifacts_dirs: vec![root.join("build/rpms/hello")],
+            state_dir: root.join("build/state"),
+            artifact_name: "hello".to_string(),
+            common_build_args: CommonBuildArgs::new(
+                root,
+                "sdk:latest".to_string(),
+                SupportedArch::X86_64,
+                DockerBackend::Legacy,
+                OutputCleanup::BeforeBuild,
+                source_date_epoch,
+            ),
+            target_build_args: TargetBuildArgs::Package(PackageBuildArgs {
+                package: "hello".to_string(),
+                package_dependencies: Vec::new(),
+                kit_dependencies: Vec::new(),
+                external_kit_dependencies: Vec::new(),
+                version_build: "0123abcd".to_string(),
+                version_build_timestamp: "1700000000000".to_string(),
+            }),
+            secrets_args: Vec::new(),
+            cache: BuildCache::default(),
+        }
+    }
+
+    fn build_arg<'a>(args: &'a [String], key: &str) -> Option<&'a str> {
+        args.iter()
+            .find_map(|arg| arg.strip_prefix(key)?.strip_prefix('='))
+    }
+
+    #[test]
+    fn source_date_epoch_defaults_to_manifest_mtime() {
+        let temp_dir = TempDir::new().unwrap();
+        let manifest_info = write_manifest(temp_dir.path(), "");
+
+        let epoch = source_date_epoch(None, &manifest_info, temp_dir.path()).unwrap();
+        assert_eq!(epoch, 1_700_000_000);
+
+        let args = package_build(temp_dir.path(), epoch).build_args();
+        assert_eq!(build_arg(&args, "SOURCE_DATE_EPOCH"), Some("1700000000"));
+    }
+
+    #[test]
+    fn source_date_epoch_from_manifest_or_override() {
+        let temp_dir = TempDir::new().unwrap();
+        let manifest_info = write_manifest(temp_dir.path(), "source-date-epoch = 1600000000\n");
+
+        let epoch = source_date_epoch(None, &manifest_info, temp_dir.path()).unwrap();
+        assert_eq!(epoch, 1_600_000_000);
+
+        let epoch =
+            source_date_epoch(Some(1_500_000_000), &manifest_info, temp_dir.path()).unwrap();
+        assert_eq!(epoch, 1_500_000_000);
+
+        let args = package_build(temp_dir.path(), epoch).build_args();
+        assert_eq!(build_arg(&args, "SOURCE_DATE_EPOCH"), Some("1500000000"));
+    }
+
+    #[test]
+    fn docker_backend_build_command() {
+        assert_eq!(
+            DockerBackend::Buildx.build_command(),
+            ["buildx", "build", "--load"]
+        );
+        assert_eq!(DockerBackend::Legacy.build_command(), ["build"]);
+        assert_eq!(
+            DockerBackend::Legacy.resolve().unwrap(),
+            DockerBackend::Legacy
+        );
+    }
+
+    #[test]
+    fn cache_refs() {
+        for cache_ref in [
+            "hello",
+            "registry.example.com:5000/cache/hello:x86_64",
+            "public.ecr.aws/cache/hello@sha256:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef",
+            "type=registry,ref=registry.example.com/cache/hello,mode=max",
+        ] {
+            assert!(valid_cache_ref(cache_ref), "{cache_ref}");
+        }
+        for cache_ref in [
+            "",
+            "Registry.example.com/hello",
+            "registry.example.com/hello latest",
+            "ref=registry.example.com/cache/hello",
+            "type=registry,ref",
+        ] {
+            assert!(!valid_cache_ref(cache_ref), "{cache_ref}");
+        }
+    }
+
+    #[test]
+    fn cache_args() {
+        assert!(BuildCache::default()
+            .args(DockerBackend::Legacy)
+            .unwrap()
+            .is_empty());
+        assert!(BuildCache::new(vec!["not an image".to_string()], None).is_err());
+
+        let cache = BuildCache::new(
+            vec!["registry.example.com/cache/hello".to_string()],
+            Some("type=registry,ref=registry.example.com/cache/hello".to_string()),
+        )
+        .unwrap();
+        assert_eq!(
+            cache.args(DockerBackend::Buildx).unwrap(),
+            [
+                "--cache-from",
+                "registry.example.com/cache/hello",
+                "--cache-to",
+                "type=registry,ref=registry.example.com/cache/hello",
+            ]
+        );
+        assert!(cache.args(DockerBackend::Legacy).is_err());
+
+        let cache = BuildCache::new(
+            vec!["type=registry,ref=registry.example.com/cache/hello".to_string()],
+            None,
+        )
+        .unwrap();
+        assert!(cache.args(DockerBackend::Legacy).is_err());
+    }
+}
@@ -16,6 +16,17 @@ pub(crate) enum Error {
     #[snafu(display("Failed to read repo root '{}'", root_json_path.display()))]
     BadRootJson { root_json_path: PathBuf },
 
+    #[snafu(display(
+        "BUILDSYS_DOCKER_BACKEND is 'buildx', but the docker CLI has no buildx plugin"
+    ))]
+    BuildxUnavailable,
+
+    #[snafu(display("Invalid build cache reference '{}': {}", cache_ref, reason))]
+    CacheRef {
+        cache_ref: String,
+        reason: &'static str,
+    },
+
     #[snafu(display("Failed to start command: {}", source))]
     CommandStart { source: std::io::Error },
 
@@ -58,6 +69,12 @@ pub(crate) enum Error {
         source: std::io::Error,
     },
 
+    #[snafu(display("Failed to get metadata for '{}': {}", path.display(), source))]
+    FileMetadata {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
     #[snafu(display("Failed to remove file '{}': {}", path.display(), source))]
     FileRemove {
         path: PathBuf,
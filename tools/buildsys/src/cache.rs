@@ -8,6 +8,16 @@ given the (name, url, hash) data that uniquely identifies each file.
 
 It implements a two-tier approach to retrieval: files are first pulled from the
 "lookaside" cache and only fetched from the upstream site if that access fails.
+Files marked `no-cache` skip both the local copy and the lookaside cache and are
+always fetched from the upstream site.
+
+In offline mode nothing is fetched. Files must already be present and match
+their hash, including files marked `no-cache`.
+
+Each file is checked against its `sha512` or `sha256` hash as it is downloaded.
+A file that declares neither cannot be checked, so it is used with a warning,
+and since the lookaside cache is keyed by the SHA-512 hash, a file without one
+can only be fetched from its upstream URL.
 
 */
 pub(crate) mod error;
@@ -16,11 +26,16 @@ use error::Result;
 use buildsys::manifest;
 use filetime::{set_file_mtime, FileTime};
 use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
-use sha2::{Digest, Sha512};
+use sha2::{Digest, Sha256, Sha512};
 use snafu::{ensure, OptionExt, ResultExt};
+use std::collections::HashMap;
 use std::fs::{self, File};
-use std::io::{self, BufWriter};
+use std::io::{self, BufWriter, Write};
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
 use url::Url;
 
 pub(crate) struct LookasideCache {
@@ -33,6 +48,15 @@ pub(crate) struct LookasideCache {
     /// Whether we are allowed to pull sources from upstream URLs. When this is false, it can be
     /// overridden by `upstream-fallback` in the manifest.
     upstream_fallback: bool,
+
+    /// Limits the number of concurrent connections to each host.
+    host_limiter: HostLimiter,
+
+    /// The most files to fetch at once.
+    fetch_workers: NonZeroUsize,
+
+    /// Whether network access is forbidden, so that only files already present can be used.
+    offline: bool,
 }
 
 impl LookasideCache {
@@ -40,80 +64,144 @@ impl LookasideCache {
         version: impl AsRef<str>,
         lookaside_cache: Url,
         upstream_fallback: bool,
+        max_connections_per_host: NonZeroUsize,
+        fetch_workers: NonZeroUsize,
+        offline: bool,
     ) -> Self {
         Self {
             version: version.as_ref().to_string(),
             lookaside_cache,
             upstream_fallback,
+            host_limiter: HostLimiter::new(max_connections_per_host),
+            fetch_workers,
+            offline,
         }
     }
 
-    /// Fetch files stored out-of-tree and ensure they match the stored hash.
+    /// Fetch files stored out-of-tree and ensure they match the stored hash. Up to `fetch_workers`
+    /// files are fetched at once, with at most `max_connections_per_host` connections open to any
+    /// one host. Every file is attempted; if any fail, the error for the first of them is returned.
     pub(crate) fn fetch(&self, files: &[manifest::ExternalFile], mtime: FileTime) -> Result<()> {
-        for f in files {
-            let url_file_name = Self::extract_file_name(&f.url)?;
-            let path = &f.path.as_ref().unwrap_or(&url_file_name);
-            ensure!(
-                path.components().count() == 1,
-                error::ExternalFileNameSnafu { path }
-            );
-
-            let hash = &f.sha512;
-            if path.is_file() {
-                match Self::verify_file(path, hash) {
-                    Ok(_) => continue,
-                    Err(e) => {
-                        println!("{}", e);
-                        fs::remove_file(path).context(error::ExternalFileDeleteSnafu { path })?;
+        let next = AtomicUsize::new(0);
+        let results: Vec<Mutex<Option<Result<()>>>> =
+            files.iter().map(|_| Mutex::new(None)).collect();
+        let workers = self.fetch_workers.get().min(files.len());
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(f) = files.get(i) else {
+                        break;
+                    };
+                    let result = self.fetch_one(f, mtime);
+                    if let Err(e) = &result {
+                        println!("Failed to fetch {}: {}", f.url, e);
                     }
+                    *results[i].lock().expect("fetch result lock poisoned") = Some(result);
+                });
+            }
+        });
+        results.into_iter().try_for_each(|result| {
+            result
+                .into_inner()
+                .expect("fetch result lock poisoned")
+                .expect("external file was not fetched")
+        })
+    }
+
+    /// Fetch a single file stored out-of-tree and ensure it matches the stored hash.
+    fn fetch_one(&self, f: &manifest::ExternalFile, mtime: FileTime) -> Result<()> {
+        let url_file_name = Self::extract_file_name(&f.url)?;
+        let path = &f.path.as_ref().unwrap_or(&url_file_name);
+        ensure!(
+            path.components().count() == 1,
+            error::ExternalFileNameSnafu { path }
+        );
+
+        let hash = ExpectedHash::of(f);
+        if hash.is_none() {
+            println!(
+                "Warning: {} has no sha512 or sha256 hash, so it cannot be verified",
+                path.display()
+            );
+        }
+        let no_cache = f.no_cache.unwrap_or(false);
+        if path.is_file() && (!no_cache || self.offline) {
+            match Self::verify_file(path, hash) {
+                Ok(_) => return Ok(()),
+                Err(e) => {
+                    println!("{}", e);
+                    fs::remove_file(path).context(error::ExternalFileDeleteSnafu { path })?;
                 }
             }
+        }
+        ensure!(!self.offline, error::OfflineSnafu { path, url: &f.url });
 
-            let name = &path.display().to_string();
-            let tmp = PathBuf::from(format!(".{}", name));
+        let name = &path.display().to_string();
+        let tmp = PathBuf::from(format!(".{}", name));
 
-            // first check the lookaside cache
-            let mut url = self.lookaside_cache.clone();
-            url.path_segments_mut()
-                .map_err(|_| {
-                    error::UrlPathSegmentsSnafu {
-                        url: self.lookaside_cache.clone(),
-                    }
-                    .build()
-                })?
-                .extend([name, hash, name]);
-            let url = url.to_string();
-            match self.fetch_file(&url, &tmp, hash) {
-                Ok(_) => {
+        if no_cache {
+            println!("Fetching {:?} from upstream source", url_file_name);
+            self.fetch_file(&f.url, &tmp, hash)?;
+            fs::rename(&tmp, path).context(error::ExternalFileRenameSnafu { path: &tmp })?;
+            set_file_mtime(path, mtime).context(error::SetMtimeSnafu { path })?;
+            return Ok(());
+        }
+
+        // first check the lookaside cache
+        let lookaside = match &f.sha512 {
+            Some(sha512) => self
+                .lookaside_url(name, sha512)
+                .and_then(|url| self.fetch_file(&url, &tmp, hash)),
+            None => error::LookasideKeySnafu { path }.fail(),
+        };
+        match lookaside {
+            Ok(_) => {
+                fs::rename(&tmp, path).context(error::ExternalFileRenameSnafu { path: &tmp })?;
+                set_file_mtime(path, mtime).context(error::SetMtimeSnafu { path })?;
+                Ok(())
+            }
+            Err(e) => {
+                // next check with upstream, if permitted
+                if f.force_upstream.unwrap_or(false) || self.upstream_fallback {
+                    println!("Error fetching from lookaside cache: {}", e);
+                    println!("Fetching {:?} from upstream source", url_file_name);
+                    self.fetch_file(&f.url, &tmp, hash)?;
                     fs::rename(&tmp, path)
                         .context(error::ExternalFileRenameSnafu { path: &tmp })?;
                     set_file_mtime(path, mtime).context(error::SetMtimeSnafu { path })?;
-                    continue;
-                }
-                Err(e) => {
-                    // next check with upstream, if permitted
-                    if f.force_upstream.unwrap_or(false) || self.upstream_fallback {
-                        println!("Error fetching from lookaside cache: {}", e);
-                        println!("Fetching {:?} from upstream source", url_file_name);
-                        self.fetch_file(&f.url, &tmp, hash)?;
-                        fs::rename(&tmp, path)
-                            .context(error::ExternalFileRenameSnafu { path: &tmp })?;
-                        set_file_mtime(path, mtime).context(error::SetMtimeSnafu { path })?;
-                    } else {
-                        // we failed to fetch from the lookaside cache, and we cannot fall back to
-                        // upstream sources, so we should not continue, we need to return the error
-                        return Err(e);
-                    }
+                    Ok(())
+                } else {
+                    // we failed to fetch from the lookaside cache, and we cannot fall back to
+                    // upstream sources, so we should not continue, we need to return the error
+                    Err(e)
                 }
             }
         }
+    }
 
-        Ok(())
+    /// The location of the file `name` with the SHA-512 hash `sha512` in the lookaside cache.
+    fn lookaside_url(&self, name: &str, sha512: &str) -> Result<String> {
+        let mut url = self.lookaside_cache.clone();
+        url.path_segments_mut()
+            .map_err(|_| {
+                error::UrlPathSegmentsSnafu {
+                    url: self.lookaside_cache.clone(),
+                }
+                .build()
+            })?
+            .extend([name, sha512, name]);
+        Ok(url.to_string())
     }
 
-    /// Retrieves a file from the specified URL and write it to the given path,
-    /// then verifies the contents against the SHA-512 hash provided.
-    fn fetch_file<P: AsRef<Path>>(&self, url: &str, path: P, hash: &str) -> Result<()> {
+    /// Retrieves a file from the specified URL and write it to the given path, verifying the
+    /// contents against the hash provided, if any, as they are written.
+    fn fetch_file<P: AsRef<Path>>(
+        &self,
+        url: &str,
+        path: P,
+        hash: Option<ExpectedHash<'_>>,
+    ) -> Result<()> {
         let path = path.as_ref();
 
         let mut headers = HeaderMap::new();
@@ -128,6 +216,11 @@ impl LookasideCache {
             )),
         );
 
+        let parsed = Url::parse(url).context(error::ExternalFileUrlSnafu { url })?;
+        let _connection = self
+            .host_limiter
+            .acquire(parsed.host_str().unwrap_or_default());
+
         let client = reqwest::blocking::Client::new();
         let mut resp = client
             .get(url)
@@ -141,18 +234,30 @@ impl LookasideCache {
         );
 
         let f = File::create(path).context(error::ExternalFileOpenSnafu { path })?;
-        let mut f = BufWriter::new(f);
+        let mut f = HashingWriter {
+            inner: BufWriter::new(f),
+            hasher: hash.map(ExpectedHash::hasher),
+        };
         resp.copy_to(&mut f)
             .context(error::ExternalFileSaveSnafu { path })?;
-        drop(f);
+        f.inner
+            .flush()
+            .context(error::ExternalFileWriteSnafu { path })?;
 
-        match Self::verify_file(path, hash) {
-            Ok(_) => Ok(()),
-            Err(e) => {
+        if let (Some(expected), Some(hasher)) = (hash, f.hasher) {
+            let actual = hasher.finish();
+            if actual != expected.value() {
                 fs::remove_file(path).context(error::ExternalFileDeleteSnafu { path })?;
-                Err(e)
+                return error::ExternalFileHashSnafu {
+                    url,
+                    path,
+                    expected: expected.value(),
+                    actual,
+                }
+                .fail();
             }
         }
+        Ok(())
     }
 
     fn extract_file_name(url: &str) -> Result<PathBuf> {
@@ -165,19 +270,459 @@ impl LookasideCache {
         Ok(name.into())
     }
 
-    /// Reads a file from disk and compares it to the expected SHA-512 hash.
-    fn verify_file<P: AsRef<Path>>(path: P, hash: &str) -> Result<()> {
+    /// Reads a file from disk and compares it to the expected hash. A file without an expected
+    /// hash is accepted as it is.
+    fn verify_file<P: AsRef<Path>>(path: P, hash: Option<ExpectedHash<'_>>) -> Result<()> {
         let path = path.as_ref();
+        let Some(expected) = hash else {
+            return Ok(());
+        };
         let mut f = File::open(path).context(error::ExternalFileOpenSnafu { path })?;
-        let mut d = Sha512::new();
-
-        io::copy(&mut f, &mut d).context(error::ExternalFileLoadSnafu { path })?;
-        let digest = hex::encode(d.finalize());
+        let mut hasher = HashingWriter {
+            inner: io::sink(),
+            hasher: Some(expected.hasher()),
+        };
+        io::copy(&mut f, &mut hasher).context(error::ExternalFileLoadSnafu { path })?;
+        let actual = hasher.hasher.map(Hasher::finish).unwrap_or_default();
 
         ensure!(
-            digest == hash,
-            error::ExternalFileVerifySnafu { path, hash }
+            actual == expected.value(),
+            error::ExternalFileVerifySnafu {
+                path,
+                expected: expected.value(),
+                actual,
+            }
         );
         Ok(())
     }
 }
+
+/// The hash an external file declares in its manifest.
+#[derive(Debug, Clone, Copy)]
+enum ExpectedHash<'a> {
+    Sha512(&'a str),
+    Sha256(&'a str),
+}
+
+impl<'a> ExpectedHash<'a> {
+    /// The strongest hash `f` declares, if any.
+    fn of(f: &'a manifest::ExternalFile) -> Option<Self> {
+        match (&f.sha512, &f.sha256) {
+            (Some(sha512), _) => Some(Self::Sha512(sha512)),
+            (None, Some(sha256)) => Some(Self::Sha256(sha256)),
+            (None, None) => None,
+        }
+    }
+
+    fn value(self) -> &'a str {
+        match self {
+            Self::Sha512(hash) | Self::Sha256(hash) => hash,
+        }
+    }
+
+    fn hasher(self) -> Hasher {
+        match self {
+            Self::Sha512(_) => Hasher::Sha512(Sha512::new()),
+            Self::Sha256(_) => Hasher::Sha256(Sha256::new()),
+        }
+    }
+}
+
+/// Computes the hash of the kind an [`ExpectedHash`] declares.
+enum Hasher {
+    Sha512(Sha512),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha512(d) => d.update(data),
+            Self::Sha256(d) => d.update(data),
+        }
+    }
+
+    /// The hex-encoded hash of everything written.
+    fn finish(self) -> String {
+        match self {
+            Self::Sha512(d) => hex::encode(d.finalize()),
+            Self::Sha256(d) => hex::encode(d.finalize()),
+        }
+    }
+}
+
+/// Hashes everything written through it, so a file can be checked while it is downloaded.
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Option<Hasher>,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Bounds the number of connections open to each host at once.
+struct HostLimiter {
+    max_per_host: NonZeroUsize,
+    active: Mutex<HashMap<String, usize>>,
+    released: Condvar,
+}
+
+impl HostLimiter {
+    fn new(max_per_host: NonZeroUsize) -> Self {
+        Self {
+            max_per_host,
+            active: Mutex::new(HashMap::new()),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Wait until a connection to `host` is allowed. The connection counts against the limit until
+    /// the returned guard is dropped.
+    fn acquire(&self, host: &str) -> HostConnection<'_> {
+        let mut active = self.active.lock().expect("host limiter lock poisoned");
+        while active.get(host).copied().unwrap_or_default() >= self.max_per_host.get() {
+            active = self
+                .released
+                .wait(active)
+                .expect("host limiter lock poisoned");
+        }
+        *active.entry(host.to_string()).or_default() += 1;
+        HostConnection {
+            limiter: self,
+            host: host.to_string(),
+        }
+    }
+}
+
+struct HostConnection<'a> {
+    limiter: &'a HostLimiter,
+    host: String,
+}
+
+impl Drop for HostConnection<'_> {
+    fn drop(&mut self) {
+        let mut active = self
+            .limiter
+            .active
+            .lock()
+            .expect("host limiter lock poisoned");
+        if let Some(count) = active.get_mut(&self.host) {
+            *count -= 1;
+        }
+        self.limiter.released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Fetches write to the current directory, so tests that change it must not run concurrently.
+    static CURRENT_DIR: Mutex<()> = Mutex::new(());
+
+    fn external_file(url: &Url, name: &str, hash: &str) -> manifest::ExternalFile {
+        manifest::ExternalFile {
+            path: None,
+            sha512: Some(hash.to_string()),
+            sha256: None,
+            url: url.join(name).unwrap().to_string(),
+            force_upstream: None,
+            no_cache: Some(true),
+            bundle_modules: None,
+            bundle_root_path: None,
+            bundle_output_path: None,
+        }
+    }
+
+    /// Serves `body` for each of `count` requests, recording the requested paths.
+    fn serve(body: &'static [u8], count: usize) -> (Url, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().take(count) {
+                let mut stream = stream.unwrap();
+                let mut buf = [0; 4096];
+                let n = stream.read(&mut buf).unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]);
+                let path = request.split_whitespace().nth(1).unwrap().to_string();
+                recorded.lock().unwrap().push(path);
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                )
+                .unwrap();
+                stream.write_all(body).unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    #[test]
+    fn no_cache_fetches_upstream() {
+        let body = b"nightly";
+        let hash = hex::encode(Sha512::digest(body));
+        let (url, requests) = serve(body, 1);
+
+        let _current_dir = CURRENT_DIR.lock().unwrap();
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::env::set_current_dir(temp_dir.path()).unwrap();
+        fs::write("nightly.tar.gz", body).unwrap();
+
+        let file = external_file(&url, "nightly.tar.gz", &hash);
+        LookasideCache::new(
+            "0.0.0",
+            url.join("lookaside").unwrap(),
+            false,
+            NonZeroUsize::new(1).unwrap(),
+            NonZeroUsize::new(4).unwrap(),
+            false,
+        )
+        .fetch(&[file], FileTime::now())
+        .unwrap();
+
+        assert_eq!(*requests.lock().unwrap(), vec!["/nightly.tar.gz"]);
+        assert_eq!(fs::read("nightly.tar.gz").unwrap(), body);
+    }
+
+    /// Serves `body` for each of `count` requests slowly, failing any request made while
+    /// `max_concurrent` others are in progress.
+    fn serve_limited(body: &'static [u8], count: usize, max_concurrent: usize) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let active = Arc::new(AtomicUsize::new(0));
+        thread::spawn(move || {
+            for stream in listener.incoming().take(count) {
+                let mut stream = stream.unwrap();
+                let active = active.clone();
+                thread::spawn(move || {
+                    let concurrent = active.fetch_add(1, Ordering::SeqCst) + 1;
+                    let mut buf = [0; 4096];
+                    let _ = stream.read(&mut buf).unwrap();
+                    let response = if concurrent > max_concurrent {
+                        "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".as_bytes().to_vec()
+                    } else {
+                        thread::sleep(Duration::from_millis(100));
+                        let mut response = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                            body.len()
+                        )
+                        .into_bytes();
+                        response.extend_from_slice(body);
+                        response
+                    };
+                    // Stop counting this request before the client can see the response, or the
+                    // client's next request may arrive while it still looks active.
+                    active.fetch_sub(1, Ordering::SeqCst);
+                    stream.write_all(&response).unwrap();
+                });
+            }
+        });
+        url
+    }
+
+    /// Fetches `count` copies of `body` into the current directory with the given limits.
+    fn fetch_concurrently(count: usize, max_connections_per_host: usize, fetch_workers: usize) {
+        let body = b"source";
+        let hash = hex::encode(Sha512::digest(body));
+        let url = serve_limited(body, count, max_connections_per_host.min(fetch_workers));
+
+        let names: Vec<String> = (0..count).map(|i| format!("source-{i}.tar.gz")).collect();
+        let files: Vec<_> = names
+            .iter()
+            .map(|name| external_file(&url, name, &hash))
+            .collect();
+        LookasideCache::new(
+            "0.0.0",
+            url.join("lookaside").unwrap(),
+            false,
+            NonZeroUsize::new(max_connections_per_host).unwrap(),
+            NonZeroUsize::new(fetch_workers).unwrap(),
+            false,
+        )
+        .fetch(&files, FileTime::now())
+        .unwrap();
+
+        for name in names {
+            assert_eq!(fs::read(name).unwrap(), body);
+        }
+    }
+
+    #[test]
+    fn connections_per_host_are_limited() {
+        let _current_dir = CURRENT_DIR.lock().unwrap();
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::env::set_current_dir(temp_dir.path()).unwrap();
+        fetch_concurrently(6, 2, 4);
+    }
+
+    #[test]
+    fn fetch_workers_are_limited() {
+        let _current_dir = CURRENT_DIR.lock().unwrap();
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::env::set_current_dir(temp_dir.path()).unwrap();
+        fetch_concurrently(4, 4, 1);
+    }
+
+    #[test]
+    fn failures_name_the_url() {
+        let body = b"source";
+        let hash = hex::encode(Sha512::digest(body));
+        let (url, _) = serve(body, 2);
+
+        let _current_dir = CURRENT_DIR.lock().unwrap();
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::env::set_current_dir(temp_dir.path()).unwrap();
+
+        let mtime = FileTime::from_unix_time(1_000_000_000, 0);
+        let files = [
+            external_file(&url, "bad.tar.gz", &hex::encode(Sha512::digest(b"other"))),
+            external_file(&url, "good.tar.gz", &hash),
+        ];
+        let err = LookasideCache::new(
+            "0.0.0",
+            url.join("lookaside").unwrap(),
+            false,
+            NonZeroUsize::new(4).unwrap(),
+            NonZeroUsize::new(4).unwrap(),
+            false,
+        )
+        .fetch(&files, mtime)
+        .unwrap_err();
+
+        assert!(
+            matches!(&err, error::Error::ExternalFileHash { url, .. } if url.ends_with("/bad.tar.gz")),
+            "{err}"
+        );
+        assert!(!Path::new("bad.tar.gz").exists());
+        let metadata = fs::metadata("good.tar.gz").unwrap();
+        assert_eq!(FileTime::from_last_modification_time(&metadata), mtime);
+    }
+
+    #[test]
+    fn sha256_and_unhashed_files() {
+        let body = b"source";
+        let (url, requests) = serve(body, 3);
+
+        let _current_dir = CURRENT_DIR.lock().unwrap();
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::env::set_current_dir(temp_dir.path()).unwrap();
+
+        let with_sha256 = |name: &str, hash: &str| manifest::ExternalFile {
+            sha512: None,
+            sha256: Some(hash.to_string()),
+            ..external_file(&url, name, "")
+        };
+        let sha256 = hex::encode(Sha256::digest(body));
+        let wrong_sha256 = hex::encode(Sha256::digest(b"other"));
+        let unhashed = manifest::ExternalFile {
+            sha512: None,
+            ..external_file(&url, "unhashed.tar.gz", "")
+        };
+        let cache = LookasideCache::new(
+            "0.0.0",
+            url.join("lookaside").unwrap(),
+            false,
+            NonZeroUsize::new(1).unwrap(),
+            NonZeroUsize::new(1).unwrap(),
+            false,
+        );
+
+        cache
+            .fetch(
+                &[with_sha256("source.tar.gz", &sha256), unhashed],
+                FileTime::now(),
+            )
+            .unwrap();
+        assert_eq!(fs::read("source.tar.gz").unwrap(), body);
+        assert_eq!(fs::read("unhashed.tar.gz").unwrap(), body);
+
+        let err = cache
+            .fetch(&[with_sha256("bad.tar.gz", &wrong_sha256)], FileTime::now())
+            .unwrap_err();
+        assert!(
+            matches!(&err, error::Error::ExternalFileHash { expected, actual, .. }
+                if *expected == wrong_sha256 && *actual == sha256),
+            "{err}"
+        );
+        assert!(!Path::new("bad.tar.gz").exists());
+        assert_eq!(requests.lock().unwrap().len(), 3);
+    }
+
+    /// A cache that refuses network access, pointing at a port nothing listens on in case it tries.
+    fn offline_cache() -> (LookasideCache, Url) {
+        let url = Url::parse("http://127.0.0.1:9/").unwrap();
+        let cache = LookasideCache::new(
+            "0.0.0",
+            url.join("lookaside").unwrap(),
+            true,
+            NonZeroUsize::new(1).unwrap(),
+            NonZeroUsize::new(4).unwrap(),
+            true,
+        );
+        (cache, url)
+    }
+
+    #[test]
+    fn offline_uses_staged_files() {
+        let body = b"source";
+        let hash = hex::encode(Sha512::digest(body));
+        let (cache, url) = offline_cache();
+
+        let _current_dir = CURRENT_DIR.lock().unwrap();
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::env::set_current_dir(temp_dir.path()).unwrap();
+        fs::write("source.tar.gz", body).unwrap();
+        fs::write("nightly.tar.gz", body).unwrap();
+
+        // `no-cache` cannot be honored offline, so the staged copy is used as long as it matches.
+        let staged = manifest::ExternalFile {
+            no_cache: None,
+            ..external_file(&url, "source.tar.gz", &hash)
+        };
+        let nightly = external_file(&url, "nightly.tar.gz", &hash);
+        cache.fetch(&[staged, nightly], FileTime::now()).unwrap();
+
+        assert_eq!(fs::read("source.tar.gz").unwrap(), body);
+        assert_eq!(fs::read("nightly.tar.gz").unwrap(), body);
+    }
+
+    #[test]
+    fn offline_fails_for_missing_files() {
+        let hash = hex::encode(Sha512::digest(b"source"));
+        let (cache, url) = offline_cache();
+
+        let _current_dir = CURRENT_DIR.lock().unwrap();
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::env::set_current_dir(temp_dir.path()).unwrap();
+
+        let missing = manifest::ExternalFile {
+            no_cache: None,
+            ..external_file(&url, "missing.tar.gz", &hash)
+        };
+        let err = cache.fetch(&[missing], FileTime::now()).unwrap_err();
+        assert!(
+            matches!(&err, error::Error::Offline { url, .. } if url.ends_with("/missing.tar.gz")),
+            "{err}"
+        );
+        assert!(!Path::new("missing.tar.gz").exists());
+    }
+}
@@ -24,6 +24,20 @@ pub(crate) enum Error {
         status: reqwest::StatusCode,
     },
 
+    #[snafu(display(
+        "File '{}' fetched from '{}' has hash '{}', expected '{}'",
+        path.display(),
+        url,
+        actual,
+        expected
+    ))]
+    ExternalFileHash {
+        url: String,
+        path: PathBuf,
+        expected: String,
+        actual: String,
+    },
+
     #[snafu(display("Failed to open file '{}': {}", path.display(), source))]
     ExternalFileOpen { path: PathBuf, source: io::Error },
 
@@ -36,8 +50,20 @@ pub(crate) enum Error {
     #[snafu(display("Failed to load file '{}': {}", path.display(), source))]
     ExternalFileLoad { path: PathBuf, source: io::Error },
 
-    #[snafu(display("Failed to verify file '{}' with hash '{}'", path.display(), hash))]
-    ExternalFileVerify { path: PathBuf, hash: String },
+    #[snafu(display(
+        "File '{}' has hash '{}', expected '{}'",
+        path.display(),
+        actual,
+        expected
+    ))]
+    ExternalFileVerify {
+        path: PathBuf,
+        expected: String,
+        actual: String,
+    },
+
+    #[snafu(display("Failed to write file '{}': {}", path.display(), source))]
+    ExternalFileWrite { path: PathBuf, source: io::Error },
 
     #[snafu(display("Failed to rename file '{}': {}", path.display(), source))]
     ExternalFileRename { path: PathBuf, source: io::Error },
@@ -45,6 +71,19 @@ pub(crate) enum Error {
     #[snafu(display("Failed to delete file '{}': {}", path.display(), source))]
     ExternalFileDelete { path: PathBuf, source: io::Error },
 
+    #[snafu(display(
+        "Cannot look up '{}' in the lookaside cache without a sha512 hash",
+        path.display()
+    ))]
+    LookasideKey { path: PathBuf },
+
+    #[snafu(display(
+        "Refusing to fetch '{}' from '{}' because network access is disabled",
+        path.display(),
+        url
+    ))]
+    Offline { path: PathBuf, url: String },
+
     #[snafu(display("Failed to set modification time for file '{}': {}", path.display(), source))]
     SetMtime { path: PathBuf, source: io::Error },
 
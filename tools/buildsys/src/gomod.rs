@@ -11,7 +11,9 @@ declared using Go modules given a tar archive containing a `go.mod` and `go.sum`
 The location where dependencies are retrieved from are controlled by the
 standard environment variables employed by the Go tool: `GOPROXY`, `GOSUMDB`, and
 `GOPRIVATE`. These variables are automatically retrieved from the host environment
-when the docker-go script is invoked.
+when the docker-go script is invoked. In offline mode, docker-go is passed
+`--offline`, which sets `GOPROXY` to `off` and runs the container without network,
+so only modules already in the module cache can be used.
 
  */
 
@@ -78,6 +80,7 @@ impl GoMod {
         external_file: &manifest::ExternalFile,
         sdk: &str,
         mtime: FileTime,
+        offline: bool,
     ) -> Result<()> {
         let url_file_name = extract_file_name(&external_file.url)?;
         let local_file_name = &external_file.path.as_ref().unwrap_or(&url_file_name);
@@ -113,11 +116,24 @@ impl GoMod {
             output_path_arg.to_string_lossy()
         );
 
+        // Vendoring runs the Go toolchain in a container, so skip it when the bundle from a
+        // previous run still matches the upstream archive and SDK.
+        let output_path = package_dir.join(output_path_arg);
+        let sdk_stamp_path = sdk_stamp_path(&output_path);
+        if is_fresh(&full_path, &output_path, &sdk_stamp_path, sdk, mtime) {
+            println!(
+                "Go modules in {} are already vendored, skipping",
+                local_file_name.to_string_lossy()
+            );
+            return Ok(());
+        }
+
         let args = DockerGoArgs {
             module_path: package_dir,
             sdk_image: sdk.to_string(),
             go_mod_cache: &root_dir.join(".gomodcache"),
             command: format!("./{}", GO_MOD_DOCKER_SCRIPT_NAME),
+            offline,
         };
 
         // Create and/or write the temporary script file to the package directory
@@ -151,12 +167,41 @@ impl GoMod {
             set_file_mtime(output_path_arg, mtime).context(error::SetMtimeSnafu {
                 path: output_path_arg,
             })?;
+            fs::write(&sdk_stamp_path, sdk).context(error::WriteFileSnafu {
+                path: &sdk_stamp_path,
+            })?;
         }
 
         res
     }
 }
 
+/// The file recording which SDK vendored the bundle at `output_path`.
+fn sdk_stamp_path(output_path: &Path) -> PathBuf {
+    let mut stamp = output_path.as_os_str().to_owned();
+    stamp.push(".sdk");
+    stamp.into()
+}
+
+/// Whether the bundle at `output_path` can be reused. It must have been vendored with `sdk`, and
+/// its modification time must be no later than the manifest's and no earlier than that of the
+/// upstream archive that holds `go.mod` and `go.sum`.
+fn is_fresh(
+    input_path: &Path,
+    output_path: &Path,
+    sdk_stamp_path: &Path,
+    sdk: &str,
+    mtime: FileTime,
+) -> bool {
+    let (Ok(input), Ok(output)) = (fs::metadata(input_path), fs::metadata(output_path)) else {
+        return false;
+    };
+    let output_mtime = FileTime::from_last_modification_time(&output);
+    output_mtime <= mtime
+        && output_mtime >= FileTime::from_last_modification_time(&input)
+        && fs::read_to_string(sdk_stamp_path).is_ok_and(|stamp| stamp == sdk)
+}
+
 fn extract_file_name(url: &str) -> Result<PathBuf> {
     let parsed = reqwest::Url::parse(url).context(error::InputUrlSnafu { url })?;
     let name = parsed
@@ -172,11 +217,12 @@ struct DockerGoArgs<'a> {
     sdk_image: String,
     go_mod_cache: &'a Path,
     command: String,
+    offline: bool,
 }
 
 /// Run `docker-go` with the specified arguments.
 fn docker_go(dg_args: &DockerGoArgs) -> Result<()> {
-    let args = vec![
+    let mut args = vec![
         "--module-path",
         dg_args
             .module_path
@@ -189,9 +235,12 @@ fn docker_go(dg_args: &DockerGoArgs) -> Result<()> {
             .go_mod_cache
             .to_str()
             .context(error::InputFileSnafu)?,
-        "--command",
-        &dg_args.command,
     ];
+    // docker-go takes everything after `--command` as the command, so this must come first.
+    if dg_args.offline {
+        args.push("--offline");
+    }
+    args.extend(["--command", &dg_args.command]);
     let arg_string = args.join(" ");
     let twoliter_tools_dir = env::var("TWOLITER_TOOLS_DIR").context(error::EnvironmentSnafu {
         var: "TWOLITER_TOOLS_DIR",
@@ -213,3 +262,80 @@ fn docker_go(dg_args: &DockerGoArgs) -> Result<()> {
     );
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::TempDir;
+
+    const SDK: &str = "public.ecr.aws/bottlerocket/bottlerocket-sdk:v0.50.0";
+
+    fn external_file() -> manifest::ExternalFile {
+        manifest::ExternalFile {
+            path: None,
+            sha512: None,
+            sha256: None,
+            url: "https://example.com/hello-1.0.tar.gz".to_string(),
+            force_upstream: None,
+            no_cache: None,
+            bundle_modules: Some(vec![manifest::BundleModule::Go]),
+            bundle_root_path: None,
+            bundle_output_path: None,
+        }
+    }
+
+    /// Write an upstream archive and a previously vendored bundle, stamped with `sdk`.
+    fn pre_vendored(package_dir: &Path, sdk: &str, mtime: FileTime) -> PathBuf {
+        let input = package_dir.join("hello-1.0.tar.gz");
+        let output = package_dir.join("bundled-hello-1.0.tar.gz");
+        fs::write(&input, "upstream").unwrap();
+        fs::write(&output, "vendored").unwrap();
+        fs::write(sdk_stamp_path(&output), sdk).unwrap();
+        set_file_mtime(&input, mtime).unwrap();
+        set_file_mtime(&output, mtime).unwrap();
+        output
+    }
+
+    #[test]
+    fn fresh_bundle_is_not_vendored_again() {
+        let temp_dir = TempDir::new().unwrap();
+        let mtime = FileTime::from_unix_time(1_700_000_000, 0);
+        let output = pre_vendored(temp_dir.path(), SDK, mtime);
+
+        // Vendoring would need `TWOLITER_TOOLS_DIR` to find docker-go, so this only succeeds if it
+        // was skipped.
+        GoMod::vendor(
+            temp_dir.path(),
+            temp_dir.path(),
+            &external_file(),
+            SDK,
+            mtime,
+            false,
+        )
+        .unwrap();
+        assert_eq!(fs::read_to_string(output).unwrap(), "vendored");
+        assert!(!temp_dir.path().join(GO_MOD_DOCKER_SCRIPT_NAME).exists());
+    }
+
+    #[test]
+    fn stale_bundle_is_vendored_again() {
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.path().join("hello-1.0.tar.gz");
+        let mtime = FileTime::from_unix_time(1_700_000_000, 0);
+        let output = pre_vendored(temp_dir.path(), SDK, mtime);
+        let stamp = sdk_stamp_path(&output);
+        assert!(is_fresh(&input, &output, &stamp, SDK, mtime));
+
+        // A different SDK may bring a different Go toolchain.
+        assert!(!is_fresh(&input, &output, &stamp, "other-sdk:v1", mtime));
+
+        // The upstream archive changed after the bundle was made.
+        let later = FileTime::from_unix_time(1_700_000_100, 0);
+        set_file_mtime(&input, later).unwrap();
+        assert!(!is_fresh(&input, &output, &stamp, SDK, later));
+
+        // The bundle is missing.
+        fs::remove_file(&output).unwrap();
+        assert!(!is_fresh(&input, &output, &stamp, SDK, mtime));
+    }
+}
@@ -26,11 +26,18 @@ use clap::Parser;
 use filetime::FileTime;
 use gomod::GoMod;
 use project::ProjectInfo;
+use serde::Serialize;
 use snafu::{ensure, ResultExt};
 use spec::SpecInfo;
 use std::path::{Path, PathBuf};
 use std::process;
 
+/// Directory in the state directory holding a JSON record, named `<name>-<arch>.json`, for each
+/// package or variant and architecture whose build was skipped because the architecture is not
+/// supported. Each build overwrites its own record, and removes it if the architecture is
+/// supported, so the directory describes the latest build of each rather than growing forever.
+const SKIPPED_ARCHES_DIR: &str = "skipped-arches";
+
 mod error {
     use snafu::Snafu;
     use std::path::PathBuf;
@@ -66,6 +73,15 @@ mod error {
             source: super::builder::error::Error,
         },
 
+        #[snafu(display("Failed to record skipped build in '{}': {}", path.display(), source))]
+        SkipRecord {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Failed to serialize skipped build record: {source}"))]
+        SkipRecordSerialize { source: serde_json::Error },
+
         #[snafu(display("Unable to instantiate the builder: {source}"))]
         BuilderInstantiation {
             source: crate::builder::error::Error,
@@ -144,6 +160,9 @@ fn build_package(args: BuildPackageArgs) -> Result<()> {
             &args.common.version_full,
             args.lookaside_cache.clone(),
             args.upstream_source_fallback == "true",
+            args.lookaside_cache_host_connections,
+            args.lookaside_cache_fetch_workers,
+            args.offline,
         );
 
         lookaside_cache
@@ -163,6 +182,7 @@ fn build_package(args: BuildPackageArgs) -> Result<()> {
                         f,
                         &args.common.sdk_image,
                         mtime,
+                        args.offline,
                     )
                     .context(error::GoModSnafu)?,
                 }
@@ -181,6 +201,16 @@ fn build_package(args: BuildPackageArgs) -> Result<()> {
         }
     }
 
+    for dep in manifest
+        .build_depends()
+        .context(error::ManifestParseSnafu)?
+    {
+        println!(
+            "cargo:rerun-if-changed={}",
+            args.packages_dir.join(dep).display()
+        );
+    }
+
     // Package developer can override name of package if desired, e.g. to name package with
     // characters invalid in Cargo crate names
     let package = manifest.info().package_name();
@@ -201,10 +231,15 @@ fn build_package(args: BuildPackageArgs) -> Result<()> {
         return Ok(());
     }
 
-    DockerBuild::new_package(args, &manifest)
-        .context(error::BuilderInstantiationSnafu)?
-        .build()
-        .context(error::BuildAttemptSnafu)
+    for arch in requested_arches(args.common.arch, &args.arches) {
+        let mut args = args.clone();
+        args.common.arch = arch;
+        DockerBuild::new_package(args, &manifest)
+            .context(error::BuilderInstantiationSnafu)?
+            .build()
+            .context(error::BuildAttemptSnafu)?;
+    }
+    Ok(())
 }
 
 fn build_kit(args: BuildKitArgs) -> Result<()> {
@@ -245,16 +280,25 @@ fn build_variant(args: BuildVariantArgs) -> Result<()> {
     )
     .context(error::ManifestParseSnafu)?;
 
-    check_arch_support(manifest.info(), args.common.arch);
+    let arches = check_arch_support(
+        manifest.info(),
+        &requested_arches(args.common.arch, &args.arches),
+        &args.common.state_dir,
+    )?;
 
     if args.common.cicd_hack {
         return Ok(());
     }
 
-    DockerBuild::new_variant(args, &manifest)
-        .context(error::BuilderInstantiationSnafu)?
-        .build()
-        .context(error::BuildAttemptSnafu)
+    for arch in arches {
+        let mut args = args.clone();
+        args.common.arch = arch;
+        DockerBuild::new_variant(args, &manifest)
+            .context(error::BuilderInstantiationSnafu)?
+            .build()
+            .context(error::BuildAttemptSnafu)?;
+    }
+    Ok(())
 }
 
 fn repack_variant(args: RepackVariantArgs) -> Result<()> {
@@ -266,9 +310,9 @@ fn repack_variant(args: RepackVariantArgs) -> Result<()> {
     )
     .context(error::ManifestParseSnafu)?;
 
-    check_arch_support(manifest.info(), args.common.arch);
+    let arches = check_arch_support(manifest.info(), &[args.common.arch], &args.common.state_dir)?;
 
-    if args.common.cicd_hack {
+    if arches.is_empty() || args.common.cicd_hack {
         return Ok(());
     }
 
@@ -278,18 +322,85 @@ fn repack_variant(args: RepackVariantArgs) -> Result<()> {
         .context(error::BuildAttemptSnafu)
 }
 
-/// Ensure that the current arch is supported by the current variant
-fn check_arch_support(manifest: &ManifestInfo, arch: SupportedArch) {
-    if let Some(supported_arches) = manifest.supported_arches() {
-        if !supported_arches.contains(&arch) {
-            let supported_arches = supported_arches
-                .iter()
-                .map(|a| a.to_string())
-                .collect::<Vec<String>>();
-            println!("cargo:warning={arch} is not one of the supported architectures ({supported_arches:?})");
-            std::process::exit(0);
+/// The architectures to build for: `arches` if any were requested, or else `arch`.
+fn requested_arches(arch: SupportedArch, arches: &[SupportedArch]) -> Vec<SupportedArch> {
+    if arches.is_empty() {
+        return vec![arch];
+    }
+    let mut requested = Vec::new();
+    for arch in arches {
+        if !requested.contains(arch) {
+            requested.push(*arch);
         }
     }
+    requested
+}
+
+/// Filter `arches` down to those supported by the current variant, recording and warning about
+/// each one that is skipped.
+fn check_arch_support(
+    manifest: &ManifestInfo,
+    arches: &[SupportedArch],
+    state_dir: &Path,
+) -> Result<Vec<SupportedArch>> {
+    let Some(supported_arches) = manifest.supported_arches() else {
+        return Ok(arches.to_vec());
+    };
+    let mut supported = Vec::new();
+    for &arch in arches {
+        if supported_arches.contains(&arch) {
+            clear_skip(state_dir, manifest.manifest_name(), arch)?;
+            supported.push(arch);
+            continue;
+        }
+        let supported_arches = supported_arches
+            .iter()
+            .map(|a| a.to_string())
+            .collect::<Vec<String>>();
+        let reason =
+            format!("{arch} is not one of the supported architectures ({supported_arches:?})");
+        record_skip(state_dir, manifest.manifest_name(), arch, &reason)?;
+        println!("cargo:warning={reason}");
+    }
+    Ok(supported)
+}
+
+#[derive(Debug, Serialize)]
+struct SkipRecord<'a> {
+    name: &'a str,
+    arch: SupportedArch,
+    reason: &'a str,
+}
+
+/// The path of the record of `name` being skipped for `arch`.
+fn skip_record_path(state_dir: &Path, name: &str, arch: SupportedArch) -> PathBuf {
+    state_dir
+        .join(SKIPPED_ARCHES_DIR)
+        .join(format!("{name}-{arch}.json"))
+}
+
+/// Record a skipped build in the state directory, so that CI can report on every package and
+/// architecture that was skipped across a build. Builds run concurrently, but each writes only
+/// its own record.
+fn record_skip(state_dir: &Path, name: &str, arch: SupportedArch, reason: &str) -> Result<()> {
+    let path = skip_record_path(state_dir, name, arch);
+    let record = serde_json::to_string(&SkipRecord { name, arch, reason })
+        .context(error::SkipRecordSerializeSnafu)?;
+
+    let dir = state_dir.join(SKIPPED_ARCHES_DIR);
+    std::fs::create_dir_all(&dir).context(error::SkipRecordSnafu { path: dir })?;
+    std::fs::write(&path, record).context(error::SkipRecordSnafu { path })
+}
+
+/// Remove any record of `name` being skipped for `arch`, which is now supported.
+fn clear_skip(state_dir: &Path, name: &str, arch: SupportedArch) -> Result<()> {
+    let path = skip_record_path(state_dir, name, arch);
+    match std::fs::remove_file(&path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(e).context(error::SkipRecordSnafu { path })
+        }
+        _ => Ok(()),
+    }
 }
 
 /// Prior to the release of Kits as a build feature, packages could, and did, declare themselves
@@ -318,3 +429,55 @@ fn ensure_package_is_not_variant_sensitive(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record_skip() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let reason = "aarch64 is not one of the supported architectures ([\"x86_64\"])";
+        record_skip(temp_dir.path(), "aws-dev", SupportedArch::Aarch64, reason).unwrap();
+        record_skip(temp_dir.path(), "metal-dev", SupportedArch::X86_64, reason).unwrap();
+        // Skipping the same build again replaces its record.
+        record_skip(temp_dir.path(), "aws-dev", SupportedArch::Aarch64, reason).unwrap();
+
+        let read = |name, arch| {
+            let path = skip_record_path(temp_dir.path(), name, arch);
+            serde_json::from_str::<serde_json::Value>(&std::fs::read_to_string(path).unwrap())
+                .unwrap()
+        };
+        let record = read("aws-dev", SupportedArch::Aarch64);
+        assert_eq!(record["name"], "aws-dev");
+        assert_eq!(record["arch"], "aarch64");
+        assert_eq!(record["reason"], reason);
+        assert_eq!(read("metal-dev", SupportedArch::X86_64)["arch"], "x86_64");
+        let records = std::fs::read_dir(temp_dir.path().join(SKIPPED_ARCHES_DIR)).unwrap();
+        assert_eq!(records.count(), 2);
+
+        // A later build for a supported architecture clears the record.
+        clear_skip(temp_dir.path(), "metal-dev", SupportedArch::X86_64).unwrap();
+        clear_skip(temp_dir.path(), "metal-dev", SupportedArch::X86_64).unwrap();
+        assert!(!skip_record_path(temp_dir.path(), "metal-dev", SupportedArch::X86_64).exists());
+    }
+
+    #[test]
+    fn test_requested_arches() {
+        assert_eq!(
+            requested_arches(SupportedArch::X86_64, &[]),
+            [SupportedArch::X86_64]
+        );
+        assert_eq!(
+            requested_arches(
+                SupportedArch::X86_64,
+                &[
+                    SupportedArch::Aarch64,
+                    SupportedArch::X86_64,
+                    SupportedArch::Aarch64
+                ]
+            ),
+            [SupportedArch::Aarch64, SupportedArch::X86_64]
+        );
+    }
+}
@@ -33,6 +33,28 @@ url = "https://bar"
 sha512 = "123456"
 ```
 
+Each file is checked against its `sha512` hash, or its `sha256` hash if it only
+has that. Files are stored in the lookaside cache by their SHA-512 hash, so a
+file without a `sha512` hash can only be fetched from its upstream URL, which
+requires upstream fallback or `force-upstream`. A file with neither hash is not
+checked, and a warning is printed when it is used.
+```ignore
+[[package.metadata.build-package.external-files]]
+url = "https://foo/baz.tar.gz"
+sha256 = "abcdef"
+```
+
+`no-cache` forces the file to always be fetched from its upstream URL, even if
+a copy already exists locally or in the lookaside cache. The downloaded file
+still replaces the local copy. This is useful when iterating against an
+upstream artifact that changes frequently.
+```ignore
+[[package.metadata.build-package.external-files]]
+url = "https://foo/nightly.tar.gz"
+sha512 = "abcdef"
+no-cache = true
+```
+
 The `bundle-*` keys on `external-files` are a group of optional modifiers
 and are used to untar an upstream external file archive, vendor any dependent
 code, and produce an additional archive with those dependencies.
@@ -87,6 +109,23 @@ to indicate a good URL for checking whether the software has had a new release.
 releases-url = "https://www.example.com/releases"
 ```
 
+`source-date-epoch` fixes the `SOURCE_DATE_EPOCH` used for the build, in seconds since the Unix
+epoch, so that timestamps embedded in the built RPMs are reproducible. If not specified, the
+modification time of the manifest is used. `BUILDSYS_SOURCE_DATE_EPOCH` overrides both.
+```ignore
+[package.metadata.build-package]
+source-date-epoch = 1704067200
+```
+
+`build-depends` lists other local packages whose built RPMs are consumed while building this
+package, for example by a spec that inspects them. Any change to the output of a listed package,
+or of the packages it in turn lists, causes this package to be rebuilt. The listed packages must
+also be `build-dependencies` of this package so that Cargo builds them first. Cycles are an error.
+```ignore
+[package.metadata.build-package]
+build-depends = ["libfoo"]
+```
+
 ## Metadata for kits
 
 When building a kit, it is necessary to include a `package.metadata.build-kit` key even though there
@@ -106,6 +145,13 @@ another-kit = { path = "../../kits/another-kit" }
 some-package = { path = "../../packages/some-package" }
 ```
 
+`supported-arches` is the list of architectures the kit is built and published for, as for
+variants. `twoliter verify-release` checks that a published kit has an image for each of them.
+```ignore
+[package.metadata.build-kit]
+supported-arches = ["x86_64", "aarch64"]
+```
+
 ## Metadata for variants
 
 `included-packages` is a list of packages that should be included in a variant.
@@ -168,6 +214,13 @@ The given parameters are inserted at the start of the command line.
 kernel-parameters = [
    "console=ttyS42",
 ]
+```
+
+`source-date-epoch` fixes the `SOURCE_DATE_EPOCH` used for the image build, as for packages.
+```ignore
+[package.metadata.build-variant]
+source-date-epoch = 1704067200
+```
 
 `image-features` is a map of image feature flags, which can be enabled or disabled. This allows us
 to conditionally use or exclude certain image-level features in variants.
@@ -318,6 +371,77 @@ impl Manifest {
         Ok(packages)
     }
 
+    /// List the packages named by `build-depends`, along with the packages that they in turn list,
+    /// using the package override name when there is one. Returns an error if the declarations
+    /// form a cycle.
+    pub fn build_depends(&self) -> Result<Vec<String>> {
+        let name = self.info().manifest_name();
+        let mut chain = vec![name.to_string()];
+        let mut visited = Vec::new();
+        self.visit_build_depends(name, &mut chain, &mut visited)?;
+
+        let mut packages = visited
+            .iter()
+            .map(|dep| Ok(get_buildsys_package_name(&self.build_depend_metadata(dep)?)))
+            .collect::<Result<Vec<_>>>()?;
+        packages.sort();
+        Ok(packages)
+    }
+
+    /// Depth-first walk of the `build-depends` declarations starting at `name`. `chain` holds the
+    /// packages on the path from the top-level manifest, and `visited` collects every package that
+    /// has been fully explored.
+    fn visit_build_depends(
+        &self,
+        name: &str,
+        chain: &mut Vec<String>,
+        visited: &mut Vec<String>,
+    ) -> Result<()> {
+        for dep in self.declared_build_depends(name)? {
+            if let Some(start) = chain.iter().position(|n| *n == dep) {
+                let mut cycle = chain[start..].to_vec();
+                cycle.push(dep);
+                return error::BuildDependCycleSnafu {
+                    cycle: cycle.join(" -> "),
+                }
+                .fail()?;
+            }
+            if visited.contains(&dep) {
+                continue;
+            }
+            chain.push(dep.clone());
+            self.visit_build_depends(&dep, chain, visited)?;
+            chain.pop();
+            visited.push(dep);
+        }
+        Ok(())
+    }
+
+    /// The `build-depends` list from the metadata of the package named `name` in the graph.
+    fn declared_build_depends(&self, name: &str) -> Result<Vec<String>> {
+        Ok(self
+            .build_depend_metadata(name)?
+            .metadata_table()
+            .get("build-package")
+            .and_then(|v| v.get("build-depends"))
+            .and_then(|v| v.as_array())
+            .map(|deps| {
+                deps.iter()
+                    .filter_map(|dep| dep.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    fn build_depend_metadata(&self, name: &str) -> Result<PackageMetadata<'_>> {
+        let id = find_id(name, &self.graph, BuildType::Package)
+            .context(error::BuildDependMissingSnafu { name })?;
+        Ok(self
+            .graph
+            .metadata(&id)
+            .context(error::CargoPackageQuerySnafuSnafu { id: id.clone() })?)
+    }
+
     /// List all kits needed for the build.
     pub fn kit_dependencies(&self) -> Result<Vec<String>> {
         let name = self.info().manifest_name();
@@ -472,10 +596,11 @@ impl ManifestInfo {
         self.build_variant().map(|b| &b.image_layout)
     }
 
-    /// Convenience method to return the supported architectures for this variant.
+    /// Convenience method to return the supported architectures for this variant or kit.
     pub fn supported_arches(&self) -> Option<&HashSet<SupportedArch>> {
         self.build_variant()
             .and_then(|b| b.supported_arches.as_ref())
+            .or_else(|| self.build_kit().and_then(|b| b.supported_arches.as_ref()))
     }
 
     /// Convenience method to return the kernel parameters for this variant.
@@ -484,6 +609,13 @@ impl ManifestInfo {
             .and_then(|b| b.kernel_parameters.as_ref())
     }
 
+    /// Convenience method to return the `SOURCE_DATE_EPOCH` fixed for a package or variant build.
+    pub fn source_date_epoch(&self) -> Option<u64> {
+        self.build_package()
+            .and_then(|b| b.source_date_epoch)
+            .or_else(|| self.build_variant().and_then(|b| b.source_date_epoch))
+    }
+
     /// Convenience method to return the enabled image features for this variant.
     pub fn image_features(&self) -> Option<HashSet<ImageFeature>> {
         let variant = self.build_variant()?;
@@ -632,6 +764,8 @@ pub struct BuildPackage {
     pub source_groups: Option<Vec<PathBuf>>,
     pub variant_sensitive: Option<VariantSensitivity>,
     pub package_features: Option<Vec<ImageFeature>>,
+    pub source_date_epoch: Option<u64>,
+    pub build_depends: Option<Vec<String>>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -640,6 +774,7 @@ pub struct BuildPackage {
 pub struct BuildKit {
     pub kit_name: Option<String>,
     pub vendor: String,
+    pub supported_arches: Option<HashSet<SupportedArch>>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -669,6 +804,7 @@ pub struct BuildVariant {
     pub supported_arches: Option<HashSet<SupportedArch>>,
     pub kernel_parameters: Option<Vec<String>>,
     pub image_features: Option<HashMap<ImageFeature, bool>>,
+    pub source_date_epoch: Option<u64>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -846,9 +982,11 @@ pub enum BundleModule {
 #[serde(rename_all = "kebab-case")]
 pub struct ExternalFile {
     pub path: Option<PathBuf>,
-    pub sha512: String,
+    pub sha512: Option<String>,
+    pub sha256: Option<String>,
     pub url: String,
     pub force_upstream: Option<bool>,
+    pub no_cache: Option<bool>,
     pub bundle_modules: Option<Vec<BundleModule>>,
     pub bundle_root_path: Option<PathBuf>,
     pub bundle_output_path: Option<PathBuf>,
@@ -975,4 +1113,82 @@ mod test {
         ];
         assert_eq!(kit_list, expected);
     }
+
+    /// Write a workspace of packages, each with the given `build-depends`, and return the path
+    /// to its cargo metadata.
+    fn build_depends_workspace(temp_dir: &TempDir, packages: &[(&str, &[&str])]) -> PathBuf {
+        let root = temp_dir.path();
+        let members = packages
+            .iter()
+            .map(|(name, _)| format!("\"{name}\""))
+            .collect::<Vec<_>>()
+            .join(", ");
+        fs::write(
+            root.join("Cargo.toml"),
+            format!("[workspace]\nresolver = \"2\"\nmembers = [{members}]\n"),
+        )
+        .unwrap();
+        fs::write(root.join("packages.rs"), "").unwrap();
+        for (name, deps) in packages {
+            let deps = deps
+                .iter()
+                .map(|dep| format!("\"{dep}\""))
+                .collect::<Vec<_>>()
+                .join(", ");
+            fs::create_dir(root.join(name)).unwrap();
+            fs::write(
+                root.join(name).join("Cargo.toml"),
+                format!(
+                    "[package]\nname = \"{name}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\
+                     publish = false\n\n[package.metadata.build-package]\n\
+                     build-depends = [{deps}]\n\n[lib]\npath = \"../packages.rs\"\n"
+                ),
+            )
+            .unwrap();
+        }
+
+        let output = MetadataCommand::new()
+            .manifest_path(root.join("Cargo.toml"))
+            .other_options(["--offline"])
+            .cargo_command()
+            .output()
+            .unwrap();
+        if !output.status.success() {
+            panic!("cargo command failed {:?}", output)
+        }
+        let output_path = root.join("cargo_metadata.json");
+        fs::write(&output_path, output.stdout).unwrap();
+        output_path
+    }
+
+    #[test]
+    fn test_build_depends() {
+        let temp_dir = TempDir::new().unwrap();
+        let cargo_metadata_path = build_depends_workspace(
+            &temp_dir,
+            &[("pkg-a", &["pkg-b"]), ("pkg-b", &["pkg-c"]), ("pkg-c", &[])],
+        );
+        let manifest_path = temp_dir.path().join("pkg-a").join("Cargo.toml");
+        let manifest = Manifest::new(manifest_path, cargo_metadata_path).unwrap();
+        assert_eq!(
+            manifest.info().build_package().unwrap().build_depends,
+            Some(vec!["pkg-b".to_string()])
+        );
+        let expected = vec!["pkg-b".to_string(), "pkg-c".to_string()];
+        assert_eq!(manifest.build_depends().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_build_depends_cycle() {
+        let temp_dir = TempDir::new().unwrap();
+        let cargo_metadata_path =
+            build_depends_workspace(&temp_dir, &[("pkg-a", &["pkg-b"]), ("pkg-b", &["pkg-a"])]);
+        let manifest_path = temp_dir.path().join("pkg-a").join("Cargo.toml");
+        let manifest = Manifest::new(manifest_path, cargo_metadata_path).unwrap();
+        let err = manifest.build_depends().unwrap_err();
+        assert!(
+            matches!(&err.0, error::Error::BuildDependCycle { cycle } if cycle == "pkg-a -> pkg-b -> pkg-a"),
+            "{err}"
+        );
+    }
 }
@@ -12,6 +12,14 @@ pub(super) enum Error {
     #[snafu(display("Failed to parse cargo_metadata json from '{}': {}", path.display(), source))]
     CargoMetadataParse { path: PathBuf, source: guppy::Error },
 
+    #[snafu(display(
+        "Package '{name}' is listed in build-depends but is not a package in the graph"
+    ))]
+    BuildDependMissing { name: String },
+
+    #[snafu(display("Package build-depends form a cycle: {cycle}"))]
+    BuildDependCycle { cycle: String },
+
     #[snafu(display("Cargo package graph query failed with root '{id}': {source}"))]
     CargoPackageQuerySnafu { id: PackageId, source: guppy::Error },
 
@@ -2,16 +2,21 @@
 This module handles iterating through project directories to discover source
 files that should be passed to Cargo to watch for changes.
 
-For now, it's a thin wrapper around `walkdir` with a filter applied to ignore
-files that shouldn't trigger rebuilds.
+For now, it's a thin wrapper around `ignore`'s directory walker with a filter
+applied to ignore files that shouldn't trigger rebuilds. Paths matching a
+`.twoliterignore` file (using gitignore syntax) are also excluded; these files
+may appear in any directory of a source group and apply to everything below it.
 
 */
 pub(crate) mod error;
 use error::Result;
 
+use ignore::{DirEntry, WalkBuilder};
 use snafu::ResultExt;
 use std::path::{Path, PathBuf};
-use walkdir::{DirEntry, WalkDir};
+
+/// Name of the gitignore-style files that exclude paths from the crawl.
+const IGNORE_FILENAME: &str = ".twoliterignore";
 
 pub(crate) struct ProjectInfo {
     pub(crate) files: Vec<PathBuf>,
@@ -23,14 +28,16 @@ impl ProjectInfo {
         let mut files = Vec::new();
 
         for dir in dirs {
-            let walker = WalkDir::new(dir)
+            let walker = WalkBuilder::new(dir)
+                .standard_filters(false)
+                .add_custom_ignore_filename(IGNORE_FILENAME)
                 .follow_links(false)
                 .same_file_system(true)
-                .into_iter();
+                .filter_entry(|e| !Self::ignored(e))
+                .build();
 
             files.extend(
                 walker
-                    .filter_entry(|e| !Self::ignored(e))
                     .flat_map(|e| e.context(error::DirectoryWalkSnafu))
                     .map(|e| e.into_path())
                     .filter(|e| e.is_file()),
@@ -49,3 +56,38 @@ impl ProjectInfo {
             .unwrap_or(false)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn crawl_respects_ignore_files() {
+        // The default temporary directory name is hidden, which the crawl would skip.
+        let temp_dir = tempfile::Builder::new()
+            .prefix("project")
+            .tempdir()
+            .unwrap();
+        let root = temp_dir.path();
+        for file in [
+            "src/lib.rs",
+            "generated/out.rs",
+            "nested/keep.rs",
+            "nested/scratch.tmp",
+        ] {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        fs::write(root.join(IGNORE_FILENAME), "generated/\n").unwrap();
+        fs::write(root.join("nested").join(IGNORE_FILENAME), "*.tmp\n").unwrap();
+
+        let mut files = ProjectInfo::crawl(&[root]).unwrap().files;
+        files.sort();
+        assert_eq!(
+            files,
+            vec![root.join("nested/keep.rs"), root.join("src/lib.rs")]
+        );
+    }
+}
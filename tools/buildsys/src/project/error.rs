@@ -4,7 +4,7 @@ use snafu::Snafu;
 #[snafu(visibility(pub(super)))]
 pub(crate) enum Error {
     #[snafu(display("Failed to walk directory to find project files: {}", source))]
-    DirectoryWalk { source: walkdir::Error },
+    DirectoryWalk { source: ignore::Error },
 }
 
 pub(super) type Result<T> = std::result::Result<T, Error>;
@@ -1,8 +1,11 @@
+mod build_retry;
+
 use flate2::{read::GzDecoder, write::GzEncoder};
 use std::fs::File;
 use std::io::{self, prelude::*};
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::Duration;
 use std::{env, fs};
 use tar::Archive;
 
@@ -10,11 +13,16 @@ const CRANE_VERSION: &str = "0.20.1";
 
 const REQUIRED_TOOLS: &[&str] = &["patch", "go"];
 
+// Retry fetching the crane sources so that a transient network failure doesn't fail the build.
+const FETCH_ATTEMPTS: u32 = 5;
+const FETCH_BACKOFF: Duration = Duration::from_secs(2);
+
 fn main() {
     let script_dir = env::current_dir().unwrap();
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
 
     println!("cargo::rerun-if-changed=../build-cache-fetch");
+    println!("cargo::rerun-if-changed=build_retry.rs");
     println!("cargo::rerun-if-changed=hashes/crane");
     println!("cargo::rerun-if-changed=patches");
 
@@ -22,10 +30,16 @@ fn main() {
 
     // Download and checksum-verify crane
     env::set_current_dir(&out_dir).expect("Failed to set current directory");
-    Command::new(script_dir.join("../build-cache-fetch"))
-        .arg(script_dir.join("hashes/crane"))
-        .status()
-        .expect("Failed to execute build-cache-fetch");
+    build_retry::run_with_retry(
+        || {
+            let mut fetch = Command::new(script_dir.join("../build-cache-fetch"));
+            fetch.arg(script_dir.join("hashes/crane"));
+            fetch
+        },
+        FETCH_ATTEMPTS,
+        FETCH_BACKOFF,
+    )
+    .unwrap_or_else(|e| panic!("Failed to fetch crane sources: {e}"));
 
     // extract crane sources
     let crane_archive = out_dir.join(format!("go-containerregistry-v{CRANE_VERSION}.tar.gz"));
@@ -0,0 +1,79 @@
+//! Retries for the commands run by the build script. This lives outside of `build.rs` so that the
+//! library's tests can include and exercise it.
+use std::process::Command;
+use std::thread::sleep;
+use std::time::Duration;
+
+/// Run the command produced by `command` until it exits successfully, making at most `attempts`
+/// attempts. The delay before each retry starts at `backoff` and doubles after every failure.
+pub(crate) fn run_with_retry<F>(
+    mut command: F,
+    attempts: u32,
+    backoff: Duration,
+) -> Result<(), String>
+where
+    F: FnMut() -> Command,
+{
+    let mut delay = backoff;
+    for attempt in 1..=attempts {
+        let mut cmd = command();
+        println!(
+            "Executing `{:?}` (attempt {attempt} of {attempts})",
+            cmd.get_program()
+        );
+        let failure = match cmd.status() {
+            Ok(status) if status.success() => return Ok(()),
+            Ok(status) => format!("{:?} failed with {status}", cmd.get_program()),
+            Err(e) => format!("Failed to execute {:?}: {e}", cmd.get_program()),
+        };
+        if attempt == attempts {
+            return Err(format!("{failure}; giving up after {attempts} attempts"));
+        }
+        println!("{failure}; retrying in {delay:?}");
+        sleep(delay);
+        delay *= 2;
+    }
+    Err("No attempts were made".to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// A command that fails until it has been run `failures` times, counting runs in `dir`.
+    fn flaky_command(dir: &TempDir, failures: usize) -> impl FnMut() -> Command + '_ {
+        move || {
+            let mut cmd = Command::new("sh");
+            cmd.arg("-c")
+                .arg(format!(
+                    "echo run >> runs; test $(wc -l < runs) -gt {failures}"
+                ))
+                .current_dir(dir.path());
+            cmd
+        }
+    }
+
+    fn runs(dir: &TempDir) -> usize {
+        fs::read_to_string(dir.path().join("runs"))
+            .unwrap()
+            .lines()
+            .count()
+    }
+
+    #[test]
+    fn retry_then_succeed() {
+        let dir = TempDir::new().unwrap();
+        run_with_retry(flaky_command(&dir, 2), 3, Duration::ZERO).unwrap();
+        assert_eq!(runs(&dir), 3);
+    }
+
+    #[test]
+    fn exhaust_retries() {
+        let dir = TempDir::new().unwrap();
+        let err = run_with_retry(flaky_command(&dir, 5), 3, Duration::ZERO).unwrap_err();
+        assert!(err.contains("giving up after 3 attempts"), "{err}");
+        assert_eq!(runs(&dir), 3);
+    }
+}
@@ -12,6 +12,8 @@ lazy_static::lazy_static! {
     pub static ref KRANE: Krane = Krane::seal().unwrap();
 }
 
+/// The krane binary, extracted to a private temporary directory. Each use of it is a separate
+/// process, so it can be run from any number of threads or tasks at once.
 #[derive(Debug)]
 pub struct Krane {
     // Hold the file in memory to keep the fd open
@@ -43,6 +45,10 @@ impl Krane {
     }
 }
 
+#[cfg(test)]
+#[path = "../build_retry.rs"]
+mod build_retry;
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -57,4 +63,27 @@ mod test {
 
         assert_eq!(status.status.code().unwrap(), 0);
     }
+
+    #[test]
+    fn test_krane_runs_concurrently() {
+        let outputs: Vec<_> = (0..8)
+            .map(|_| {
+                std::thread::spawn(|| {
+                    Command::new(KRANE.path())
+                        .arg("--help")
+                        .output()
+                        .expect("failed to run krane")
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect();
+
+        for output in &outputs {
+            assert!(output.status.success());
+            assert_eq!(output.stdout, outputs[0].stdout);
+            assert!(String::from_utf8_lossy(&output.stdout).contains("Usage:"));
+        }
+    }
 }
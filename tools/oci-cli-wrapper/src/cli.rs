@@ -1,16 +1,462 @@
-use snafu::{ensure, ResultExt};
-use std::path::PathBuf;
-use tokio::process::Command;
+use snafu::ensure;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Output, Stdio};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::process::{Child, Command};
 
-use crate::{error, Result};
+use crate::registry_auth::DOCKER_CONFIG_ENV;
+use crate::{error, RegistryClientCerts, RegistryCredentials, Result};
+
+/// Environment variable that, when set to `1` or `true`, echoes the output of image tool commands
+/// to the terminal as they run
+pub const VERBOSE_SUBPROCESS_ENV: &str = "TWOLITER_VERBOSE_SUBPROCESS";
+
+/// Whether `TWOLITER_VERBOSE_SUBPROCESS` asks for live subprocess output.
+fn verbose_from_env() -> bool {
+    matches!(
+        std::env::var(VERBOSE_SUBPROCESS_ENV).as_deref(),
+        Ok("1" | "true")
+    )
+}
+
+/// Environment variable that, when set to `1` or `true`, refuses to run any image tool command
+/// that would contact a registry
+pub const OFFLINE_ENV: &str = "TWOLITER_OFFLINE";
+
+/// Whether `TWOLITER_OFFLINE` forbids network access.
+fn offline_from_env() -> bool {
+    matches!(std::env::var(OFFLINE_ENV).as_deref(), Ok("1" | "true"))
+}
+
+/// Environment variable that, when set to `1` or `true`, logs the image tool commands that would
+/// change a registry or write an image to disk instead of running them
+pub const DRY_RUN_ENV: &str = "TWOLITER_DRY_RUN";
+
+/// Whether `TWOLITER_DRY_RUN` asks for mutating commands to be skipped.
+fn dry_run_from_env() -> bool {
+    matches!(std::env::var(DRY_RUN_ENV).as_deref(), Ok("1" | "true"))
+}
+
+/// Whether an image tool command contacts a registry, which decides if it may run in offline
+/// mode. Only the operation running the command knows this: the same subcommand can be local for
+/// one tool and remote for another, such as `podman tag` and `crane tag`, and flags can change it,
+/// as `--tarball` does for `crane digest`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Access {
+    /// Only reads or changes local files and storage
+    Local,
+    /// Contacts a registry
+    Registry,
+}
+
+/// How image tool commands are run. This is the only place the image tool's settings come from:
+/// callers that already know them pass them to [`crate::ImageTool::from_selection`] rather than
+/// exporting them into the environment, and the rest read them all at once with
+/// [`CommandOptions::from_env`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandOptions {
+    /// Echo the output of commands whose output is captured as it is produced
+    pub verbose: bool,
+    /// Fail any command that would contact a registry instead of running it
+    pub offline: bool,
+    /// Log commands that would change a registry or write an image instead of running them
+    pub dry_run: bool,
+    /// How many times a command that fails with a transient registry error is retried
+    pub retries: u32,
+    /// How long each attempt at a command may run before it is killed
+    pub timeout: Duration,
+}
+
+impl Default for CommandOptions {
+    fn default() -> Self {
+        Self {
+            verbose: false,
+            offline: false,
+            dry_run: false,
+            retries: DEFAULT_RETRIES,
+            timeout: DEFAULT_OPERATION_TIMEOUT,
+        }
+    }
+}
+
+impl CommandOptions {
+    /// Reads the options from `TWOLITER_VERBOSE_SUBPROCESS`, `TWOLITER_OFFLINE`,
+    /// `TWOLITER_DRY_RUN`, `TWOLITER_REGISTRY_RETRIES` and `TWOLITER_OPERATION_TIMEOUT`.
+    pub fn from_env() -> Self {
+        Self {
+            verbose: verbose_from_env(),
+            offline: offline_from_env(),
+            dry_run: dry_run_from_env(),
+            retries: retries_from_env(),
+            timeout: operation_timeout_from_env(),
+        }
+    }
+}
+
+/// Environment variable holding how many times a command that fails with a transient registry
+/// error is retried
+pub const REGISTRY_RETRIES_ENV: &str = "TWOLITER_REGISTRY_RETRIES";
+
+/// Retries used when `TWOLITER_REGISTRY_RETRIES` is unset
+const DEFAULT_RETRIES: u32 = 3;
+
+/// Errors, as reported on the image tool's stderr, that mean retrying cannot help. These are
+/// checked first, so that a permanent error is never retried even if it looks transient.
+const PERMANENT_ERRORS: &[&str] = &[
+    "UNAUTHORIZED",
+    "DENIED",
+    "MANIFEST_UNKNOWN",
+    "NAME_UNKNOWN",
+    "status code 401",
+    "status code 403",
+    "status code 404",
+];
+
+/// Errors, as reported on the image tool's stderr, that a registry or the network may recover from
+const TRANSIENT_ERRORS: &[&str] = &[
+    "TLS handshake timeout",
+    "connection reset by peer",
+    "TOOMANYREQUESTS",
+    "status code 429",
+    "status code 500",
+    "status code 502",
+    "status code 503",
+];
+
+/// Errors, as reported on the image tool's stderr, that mean the requested image does not exist
+const NOT_FOUND_ERRORS: &[&str] = &[
+    "manifest_unknown",
+    "manifest unknown",
+    "name_unknown",
+    "status code 404",
+];
+
+/// Errors, as reported on the image tool's stderr, that mean the registry does not allow the
+/// requested operation, such as deleting a manifest by tag
+const UNSUPPORTED_ERRORS: &[&str] = &["unsupported", "status code 405", "method not allowed"];
+
+/// Whether `err` is an image tool failure reporting that the image does not exist, as opposed to
+/// a failure to reach the registry or any other error.
+pub(crate) fn is_not_found(err: &error::Error) -> bool {
+    reports_any(err, NOT_FOUND_ERRORS)
+}
+
+/// Whether `err` is an image tool failure reporting that the registry does not allow the
+/// operation at all.
+pub(crate) fn is_unsupported(err: &error::Error) -> bool {
+    reports_any(err, UNSUPPORTED_ERRORS)
+}
+
+fn reports_any(err: &error::Error, errors: &[&str]) -> bool {
+    match err {
+        error::Error::OperationFailed {
+            message, stderr, ..
+        } => {
+            let message = format!("{message}\n{stderr}").to_lowercase();
+            errors.iter().any(|e| message.contains(e))
+        }
+        _ => false,
+    }
+}
+
+/// How commands that fail with a transient registry error are retried
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct RetryPolicy {
+    /// How many times to retry after the first attempt
+    pub(crate) retries: u32,
+    /// The delay before the first retry, which doubles for each retry after it
+    pub(crate) backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: DEFAULT_RETRIES,
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry.
+    #[cfg(test)]
+    pub(crate) fn none() -> Self {
+        Self {
+            retries: 0,
+            backoff: Duration::ZERO,
+        }
+    }
+
+    /// The delay before retry number `attempt`, counting from 1, or `None` if the command should
+    /// not be retried because it has used up its retries or failed with `stderr` for a reason
+    /// retrying will not fix.
+    fn delay(&self, attempt: u32, stderr: &[u8]) -> Option<Duration> {
+        let stderr = String::from_utf8_lossy(stderr);
+        let retryable = !PERMANENT_ERRORS.iter().any(|e| stderr.contains(e))
+            && TRANSIENT_ERRORS.iter().any(|e| stderr.contains(e));
+        (retryable && attempt <= self.retries).then(|| {
+            self.backoff
+                .saturating_mul(2u32.saturating_pow(attempt - 1))
+        })
+    }
+}
+
+/// The retry count from `TWOLITER_REGISTRY_RETRIES`, if it is set and valid.
+fn retries_from_env() -> u32 {
+    match std::env::var(REGISTRY_RETRIES_ENV) {
+        Ok(retries) => retries.parse().unwrap_or_else(|_| {
+            log::warn!(
+                "Ignoring {REGISTRY_RETRIES_ENV}='{retries}', which is not a number; retrying \
+                up to {DEFAULT_RETRIES} times"
+            );
+            DEFAULT_RETRIES
+        }),
+        Err(_) => DEFAULT_RETRIES,
+    }
+}
+
+/// Environment variable holding how many seconds an image tool command may run before it is
+/// killed
+pub const OPERATION_TIMEOUT_ENV: &str = "TWOLITER_OPERATION_TIMEOUT";
+
+/// How long a command may run when `TWOLITER_OPERATION_TIMEOUT` is unset. Pulls and pushes of large
+/// kits can legitimately take many minutes, so this only catches commands that are stuck.
+pub(crate) const DEFAULT_OPERATION_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// The timeout from `TWOLITER_OPERATION_TIMEOUT`, if it is set and valid.
+fn operation_timeout_from_env() -> Duration {
+    match std::env::var(OPERATION_TIMEOUT_ENV) {
+        Ok(seconds) => match seconds.parse::<u64>() {
+            Ok(seconds) if seconds > 0 => Duration::from_secs(seconds),
+            _ => {
+                log::warn!(
+                    "Ignoring {OPERATION_TIMEOUT_ENV}='{seconds}', which is not a positive number \
+                    of seconds; using {DEFAULT_OPERATION_TIMEOUT:?}"
+                );
+                DEFAULT_OPERATION_TIMEOUT
+            }
+        },
+        Err(_) => DEFAULT_OPERATION_TIMEOUT,
+    }
+}
+
+/// How often a command run with [`CommandLine::spawn_with_progress`] logs that it is still running
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Progress reported on a line of an image tool's stderr, either as a percentage or as a count of
+/// bytes transferred out of a total, e.g. `1048576/2097152`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Progress {
+    pub(crate) percent: u64,
+    pub(crate) bytes: Option<(u64, u64)>,
+}
+
+impl Progress {
+    /// Find the progress reported on `line`, if any.
+    pub(crate) fn parse(line: &str) -> Option<Self> {
+        let words = || {
+            line.split_whitespace()
+                .map(|word| word.trim_end_matches([',', ')']))
+        };
+        let bytes = words().find_map(|word| {
+            let (complete, total) = word.trim_start_matches('(').split_once('/')?;
+            let (complete, total) = (complete.parse::<u64>().ok()?, total.parse::<u64>().ok()?);
+            (total > 0 && complete <= total).then_some((complete, total))
+        });
+        if let Some((complete, total)) = bytes {
+            return Some(Self {
+                percent: complete * 100 / total,
+                bytes,
+            });
+        }
+        let percent = words().find_map(|word| {
+            let percent = word.strip_suffix('%')?.parse::<f64>().ok()?;
+            (0.0..=100.0).contains(&percent).then_some(percent as u64)
+        })?;
+        Some(Self {
+            percent,
+            bytes: None,
+        })
+    }
+}
+
+impl std::fmt::Display for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}%", self.percent)?;
+        if let Some((complete, total)) = self.bytes {
+            write!(f, " ({complete} of {total} bytes)")?;
+        }
+        Ok(())
+    }
+}
 
 #[derive(Debug)]
 pub(crate) struct CommandLine {
     pub(crate) path: PathBuf,
+    pub(crate) client_certs: RegistryClientCerts,
+    pub(crate) credentials: RegistryCredentials,
+    /// Echo the output of commands whose output is captured as it is produced
+    pub(crate) verbose: bool,
+    /// Fail any command that would contact a registry instead of running it
+    pub(crate) offline: bool,
+    /// Log commands that would change a registry or write an image instead of running them
+    pub(crate) dry_run: bool,
+    /// Retry commands that fail with a transient registry error
+    pub(crate) retry: RetryPolicy,
+    /// How long each attempt at a command may run before it is killed
+    pub(crate) timeout: Duration,
 }
 
 impl CommandLine {
+    pub(crate) fn new(
+        path: PathBuf,
+        client_certs: RegistryClientCerts,
+        credentials: RegistryCredentials,
+        options: CommandOptions,
+    ) -> Self {
+        Self {
+            path,
+            client_certs,
+            credentials,
+            verbose: options.verbose,
+            offline: options.offline,
+            dry_run: options.dry_run,
+            retry: RetryPolicy {
+                retries: options.retries,
+                ..RetryPolicy::default()
+            },
+            timeout: options.timeout,
+        }
+    }
+
+    /// Fail with an error naming the command if it contacts a registry and we are offline.
+    fn ensure_online(&self, args: &[&str], access: Access) -> Result<()> {
+        ensure!(
+            !self.offline || access == Access::Local,
+            error::OfflineSnafu {
+                operation: [self.path.display().to_string()]
+                    .into_iter()
+                    .chain(args.iter().map(|arg| arg.to_string()))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            }
+        );
+        Ok(())
+    }
+
+    /// In dry-run mode, log the command instead of running it and return `true`, so that the caller
+    /// skips it. Callers only ask this of commands that change a registry or write an image to
+    /// disk; reads still run, so that the steps that depend on them behave as usual.
+    pub(crate) fn skip_in_dry_run(&self, args: &[&str]) -> bool {
+        if self.dry_run {
+            log::info!(
+                "Dry run, skipping [{}]",
+                [self.path.display().to_string()]
+                    .into_iter()
+                    .chain(args.iter().map(|arg| arg.to_string()))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            );
+        }
+        self.dry_run
+    }
+
+    /// Wait before retrying a command that failed with `stderr`, returning `false` instead if it
+    /// should not be retried.
+    async fn backoff(&self, debug_cmd: &str, attempt: u32, stderr: &[u8]) -> bool {
+        let Some(delay) = self.retry.delay(attempt, stderr) else {
+            return false;
+        };
+        log::warn!(
+            "[{debug_cmd}] failed with a transient registry error, retrying in {delay:?} \
+            (retry {attempt} of {})",
+            self.retry.retries
+        );
+        tokio::time::sleep(delay).await;
+        true
+    }
+
+    /// Build the command, pointing it at the docker configuration in `docker_config` if given.
+    fn command(&self, args: &[&str], docker_config: Option<&Path>) -> Command {
+        let mut command = Command::new(&self.path);
+        // Don't leave the command running if the operation waiting on it is abandoned.
+        command
+            .args(args)
+            .envs(self.client_certs.krane_env(args))
+            .kill_on_drop(true);
+        if let Some(docker_config) = docker_config {
+            command.env(DOCKER_CONFIG_ENV, docker_config);
+        }
+        command
+    }
+
+    /// Wait for `child` to exit once `io`, which handles its stdin and output, is done. If that
+    /// takes longer than the timeout, the child is killed and reaped, and the error is `TimedOut`.
+    async fn wait_with_timeout<T>(
+        &self,
+        child: &mut Child,
+        io: impl Future<Output = std::io::Result<T>>,
+    ) -> std::io::Result<(ExitStatus, T)> {
+        let run = async {
+            let output = io.await?;
+            Ok((child.wait().await?, output))
+        };
+        let result = tokio::time::timeout(self.timeout, run).await;
+        match result {
+            Ok(result) => result,
+            Err(_) => {
+                child.kill().await?;
+                Err(std::io::ErrorKind::TimedOut.into())
+            }
+        }
+    }
+
+    /// Convert a failure to run the command into an error, reporting a timeout separately.
+    fn run_error(&self, err: std::io::Error, debug_cmd: &str, error_msg: &str) -> error::Error {
+        if err.kind() == std::io::ErrorKind::TimedOut {
+            return error::Error::Timeout {
+                message: format!("{error_msg} [{debug_cmd}]"),
+                elapsed: self.timeout,
+            };
+        }
+        error::Error::CommandFailed {
+            message: error_msg.to_string(),
+            source: err,
+        }
+    }
+
     pub(crate) async fn output(&self, args: &[&str], error_msg: String) -> Result<Vec<u8>> {
+        self.output_with_access(args, None, error_msg, Access::Registry)
+            .await
+    }
+
+    /// Like `output`, for a command that does not contact a registry and so also runs offline.
+    pub(crate) async fn local_output(&self, args: &[&str], error_msg: String) -> Result<Vec<u8>> {
+        self.output_with_access(args, None, error_msg, Access::Local)
+            .await
+    }
+
+    /// Like `output`, but writes `stdin` to the child process before waiting on it.
+    pub(crate) async fn output_with_stdin(
+        &self,
+        args: &[&str],
+        stdin: Option<&[u8]>,
+        error_msg: String,
+    ) -> Result<Vec<u8>> {
+        self.output_with_access(args, stdin, error_msg, Access::Registry)
+            .await
+    }
+
+    async fn output_with_access(
+        &self,
+        args: &[&str],
+        stdin: Option<&[u8]>,
+        error_msg: String,
+        access: Access,
+    ) -> Result<Vec<u8>> {
+        self.ensure_online(args, access)?;
         let debug_cmd = [
             vec![format!("{}", self.path.display())],
             args.iter()
@@ -21,23 +467,32 @@ impl CommandLine {
         .join(", ");
 
         log::debug!("Executing [{debug_cmd}]",);
-        let output = Command::new(&self.path)
-            .args(args)
-            .output()
-            .await
-            .context(error::CommandFailedSnafu { message: error_msg })?;
+        // Held until the command exits, when the credentials are removed.
+        let docker_config = self.credentials.docker_config(args)?;
+        let mut attempt = 0;
+        let output = loop {
+            let output = self
+                .captured_output(args, stdin, docker_config.as_ref().map(|dir| dir.path()))
+                .await
+                .map_err(|e| self.run_error(e, &debug_cmd, &error_msg))?;
+            attempt += 1;
+            if output.status.success() || !self.backoff(&debug_cmd, attempt, &output.stderr).await {
+                break output;
+            }
+        };
 
         ensure!(
             output.status.success(),
             error::OperationFailedSnafu {
                 message: format!(
-                    "[{debug_cmd}]: status: {} stderr: {} stdout: {}",
+                    "{error_msg} [{debug_cmd}]: status: {}{}",
                     &output.status,
-                    String::from_utf8_lossy(&output.stderr),
-                    String::from_utf8_lossy(&output.stdout)
+                    error::describe_output("stdout", &tail(&output.stdout, STDOUT_TAIL_BYTES))
                 ),
                 program: self.path.clone(),
-                args: args.iter().map(|x| x.to_string()).collect::<Vec<_>>()
+                args: args.iter().map(|x| x.to_string()).collect::<Vec<_>>(),
+                exit_code: output.status.code(),
+                stderr: tail(&output.stderr, STDERR_TAIL_BYTES),
             }
         );
 
@@ -53,34 +508,654 @@ impl CommandLine {
         Ok(output.stdout)
     }
 
+    /// Run the command, writing `input` to its stdin if given, and capture its output. In verbose
+    /// mode the output is also echoed to our stdout and stderr while the command runs.
+    async fn captured_output(
+        &self,
+        args: &[&str],
+        input: Option<&[u8]>,
+        docker_config: Option<&Path>,
+    ) -> std::io::Result<Output> {
+        let mut child = self
+            .command(args, docker_config)
+            .stdin(if input.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take();
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| std::io::Error::other("child process has no stdout"))?;
+        let stderr = child
+            .stderr
+            .take()
+            .ok_or_else(|| std::io::Error::other("child process has no stderr"))?;
+        let io = async {
+            if let Some(input) = input {
+                let mut stdin =
+                    stdin.ok_or_else(|| std::io::Error::other("child process has no stdin"))?;
+                stdin.write_all(input).await?;
+            }
+            if self.verbose {
+                tokio::try_join!(
+                    tee(stdout, tokio::io::stdout()),
+                    tee(stderr, tokio::io::stderr())
+                )
+            } else {
+                tokio::try_join!(read_all(stdout), read_all(stderr))
+            }
+        };
+        let (status, (stdout, stderr)) = self.wait_with_timeout(&mut child, io).await?;
+        Ok(Output {
+            status,
+            stdout,
+            stderr,
+        })
+    }
+
     pub(crate) async fn spawn(&self, args: &[&str], error_msg: String) -> Result<()> {
-        log::debug!(
-            "Executing '{}' with args [{}]",
+        self.spawn_reporting(args, error_msg, false, Access::Registry)
+            .await
+    }
+
+    /// Like `spawn`, for a command that does not contact a registry and so also runs offline.
+    pub(crate) async fn local_spawn(&self, args: &[&str], error_msg: String) -> Result<()> {
+        self.spawn_reporting(args, error_msg, false, Access::Local)
+            .await
+    }
+
+    /// Like `spawn`, but for long transfers. Unless verbose, the command's stderr is not echoed;
+    /// progress it reports is logged at debug level instead, and a heartbeat is logged at info
+    /// level every `HEARTBEAT_INTERVAL` so that a long pull does not look like a hang.
+    pub(crate) async fn spawn_with_progress(&self, args: &[&str], error_msg: String) -> Result<()> {
+        self.spawn_reporting(args, error_msg, !self.verbose, Access::Registry)
+            .await
+    }
+
+    async fn spawn_reporting(
+        &self,
+        args: &[&str],
+        error_msg: String,
+        progress: bool,
+        access: Access,
+    ) -> Result<()> {
+        self.ensure_online(args, access)?;
+        let debug_cmd = format!(
+            "'{}' with args [{}]",
             self.path.display(),
             args.iter()
                 .map(|arg| format!("'{}'", arg))
                 .collect::<Vec<_>>()
                 .join(", ")
         );
-        let status = Command::new(&self.path)
-            .args(args)
-            .spawn()
-            .context(error::CommandFailedSnafu {
-                message: error_msg.clone(),
-            })?
-            .wait()
-            .await
-            .context(error::CommandFailedSnafu {
-                message: error_msg.clone(),
-            })?;
+        log::debug!("Executing {debug_cmd}");
+        let docker_config = self.credentials.docker_config(args)?;
+        let mut attempt = 0;
+        let (status, stderr) = loop {
+            let docker_config = docker_config.as_ref().map(|dir| dir.path());
+            let run = if progress {
+                self.run_reporting_progress(args, docker_config, &debug_cmd)
+                    .await
+            } else {
+                self.run_echoing_stderr(args, docker_config).await
+            };
+            let (status, stderr) = run.map_err(|e| self.run_error(e, &debug_cmd, &error_msg))?;
+            attempt += 1;
+            if status.success() || !self.backoff(&debug_cmd, attempt, &stderr).await {
+                break (status, stderr);
+            }
+        };
         ensure!(
             status.success(),
             error::OperationFailedSnafu {
                 message: error_msg.clone(),
                 program: self.path.clone(),
-                args: args.iter().map(|x| x.to_string()).collect::<Vec<_>>()
+                args: args.iter().map(|x| x.to_string()).collect::<Vec<_>>(),
+                exit_code: status.code(),
+                stderr: tail(&stderr, STDERR_TAIL_BYTES),
             }
         );
         Ok(())
     }
+
+    /// Run the command with its stdout connected to ours, echoing its stderr to ours as it is
+    /// produced and returning it so that failures can be examined.
+    async fn run_echoing_stderr(
+        &self,
+        args: &[&str],
+        docker_config: Option<&Path>,
+    ) -> std::io::Result<(std::process::ExitStatus, Vec<u8>)> {
+        let mut child = self
+            .command(args, docker_config)
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stderr = child
+            .stderr
+            .take()
+            .ok_or_else(|| std::io::Error::other("child process has no stderr"))?;
+        self.wait_with_timeout(&mut child, tee(stderr, tokio::io::stderr()))
+            .await
+    }
+
+    /// Run the command with its stdout connected to ours, logging the progress it reports on
+    /// stderr and returning its stderr so that failures can be examined.
+    async fn run_reporting_progress(
+        &self,
+        args: &[&str],
+        docker_config: Option<&Path>,
+        debug_cmd: &str,
+    ) -> std::io::Result<(std::process::ExitStatus, Vec<u8>)> {
+        let mut child = self
+            .command(args, docker_config)
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stderr = child
+            .stderr
+            .take()
+            .ok_or_else(|| std::io::Error::other("child process has no stderr"))?;
+        self.wait_with_timeout(
+            &mut child,
+            report_progress(stderr, debug_cmd, HEARTBEAT_INTERVAL),
+        )
+        .await
+    }
+}
+
+/// Read everything from `reader`, logging each line at debug level, with progress reports
+/// summarized, and a heartbeat naming the latest progress at info level every `heartbeat`.
+/// Progress bars that redraw with `\r` are split into lines as well. Returns what was read.
+async fn report_progress<R>(
+    mut reader: R,
+    debug_cmd: &str,
+    heartbeat: Duration,
+) -> std::io::Result<Vec<u8>>
+where
+    R: AsyncRead + Unpin,
+{
+    let start = tokio::time::Instant::now();
+    let mut ticker = tokio::time::interval_at(start + heartbeat, heartbeat);
+    let mut latest = None;
+    let mut captured = Vec::new();
+    let mut line = Vec::new();
+    let mut buf = [0; 8192];
+    let log_line = |line: &[u8], latest: &mut Option<Progress>| {
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+        match Progress::parse(line) {
+            Some(progress) => {
+                log::debug!("[{debug_cmd}] progress: {progress}");
+                *latest = Some(progress);
+            }
+            None => log::debug!("[{debug_cmd}] {line}"),
+        }
+    };
+    loop {
+        tokio::select! {
+            n = reader.read(&mut buf) => {
+                let n = n?;
+                if n == 0 {
+                    log_line(&line, &mut latest);
+                    return Ok(captured);
+                }
+                captured.extend_from_slice(&buf[..n]);
+                for &byte in &buf[..n] {
+                    if byte == b'\n' || byte == b'\r' {
+                        log_line(&line, &mut latest);
+                        line.clear();
+                    } else {
+                        line.push(byte);
+                    }
+                }
+            }
+            _ = ticker.tick() => {
+                let elapsed = start.elapsed().as_secs();
+                match latest {
+                    Some(progress) => {
+                        log::info!("[{debug_cmd}] still running after {elapsed}s, {progress}")
+                    }
+                    None => log::info!("[{debug_cmd}] still running after {elapsed}s"),
+                }
+            }
+        }
+    }
+}
+
+/// How much of a failed command's stderr is kept in its error.
+const STDERR_TAIL_BYTES: usize = 2048;
+
+/// How much of a failed command's captured stdout is kept in its error. Tools print results
+/// there rather than diagnostics, so only the end is usually useful.
+const STDOUT_TAIL_BYTES: usize = 512;
+
+/// The last `limit` bytes of `output`, marked with a leading `...` if anything was cut off.
+fn tail(output: &[u8], limit: usize) -> String {
+    let output = String::from_utf8_lossy(output);
+    if output.len() <= limit {
+        return output.into_owned();
+    }
+    let mut start = output.len() - limit;
+    while !output.is_char_boundary(start) {
+        start += 1;
+    }
+    format!("...{}", &output[start..])
+}
+
+/// Read everything from `reader`.
+async fn read_all<R: AsyncRead + Unpin>(mut reader: R) -> std::io::Result<Vec<u8>> {
+    let mut captured = Vec::new();
+    reader.read_to_end(&mut captured).await?;
+    Ok(captured)
+}
+
+/// Copy everything from `reader` to `echo` as it arrives, returning what was read.
+async fn tee<R, W>(mut reader: R, mut echo: W) -> std::io::Result<Vec<u8>>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut captured = Vec::new();
+    let mut buf = [0; 8192];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(captured);
+        }
+        echo.write_all(&buf[..n]).await?;
+        echo.flush().await?;
+        captured.extend_from_slice(&buf[..n]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn tee_echoes_and_captures() {
+        let mut echoed = Vec::new();
+        let captured = tee(&b"pulling layer 1/2\npulling layer 2/2\n"[..], &mut echoed)
+            .await
+            .unwrap();
+        assert_eq!(captured, b"pulling layer 1/2\npulling layer 2/2\n");
+        assert_eq!(echoed, captured);
+    }
+
+    #[test]
+    fn parse_progress() {
+        assert_eq!(
+            Progress::parse("pulling layer sha256:abcd: 1048576/2097152"),
+            Some(Progress {
+                percent: 50,
+                bytes: Some((1048576, 2097152))
+            })
+        );
+        assert_eq!(
+            Progress::parse("[====>     ] 45.3% (2 of 5 layers)"),
+            Some(Progress {
+                percent: 45,
+                bytes: None
+            })
+        );
+        assert_eq!(Progress::parse("pulling example.com/kit:v1"), None);
+        assert_eq!(Progress::parse("built 2024/06"), None);
+        assert_eq!(
+            Progress::parse("12/20").unwrap().to_string(),
+            "60% (12 of 20 bytes)"
+        );
+    }
+
+    #[tokio::test]
+    async fn progress_is_captured() {
+        let stderr = b"pulling example.com/kit:v1\n10/100\r55/100\r100/100\nerror: oops";
+        let captured = report_progress(&stderr[..], "crane pull", Duration::from_secs(30))
+            .await
+            .unwrap();
+        assert_eq!(captured, stderr);
+    }
+
+    #[tokio::test]
+    async fn verbose_output_is_captured() {
+        let cli = CommandLine {
+            path: PathBuf::from("/bin/sh"),
+            client_certs: RegistryClientCerts::default(),
+            credentials: RegistryCredentials::default(),
+            verbose: true,
+            offline: false,
+            dry_run: false,
+            retry: RetryPolicy::none(),
+            timeout: DEFAULT_OPERATION_TIMEOUT,
+        };
+        let stdout = cli
+            .output_with_stdin(
+                &["-c", "cat; echo progress >&2"],
+                Some(b"sha256:abcd"),
+                "failed".to_string(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(stdout, b"sha256:abcd");
+    }
+
+    #[tokio::test]
+    async fn offline_refuses_network_commands() {
+        let cli = CommandLine {
+            path: PathBuf::from("/bin/echo"),
+            client_certs: RegistryClientCerts::default(),
+            credentials: RegistryCredentials::default(),
+            verbose: false,
+            offline: true,
+            dry_run: false,
+            retry: RetryPolicy::none(),
+            timeout: DEFAULT_OPERATION_TIMEOUT,
+        };
+        let err = cli
+            .output(
+                &["digest", "public.ecr.aws/bottlerocket/kit:v1"],
+                "failed".to_string(),
+            )
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, error::Error::Offline { operation }
+                if operation == "/bin/echo digest public.ecr.aws/bottlerocket/kit:v1"),
+            "{err}"
+        );
+        assert!(cli
+            .spawn(
+                &["pull", "public.ecr.aws/bottlerocket/kit:v1"],
+                "failed".to_string()
+            )
+            .await
+            .is_err());
+
+        let stdout = cli
+            .local_output(&["version"], "failed".to_string())
+            .await
+            .unwrap();
+        assert_eq!(stdout, b"version\n");
+    }
+
+    #[tokio::test]
+    async fn offline_allows_local_commands() {
+        let dir = TempDir::new().unwrap();
+        let script = dir.path().join("podman");
+        let log = dir.path().join("log");
+        std::fs::write(
+            &script,
+            format!("#!/bin/sh\necho \"$@\" >> {}\n", log.display()),
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let cli = CommandLine {
+            path: script,
+            client_certs: RegistryClientCerts::default(),
+            credentials: RegistryCredentials::default(),
+            verbose: false,
+            offline: true,
+            dry_run: false,
+            retry: RetryPolicy::none(),
+            timeout: DEFAULT_OPERATION_TIMEOUT,
+        };
+
+        cli.local_output(
+            &["tag", "localhost/kit:v1", "localhost/kit:latest"],
+            "failed".to_string(),
+        )
+        .await
+        .unwrap();
+        cli.local_spawn(&["load", "--input", "kit.tar"], "failed".to_string())
+            .await
+            .unwrap();
+        // The same subcommand is refused when it would reach a registry.
+        assert!(cli
+            .output(
+                &["tag", "example.com/kit:v1", "latest"],
+                "failed".to_string()
+            )
+            .await
+            .is_err());
+        assert_eq!(
+            std::fs::read_to_string(&log).unwrap(),
+            "tag localhost/kit:v1 localhost/kit:latest\nload --input kit.tar\n"
+        );
+    }
+
+    /// A command that fails with `stderr` the first `failures` times it runs, recording each run in
+    /// `dir`.
+    fn flaky_command(dir: &Path, failures: usize, stderr: &str) -> CommandLine {
+        let script = dir.join("flaky");
+        let runs = dir.join("runs");
+        std::fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\necho run >> {runs}\nif [ $(wc -l < {runs}) -le {failures} ]; then\n  \
+                echo '{stderr}' >&2\n  exit 1\nfi\necho sha256:abcd\n",
+                runs = runs.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        CommandLine {
+            path: script,
+            client_certs: RegistryClientCerts::default(),
+            credentials: RegistryCredentials::default(),
+            verbose: false,
+            offline: false,
+            dry_run: false,
+            retry: RetryPolicy {
+                retries: 3,
+                backoff: Duration::ZERO,
+            },
+            timeout: DEFAULT_OPERATION_TIMEOUT,
+        }
+    }
+
+    fn runs(dir: &Path) -> usize {
+        std::fs::read_to_string(dir.join("runs"))
+            .unwrap()
+            .lines()
+            .count()
+    }
+
+    #[tokio::test]
+    async fn transient_failures_are_retried() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let cli = flaky_command(
+            dir,
+            2,
+            "GET https://registry.example.com/v2/: unexpected status code 503 Service Unavailable",
+        );
+        let stdout = cli.output(&["digest"], "failed".to_string()).await.unwrap();
+        assert_eq!(stdout, b"sha256:abcd\n");
+        assert_eq!(runs(dir), 3);
+
+        std::fs::remove_file(dir.join("runs")).unwrap();
+        cli.spawn(&["pull"], "failed".to_string()).await.unwrap();
+        assert_eq!(runs(dir), 3);
+    }
+
+    #[tokio::test]
+    async fn not_implemented_is_not_retried() {
+        // A registry that does not implement an operation will not start to on a retry.
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let cli = flaky_command(
+            dir,
+            1,
+            "DELETE https://registry.example.com/v2/kit/manifests/v1: unexpected status code 501 \
+            Not Implemented",
+        );
+        assert!(cli.output(&["delete"], "failed".to_string()).await.is_err());
+        assert_eq!(runs(dir), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_are_limited() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let cli = flaky_command(dir, 10, "read: connection reset by peer");
+        let err = cli
+            .spawn(&["pull"], "failed".to_string())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, error::Error::OperationFailed { .. }));
+        assert_eq!(runs(dir), 4);
+    }
+
+    #[tokio::test]
+    async fn failures_report_exit_code_and_stderr() {
+        let cli = CommandLine {
+            path: PathBuf::from("/bin/sh"),
+            client_certs: RegistryClientCerts::default(),
+            credentials: RegistryCredentials::default(),
+            verbose: false,
+            offline: false,
+            dry_run: false,
+            retry: RetryPolicy::none(),
+            timeout: DEFAULT_OPERATION_TIMEOUT,
+        };
+        let script = "echo 'pulling example.com/kit:v1' >&2; \
+            echo 'DENIED: requested access to the resource is denied' >&2; echo >&2; exit 7";
+
+        let err = cli
+            .output(&["-c", script], "failed".to_string())
+            .await
+            .unwrap_err();
+        assert_eq!(err.exit_code(), Some(7));
+        assert_eq!(
+            err.stderr_line(),
+            Some("DENIED: requested access to the resource is denied")
+        );
+
+        let err = cli
+            .spawn(&["-c", script], "failed".to_string())
+            .await
+            .unwrap_err();
+        assert_eq!(err.exit_code(), Some(7));
+        assert!(err.stderr_line().unwrap().starts_with("DENIED"));
+        assert!(!is_not_found(&err));
+        assert!(!is_unsupported(&err));
+    }
+
+    #[tokio::test]
+    async fn failures_display_output() {
+        let cli = CommandLine {
+            path: PathBuf::from("/bin/sh"),
+            client_certs: RegistryClientCerts::default(),
+            credentials: RegistryCredentials::default(),
+            verbose: false,
+            offline: false,
+            dry_run: false,
+            retry: RetryPolicy::none(),
+            timeout: DEFAULT_OPERATION_TIMEOUT,
+        };
+        let script = "echo 'wrote sha256:abcd'; \
+            echo 'MANIFEST_UNKNOWN: manifest unknown' >&2; exit 1";
+
+        let err = cli
+            .output(&["-c", script], "failed".to_string())
+            .await
+            .unwrap_err();
+        let display = err.to_string();
+        assert!(display.contains("\n stderr:\n   MANIFEST_UNKNOWN: manifest unknown"));
+        assert!(display.contains("\n stdout:\n   wrote sha256:abcd"));
+
+        let err = cli
+            .spawn(&["-c", script], "failed".to_string())
+            .await
+            .unwrap_err();
+        let display = err.to_string();
+        assert!(display.contains("MANIFEST_UNKNOWN: manifest unknown"));
+        assert!(!display.contains("stdout:"));
+
+        let err = cli
+            .output(
+                &["-c", "head -c 5000 /dev/zero | tr '\\0' x >&2; exit 1"],
+                "failed".to_string(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.stderr().unwrap(),
+            format!("...{}", "x".repeat(STDERR_TAIL_BYTES))
+        );
+    }
+
+    #[tokio::test]
+    async fn permanent_failures_are_not_retried() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let cli = flaky_command(
+            dir,
+            1,
+            "MANIFEST_UNKNOWN: manifest unknown; unexpected status code 404 Not Found",
+        );
+        assert!(cli.output(&["digest"], "failed".to_string()).await.is_err());
+        assert_eq!(runs(dir), 1);
+    }
+
+    #[test]
+    fn backoff_doubles() {
+        let policy = RetryPolicy {
+            retries: 3,
+            backoff: Duration::from_secs(1),
+        };
+        let stderr = b"net/http: TLS handshake timeout";
+        assert_eq!(policy.delay(1, stderr), Some(Duration::from_secs(1)));
+        assert_eq!(policy.delay(3, stderr), Some(Duration::from_secs(4)));
+        assert_eq!(policy.delay(4, stderr), None);
+        assert_eq!(
+            policy.delay(1, b"UNAUTHORIZED: authentication required"),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn stuck_commands_are_killed() {
+        let temp_dir = TempDir::new().unwrap();
+        let pid_file = temp_dir.path().join("pid");
+        let cli = CommandLine {
+            path: PathBuf::from("/bin/sh"),
+            client_certs: RegistryClientCerts::default(),
+            credentials: RegistryCredentials::default(),
+            verbose: false,
+            offline: false,
+            dry_run: false,
+            retry: RetryPolicy::none(),
+            timeout: Duration::from_millis(500),
+        };
+        let script = format!("echo $$ > {}; exec sleep 30", pid_file.display());
+        let reaped = || {
+            let pid = std::fs::read_to_string(&pid_file).unwrap();
+            !Path::new("/proc").join(pid.trim()).exists()
+        };
+
+        let err = cli
+            .output(&["-c", &script], "failed".to_string())
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, error::Error::Timeout { elapsed, .. } if elapsed == cli.timeout),
+            "{err}"
+        );
+        assert!(reaped());
+
+        let err = cli
+            .spawn(&["-c", &script], "failed".to_string())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, error::Error::Timeout { .. }), "{err}");
+        assert!(reaped());
+    }
 }
@@ -0,0 +1,148 @@
+//! TLS client certificates for registries that require mutual TLS.
+//!
+//! Certificates are configured per registry host through `TWOLITER_REGISTRY_CLIENT_CERTS`, a
+//! comma-separated list of `host=cert:key` entries, e.g.
+//! `registry.example.com=/etc/pki/client.crt:/etc/pki/client.key`.
+//!
+//! crane has no command line flags for client certificates, so the certificate and key for the
+//! registry an invocation talks to are handed to krane through `KRANE_CLIENT_CERT` and
+//! `KRANE_CLIENT_KEY`, which the bundled krane is patched to present on its TLS connections.
+//! Other crane builds ignore them. Docker instead reads client certificates from
+//! `/etc/docker/certs.d/<host>/client.cert` and `client.key`, which must be set up on the host
+//! running the daemon.
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use snafu::{OptionExt, ResultExt};
+
+use crate::{error, Result};
+
+/// Environment variable holding the per-registry client certificate configuration
+pub const REGISTRY_CLIENT_CERTS_ENV: &str = "TWOLITER_REGISTRY_CLIENT_CERTS";
+/// Environment variable through which krane receives the client certificate path
+pub const KRANE_CLIENT_CERT_ENV: &str = "KRANE_CLIENT_CERT";
+/// Environment variable through which krane receives the client key path
+pub const KRANE_CLIENT_KEY_ENV: &str = "KRANE_CLIENT_KEY";
+
+/// A client certificate and its private key, both PEM encoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCert {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+impl ClientCert {
+    /// Create a client certificate, checking that the certificate and key can be read.
+    pub fn new(cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Result<Self> {
+        let client_cert = Self {
+            cert: cert.into(),
+            key: key.into(),
+        };
+        ensure_readable(&client_cert.cert)?;
+        ensure_readable(&client_cert.key)?;
+        Ok(client_cert)
+    }
+}
+
+fn ensure_readable(path: &Path) -> Result<()> {
+    File::open(path).context(error::ClientCertReadSnafu { path })?;
+    Ok(())
+}
+
+/// Client certificates keyed by registry host
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegistryClientCerts {
+    certs: BTreeMap<String, ClientCert>,
+}
+
+impl RegistryClientCerts {
+    /// Use `cert` for connections to `host`, which may include a port.
+    pub fn insert(&mut self, host: impl Into<String>, cert: ClientCert) {
+        self.certs.insert(host.into(), cert);
+    }
+
+    /// Parse a comma-separated list of `host=cert:key` entries.
+    pub fn from_spec(spec: &str) -> Result<Self> {
+        let mut client_certs = Self::default();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (host, cert, key) = entry
+                .split_once('=')
+                .and_then(|(host, paths)| {
+                    let (cert, key) = paths.split_once(':')?;
+                    Some((host, cert, key))
+                })
+                .context(error::ClientCertSpecSnafu { entry })?;
+            client_certs.insert(host, ClientCert::new(cert, key)?);
+        }
+        Ok(client_certs)
+    }
+
+    /// Read the configuration from `TWOLITER_REGISTRY_CLIENT_CERTS`, which may be unset.
+    pub fn from_env() -> Result<Self> {
+        match std::env::var(REGISTRY_CLIENT_CERTS_ENV) {
+            Ok(spec) => Self::from_spec(&spec),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// The client certificate for the registry of the first image reference in `args` with one.
+    pub(crate) fn for_args(&self, args: &[&str]) -> Option<&ClientCert> {
+        if self.certs.is_empty() {
+            return None;
+        }
+        args.iter()
+            .find_map(|arg| self.certs.get(arg.split_once('/')?.0))
+    }
+
+    /// The environment to pass krane for an invocation with the given arguments.
+    pub(crate) fn krane_env(&self, args: &[&str]) -> Vec<(&'static str, &Path)> {
+        match self.for_args(args) {
+            Some(cert) => vec![
+                (KRANE_CLIENT_CERT_ENV, cert.cert.as_path()),
+                (KRANE_CLIENT_KEY_ENV, cert.key.as_path()),
+            ],
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn parse_spec() {
+        let temp_dir = TempDir::new().unwrap();
+        let cert = temp_dir.path().join("client.crt");
+        let key = temp_dir.path().join("client.key");
+        std::fs::write(&cert, "cert").unwrap();
+        std::fs::write(&key, "key").unwrap();
+
+        let spec = format!(
+            "registry.example.com:5000={}:{}",
+            cert.display(),
+            key.display()
+        );
+        let client_certs = RegistryClientCerts::from_spec(&spec).unwrap();
+        assert_eq!(
+            client_certs.for_args(&["copy", "registry.example.com:5000/kit:v1"]),
+            Some(&ClientCert { cert, key })
+        );
+        assert_eq!(
+            client_certs.for_args(&["copy", "public.ecr.aws/kit:v1"]),
+            None
+        );
+    }
+
+    #[test]
+    fn invalid_spec() {
+        assert!(RegistryClientCerts::from_spec("registry.example.com").is_err());
+        assert!(RegistryClientCerts::from_spec("registry.example.com=/missing.crt").is_err());
+        assert!(matches!(
+            RegistryClientCerts::from_spec("registry.example.com=/missing.crt:/missing.key"),
+            Err(error::Error::ClientCertRead { .. })
+        ));
+    }
+}
@@ -1,15 +1,26 @@
+use std::collections::HashMap;
 use std::fs::File;
+use std::io::{BufReader, Read};
 use std::path::Path;
 
 use async_trait::async_trait;
-use snafu::ResultExt;
+use snafu::{ensure, OptionExt, ResultExt};
 use tar::Archive as TarArchive;
 use tempfile::TempDir;
 
+use crate::manifest::{
+    add_annotations, annotate_attestations, is_oci_layout, set_platforms, AttestationManifest,
+    ManifestMediaType,
+};
 use crate::{
-    cli::CommandLine, error, ConfigView, DockerArchitecture, ImageToolImpl, ImageView, Result,
+    cli::{is_not_found, is_unsupported, CommandLine},
+    document, error, split_reference, ConfigView, ImageToolImpl, PlatformSpec, Result, ToolInfo,
 };
 
+/// The size of the reads used to unpack an image archive. Files are copied out of the archive one at
+/// a time through a buffer of this size, so memory use stays the same however large the layers are.
+const ARCHIVE_CHUNK_SIZE: usize = 1 << 20;
+
 #[derive(Debug)]
 pub struct CraneCLI {
     pub(crate) cli: CommandLine,
@@ -24,17 +35,129 @@ impl CraneCLI {
             cmd.into()
         }
     }
+
+    /// Push the OCI image layout in the directory `layout` to `uri`.
+    async fn push_layout(&self, layout: &Path, uri: &str) -> Result<()> {
+        self.cli
+            .spawn(
+                &Self::crane_cmd(&["push", &layout.to_string_lossy(), uri]),
+                format!("failed to push image {}", uri),
+            )
+            .await
+    }
+
+    /// Create or update the image index at `uri` so that it references `images`.
+    async fn index_append(
+        &self,
+        images: &[&str],
+        uri: &str,
+        media_type: ManifestMediaType,
+    ) -> Result<()> {
+        let mut manifest_create_args = vec!["index", "append"];
+        if media_type == ManifestMediaType::DockerManifestList {
+            // Without a base index, crane appends to an empty index; ask for a Docker one.
+            manifest_create_args.push("--docker-empty-base");
+        }
+        for image in images {
+            manifest_create_args.extend_from_slice(&["-m", image])
+        }
+        manifest_create_args.extend_from_slice(&["-t", uri]);
+        if self
+            .cli
+            .skip_in_dry_run(&Self::crane_cmd(&manifest_create_args))
+        {
+            return Ok(());
+        }
+        self.cli
+            .output(
+                &Self::crane_cmd(&manifest_create_args),
+                format!("could not push multi-platform manifest to {}", uri),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// The OCI platform objects of the platform images, by image digest. `crane index append`
+    /// infers each entry's platform from the image config, which may lack the variant or OS
+    /// version, so these are written over the entries it creates. There are none when every
+    /// platform is the default for its architecture, since crane already infers those.
+    async fn explicit_platforms(
+        &self,
+        platform_images: &[(PlatformSpec, String)],
+    ) -> Result<HashMap<String, serde_json::Value>> {
+        let mut platforms = HashMap::new();
+        if platform_images
+            .iter()
+            .all(|(platform, _)| platform.is_default())
+        {
+            return Ok(platforms);
+        }
+        for (platform, image) in platform_images {
+            platforms.insert(self.get_digest(image).await?, platform.to_json());
+        }
+        Ok(platforms)
+    }
+
+    /// Delete the tag `uri`, which refers to `digest`, by deleting the image itself, for
+    /// registries that can only delete manifests by digest. That would delete every other tag of
+    /// the image too, so it is refused if the repository has any.
+    async fn delete_by_digest(&self, uri: &str, digest: &str) -> Result<()> {
+        let (repository, tag) = split_reference(uri);
+        let mut other_tags = Vec::new();
+        for other in self.list_tags(repository).await? {
+            if Some(other.as_str()) != tag
+                && self.get_digest(&format!("{repository}:{other}")).await? == digest
+            {
+                other_tags.push(other);
+            }
+        }
+        ensure!(
+            other_tags.is_empty(),
+            error::TagDeleteUnsupportedSnafu {
+                uri,
+                digest,
+                tags: other_tags,
+            }
+        );
+
+        let by_digest = format!("{repository}@{digest}");
+        log::info!("Registry cannot delete tags, deleting {by_digest} instead");
+        self.cli
+            .output(
+                &Self::crane_cmd(&["delete", &by_digest]),
+                format!("failed to delete {} (tagged {})", by_digest, uri),
+            )
+            .await?;
+        Ok(())
+    }
 }
 
 #[async_trait]
 impl ImageToolImpl for CraneCLI {
+    async fn tool_info(&self) -> Result<ToolInfo> {
+        let version = self
+            .cli
+            .local_output(&["version"], "failed to get crane version".to_string())
+            .await
+            .map(|bytes| String::from_utf8_lossy(&bytes).trim().to_string())
+            .map_err(|e| log::debug!("{e}"))
+            .ok();
+        Ok(ToolInfo {
+            backend: "crane".to_string(),
+            path: self.cli.path.clone(),
+            version,
+        })
+    }
+
     async fn pull_oci_image(&self, path: &Path, uri: &str) -> Result<()> {
         let archive_path = path.to_string_lossy();
+        let args = Self::crane_cmd(&["pull", "--format", "oci", uri, archive_path.as_ref()]);
+        if self.cli.skip_in_dry_run(&args) {
+            return Ok(());
+        }
         self.cli
-            .spawn(
-                &Self::crane_cmd(&["pull", "--format", "oci", uri, archive_path.as_ref()]),
-                format!("failed to pull image archive from {}", uri),
-            )
+            .spawn_with_progress(&args, format!("failed to pull image archive from {}", uri))
             .await?;
         Ok(())
     }
@@ -48,6 +171,68 @@ impl ImageToolImpl for CraneCLI {
             .await
     }
 
+    async fn get_digest(&self, uri: &str) -> Result<String> {
+        let bytes = self
+            .cli
+            .output(
+                &Self::crane_cmd(&["digest", uri]),
+                format!("failed to fetch digest for resource at {}", uri),
+            )
+            .await?;
+        parse_digest(uri, &bytes)
+    }
+
+    async fn delete_tag(&self, uri: &str) -> Result<()> {
+        // Resolve the image first, so that the log shows exactly what is being deleted.
+        let digest = self.get_digest(uri).await?;
+        let args = Self::crane_cmd(&["delete", uri]);
+        if self.cli.skip_in_dry_run(&args) {
+            return Ok(());
+        }
+        log::info!("Deleting {uri}, which refers to {digest}");
+        let deleted = self
+            .cli
+            .output(&args, format!("failed to delete {} ({})", uri, digest))
+            .await;
+        match deleted {
+            Ok(_) => Ok(()),
+            Err(e) if is_unsupported(&e) && !uri.contains('@') => {
+                self.delete_by_digest(uri, &digest).await
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn list_tags(&self, repo: &str) -> Result<Vec<String>> {
+        let bytes = self
+            .cli
+            .output(
+                &Self::crane_cmd(&["ls", repo]),
+                format!("failed to list tags in {}", repo),
+            )
+            .await?;
+        Ok(parse_tags(&bytes))
+    }
+
+    async fn image_exists(&self, uri: &str) -> Result<bool> {
+        match self.get_manifest(uri).await {
+            Ok(_) => Ok(true),
+            Err(e) if is_not_found(&e) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn tag_image(&self, uri: &str, tag: &str) -> Result<()> {
+        let args = Self::crane_cmd(&["tag", uri, tag]);
+        if self.cli.skip_in_dry_run(&args) {
+            return Ok(());
+        }
+        self.cli
+            .output(&args, format!("failed to tag {} as {}", uri, tag))
+            .await?;
+        Ok(())
+    }
+
     async fn get_config(&self, uri: &str) -> Result<ConfigView> {
         let bytes = self
             .cli
@@ -56,50 +241,762 @@ impl ImageToolImpl for CraneCLI {
                 format!("failed to fetch image config from {}", uri),
             )
             .await?;
-        let image_view: ImageView =
-            serde_json::from_slice(bytes.as_slice()).context(error::ConfigDeserializeSnafu)?;
-        Ok(image_view.config)
+        ConfigView::from_image_config(&bytes)
     }
 
     async fn push_oci_archive(&self, path: &Path, uri: &str) -> Result<()> {
+        if self
+            .cli
+            .skip_in_dry_run(&Self::crane_cmd(&["push", &path.to_string_lossy(), uri]))
+        {
+            return Ok(());
+        }
+        // crane pushes an OCI image layout in place, so only archives need to be unpacked, which
+        // temporarily doubles the disk space the image takes.
+        if is_oci_layout(path) {
+            return self.push_layout(path, uri).await;
+        }
+
         let temp_dir = TempDir::new_in(path.parent().unwrap()).context(error::CraneTempSnafu)?;
 
-        let mut oci_file = File::open(path).context(error::ArchiveReadSnafu)?;
+        let oci_file = File::open(path).context(error::ArchiveReadSnafu)?;
+        unpack_archive(oci_file, temp_dir.path())?;
+        self.push_layout(temp_dir.path(), uri).await
+    }
 
-        let mut oci_archive = TarArchive::new(&mut oci_file);
-        oci_archive
-            .unpack(temp_dir.path())
-            .context(error::ArchiveExtractSnafu)?;
+    async fn copy_image_with_annotations(
+        &self,
+        src: &str,
+        dst: &str,
+        annotations: &HashMap<String, String>,
+    ) -> Result<()> {
+        let args = Self::crane_cmd(&["copy", src, dst]);
+        if self.cli.skip_in_dry_run(&args) {
+            return Ok(());
+        }
         self.cli
-            .spawn(
-                &Self::crane_cmd(&["push", &temp_dir.path().to_string_lossy(), uri]),
-                format!("failed to push image {}", uri),
+            .spawn(&args, format!("failed to copy image {} to {}", src, dst))
+            .await?;
+        if annotations.is_empty() {
+            return Ok(());
+        }
+
+        // Edit the copied manifest rather than using `crane mutate`, which rebuilds image
+        // manifests and cannot add annotations to every kind of image index.
+        let mut manifest: serde_json::Value =
+            document::manifest_from_slice(&self.get_manifest(dst).await?)?;
+        add_annotations(&mut manifest, annotations)?;
+        let manifest_bytes =
+            serde_json::to_vec(&manifest).context(error::ManifestSerializeSnafu)?;
+
+        self.cli
+            .output_with_stdin(
+                &Self::crane_cmd(&["edit", "manifest", dst]),
+                Some(&manifest_bytes),
+                format!("could not annotate manifest at {}", dst),
             )
-            .await
+            .await?;
+
+        Ok(())
     }
 
     async fn push_multi_platform_manifest(
         &self,
-        platform_images: Vec<(DockerArchitecture, String)>,
+        platform_images: Vec<(PlatformSpec, String)>,
         uri: &str,
+        media_type: ManifestMediaType,
+        annotations: &HashMap<String, String>,
     ) -> Result<()> {
         let images: Vec<&str> = platform_images
             .iter()
             .map(|(_, image)| image.as_str())
             .collect();
 
-        let mut manifest_create_args = vec!["index", "append"];
-        for image in images {
-            manifest_create_args.extend_from_slice(&["-m", image])
+        self.index_append(&images, uri, media_type).await?;
+        // There is no pushed index to edit.
+        if self.cli.dry_run {
+            return Ok(());
         }
-        manifest_create_args.extend_from_slice(&["-t", uri]);
+
+        // `crane index append` cannot set annotations, so they are added by editing the pushed
+        // index, along with any platforms crane could not infer.
+        let platforms = self.explicit_platforms(&platform_images).await?;
+        if platforms.is_empty() && annotations.is_empty() {
+            return Ok(());
+        }
+        let mut index: serde_json::Value =
+            document::manifest_from_slice(&self.get_manifest(uri).await?)?;
+        set_platforms(&mut index, &platforms)?;
+        add_annotations(&mut index, annotations)?;
+        let index_bytes = serde_json::to_vec(&index).context(error::ManifestSerializeSnafu)?;
+
         self.cli
-            .output(
-                &Self::crane_cmd(&manifest_create_args),
-                format!("could not push multi-platform manifest to {}", uri),
+            .output_with_stdin(
+                &Self::crane_cmd(&["edit", "manifest", uri]),
+                Some(&index_bytes),
+                format!(
+                    "could not set platforms and annotations in manifest at {}",
+                    uri
+                ),
             )
             .await?;
 
         Ok(())
     }
+
+    async fn push_multi_platform_manifest_with_attestations(
+        &self,
+        platform_images: Vec<(PlatformSpec, String)>,
+        attestations: Vec<AttestationManifest>,
+        uri: &str,
+    ) -> Result<()> {
+        let images: Vec<&str> = platform_images
+            .iter()
+            .map(|(_, image)| image.as_str())
+            .chain(
+                attestations
+                    .iter()
+                    .map(|attestation| attestation.image.as_str()),
+            )
+            .collect();
+        // Attestation manifests are only defined for OCI image indexes.
+        self.index_append(&images, uri, ManifestMediaType::OciIndex)
+            .await?;
+        if self.cli.dry_run {
+            return Ok(());
+        }
+
+        // `crane index append` has no notion of attestations, so rewrite the resulting index with
+        // the annotations BuildKit uses to link each attestation to its platform image.
+        let mut references = HashMap::new();
+        for attestation in &attestations {
+            let (_, subject) = platform_images
+                .iter()
+                .find(|(platform, _)| platform.arch == attestation.architecture)
+                .context(error::MissingAttestationSubjectSnafu {
+                    architecture: attestation.architecture.clone(),
+                })?;
+            references.insert(
+                self.get_digest(&attestation.image).await?,
+                self.get_digest(subject).await?,
+            );
+        }
+
+        let mut index: serde_json::Value =
+            document::manifest_from_slice(&self.get_manifest(uri).await?)?;
+        annotate_attestations(&mut index, &references)?;
+        set_platforms(
+            &mut index,
+            &self.explicit_platforms(&platform_images).await?,
+        )?;
+        let index_bytes = serde_json::to_vec(&index).context(error::ManifestSerializeSnafu)?;
+
+        self.cli
+            .output_with_stdin(
+                &Self::crane_cmd(&["edit", "manifest", uri]),
+                Some(&index_bytes),
+                format!("could not annotate attestations in manifest at {}", uri),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Parse the output of `crane ls`, which prints one tag per line in the order the registry lists
+/// them.
+fn parse_tags(output: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(output)
+        .lines()
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Parse the output of `crane digest`, which must be a canonical `sha256:<hex>` digest.
+pub(crate) fn parse_digest(uri: &str, output: &[u8]) -> Result<String> {
+    let digest = String::from_utf8_lossy(output).trim().to_string();
+    let is_canonical = digest.strip_prefix("sha256:").is_some_and(|hex| {
+        hex.len() == 64 && hex.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f'))
+    });
+    ensure!(is_canonical, error::InvalidDigestSnafu { uri, digest });
+    Ok(digest)
+}
+
+/// Unpack the tar archive read from `reader` into `dir`, streaming each file to disk in chunks of
+/// `ARCHIVE_CHUNK_SIZE` rather than holding any of them in memory.
+fn unpack_archive<R: Read>(reader: R, dir: &Path) -> Result<()> {
+    TarArchive::new(BufReader::with_capacity(ARCHIVE_CHUNK_SIZE, reader))
+        .unpack(dir)
+        .context(error::ArchiveExtractSnafu)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cli::{RetryPolicy, DEFAULT_OPERATION_TIMEOUT};
+    use crate::{
+        ClientCert, DockerArchitecture, ImageTool, RegistryClientCerts, RegistryCredentials,
+    };
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Writes a stand-in for crane to `dir` that records its arguments to `args`.
+    fn recording_crane(dir: &Path) -> CraneCLI {
+        let crane = dir.join("crane");
+        std::fs::write(
+            &crane,
+            format!("#!/bin/sh\necho \"$@\" > {}\n", dir.join("args").display()),
+        )
+        .unwrap();
+        std::fs::set_permissions(&crane, std::fs::Permissions::from_mode(0o755)).unwrap();
+        CraneCLI {
+            cli: CommandLine {
+                path: crane,
+                client_certs: RegistryClientCerts::default(),
+                credentials: RegistryCredentials::default(),
+                verbose: false,
+                offline: false,
+                dry_run: false,
+                retry: RetryPolicy::none(),
+                timeout: DEFAULT_OPERATION_TIMEOUT,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn dry_run_skips_mutating_commands() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut crane = recording_crane(temp_dir.path());
+        crane.cli.dry_run = true;
+        let args = temp_dir.path().join("args");
+
+        crane
+            .tag_image("example.com/kit:v1", "latest")
+            .await
+            .unwrap();
+        crane
+            .copy_image_with_annotations(
+                "example.com/kit:v1",
+                "example.com/copy:v1",
+                &HashMap::from([("key".to_string(), "value".to_string())]),
+            )
+            .await
+            .unwrap();
+        crane
+            .push_multi_platform_manifest(
+                vec![(
+                    PlatformSpec::new(DockerArchitecture::Arm64).with_variant("v8"),
+                    "example.com/kit:v1-arm64".to_string(),
+                )],
+                "example.com/kit:v1",
+                ManifestMediaType::OciIndex,
+                &HashMap::new(),
+            )
+            .await
+            .unwrap();
+        crane
+            .pull_oci_image(&temp_dir.path().join("kit.tar"), "example.com/kit:v1")
+            .await
+            .unwrap();
+        assert!(!args.exists());
+
+        // Reads still run.
+        crane.get_manifest("example.com/kit:v1").await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&args).unwrap().trim(),
+            "manifest example.com/kit:v1"
+        );
+    }
+
+    #[tokio::test]
+    async fn push_layout_without_extraction() {
+        let temp_dir = TempDir::new().unwrap();
+        let crane = recording_crane(temp_dir.path());
+
+        let build_dir = temp_dir.path().join("build");
+        let layout = build_dir.join("kit");
+        std::fs::create_dir_all(layout.join("blobs/sha256")).unwrap();
+        std::fs::write(
+            layout.join("oci-layout"),
+            r#"{"imageLayoutVersion":"1.0.0"}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            layout.join("index.json"),
+            r#"{"schemaVersion":2,"manifests":[]}"#,
+        )
+        .unwrap();
+        std::fs::write(layout.join("blobs/sha256/abcd"), vec![0; 1 << 20]).unwrap();
+
+        crane
+            .push_oci_archive(&layout, "example.com/kit:v1")
+            .await
+            .unwrap();
+
+        let args = std::fs::read_to_string(temp_dir.path().join("args")).unwrap();
+        assert_eq!(
+            args.trim(),
+            format!("push {} example.com/kit:v1", layout.display())
+        );
+        // Nothing was unpacked next to the layout.
+        let entries: Vec<_> = std::fs::read_dir(&build_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(entries, vec![layout]);
+    }
+
+    #[tokio::test]
+    async fn client_cert_passed_for_matching_registry() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let cert = dir.join("client.crt");
+        let key = dir.join("client.key");
+        std::fs::write(&cert, "cert").unwrap();
+        std::fs::write(&key, "key").unwrap();
+
+        // A stand-in for crane that records the client certificate it was given.
+        let env_file = dir.join("env");
+        let crane = dir.join("crane");
+        std::fs::write(
+            &crane,
+            format!(
+                "#!/bin/sh\necho \"$KRANE_CLIENT_CERT $KRANE_CLIENT_KEY\" > {}\necho sha256:{}\n",
+                env_file.display(),
+                "a".repeat(64)
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&crane, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut client_certs = RegistryClientCerts::default();
+        client_certs.insert(
+            "registry.example.com",
+            ClientCert::new(&cert, &key).unwrap(),
+        );
+        let crane = CraneCLI {
+            cli: CommandLine {
+                path: crane,
+                client_certs,
+                credentials: RegistryCredentials::default(),
+                verbose: false,
+                offline: false,
+                dry_run: false,
+                retry: RetryPolicy::none(),
+                timeout: DEFAULT_OPERATION_TIMEOUT,
+            },
+        };
+
+        crane
+            .get_digest("registry.example.com/kit:v1")
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&env_file).unwrap().trim(),
+            format!("{} {}", cert.display(), key.display())
+        );
+
+        crane.get_digest("public.ecr.aws/kit:v1").await.unwrap();
+        assert_eq!(std::fs::read_to_string(&env_file).unwrap().trim(), "");
+    }
+
+    #[tokio::test]
+    async fn credentials_passed_through_docker_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+
+        // A stand-in for crane that records the docker configuration it was given, then fails.
+        let config_file = dir.join("config");
+        let crane = dir.join("crane");
+        std::fs::write(
+            &crane,
+            format!(
+                "#!/bin/sh\necho \"$DOCKER_CONFIG\" > {0}\ncat \"$DOCKER_CONFIG/config.json\" >> {0}\nexit 1\n",
+                config_file.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&crane, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let crane = CraneCLI {
+            cli: CommandLine {
+                path: crane,
+                client_certs: RegistryClientCerts::default(),
+                credentials: RegistryCredentials::from_spec("mirror.example.com=ci:hunter2")
+                    .unwrap(),
+                verbose: false,
+                offline: false,
+                dry_run: false,
+                retry: RetryPolicy::none(),
+                timeout: DEFAULT_OPERATION_TIMEOUT,
+            },
+        };
+
+        let err = crane
+            .get_digest("mirror.example.com/kit:v1")
+            .await
+            .unwrap_err();
+        let recorded = std::fs::read_to_string(&config_file).unwrap();
+        let (docker_config, config) = recorded.split_once('\n').unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(config).unwrap(),
+            serde_json::json!({
+                "auths": { "mirror.example.com": { "username": "ci", "password": "hunter2" } }
+            })
+        );
+        assert!(!Path::new(docker_config).exists());
+        match &err {
+            error::Error::OperationFailed { args, .. } => {
+                assert!(args.iter().all(|arg| !arg.contains("hunter2")))
+            }
+            _ => panic!("unexpected error: {err}"),
+        }
+        assert!(!err.to_string().contains("hunter2"));
+    }
+
+    /// Writes a stand-in for crane to `dir` that writes `stderr` and exits with `status`.
+    fn failing_crane(dir: &Path, status: i32, stderr: &str) -> CraneCLI {
+        let crane = dir.join("crane");
+        std::fs::write(
+            &crane,
+            format!("#!/bin/sh\necho '{stderr}' >&2\nexit {status}\n"),
+        )
+        .unwrap();
+        std::fs::set_permissions(&crane, std::fs::Permissions::from_mode(0o755)).unwrap();
+        CraneCLI {
+            cli: CommandLine {
+                path: crane,
+                client_certs: RegistryClientCerts::default(),
+                credentials: RegistryCredentials::default(),
+                verbose: false,
+                offline: false,
+                dry_run: false,
+                retry: RetryPolicy::none(),
+                timeout: DEFAULT_OPERATION_TIMEOUT,
+            },
+        }
+    }
+
+    /// Writes a stand-in for crane to `dir` that records its arguments to `args` and behaves like
+    /// a registry that cannot delete tags. `pr-1` and `pr-2` refer to the same image if `shared`.
+    fn tag_deleting_crane(dir: &Path, shared: bool) -> CraneCLI {
+        let crane = dir.join("crane");
+        let pr_2 = if shared { 'a' } else { 'b' };
+        std::fs::write(
+            &crane,
+            format!(
+                r#"#!/bin/sh
+echo "$@" >> {args}
+case "$1 $2" in
+    "digest example.com/kit:pr-2") printf 'sha256:%064d\n' 0 | tr 0 {pr_2} ;;
+    "digest "*) printf 'sha256:%064d\n' 0 | tr 0 a ;;
+    "ls "*) printf 'pr-1\npr-2\n' ;;
+    "delete "*@*) ;;
+    "delete "*) echo 'DELETE https://example.com/v2/kit/manifests/pr-1: unexpected status code 405 Method Not Allowed' >&2; exit 1 ;;
+esac
+"#,
+                args = dir.join("args").display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&crane, std::fs::Permissions::from_mode(0o755)).unwrap();
+        CraneCLI {
+            cli: CommandLine {
+                path: crane,
+                client_certs: RegistryClientCerts::default(),
+                credentials: RegistryCredentials::default(),
+                verbose: false,
+                offline: false,
+                dry_run: false,
+                retry: RetryPolicy::none(),
+                timeout: DEFAULT_OPERATION_TIMEOUT,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn delete_tag_falls_back_to_digest() {
+        let temp_dir = TempDir::new().unwrap();
+        let crane = tag_deleting_crane(temp_dir.path(), false);
+        crane.delete_tag("example.com/kit:pr-1").await.unwrap();
+
+        let digest = format!("sha256:{}", "a".repeat(64));
+        let args = std::fs::read_to_string(temp_dir.path().join("args")).unwrap();
+        assert_eq!(
+            args.lines().collect::<Vec<_>>(),
+            [
+                "digest example.com/kit:pr-1",
+                "delete example.com/kit:pr-1",
+                "ls example.com/kit",
+                "digest example.com/kit:pr-2",
+                &format!("delete example.com/kit@{digest}"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn delete_tag_keeps_shared_image() {
+        let temp_dir = TempDir::new().unwrap();
+        let crane = tag_deleting_crane(temp_dir.path(), true);
+        let err = crane.delete_tag("example.com/kit:pr-1").await.unwrap_err();
+        match &err {
+            error::Error::TagDeleteUnsupported { uri, tags, .. } => {
+                assert_eq!(uri, "example.com/kit:pr-1");
+                assert_eq!(tags, &["pr-2"]);
+            }
+            _ => panic!("unexpected error: {err}"),
+        }
+        let args = std::fs::read_to_string(temp_dir.path().join("args")).unwrap();
+        assert!(!args.contains('@'), "{args}");
+    }
+
+    #[tokio::test]
+    async fn copy_image_copies_every_platform() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let args = dir.join("args");
+        let crane = dir.join("crane");
+        std::fs::write(
+            &crane,
+            format!("#!/bin/sh\necho \"$@\" >> {}\n", args.display()),
+        )
+        .unwrap();
+        std::fs::set_permissions(&crane, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let image_tool = ImageTool::new(Box::new(CraneCLI {
+            cli: CommandLine {
+                path: crane,
+                client_certs: RegistryClientCerts::default(),
+                credentials: RegistryCredentials::default(),
+                verbose: false,
+                offline: false,
+                dry_run: false,
+                retry: RetryPolicy::none(),
+                timeout: DEFAULT_OPERATION_TIMEOUT,
+            },
+        }));
+
+        image_tool
+            .copy_image("upstream.example.com/kit:v1", "mirror.example.com/kit:v1")
+            .await
+            .unwrap();
+
+        // A single `crane copy` without `--platform` copies the whole image index.
+        assert_eq!(
+            std::fs::read_to_string(&args).unwrap(),
+            "copy upstream.example.com/kit:v1 mirror.example.com/kit:v1\n"
+        );
+    }
+
+    /// Writes a stand-in for crane to `dir` that records its arguments to `args` and behaves like
+    /// a registry holding the index crane pushes. Like crane, it rejects `index append
+    /// --annotation`, and it saves the manifest given to `edit manifest` to `edited`.
+    fn index_editing_crane(dir: &Path) -> CraneCLI {
+        let crane = dir.join("crane");
+        std::fs::write(
+            &crane,
+            format!(
+                r#"#!/bin/sh
+echo "$@" >> {args}
+case "$*" in
+    *--annotation*) echo 'Error: unknown flag: --annotation' >&2; exit 1 ;;
+    "index append "*) ;;
+    "manifest "*) printf '%s' '{index}' ;;
+    "edit manifest "*) cat > {edited} ;;
+    *) exit 1 ;;
+esac
+"#,
+                args = dir.join("args").display(),
+                edited = dir.join("edited").display(),
+                index = serde_json::json!({
+                    "schemaVersion": 2,
+                    "mediaType": "application/vnd.oci.image.index.v1+json",
+                    "manifests": [{
+                        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                        "digest": format!("sha256:{}", "a".repeat(64)),
+                        "size": 1,
+                        "platform": { "architecture": "amd64", "os": "linux" }
+                    }]
+                }),
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&crane, std::fs::Permissions::from_mode(0o755)).unwrap();
+        CraneCLI {
+            cli: CommandLine {
+                path: crane,
+                client_certs: RegistryClientCerts::default(),
+                credentials: RegistryCredentials::default(),
+                verbose: false,
+                offline: false,
+                dry_run: false,
+                retry: RetryPolicy::none(),
+                timeout: DEFAULT_OPERATION_TIMEOUT,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn manifest_list_annotations_are_added_by_editing_the_index() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let image_tool = ImageTool::new(Box::new(index_editing_crane(dir)));
+        let annotations = HashMap::from([
+            (
+                "org.opencontainers.image.created".to_string(),
+                "2024-07-11T00:00:00Z".to_string(),
+            ),
+            ("bottlerocket.kit.name".to_string(), "core-kit".to_string()),
+        ]);
+        image_tool
+            .push_multi_platform_manifest_with_annotations(
+                vec![(
+                    DockerArchitecture::Amd64.into(),
+                    "example.com/kit:v1-amd64".to_string(),
+                )],
+                "example.com/kit:v1",
+                ManifestMediaType::OciIndex,
+                &annotations,
+            )
+            .await
+            .unwrap();
+
+        let args = std::fs::read_to_string(dir.join("args")).unwrap();
+        let args: Vec<_> = args.lines().collect();
+        assert_eq!(
+            args.first(),
+            Some(&"index append -m example.com/kit:v1-amd64 -t example.com/kit:v1")
+        );
+        assert_eq!(args.last(), Some(&"edit manifest example.com/kit:v1"));
+        let edited: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(dir.join("edited")).unwrap()).unwrap();
+        assert_eq!(
+            edited["annotations"],
+            serde_json::json!({
+                "bottlerocket.kit.name": "core-kit",
+                "org.opencontainers.image.created": "2024-07-11T00:00:00Z"
+            })
+        );
+        assert_eq!(edited["manifests"][0]["platform"]["architecture"], "amd64");
+
+        let annotations = HashMap::from([(String::new(), "core-kit".to_string())]);
+        let err = image_tool
+            .push_multi_platform_manifest_with_annotations(
+                vec![],
+                "example.com/kit:v1",
+                ManifestMediaType::OciIndex,
+                &annotations,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, error::Error::EmptyAnnotationKey { .. }));
+    }
+
+    #[tokio::test]
+    async fn image_exists_distinguishes_missing_from_unreachable() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let uri = "registry.example.com/kit:v1";
+
+        assert!(failing_crane(dir, 0, "").image_exists(uri).await.unwrap());
+        for missing in [
+            "MANIFEST_UNKNOWN: manifest unknown; map[Tag:v1]",
+            "NAME_UNKNOWN: repository name not known to registry",
+            "GET https://registry.example.com/v2/kit/manifests/v1: unexpected status code 404 Not Found (HEAD responses have no body, use GET for details)",
+        ] {
+            assert!(!failing_crane(dir, 1, missing)
+                .image_exists(uri)
+                .await
+                .unwrap());
+        }
+        for unreachable in [
+            "Get \"https://registry.example.com/v2/\": dial tcp: lookup registry.example.com: no such host",
+            "UNAUTHORIZED: authentication required",
+        ] {
+            assert!(failing_crane(dir, 1, unreachable)
+                .image_exists(uri)
+                .await
+                .is_err());
+        }
+    }
+
+    /// Records the largest read made from the wrapped reader, and how many reads there were.
+    struct ReadRecorder<R> {
+        inner: R,
+        largest: usize,
+        reads: usize,
+    }
+
+    impl<R: Read> Read for ReadRecorder<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.largest = self.largest.max(n);
+            self.reads += 1;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn archive_layers_are_streamed() {
+        const LAYER_SIZE: u64 = 64 << 20;
+        let temp_dir = TempDir::new().unwrap();
+        let archive_path = temp_dir.path().join("kit.tar");
+
+        let mut builder = tar::Builder::new(File::create(&archive_path).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(LAYER_SIZE);
+        header.set_mode(0o644);
+        builder
+            .append_data(
+                &mut header,
+                "blobs/sha256/layer",
+                std::io::repeat(7).take(LAYER_SIZE),
+            )
+            .unwrap();
+        builder.into_inner().unwrap();
+
+        let out_dir = temp_dir.path().join("layout");
+        let mut reader = ReadRecorder {
+            inner: File::open(&archive_path).unwrap(),
+            largest: 0,
+            reads: 0,
+        };
+        unpack_archive(&mut reader, &out_dir).unwrap();
+
+        let layer = out_dir.join("blobs/sha256/layer");
+        assert_eq!(std::fs::metadata(layer).unwrap().len(), LAYER_SIZE);
+        assert!(reader.largest <= ARCHIVE_CHUNK_SIZE, "{}", reader.largest);
+        assert!(
+            reader.reads as u64 >= LAYER_SIZE / ARCHIVE_CHUNK_SIZE as u64,
+            "{}",
+            reader.reads
+        );
+    }
+
+    #[test]
+    fn tags_keep_registry_order() {
+        assert_eq!(
+            parse_tags(b"v1.2.0\nv1.10.0\n\nlatest\r\nv1.2.0-amd64\n"),
+            ["v1.2.0", "v1.10.0", "latest", "v1.2.0-amd64"]
+        );
+        assert!(parse_tags(b"").is_empty());
+    }
+
+    #[test]
+    fn digest_output_is_validated() {
+        let digest = format!("sha256:{}", "0123456789abcdef".repeat(4));
+        assert_eq!(
+            parse_digest("example.com/kit:v1", format!("{digest}\n").as_bytes()).unwrap(),
+            digest
+        );
+        for output in ["", "sha256:abcd", "latest", &digest.to_uppercase()] {
+            assert!(
+                matches!(
+                    parse_digest("example.com/kit:v1", output.as_bytes()),
+                    Err(error::Error::InvalidDigest { .. })
+                ),
+                "{output}"
+            );
+        }
+    }
 }
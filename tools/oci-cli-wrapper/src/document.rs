@@ -0,0 +1,53 @@
+//! Deserialization of JSON documents (manifests, indexes and image configs) returned by the image
+//! tool.
+//!
+//! Registries and build tools do not always agree on schemas, so failures carry the schema the
+//! document declares and the start of the raw JSON to make unexpected formats easy to diagnose.
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use snafu::ResultExt;
+
+use crate::{error, Result};
+
+/// How much of a document to include in deserialization errors.
+const SNIPPET_LEN: usize = 512;
+
+/// Deserialize an image manifest or image index.
+pub(crate) fn manifest_from_slice<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    serde_json::from_slice(bytes).with_context(|_| error::ManifestDeserializeSnafu {
+        schema: declared_schema(bytes),
+        snippet: snippet(bytes),
+    })
+}
+
+/// Deserialize an image config.
+pub(crate) fn config_from_slice<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    serde_json::from_slice(bytes).with_context(|_| error::ConfigDeserializeSnafu {
+        schema: declared_schema(bytes),
+        snippet: snippet(bytes),
+    })
+}
+
+/// Describe the `mediaType` and `schemaVersion` declared by a document, if it is valid JSON.
+fn declared_schema(bytes: &[u8]) -> String {
+    let Ok(document) = serde_json::from_slice::<Value>(bytes) else {
+        return "not valid JSON".to_string();
+    };
+    let media_type = document
+        .get("mediaType")
+        .and_then(Value::as_str)
+        .unwrap_or("unspecified");
+    let schema_version = document
+        .get("schemaVersion")
+        .map_or_else(|| "unspecified".to_string(), Value::to_string);
+    format!("media type {media_type}, schema version {schema_version}")
+}
+
+/// The start of the raw document, truncated to `SNIPPET_LEN` characters.
+fn snippet(bytes: &[u8]) -> String {
+    let document = String::from_utf8_lossy(bytes);
+    match document.char_indices().nth(SNIPPET_LEN) {
+        Some((end, _)) => format!("{}...", &document[..end]),
+        None => document.into_owned(),
+    }
+}
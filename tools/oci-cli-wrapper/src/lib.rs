@@ -1,48 +1,367 @@
 //! ImageTool enablement library implements a standardized way of calling commandline container image
 //! tools for interacting primarily with kit images in a container registry.
 //!
-//! Current two tools are supported:
+//! Current three tools are supported:
 //! * crane, gcrane, krane
 //!     Crane provides a more direct interaction with the container registry,
 //!     allowing us to query image information in the registry without having to pull the full image to
 //!     disk. It also does not require a daemon to operate and has optimizations for pulling large images to disk
+//! * podman
+//!     Podman works without a daemon or root, for environments where docker is unavailable. Like
+//!     docker, it has to pull an image locally to inspect its config or digest.
 //! * docker
 //!     Docker can perform all interactions we need with several caveats that make it less efficient than
 //!     crane. The image needs to be pulled locally in order for docker to inspect the manifest and extract
 //!     metadata. In addition, in order to operate with OCI image format, the containerd-snapshotter
 //!     feature has to be enabled in the docker daemon
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
-use std::{collections::HashMap, path::Path};
+use std::path::{Path, PathBuf};
 
 use async_trait::async_trait;
 use cli::CommandLine;
 use crane::CraneCLI;
+use futures::{StreamExt, TryStreamExt};
 use krane_bundle::KRANE;
 use olpc_cjson::CanonicalFormatter;
+use podman::PodmanCLI;
 use serde::{Deserialize, Serialize};
-use snafu::ResultExt;
+use snafu::{OptionExt, ResultExt};
 
 mod cli;
+mod client_certs;
 mod crane;
+mod document;
+mod manifest;
+mod mirror;
+#[cfg(any(test, feature = "testing"))]
+mod mock;
+mod podman;
+mod registry_auth;
+mod replay;
+mod rewrite;
+
+pub use cli::{
+    CommandOptions, DRY_RUN_ENV, OFFLINE_ENV, OPERATION_TIMEOUT_ENV, REGISTRY_RETRIES_ENV,
+    VERBOSE_SUBPROCESS_ENV,
+};
+pub use client_certs::{
+    ClientCert, RegistryClientCerts, KRANE_CLIENT_CERT_ENV, KRANE_CLIENT_KEY_ENV,
+    REGISTRY_CLIENT_CERTS_ENV,
+};
+pub use manifest::{
+    AttestationDescriptor, AttestationManifest, Descriptor, ManifestMediaType, ManifestView,
+    PlatformDescriptor, DOCKER_MANIFEST_LIST_MEDIA_TYPE, OCI_INDEX_MEDIA_TYPE,
+};
+pub use mirror::{RegistryMirrors, REGISTRY_MIRRORS_ENV};
+#[cfg(any(test, feature = "testing"))]
+pub use mock::{MockCall, MockImageTool};
+pub use registry_auth::{
+    RegistryAuth, RegistryCredentials, REGISTRY_AUTH_ENV, REGISTRY_AUTH_FILE_ENV,
+};
+pub use replay::{RegistryFixtures, REGISTRY_RECORD_ENV, REGISTRY_REPLAY_ENV};
+pub use rewrite::{
+    uri_rewriter_from_env, IdentityUriRewriter, RegexUriRewriter, UriRewriter, URI_REWRITE_ENV,
+};
+
+/// Environment variable naming the image tool to use, `crane` or `podman`. If unset, the first
+/// available tool in `TWOLITER_KIT_IMAGE_TOOL_ORDER` is used.
+pub const IMAGE_TOOL_ENV: &str = "TWOLITER_KIT_IMAGE_TOOL";
+
+/// Environment variable holding the comma-separated image tools to look for, in order of
+/// preference, when `TWOLITER_KIT_IMAGE_TOOL` is unset, e.g. `podman,krane`. `krane` is the builtin
+/// krane, while `crane` and `podman` are searched for on `PATH`. If unset, the builtin krane is
+/// used, falling back to podman if it cannot be found.
+pub const IMAGE_TOOL_ORDER_ENV: &str = "TWOLITER_KIT_IMAGE_TOOL_ORDER";
+
+/// The image tools looked for when `TWOLITER_KIT_IMAGE_TOOL_ORDER` is unset
+const DEFAULT_IMAGE_TOOL_ORDER: [&str; 2] = ["krane", "podman"];
+
+/// Environment variable holding the path of the image tool binary to run, for a tool that is not
+/// on `PATH`. The tool is the one named in `TWOLITER_KIT_IMAGE_TOOL`, or crane if that is unset.
+pub const IMAGE_TOOL_PATH_ENV: &str = "TWOLITER_KIT_IMAGE_TOOL_PATH";
+
+/// Which image tool to use and where to find it, as configured by `TWOLITER_KIT_IMAGE_TOOL`,
+/// `TWOLITER_KIT_IMAGE_TOOL_ORDER` and `TWOLITER_KIT_IMAGE_TOOL_PATH`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImageToolSelection {
+    /// The tool to use, `crane`, `krane` or `podman`
+    pub tool: Option<String>,
+    /// The comma-separated tools to look for when `tool` is unset
+    pub order: Option<String>,
+    /// The binary to run as `tool`, instead of searching for it
+    pub path: Option<PathBuf>,
+}
+
+impl ImageToolSelection {
+    /// Reads the selection from the environment, treating empty variables as unset.
+    pub fn from_env() -> Self {
+        Self {
+            tool: std::env::var(IMAGE_TOOL_ENV)
+                .ok()
+                .filter(|tool| !tool.is_empty()),
+            order: std::env::var(IMAGE_TOOL_ORDER_ENV).ok(),
+            path: std::env::var_os(IMAGE_TOOL_PATH_ENV)
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from),
+        }
+    }
+}
+
+/// How many platform images [`ImageTool::push_all_platforms`] pushes at once by default
+const DEFAULT_PUSH_CONCURRENCY: usize = 4;
 
 #[derive(Debug)]
 pub struct ImageTool {
     image_tool_impl: Box<dyn ImageToolImpl>,
+    skip_existing: bool,
+    push_concurrency: usize,
+    /// Whether dry run mode keeps the image tool from pushing anything
+    dry_run: bool,
 }
 
 impl ImageTool {
     /// Uses the builtin `krane` provided by the `tools/krane` crate.
     pub fn from_builtin_krane() -> Self {
-        let image_tool_impl = Box::new(CraneCLI {
-            cli: CommandLine {
-                path: KRANE.path().to_path_buf(),
-            },
-        });
-        Self { image_tool_impl }
+        Self::from_builtin_krane_with_client_certs(RegistryClientCerts::default())
+    }
+
+    /// Uses the builtin `krane`, presenting the configured TLS client certificate to each
+    /// registry that has one.
+    pub fn from_builtin_krane_with_client_certs(client_certs: RegistryClientCerts) -> Self {
+        Self::from_builtin_krane_with_auth(client_certs, RegistryCredentials::default())
+    }
+
+    /// Uses the builtin `krane`, presenting the configured TLS client certificate and
+    /// credentials to each registry that has them. Registries without credentials fall back to
+    /// the ambient docker configuration.
+    pub fn from_builtin_krane_with_auth(
+        client_certs: RegistryClientCerts,
+        credentials: RegistryCredentials,
+    ) -> Self {
+        Self::from_builtin_krane_with_options(client_certs, credentials, CommandOptions::from_env())
+    }
+
+    fn from_builtin_krane_with_options(
+        client_certs: RegistryClientCerts,
+        credentials: RegistryCredentials,
+        options: CommandOptions,
+    ) -> Self {
+        let cli = CommandLine::new(
+            KRANE.path().to_path_buf(),
+            client_certs,
+            credentials,
+            options,
+        );
+        Self::from_cli(Box::new(CraneCLI { cli }), options)
+    }
+
+    /// Uses the image tool named in `TWOLITER_KIT_IMAGE_TOOL`, or else the first available of the
+    /// tools in `TWOLITER_KIT_IMAGE_TOOL_ORDER`, which defaults to the builtin krane and then
+    /// podman. If `TWOLITER_KIT_IMAGE_TOOL_PATH` is set, the tool is run from that path instead of
+    /// being searched for.
+    pub fn from_environment(
+        client_certs: RegistryClientCerts,
+        credentials: RegistryCredentials,
+    ) -> Result<Self> {
+        Self::from_environment_with_options(client_certs, credentials, CommandOptions::from_env())
+    }
+
+    /// Chooses the image tool like [`ImageTool::from_environment`], but runs its commands with
+    /// `options` instead of reading them from the environment.
+    pub fn from_environment_with_options(
+        client_certs: RegistryClientCerts,
+        credentials: RegistryCredentials,
+        options: CommandOptions,
+    ) -> Result<Self> {
+        Self::from_selection(
+            ImageToolSelection::from_env(),
+            client_certs,
+            credentials,
+            options,
+        )
+    }
+
+    /// Uses the image tool chosen by `selection`, running its commands with `options`.
+    pub fn from_selection(
+        selection: ImageToolSelection,
+        client_certs: RegistryClientCerts,
+        credentials: RegistryCredentials,
+        options: CommandOptions,
+    ) -> Result<Self> {
+        let ImageToolSelection { tool, order, path } = selection;
+        if let Some(path) = path {
+            let tool = tool.as_deref().unwrap_or("crane");
+            return Self::from_path_with_options(tool, path, client_certs, credentials, options);
+        }
+        match tool.as_deref() {
+            Some("crane" | "krane") => Ok(Self::from_builtin_krane_with_options(
+                client_certs,
+                credentials,
+                options,
+            )),
+            Some("podman") => {
+                let path =
+                    which::which("podman").context(error::NotFoundSnafu { name: "podman" })?;
+                let cli = CommandLine::new(path, client_certs, credentials, options);
+                Ok(Self::from_cli(Box::new(PodmanCLI { cli }), options))
+            }
+            Some(name) => error::UnsupportedSnafu { name }.fail(),
+            None => {
+                let order = image_tool_order(order.as_deref())?;
+                Self::detect(&order, client_certs, credentials, options)
+            }
+        }
+    }
+
+    /// Uses the first of `order` that can be found.
+    fn detect(
+        order: &[String],
+        client_certs: RegistryClientCerts,
+        credentials: RegistryCredentials,
+        options: CommandOptions,
+    ) -> Result<Self> {
+        for tool in order {
+            let path = match tool.as_str() {
+                "krane" => Some(KRANE.path().to_path_buf()).filter(|path| path.is_file()),
+                name => which::which(name).ok(),
+            };
+            if let Some(path) = path {
+                log::debug!("Using {tool} at {}", path.display());
+                return Self::from_path_with_options(
+                    tool,
+                    path,
+                    client_certs,
+                    credentials,
+                    options,
+                );
+            }
+        }
+        error::NoneFoundSnafu {
+            tools: order.join(", "),
+        }
+        .fail()
+    }
+
+    /// Uses the binary at `path` as the image tool `tool`, either `crane` (or `krane`) or `podman`,
+    /// without searching `PATH` for it.
+    pub fn from_path(
+        tool: &str,
+        path: PathBuf,
+        client_certs: RegistryClientCerts,
+        credentials: RegistryCredentials,
+    ) -> Result<Self> {
+        Self::from_path_with_options(
+            tool,
+            path,
+            client_certs,
+            credentials,
+            CommandOptions::from_env(),
+        )
+    }
+
+    fn from_path_with_options(
+        tool: &str,
+        path: PathBuf,
+        client_certs: RegistryClientCerts,
+        credentials: RegistryCredentials,
+        options: CommandOptions,
+    ) -> Result<Self> {
+        snafu::ensure!(
+            path.is_file(),
+            error::ToolPathNotFoundSnafu { name: tool, path }
+        );
+        let cli = CommandLine::new(path, client_certs, credentials, options);
+        match tool {
+            "crane" | "krane" => Ok(Self::from_cli(Box::new(CraneCLI { cli }), options)),
+            "podman" => Ok(Self::from_cli(Box::new(PodmanCLI { cli }), options)),
+            name => error::UnsupportedSnafu { name }.fail(),
+        }
+    }
+
+    /// Uses the `podman` binary at `path`.
+    pub fn from_podman(
+        path: PathBuf,
+        client_certs: RegistryClientCerts,
+        credentials: RegistryCredentials,
+    ) -> Self {
+        let options = CommandOptions::from_env();
+        let cli = CommandLine::new(path, client_certs, credentials, options);
+        Self::from_cli(Box::new(PodmanCLI { cli }), options)
+    }
+
+    fn from_cli(image_tool_impl: Box<dyn ImageToolImpl>, options: CommandOptions) -> Self {
+        Self {
+            dry_run: options.dry_run,
+            ..Self::new(image_tool_impl)
+        }
     }
 
+    /// Wraps `image_tool_impl` with dry-run mode off. The constructors that take
+    /// [`CommandOptions`] turn it on from there, so it is never read from the environment here.
     pub fn new(image_tool_impl: Box<dyn ImageToolImpl>) -> Self {
-        Self { image_tool_impl }
+        Self {
+            image_tool_impl,
+            skip_existing: false,
+            push_concurrency: DEFAULT_PUSH_CONCURRENCY,
+            dry_run: false,
+        }
+    }
+
+    /// Serves read-only operations from responses previously recorded into `dir` with
+    /// [`ImageTool::record_to`], without contacting a registry.
+    pub fn from_fixtures(dir: impl Into<PathBuf>) -> Self {
+        Self::new(Box::new(replay::ReplayingImageTool { dir: dir.into() }))
+    }
+
+    /// Skip uploads when the registry already holds the content being pushed, only updating the
+    /// tag if needed. This makes re-running a partially failed publish cheap.
+    pub fn skip_existing(mut self, skip_existing: bool) -> Self {
+        self.skip_existing = skip_existing;
+        self
+    }
+
+    /// Push at most `push_concurrency` platform images at once in
+    /// [`ImageTool::push_all_platforms`]. Values below one are treated as one.
+    pub fn push_concurrency(mut self, push_concurrency: usize) -> Self {
+        self.push_concurrency = push_concurrency.max(1);
+        self
+    }
+
+    /// Save the response to each read-only operation into `dir`, so that it can be replayed later
+    /// with [`ImageTool::from_fixtures`].
+    pub fn record_to(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.image_tool_impl = Box::new(replay::RecordingImageTool {
+            inner: self.image_tool_impl,
+            dir: dir.into(),
+        });
+        self
+    }
+
+    /// Read images through the registry mirrors in `mirrors`, leaving pushes untouched.
+    pub fn registry_mirrors(mut self, mirrors: RegistryMirrors) -> Self {
+        if !mirrors.is_empty() {
+            self.image_tool_impl = Box::new(mirror::MirroringImageTool {
+                inner: self.image_tool_impl,
+                mirrors,
+            });
+        }
+        self
+    }
+
+    /// Rewrite every image URI with `rewriter` before it is passed to the image tool.
+    pub fn uri_rewriter(mut self, rewriter: Box<dyn UriRewriter>) -> Self {
+        self.image_tool_impl = Box::new(rewrite::RewritingImageTool {
+            inner: self.image_tool_impl,
+            rewriter,
+        });
+        self
+    }
+
+    /// Whether dry-run mode is on, in which case pulls and pushes are only logged. Callers of
+    /// [`ImageTool::pull_oci_image`] check this to avoid reading an archive that was never written.
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
     }
 
     /// Pull an image archive to disk
@@ -50,16 +369,59 @@ impl ImageTool {
         self.image_tool_impl.pull_oci_image(path, uri).await
     }
 
-    /// Fetch the image config
-    pub async fn get_config(&self, uri: &str) -> Result<ConfigView> {
-        self.image_tool_impl.get_config(uri).await
+    /// Check that the OCI image layout at `path`, as written by `pull_oci_image`, holds the image
+    /// with digest `expected` and that none of its content is missing or corrupt.
+    pub fn verify_oci_image(&self, path: &Path, expected: &str) -> Result<()> {
+        manifest::verify_oci_layout(path, expected)
+    }
+
+    /// Pull an image archive into `dir`, naming it after the image's digest so that archives are
+    /// content-addressed, e.g. `sha256-<hex>`. The image is pulled by the resolved digest, so the
+    /// archive always matches its name even if the tag moves. Returns the path of the archive,
+    /// which does not exist in dry-run mode.
+    pub async fn pull_oci_image_into_dir(&self, dir: &Path, uri: &str) -> Result<PathBuf> {
+        let digest = self.get_digest(uri).await?;
+        let (repository, _) = split_reference(uri);
+        let path = dir.join(digest.replace(':', "-"));
+        self.pull_oci_image(&path, &format!("{repository}@{digest}"))
+            .await?;
+        Ok(path)
+    }
+
+    /// Describe the image tool backend that will perform registry operations
+    pub async fn tool_info(&self) -> Result<ToolInfo> {
+        self.image_tool_impl.tool_info().await
+    }
+
+    /// Fetch the image config. For a multi-arch image, `platform` selects which image's config to
+    /// fetch, since an image index has no config of its own.
+    pub async fn get_config(
+        &self,
+        uri: &str,
+        platform: Option<&DockerArchitecture>,
+    ) -> Result<ConfigView> {
+        let image_uri = self.get_platform_image(uri, platform).await?;
+        self.image_tool_impl.get_config(&image_uri).await
+    }
+
+    /// Find the single-platform image that `uri` refers to. For an image index this is the
+    /// `platform` image, pinned by digest, e.g. `<repository>@sha256:<hex>`; a single image is
+    /// returned as is.
+    pub async fn get_platform_image(
+        &self,
+        uri: &str,
+        platform: Option<&DockerArchitecture>,
+    ) -> Result<String> {
+        match self.get_manifest_parsed(uri).await? {
+            ManifestView::Image { .. } => Ok(uri.to_string()),
+            ManifestView::Index { manifests, .. } => platform_image(uri, manifests, platform),
+        }
     }
 
     /// Fetch the manifest
     pub async fn get_manifest(&self, uri: &str) -> Result<Vec<u8>> {
         let manifest_bytes = self.image_tool_impl.get_manifest(uri).await?;
-        let manifest_object: serde_json::Value =
-            serde_json::from_slice(&manifest_bytes).context(error::ManifestDeserializeSnafu)?;
+        let manifest_object: serde_json::Value = document::manifest_from_slice(&manifest_bytes)?;
 
         let mut canonicalized_manifest = Vec::new();
         let mut ser = serde_json::Serializer::with_formatter(
@@ -74,55 +436,541 @@ impl ImageTool {
         Ok(canonicalized_manifest)
     }
 
-    /// Push a single-arch image in oci archive format
+    /// Fetch the digest of the manifest
+    pub async fn get_digest(&self, uri: &str) -> Result<String> {
+        self.image_tool_impl.get_digest(uri).await
+    }
+
+    /// Fetch the digest of the manifest of an image that is only being read, such as a
+    /// dependency. Unlike [`ImageTool::get_digest`], this goes through any registry mirror, so it
+    /// must not be used to decide what to push.
+    pub async fn resolve_digest(&self, uri: &str) -> Result<String> {
+        self.image_tool_impl.resolve_digest(uri).await
+    }
+
+    /// Whether the registry has an image at `uri`. An error means the registry could not say,
+    /// not that the image is missing.
+    pub async fn image_exists(&self, uri: &str) -> Result<bool> {
+        self.image_tool_impl.image_exists(uri).await
+    }
+
+    /// Delete the tag `uri`, e.g. `public.ecr.aws/bottlerocket/core-kit:pr-123`. The image it
+    /// refers to is resolved first, so that the log shows exactly which image was deleted.
+    pub async fn delete_tag(&self, uri: &str) -> Result<()> {
+        self.image_tool_impl.delete_tag(uri).await
+    }
+
+    /// List the tags in the repository `repo`, e.g. `public.ecr.aws/bottlerocket/core-kit`, in the
+    /// order the registry returns them
+    pub async fn list_tags(&self, repo: &str) -> Result<Vec<String>> {
+        self.image_tool_impl.list_tags(repo).await
+    }
+
+    /// Fetch and parse the manifest, distinguishing image manifests from image indexes
+    pub async fn get_manifest_parsed(&self, uri: &str) -> Result<ManifestView> {
+        let manifest_bytes = self.image_tool_impl.get_manifest(uri).await?;
+        ManifestView::from_slice(&manifest_bytes)
+    }
+
+    /// Whether `uri` refers to a manifest list or image index rather than a single image
+    pub async fn is_manifest_list(&self, uri: &str) -> Result<bool> {
+        Ok(matches!(
+            self.get_manifest_parsed(uri).await?,
+            ManifestView::Index { .. }
+        ))
+    }
+
+    /// Sum the compressed layer sizes of the image at `uri`, i.e. roughly how much would be
+    /// downloaded to pull it. For a multi-arch image, `platform` selects which image to measure.
+    pub async fn get_download_size(
+        &self,
+        uri: &str,
+        platform: Option<&DockerArchitecture>,
+    ) -> Result<u64> {
+        let layers = match self.get_manifest_parsed(uri).await? {
+            ManifestView::Image { layers, .. } => layers,
+            ManifestView::Index { manifests, .. } => {
+                let image_uri = platform_image(uri, manifests, platform)?;
+                match self.get_manifest_parsed(&image_uri).await? {
+                    ManifestView::Image { layers, .. } => layers,
+                    ManifestView::Index { .. } => return error::InvalidManifestSnafu.fail(),
+                }
+            }
+        };
+        Ok(layers.iter().map(|layer| layer.size).sum())
+    }
+
+    /// Push a single-arch image from an oci archive or an unpacked OCI image layout directory
     pub async fn push_oci_archive(&self, path: &Path, uri: &str) -> Result<()> {
+        if self.skip_existing {
+            if let Some(digest) = manifest::archive_manifest_digest(path)? {
+                if self.get_digest(uri).await.ok().as_deref() == Some(digest.as_str()) {
+                    log::info!("Image {uri} is already up to date, skipping push");
+                    return Ok(());
+                }
+                let (repository, tag) = split_reference(uri);
+                let by_digest = format!("{repository}@{digest}");
+                if let (Some(tag), Ok(_)) = (tag, self.get_digest(&by_digest).await) {
+                    log::info!("Image {by_digest} already exists, tagging it as {uri}");
+                    return self.image_tool_impl.tag_image(&by_digest, tag).await;
+                }
+            }
+        }
         self.image_tool_impl.push_oci_archive(path, uri).await
     }
 
-    /// Push the multi-arch kit manifest list
+    /// Push a single-arch image as `push_oci_archive` does, returning its digest and size for
+    /// recording in a lock file. There is no result in dry-run mode, since nothing is pushed.
+    pub async fn push_oci_archive_with_result(
+        &self,
+        path: &Path,
+        uri: &str,
+    ) -> Result<Option<PushResult>> {
+        self.push_oci_archive(path, uri).await?;
+        self.push_result(uri).await
+    }
+
+    /// Copy the image at `src` to `dst` directly between registries, without writing it to disk.
+    /// A multi-arch image is copied with its image index and every platform image.
+    pub async fn copy_image(&self, src: &str, dst: &str) -> Result<()> {
+        self.copy_image_with_annotations(src, dst, &HashMap::new())
+            .await
+    }
+
+    /// Copy the image at `src` to `dst`, then set `annotations` on the destination manifest. For
+    /// a multi-arch image the annotations are set on the image index. Existing annotations with
+    /// other keys are kept.
+    pub async fn copy_image_with_annotations(
+        &self,
+        src: &str,
+        dst: &str,
+        annotations: &HashMap<String, String>,
+    ) -> Result<()> {
+        self.image_tool_impl
+            .copy_image_with_annotations(src, dst, annotations)
+            .await
+    }
+
+    /// Push the multi-arch kit manifest list, as an OCI image index or Docker manifest list
+    /// depending on `media_type`. The platform images may be in other repositories than `uri`,
+    /// such as a sub-path of it, but must be in the same registry. Each image's entry is given
+    /// the whole platform, including its variant and OS version.
     pub async fn push_multi_platform_manifest(
         &self,
-        platform_images: Vec<(DockerArchitecture, String)>,
+        platform_images: Vec<(PlatformSpec, String)>,
+        uri: &str,
+        media_type: ManifestMediaType,
+    ) -> Result<()> {
+        self.push_multi_platform_manifest_with_annotations(
+            platform_images,
+            uri,
+            media_type,
+            &HashMap::new(),
+        )
+        .await
+    }
+
+    /// Push the multi-arch kit manifest list as `push_multi_platform_manifest` does, setting
+    /// `annotations`, e.g. `org.opencontainers.image.created`, on the manifest list itself.
+    pub async fn push_multi_platform_manifest_with_annotations(
+        &self,
+        platform_images: Vec<(PlatformSpec, String)>,
+        uri: &str,
+        media_type: ManifestMediaType,
+        annotations: &HashMap<String, String>,
+    ) -> Result<()> {
+        ensure_same_registry(&platform_images, uri)?;
+        snafu::ensure!(
+            annotations.keys().all(|key| !key.is_empty()),
+            error::EmptyAnnotationKeySnafu { uri }
+        );
+        if self.skip_existing
+            && self
+                .index_is_current(&platform_images, uri, media_type, annotations)
+                .await
+        {
+            log::info!("Manifest list {uri} is already up to date, skipping push");
+            return Ok(());
+        }
+        self.image_tool_impl
+            .push_multi_platform_manifest(platform_images, uri, media_type, annotations)
+            .await
+    }
+
+    /// Push the multi-arch kit manifest list as `push_multi_platform_manifest` does, returning its
+    /// digest and the total size of the platform images for recording in a lock file. There is no
+    /// result in dry-run mode, since nothing is pushed.
+    pub async fn push_multi_platform_manifest_with_result(
+        &self,
+        platform_images: Vec<(PlatformSpec, String)>,
+        uri: &str,
+        media_type: ManifestMediaType,
+    ) -> Result<Option<PushResult>> {
+        self.push_multi_platform_manifest(platform_images, uri, media_type)
+            .await?;
+        self.push_result(uri).await
+    }
+
+    /// Describe what was pushed to `uri`. The digest is resolved with `get_digest`, so it matches
+    /// what a later lookup of the tag reports, and the size is read from the manifest at that
+    /// digest, so it describes the same image even if the tag has moved since.
+    async fn push_result(&self, uri: &str) -> Result<Option<PushResult>> {
+        if self.dry_run {
+            return Ok(None);
+        }
+        let digest = self.get_digest(uri).await?;
+        let (repository, _) = split_reference(uri);
+        let size_bytes = match self
+            .get_manifest_parsed(&format!("{repository}@{digest}"))
+            .await?
+        {
+            ManifestView::Image { layers, .. } => layers.iter().map(|layer| layer.size).sum(),
+            ManifestView::Index { manifests, .. } => {
+                let mut size_bytes = 0;
+                for platform in manifests {
+                    let image_uri = format!("{repository}@{}", platform.digest);
+                    size_bytes += self.get_download_size(&image_uri, None).await?;
+                }
+                size_bytes
+            }
+        };
+        Ok(Some(PushResult { digest, size_bytes }))
+    }
+
+    /// Push a single-arch archive for each platform, then the manifest list at `uri` referencing
+    /// them. Each platform image is tagged as `uri` with the architecture appended to its tag, e.g.
+    /// `kit:v1-arm64`. The platform images are pushed concurrently, and the manifest list is only
+    /// pushed once all of them succeed, so a failed push never leaves `uri` pointing at a partial
+    /// manifest list.
+    pub async fn push_all_platforms(
+        &self,
+        archives: Vec<(DockerArchitecture, PathBuf)>,
+        uri: &str,
+    ) -> Result<()> {
+        let (repository, tag) = split_reference(uri);
+        let tag = tag.unwrap_or("latest");
+        let platform_images: Vec<_> = futures::stream::iter(archives)
+            .map(|(arch, path)| async move {
+                let platform_uri = format!("{repository}:{tag}-{arch}");
+                log::info!("Pushing {arch} image to {platform_uri}");
+                self.push_oci_archive(&path, &platform_uri).await?;
+                Ok((PlatformSpec::new(arch), platform_uri))
+            })
+            .buffered(self.push_concurrency)
+            .try_collect()
+            .await?;
+        self.push_multi_platform_manifest(platform_images, uri, ManifestMediaType::default())
+            .await
+    }
+
+    /// Whether the image index at `uri` already has the given media type and references exactly
+    /// the given platform images, for the same platforms.
+    async fn index_is_current(
+        &self,
+        platform_images: &[(PlatformSpec, String)],
+        uri: &str,
+        media_type: ManifestMediaType,
+        annotations: &HashMap<String, String>,
+    ) -> bool {
+        let Ok(ManifestView::Index {
+            media_type: existing_media_type,
+            manifests,
+            annotations: existing_annotations,
+            ..
+        }) = self.get_manifest_parsed(uri).await
+        else {
+            return false;
+        };
+        if existing_media_type.as_deref() != Some(media_type.as_str()) {
+            return false;
+        }
+        if annotations
+            .iter()
+            .any(|(key, value)| existing_annotations.get(key) != Some(value))
+        {
+            return false;
+        }
+        let mut expected = Vec::new();
+        for (platform, image) in platform_images {
+            match self.get_digest(image).await {
+                Ok(digest) => expected.push((
+                    platform.arch.to_string(),
+                    platform.os.clone(),
+                    platform.variant.clone(),
+                    platform.os_version.clone(),
+                    digest,
+                )),
+                Err(_) => return false,
+            }
+        }
+        let mut existing: Vec<_> = manifests
+            .into_iter()
+            .map(|platform| {
+                (
+                    platform.architecture.to_string(),
+                    platform.os,
+                    platform.variant,
+                    platform.os_version,
+                    platform.digest,
+                )
+            })
+            .collect();
+        expected.sort();
+        existing.sort();
+        expected == existing
+    }
+
+    /// Push the multi-arch kit manifest list, including attestation manifests (e.g. SLSA
+    /// provenance) for the platform images
+    pub async fn push_multi_platform_manifest_with_attestations(
+        &self,
+        platform_images: Vec<(PlatformSpec, String)>,
+        attestations: Vec<AttestationManifest>,
         uri: &str,
     ) -> Result<()> {
+        ensure_same_registry(&platform_images, uri)?;
         self.image_tool_impl
-            .push_multi_platform_manifest(platform_images, uri)
+            .push_multi_platform_manifest_with_attestations(platform_images, attestations, uri)
             .await
     }
 }
 
 #[async_trait]
 pub trait ImageToolImpl: std::fmt::Debug + Send + Sync + 'static {
+    /// Describe the backend and the binary it runs
+    async fn tool_info(&self) -> Result<ToolInfo>;
     /// Pull an image archive to disk
     async fn pull_oci_image(&self, path: &Path, uri: &str) -> Result<()>;
     /// Fetch the image config
     async fn get_config(&self, uri: &str) -> Result<ConfigView>;
     /// Fetch the manifest
     async fn get_manifest(&self, uri: &str) -> Result<Vec<u8>>;
-    /// Push a single-arch image in oci archive format
+    /// Fetch the digest of the manifest
+    async fn get_digest(&self, uri: &str) -> Result<String>;
+    /// Fetch the digest of the manifest of an image that is only being read
+    async fn resolve_digest(&self, uri: &str) -> Result<String> {
+        self.get_digest(uri).await
+    }
+    /// Check whether the registry has an image at `uri`, failing if the registry cannot be asked
+    async fn image_exists(&self, uri: &str) -> Result<bool>;
+    /// Delete the tag `uri` from its repository
+    async fn delete_tag(&self, uri: &str) -> Result<()>;
+    /// List the tags in the repository `repo`
+    async fn list_tags(&self, repo: &str) -> Result<Vec<String>>;
+    /// Point `tag` in the repository of `uri` at the image referenced by `uri`
+    async fn tag_image(&self, uri: &str, tag: &str) -> Result<()>;
+    /// Push a single-arch image from an oci archive or an unpacked OCI image layout directory
     async fn push_oci_archive(&self, path: &Path, uri: &str) -> Result<()>;
-    /// Push the multi-arch kit manifest list
+    /// Copy an image between references and set annotations on the destination manifest
+    async fn copy_image_with_annotations(
+        &self,
+        src: &str,
+        dst: &str,
+        annotations: &HashMap<String, String>,
+    ) -> Result<()>;
+    /// Push the multi-arch kit manifest list with the given media type and annotations
     async fn push_multi_platform_manifest(
         &self,
-        platform_images: Vec<(DockerArchitecture, String)>,
+        platform_images: Vec<(PlatformSpec, String)>,
+        uri: &str,
+        media_type: ManifestMediaType,
+        annotations: &HashMap<String, String>,
+    ) -> Result<()>;
+    /// Push the multi-arch kit manifest list along with attestation manifests
+    async fn push_multi_platform_manifest_with_attestations(
+        &self,
+        platform_images: Vec<(PlatformSpec, String)>,
+        attestations: Vec<AttestationManifest>,
         uri: &str,
     ) -> Result<()>;
 }
 
-#[derive(Deserialize, Debug, Clone, PartialEq)]
+/// Parse the comma-separated image tools to look for, defaulting to the builtin krane and then
+/// podman if `order` is unset or empty.
+fn image_tool_order(order: Option<&str>) -> Result<Vec<String>> {
+    let Some(order) = order.filter(|order| !order.trim().is_empty()) else {
+        return Ok(DEFAULT_IMAGE_TOOL_ORDER.map(String::from).to_vec());
+    };
+    order
+        .split(',')
+        .map(str::trim)
+        .map(|tool| match tool {
+            "krane" | "crane" | "podman" => Ok(tool.to_string()),
+            name => error::UnsupportedSnafu { name }.fail(),
+        })
+        .collect()
+}
+
+/// The registry host of an image reference, e.g. `public.ecr.aws` for
+/// `public.ecr.aws/bottlerocket/kit:v1`.
+fn registry_of(uri: &str) -> &str {
+    uri.split_once('/').map_or(uri, |(registry, _)| registry)
+}
+
+/// Image indexes can only reference manifests in their own registry, so check that the platform
+/// images are in the same registry as the index, though they may be in other repositories.
+fn ensure_same_registry(platform_images: &[(PlatformSpec, String)], uri: &str) -> Result<()> {
+    for (_, image) in platform_images {
+        snafu::ensure!(
+            registry_of(image) == registry_of(uri),
+            error::IncompatibleRegistrySnafu { image, uri }
+        );
+    }
+    Ok(())
+}
+
+/// Select the image for `platform` from the entries of the image index at `uri`, returning a
+/// reference to it by digest.
+fn platform_image(
+    uri: &str,
+    manifests: Vec<PlatformDescriptor>,
+    platform: Option<&DockerArchitecture>,
+) -> Result<String> {
+    let platform = platform.context(error::PlatformRequiredSnafu { uri })?;
+    let digest = manifests
+        .into_iter()
+        .find(|manifest| &manifest.architecture == platform)
+        .context(error::PlatformNotFoundSnafu {
+            uri,
+            platform: platform.clone(),
+        })?
+        .digest;
+    let (repository, _) = split_reference(uri);
+    Ok(format!("{repository}@{digest}"))
+}
+
+/// Split an image reference into its repository and tag, if it has one.
+fn split_reference(uri: &str) -> (&str, Option<&str>) {
+    let name = uri.split_once('@').map_or(uri, |(name, _)| name);
+    match name.rsplit_once(':') {
+        Some((repository, tag)) if !tag.contains('/') => (repository, Some(tag)),
+        _ => (name, None),
+    }
+}
+
+/// An image platform architecture, named as in the `architecture` field of an OCI platform
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 pub enum DockerArchitecture {
     Amd64,
     Arm64,
+    /// 32-bit ARMv7
+    Arm,
+    #[serde(rename = "386")]
+    I386,
+    Ppc64le,
+    S390x,
+    Riscv64,
+}
+
+impl DockerArchitecture {
+    /// The OCI platform variant, for architectures that need one to identify the instruction set
+    pub fn variant(&self) -> Option<&'static str> {
+        match self {
+            Self::Arm => Some("v7"),
+            Self::Amd64
+            | Self::Arm64
+            | Self::I386
+            | Self::Ppc64le
+            | Self::S390x
+            | Self::Riscv64 => None,
+        }
+    }
+}
+
+/// The platform an image in a multi-platform manifest runs on, as described by the `platform` of
+/// its entry. Runtimes pick an entry by all of these fields, so images for several ARM revisions or
+/// Windows builds of one architecture need a `variant` or `os_version` to tell them apart.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlatformSpec {
+    pub arch: DockerArchitecture,
+    /// The operating system, e.g. `linux` or `windows`
+    pub os: String,
+    /// The CPU variant, e.g. `v8` for arm64
+    pub variant: Option<String>,
+    /// The operating system version, e.g. `10.0.20348.2700` for Windows
+    pub os_version: Option<String>,
+}
+
+impl PlatformSpec {
+    /// A `linux` platform for `arch`, with the variant the architecture needs, if any.
+    pub fn new(arch: DockerArchitecture) -> Self {
+        Self {
+            variant: arch.variant().map(str::to_string),
+            arch,
+            os: "linux".to_string(),
+            os_version: None,
+        }
+    }
+
+    pub fn with_os(mut self, os: impl Into<String>) -> Self {
+        self.os = os.into();
+        self
+    }
+
+    pub fn with_variant(mut self, variant: impl Into<String>) -> Self {
+        self.variant = Some(variant.into());
+        self
+    }
+
+    pub fn with_os_version(mut self, os_version: impl Into<String>) -> Self {
+        self.os_version = Some(os_version.into());
+        self
+    }
+
+    /// Whether this is the platform [`PlatformSpec::new`] gives for its architecture, which the
+    /// image tools also infer from the image config of a Linux image.
+    pub(crate) fn is_default(&self) -> bool {
+        *self == Self::new(self.arch.clone())
+    }
+
+    /// The OCI platform object for an image index entry
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        let mut platform = serde_json::json!({
+            "architecture": self.arch.to_string(),
+            "os": self.os,
+        });
+        if let Some(variant) = &self.variant {
+            platform["variant"] = variant.as_str().into();
+        }
+        if let Some(os_version) = &self.os_version {
+            platform["os.version"] = os_version.as_str().into();
+        }
+        platform
+    }
+}
+
+impl From<DockerArchitecture> for PlatformSpec {
+    fn from(arch: DockerArchitecture) -> Self {
+        Self::new(arch)
+    }
+}
+
+/// Formats as `os/architecture[/variant]`, e.g. `linux/arm/v7`.
+impl Display for PlatformSpec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.os, self.arch)?;
+        if let Some(variant) = &self.variant {
+            write!(f, "/{variant}")?;
+        }
+        Ok(())
+    }
 }
 
 impl TryFrom<&str> for DockerArchitecture {
     type Error = error::Error;
 
+    /// Accepts both the Go spelling used in OCI platforms, e.g. `386`, and the kernel spelling
+    /// reported by `uname -m`, e.g. `i686`.
     fn try_from(value: &str) -> std::result::Result<Self, Self::Error> {
         match value {
             "x86_64" | "amd64" => Ok(DockerArchitecture::Amd64),
             "aarch64" | "arm64" => Ok(DockerArchitecture::Arm64),
+            "armv7l" | "armv7" | "arm" => Ok(DockerArchitecture::Arm),
+            "i386" | "i686" | "386" => Ok(DockerArchitecture::I386),
+            "ppc64le" => Ok(DockerArchitecture::Ppc64le),
+            "s390x" => Ok(DockerArchitecture::S390x),
+            "riscv64" => Ok(DockerArchitecture::Riscv64),
             _ => Err(error::Error::InvalidArchitecture {
                 value: value.to_string(),
             }),
@@ -135,29 +983,85 @@ impl Display for DockerArchitecture {
         f.write_str(match self {
             Self::Amd64 => "amd64",
             Self::Arm64 => "arm64",
+            Self::Arm => "arm",
+            Self::I386 => "386",
+            Self::Ppc64le => "ppc64le",
+            Self::S390x => "s390x",
+            Self::Riscv64 => "riscv64",
         })
     }
 }
 
+/// What a push left in the registry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushResult {
+    /// The digest of the pushed manifest or manifest list
+    pub digest: String,
+    /// The compressed size of the pushed image's layers. For a manifest list, the layers of every
+    /// platform image are counted.
+    pub size_bytes: u64,
+}
+
+/// The container image tool backing an [`ImageTool`]
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ToolInfo {
+    /// The kind of tool, e.g. `crane`
+    pub backend: String,
+    /// The binary that is run
+    pub path: PathBuf,
+    /// The version reported by the binary, if it could be determined
+    pub version: Option<String>,
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "snake_case")]
 struct ImageView {
     config: ConfigView,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
 #[serde(rename_all = "PascalCase")]
 pub struct ConfigView {
+    /// Image labels. The field is required, but images without labels may set it to `null`.
+    #[serde(deserialize_with = "null_as_default")]
     pub labels: HashMap<String, String>,
+    /// Environment variables in `NAME=value` form
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entrypoint: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cmd: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+}
+
+impl ConfigView {
+    /// Parse the `config` section of an image config document. Unknown fields are ignored.
+    pub(crate) fn from_image_config(bytes: &[u8]) -> Result<Self> {
+        let image_view: ImageView = document::config_from_slice(bytes)?;
+        Ok(image_view.config)
+    }
+}
+
+fn null_as_default<'de, D, T>(deserializer: D) -> std::result::Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
 }
 
 pub type Result<T> = std::result::Result<T, error::Error>;
 
 pub mod error {
     use std::path::PathBuf;
+    use std::time::Duration;
 
     use snafu::Snafu;
 
+    use crate::DockerArchitecture;
+
     #[derive(Snafu, Debug)]
     #[snafu(visibility(pub(super)))]
     pub enum Error {
@@ -167,38 +1071,143 @@ pub mod error {
         #[snafu(display("Failed to read archive: {source}"))]
         ArchiveRead { source: std::io::Error },
 
+        #[snafu(display(
+            "OCI archive '{}' does not hold the manifest of exactly one image",
+            path.display()
+        ))]
+        ArchiveManifestMissing { path: PathBuf },
+
+        #[snafu(display("Failed to deserialize archive index: {source}"))]
+        ArchiveIndexDeserialize { source: serde_json::Error },
+
+        #[snafu(display("Failed to read '{}' from OCI layout: {source}", path.display()))]
+        BlobRead {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Failed to read registry client certificate file '{}': {source}", path.display()))]
+        ClientCertRead {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display(
+            "Invalid registry client certificate entry '{entry}', expected 'host=cert:key'"
+        ))]
+        ClientCertSpec { entry: String },
+
         #[snafu(display("Failed to execute image tool, {message}: {source}"))]
         CommandFailed {
             message: String,
             source: std::io::Error,
         },
 
-        #[snafu(display("Failed to deserialize image config: {source}"))]
-        ConfigDeserialize { source: serde_json::Error },
+        #[snafu(display("Failed to deserialize image config ({schema}): {source}\n{snippet}"))]
+        ConfigDeserialize {
+            schema: String,
+            snippet: String,
+            source: serde_json::Error,
+        },
 
         #[snafu(display("Failed to create temporary directory for crane push: {source}"))]
         CraneTemp { source: std::io::Error },
 
+        #[snafu(display("'{}' has digest {actual}, expected {expected}", path.display()))]
+        DigestMismatch {
+            path: PathBuf,
+            expected: String,
+            actual: String,
+        },
+
         #[snafu(display("Failed to create temporary directory for docker save: {source}"))]
         DockerTemp { source: std::io::Error },
 
+        #[snafu(display("Annotation keys for the manifest list at {uri} must not be empty"))]
+        EmptyAnnotationKey { uri: String },
+
+        #[snafu(display(
+            "No recorded response for {operation} of {uri} in '{}'",
+            dir.display()
+        ))]
+        FixtureMissing {
+            operation: String,
+            uri: String,
+            dir: PathBuf,
+        },
+
+        #[snafu(display("Failed to read registry fixture '{}': {source}", path.display()))]
+        FixtureRead {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Failed to write registry fixture '{}': {source}", path.display()))]
+        FixtureWrite {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display(
+            "Platform image '{image}' is not in the same registry as the image index '{uri}'"
+        ))]
+        IncompatibleRegistry { image: String, uri: String },
+
+        #[snafu(display("Failed to deserialize image inspect output for {uri}: {source}"))]
+        InspectDeserialize {
+            uri: String,
+            source: serde_json::Error,
+        },
+
+        #[snafu(display("Image inspect output for {uri} describes no image"))]
+        InspectEmpty { uri: String },
+
         #[snafu(display("invalid architecture '{value}'"))]
         InvalidArchitecture { value: String },
 
-        #[snafu(display("Failed to deserialize image manifest: {source}"))]
-        ManifestDeserialize { source: serde_json::Error },
+        #[snafu(display(
+            "Image tool returned '{digest}' as the digest of {uri}, expected 'sha256:<hex>'"
+        ))]
+        InvalidDigest { uri: String, digest: String },
+
+        #[snafu(display("Manifest is neither an image manifest nor an image index"))]
+        InvalidManifest,
+
+        #[snafu(display("Attestation manifest '{digest}' does not reference a platform image"))]
+        MissingAttestationReference { digest: String },
+
+        #[snafu(display(
+            "No platform image for architecture '{architecture}' to attach attestation to"
+        ))]
+        MissingAttestationSubject { architecture: DockerArchitecture },
+
+        #[snafu(display("Image index has no entry for manifest '{digest}'"))]
+        MissingIndexEntry { digest: String },
+
+        #[snafu(display("Mock image tool has no image to {operation} at {uri}"))]
+        MockImageMissing { operation: String, uri: String },
+
+        #[snafu(display("Failed to deserialize image manifest ({schema}): {source}\n{snippet}"))]
+        ManifestDeserialize {
+            schema: String,
+            snippet: String,
+            source: serde_json::Error,
+        },
 
         #[snafu(display("Failed to canonicalize image manifest: {source}"))]
         ManifestCanonicalize { source: serde_json::Error },
 
+        #[snafu(display("Failed to serialize image manifest: {source}"))]
+        ManifestSerialize { source: serde_json::Error },
+
         #[snafu(display("No digest returned by `docker load`"))]
         NoDigest,
 
         #[snafu(display(
-            "Unable to find any supported container image tool, please install docker or crane: {}",
-            source
+            "Unable to find any supported container image tool, please install crane or podman \
+            (looked for {tools})"
         ))]
-        NoneFound { source: which::Error },
+        NoneFound { tools: String },
 
         #[snafu(display(
             "Unable to find a container image tool by name '{}' in current environment",
@@ -206,17 +1215,1118 @@ pub mod error {
         ))]
         NotFound { name: String, source: which::Error },
 
-        #[snafu(display("Failed to run operation with image tool: {message}\n command: {} {}", program.display(), args.join(" ")))]
+        #[snafu(display("Refusing to run '{operation}' because network access is disabled"))]
+        Offline { operation: String },
+
+        #[snafu(display("Failed to run operation with image tool: {message}\n command: {} {}{}", program.display(), args.join(" "), describe_output("stderr", stderr)))]
         OperationFailed {
             message: String,
             program: PathBuf,
             args: Vec<String>,
+            /// The tool's exit code, or `None` if it was killed by a signal
+            exit_code: Option<i32>,
+            /// The end of what the tool wrote to stderr, whose last line usually says why it failed
+            stderr: String,
+        },
+
+        #[snafu(display("{tool} does not support operation: {operation}"))]
+        OperationUnsupported { tool: String, operation: String },
+
+        #[snafu(display("Image index at {uri} has no image for platform '{platform}'"))]
+        PlatformNotFound {
+            uri: String,
+            platform: DockerArchitecture,
+        },
+
+        #[snafu(display("{uri} is a multi-arch image, a platform must be specified"))]
+        PlatformRequired { uri: String },
+
+        #[snafu(display("Failed to read registry auth file '{}': {source}", path.display()))]
+        RegistryAuthFile {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Registry auth file '{}' is not a docker config.json: {source}", path.display()))]
+        RegistryAuthFileParse {
+            path: PathBuf,
+            source: serde_json::Error,
         },
 
+        #[snafu(display(
+            "Invalid credentials for registry '{registry}', expected 'host=username:password'"
+        ))]
+        RegistryAuthSpec { registry: String },
+
+        #[snafu(display("Failed to serialize registry credentials: {source}"))]
+        RegistryAuthSerialize { source: serde_json::Error },
+
+        #[snafu(display("Failed to write registry credentials: {source}"))]
+        RegistryAuthWrite { source: std::io::Error },
+
+        #[snafu(display(
+            "Only one of TWOLITER_REGISTRY_RECORD and TWOLITER_REGISTRY_REPLAY may be set"
+        ))]
+        RegistryFixturesConflict,
+
         #[snafu(display("Failed to parse kit filename: {}", source))]
         Regex { source: regex::Error },
 
+        #[snafu(display(
+            "Invalid registry mirror '{rule}', expected 'registry=mirror' such as \
+            'public.ecr.aws=mirror.internal/public.ecr.aws'"
+        ))]
+        RegistryMirrorRule { rule: String },
+
+        #[snafu(display("Cannot {operation} while replaying recorded registry responses"))]
+        ReplayUnsupported { operation: String },
+
+        #[snafu(display(
+            "Registry cannot delete the tag {uri}, and deleting its image {digest} would also \
+            delete the tags {}",
+            tags.join(", ")
+        ))]
+        TagDeleteUnsupported {
+            uri: String,
+            digest: String,
+            tags: Vec<String>,
+        },
+
+        #[snafu(display("Image tool operation timed out after {elapsed:?}: {message}"))]
+        Timeout { message: String, elapsed: Duration },
+
+        #[snafu(display("Container image tool '{name}' not found at {}", path.display()))]
+        ToolPathNotFound { name: String, path: PathBuf },
+
         #[snafu(display("Unsupported container image tool '{}'", name))]
         Unsupported { name: String },
+
+        #[snafu(display("Invalid URI rewrite pattern '{pattern}': {source}"))]
+        UriRewritePattern {
+            pattern: String,
+            source: regex::Error,
+        },
+
+        #[snafu(display("Invalid URI rewrite rule '{rule}', expected 'pattern=replacement'"))]
+        UriRewriteRule { rule: String },
+    }
+
+    impl Error {
+        /// The exit code of the image tool, if this error is an image tool command that failed.
+        pub fn exit_code(&self) -> Option<i32> {
+            match self {
+                Error::OperationFailed { exit_code, .. } => *exit_code,
+                _ => None,
+            }
+        }
+
+        /// The last line the image tool wrote to stderr, if this error is an image tool command
+        /// that failed and wrote to stderr.
+        pub fn stderr_line(&self) -> Option<&str> {
+            match self {
+                Error::OperationFailed { stderr, .. } => stderr
+                    .lines()
+                    .rev()
+                    .map(str::trim)
+                    .find(|line| !line.is_empty()),
+                _ => None,
+            }
+        }
+
+        /// The end of what the image tool wrote to stderr, if this error is an image tool
+        /// command that failed.
+        pub fn stderr(&self) -> Option<&str> {
+            match self {
+                Error::OperationFailed { stderr, .. } => Some(stderr),
+                _ => None,
+            }
+        }
+    }
+
+    /// Output a failed command wrote to the stream `name`, indented below the command, or nothing
+    /// if it wrote none.
+    pub(crate) fn describe_output(name: &str, output: &str) -> String {
+        let output = output.trim_end();
+        if output.is_empty() {
+            return String::new();
+        }
+        format!("\n {name}:\n   {}", output.replace('\n', "\n   "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use tempfile::TempDir;
+
+    /// An in-memory registry that counts uploads.
+    #[derive(Debug, Default)]
+    struct FakeRegistry {
+        tags: Mutex<HashMap<String, String>>,
+        manifests: Mutex<HashMap<String, String>>,
+        configs: HashMap<String, String>,
+        pushes: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl ImageToolImpl for FakeRegistry {
+        async fn tool_info(&self) -> Result<ToolInfo> {
+            error::OperationUnsupportedSnafu {
+                tool: "fake registry",
+                operation: "report tool info",
+            }
+            .fail()
+        }
+
+        async fn pull_oci_image(&self, path: &Path, uri: &str) -> Result<()> {
+            self.get_digest(uri).await?;
+            std::fs::write(path, uri).unwrap();
+            Ok(())
+        }
+
+        async fn get_config(&self, uri: &str) -> Result<ConfigView> {
+            ConfigView::from_image_config(self.configs[uri].as_bytes())
+        }
+
+        async fn get_manifest(&self, uri: &str) -> Result<Vec<u8>> {
+            let manifests = self.manifests.lock().unwrap();
+            let manifest = manifests.get(uri).ok_or(error::Error::NoDigest)?;
+            Ok(manifest.clone().into_bytes())
+        }
+
+        async fn image_exists(&self, uri: &str) -> Result<bool> {
+            Ok(self.get_digest(uri).await.is_ok())
+        }
+
+        async fn delete_tag(&self, uri: &str) -> Result<()> {
+            self.tags.lock().unwrap().remove(uri);
+            Ok(())
+        }
+
+        async fn list_tags(&self, repo: &str) -> Result<Vec<String>> {
+            let mut tags: Vec<_> = self
+                .tags
+                .lock()
+                .unwrap()
+                .keys()
+                .filter_map(|uri| match split_reference(uri) {
+                    (repository, Some(tag)) if repository == repo => Some(tag.to_string()),
+                    _ => None,
+                })
+                .collect();
+            tags.sort();
+            Ok(tags)
+        }
+
+        async fn get_digest(&self, uri: &str) -> Result<String> {
+            let tags = self.tags.lock().unwrap();
+            let digest = match uri.split_once('@') {
+                Some((_, digest)) => tags.values().find(|d| *d == digest).cloned(),
+                None => tags.get(uri).cloned(),
+            };
+            digest.ok_or(error::Error::NoDigest)
+        }
+
+        async fn tag_image(&self, uri: &str, tag: &str) -> Result<()> {
+            let (repository, digest) = uri.split_once('@').unwrap();
+            self.tags
+                .lock()
+                .unwrap()
+                .insert(format!("{repository}:{tag}"), digest.to_string());
+            Ok(())
+        }
+
+        async fn push_oci_archive(&self, path: &Path, uri: &str) -> Result<()> {
+            let digest = manifest::archive_manifest_digest(path)?.unwrap();
+            self.tags.lock().unwrap().insert(uri.to_string(), digest);
+            self.pushes.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn copy_image_with_annotations(
+            &self,
+            src: &str,
+            dst: &str,
+            annotations: &HashMap<String, String>,
+        ) -> Result<()> {
+            let mut manifest: serde_json::Value =
+                serde_json::from_slice(&self.get_manifest(src).await?).unwrap();
+            manifest::add_annotations(&mut manifest, annotations)?;
+            self.manifests
+                .lock()
+                .unwrap()
+                .insert(dst.to_string(), manifest.to_string());
+            Ok(())
+        }
+
+        async fn push_multi_platform_manifest(
+            &self,
+            platform_images: Vec<(PlatformSpec, String)>,
+            uri: &str,
+            media_type: ManifestMediaType,
+            annotations: &HashMap<String, String>,
+        ) -> Result<()> {
+            let mut manifests = Vec::new();
+            for (platform, image) in &platform_images {
+                manifests.push(serde_json::json!({
+                    "digest": self.get_digest(image).await?,
+                    "platform": platform.to_json(),
+                }));
+            }
+            let mut index = serde_json::json!({
+                "schemaVersion": 2,
+                "mediaType": media_type.as_str(),
+                "manifests": manifests,
+            });
+            if !annotations.is_empty() {
+                manifest::add_annotations(&mut index, annotations)?;
+            }
+            self.manifests
+                .lock()
+                .unwrap()
+                .insert(uri.to_string(), index.to_string());
+            Ok(())
+        }
+
+        async fn push_multi_platform_manifest_with_attestations(
+            &self,
+            _: Vec<(PlatformSpec, String)>,
+            _: Vec<AttestationManifest>,
+            _: &str,
+        ) -> Result<()> {
+            error::OperationUnsupportedSnafu {
+                tool: "fake registry",
+                operation: "push attestation manifests",
+            }
+            .fail()
+        }
+    }
+
+    fn oci_archive(dir: &Path) -> std::path::PathBuf {
+        let index = br#"{"schemaVersion":2,"manifests":[{"digest":"sha256:abcd","size":1}]}"#;
+        let path = dir.join("kit.tar");
+        let mut builder = tar::Builder::new(std::fs::File::create(&path).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(index.len() as u64);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "index.json", &index[..])
+            .unwrap();
+        builder.finish().unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn skip_existing_push() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = oci_archive(temp_dir.path());
+        let registry = FakeRegistry::default();
+        let pushes = registry.pushes.clone();
+        let image_tool = ImageTool::new(Box::new(registry)).skip_existing(true);
+
+        image_tool
+            .push_oci_archive(&archive, "example.com/kit:v1")
+            .await
+            .unwrap();
+        image_tool
+            .push_oci_archive(&archive, "example.com/kit:v1")
+            .await
+            .unwrap();
+        image_tool
+            .push_oci_archive(&archive, "example.com/kit:v2")
+            .await
+            .unwrap();
+
+        assert_eq!(pushes.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            image_tool.get_digest("example.com/kit:v2").await.unwrap(),
+            "sha256:abcd"
+        );
+    }
+
+    #[tokio::test]
+    async fn push_all_platforms_builds_manifest_list() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = oci_archive(temp_dir.path());
+        let registry = FakeRegistry::default();
+        let pushes = registry.pushes.clone();
+        let image_tool = ImageTool::new(Box::new(registry)).push_concurrency(2);
+
+        image_tool
+            .push_all_platforms(
+                vec![
+                    (DockerArchitecture::Amd64, archive.clone()),
+                    (DockerArchitecture::Arm64, archive.clone()),
+                    (DockerArchitecture::Arm, archive.clone()),
+                ],
+                "example.com/kit:v1",
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(pushes.load(Ordering::SeqCst), 3);
+        let ManifestView::Index { manifests, .. } = image_tool
+            .get_manifest_parsed("example.com/kit:v1")
+            .await
+            .unwrap()
+        else {
+            panic!("expected an image index");
+        };
+        let architectures: Vec<_> = manifests.into_iter().map(|m| m.architecture).collect();
+        assert_eq!(
+            architectures,
+            [
+                DockerArchitecture::Amd64,
+                DockerArchitecture::Arm64,
+                DockerArchitecture::Arm
+            ]
+        );
+        assert_eq!(
+            image_tool
+                .get_digest("example.com/kit:v1-arm64")
+                .await
+                .unwrap(),
+            "sha256:abcd"
+        );
+    }
+
+    #[tokio::test]
+    async fn push_all_platforms_fails_without_manifest_list() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = oci_archive(temp_dir.path());
+        let registry = FakeRegistry::default();
+        let pushes = registry.pushes.clone();
+        let image_tool = ImageTool::new(Box::new(registry));
+
+        let err = image_tool
+            .push_all_platforms(
+                vec![
+                    (DockerArchitecture::Amd64, archive),
+                    (
+                        DockerArchitecture::Arm64,
+                        temp_dir.path().join("missing.tar"),
+                    ),
+                ],
+                "example.com/kit:v1",
+            )
+            .await;
+
+        assert!(err.is_err());
+        assert_eq!(pushes.load(Ordering::SeqCst), 1);
+        assert!(image_tool.get_manifest("example.com/kit:v1").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn pull_into_dir_names_archive_by_digest() {
+        let temp_dir = TempDir::new().unwrap();
+        let registry = FakeRegistry::default();
+        registry
+            .tags
+            .lock()
+            .unwrap()
+            .insert("example.com/kit:v1".to_string(), "sha256:abcd".to_string());
+        let image_tool = ImageTool::new(Box::new(registry));
+
+        let path = image_tool
+            .pull_oci_image_into_dir(temp_dir.path(), "example.com/kit:v1")
+            .await
+            .unwrap();
+
+        assert_eq!(path, temp_dir.path().join("sha256-abcd"));
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "example.com/kit@sha256:abcd"
+        );
+    }
+
+    #[tokio::test]
+    async fn multi_platform_manifest_media_type() {
+        let registry = FakeRegistry::default();
+        registry.tags.lock().unwrap().extend([
+            (
+                "example.com/kit:v1-amd64".to_string(),
+                "sha256:aaaa".to_string(),
+            ),
+            (
+                "example.com/kit:v1-arm64".to_string(),
+                "sha256:bbbb".to_string(),
+            ),
+        ]);
+        let image_tool = ImageTool::new(Box::new(registry));
+        let platform_images = vec![
+            (
+                DockerArchitecture::Amd64.into(),
+                "example.com/kit:v1-amd64".to_string(),
+            ),
+            (
+                DockerArchitecture::Arm64.into(),
+                "example.com/kit:v1-arm64".to_string(),
+            ),
+        ];
+
+        for (media_type, expected) in [
+            (ManifestMediaType::OciIndex, OCI_INDEX_MEDIA_TYPE),
+            (
+                ManifestMediaType::DockerManifestList,
+                DOCKER_MANIFEST_LIST_MEDIA_TYPE,
+            ),
+        ] {
+            image_tool
+                .push_multi_platform_manifest(
+                    platform_images.clone(),
+                    "example.com/kit:v1",
+                    media_type,
+                )
+                .await
+                .unwrap();
+            let ManifestView::Index {
+                media_type,
+                manifests,
+                ..
+            } = image_tool
+                .get_manifest_parsed("example.com/kit:v1")
+                .await
+                .unwrap()
+            else {
+                panic!("expected an image index");
+            };
+            assert_eq!(media_type.as_deref(), Some(expected));
+            assert_eq!(manifests.len(), 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn copy_image_with_annotations() {
+        let image = r#"{
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": { "digest": "sha256:cccc", "size": 10 },
+            "layers": [],
+            "annotations": { "org.opencontainers.image.version": "1.0.0" }
+        }"#;
+        let registry = FakeRegistry {
+            manifests: Mutex::new(HashMap::from([(
+                "public.example.com/kit:v1".to_string(),
+                image.to_string(),
+            )])),
+            ..Default::default()
+        };
+        let image_tool = ImageTool::new(Box::new(registry));
+
+        let annotations =
+            HashMap::from([("com.example.provenance".to_string(), "mirrored".to_string())]);
+        image_tool
+            .copy_image_with_annotations(
+                "public.example.com/kit:v1",
+                "internal.example.com/kit:v1",
+                &annotations,
+            )
+            .await
+            .unwrap();
+
+        let ManifestView::Image { annotations, .. } = image_tool
+            .get_manifest_parsed("internal.example.com/kit:v1")
+            .await
+            .unwrap()
+        else {
+            panic!("expected an image manifest");
+        };
+        assert_eq!(
+            annotations,
+            HashMap::from([
+                (
+                    "org.opencontainers.image.version".to_string(),
+                    "1.0.0".to_string()
+                ),
+                ("com.example.provenance".to_string(), "mirrored".to_string()),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn multi_platform_manifest_across_repositories() {
+        let registry = FakeRegistry::default();
+        registry.tags.lock().unwrap().extend([
+            (
+                "example.com/kit/platforms:v1-amd64".to_string(),
+                "sha256:aaaa".to_string(),
+            ),
+            (
+                "example.com/kit/platforms:v1-arm64".to_string(),
+                "sha256:bbbb".to_string(),
+            ),
+        ]);
+        let image_tool = ImageTool::new(Box::new(registry));
+        let platform_images = vec![
+            (
+                DockerArchitecture::Amd64.into(),
+                "example.com/kit/platforms:v1-amd64".to_string(),
+            ),
+            (
+                DockerArchitecture::Arm64.into(),
+                "example.com/kit/platforms:v1-arm64".to_string(),
+            ),
+        ];
+
+        image_tool
+            .push_multi_platform_manifest(
+                platform_images.clone(),
+                "example.com/kit:v1",
+                ManifestMediaType::default(),
+            )
+            .await
+            .unwrap();
+        let ManifestView::Index { manifests, .. } = image_tool
+            .get_manifest_parsed("example.com/kit:v1")
+            .await
+            .unwrap()
+        else {
+            panic!("expected an image index");
+        };
+        let digests: Vec<_> = manifests
+            .iter()
+            .map(|manifest| (manifest.architecture.clone(), manifest.digest.as_str()))
+            .collect();
+        assert_eq!(
+            digests,
+            [
+                (DockerArchitecture::Amd64, "sha256:aaaa"),
+                (DockerArchitecture::Arm64, "sha256:bbbb"),
+            ]
+        );
+
+        let err = image_tool
+            .push_multi_platform_manifest(
+                platform_images,
+                "mirror.example.com/kit:v1",
+                ManifestMediaType::default(),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, error::Error::IncompatibleRegistry { .. }));
+    }
+
+    #[test]
+    fn config_with_unknown_fields() {
+        let config = br#"{
+            "architecture": "amd64",
+            "os": "linux",
+            "config": {
+                "Labels": { "org.opencontainers.image.version": "1.0.0" },
+                "SomeFutureField": { "nested": [1, 2, 3] }
+            },
+            "rootfs": { "type": "layers", "diff_ids": [] },
+            "someFutureTopLevelField": true
+        }"#;
+        let config = ConfigView::from_image_config(config).unwrap();
+        assert_eq!(
+            config.labels["org.opencontainers.image.version"],
+            "1.0.0".to_string()
+        );
+
+        let config = ConfigView::from_image_config(br#"{"config": {"Labels": null}}"#).unwrap();
+        assert!(config.labels.is_empty());
+    }
+
+    #[test]
+    fn config_without_labels() {
+        let config = br#"{
+            "architecture": "amd64",
+            "os": "linux",
+            "config": { "Env": ["PATH=/usr/bin:/bin"] },
+            "rootfs": { "type": "layers", "diff_ids": [] }
+        }"#;
+        assert!(ConfigView::from_image_config(config).is_err());
+    }
+
+    #[test]
+    fn config_runtime_fields() {
+        let config = br#"{
+            "created": "2024-06-01T00:00:00Z",
+            "architecture": "arm64",
+            "os": "linux",
+            "config": {
+                "Entrypoint": ["/usr/bin/kit-init"],
+                "Cmd": ["--serve"],
+                "WorkingDir": "/kit",
+                "Labels": { "org.bottlerocket.kit.name": "core-kit" }
+            },
+            "rootfs": {
+                "type": "layers",
+                "diff_ids": ["sha256:0a1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f9"]
+            },
+            "history": [{ "created": "2024-06-01T00:00:00Z", "created_by": "COPY . /kit" }]
+        }"#;
+        let config = ConfigView::from_image_config(config).unwrap();
+        assert_eq!(config.labels["org.bottlerocket.kit.name"], "core-kit");
+        assert_eq!(config.env, None);
+        assert_eq!(
+            config.entrypoint,
+            Some(vec!["/usr/bin/kit-init".to_string()])
+        );
+        assert_eq!(config.cmd, Some(vec!["--serve".to_string()]));
+        assert_eq!(config.working_dir.as_deref(), Some("/kit"));
+
+        let config = ConfigView::from_image_config(
+            br#"{"config": {"Env": ["PATH=/usr/bin:/bin"], "Labels": {}}}"#,
+        )
+        .unwrap();
+        assert_eq!(config.env, Some(vec!["PATH=/usr/bin:/bin".to_string()]));
+        assert_eq!(config.cmd, None);
+    }
+
+    #[test]
+    fn malformed_config() {
+        let config = br#"{"mediaType": "application/vnd.example+json", "config": {"Labels": ["#;
+        let err = ConfigView::from_image_config(config).unwrap_err();
+        assert!(matches!(err, error::Error::ConfigDeserialize { .. }));
+        let message = err.to_string();
+        assert!(message.contains("not valid JSON"), "{message}");
+        assert!(message.contains(r#""Labels": ["#), "{message}");
+
+        let config = br#"{"schemaVersion": 3, "config": {"Labels": "oops"}}"#;
+        let message = ConfigView::from_image_config(config)
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("schema version 3"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn download_size_of_platform_image() {
+        let index = r#"{
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": [
+                {
+                    "digest": "sha256:aaaa",
+                    "platform": { "architecture": "amd64", "os": "linux" }
+                },
+                {
+                    "digest": "sha256:bbbb",
+                    "platform": { "architecture": "arm64", "os": "linux" }
+                }
+            ]
+        }"#;
+        let image = r#"{
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": { "digest": "sha256:cccc", "size": 10 },
+            "layers": [
+                { "digest": "sha256:dddd", "size": 1000 },
+                { "digest": "sha256:eeee", "size": 234 }
+            ]
+        }"#;
+        let registry = FakeRegistry {
+            manifests: Mutex::new(HashMap::from([
+                ("example.com/kit:v1".to_string(), index.to_string()),
+                ("example.com/kit@sha256:bbbb".to_string(), image.to_string()),
+            ])),
+            ..Default::default()
+        };
+        let image_tool = ImageTool::new(Box::new(registry));
+
+        let size = image_tool
+            .get_download_size("example.com/kit:v1", Some(&DockerArchitecture::Arm64))
+            .await
+            .unwrap();
+        assert_eq!(size, 1234);
+        assert!(image_tool
+            .get_download_size("example.com/kit:v1", None)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn from_path_requires_binary() {
+        let temp_dir = TempDir::new().unwrap();
+        let krane = temp_dir.path().join("krane");
+        let from_path = |tool, path: &Path| {
+            ImageTool::from_path(
+                tool,
+                path.to_path_buf(),
+                RegistryClientCerts::default(),
+                RegistryCredentials::default(),
+            )
+        };
+
+        let err = from_path("crane", &krane).unwrap_err();
+        assert!(matches!(err, error::Error::ToolPathNotFound { .. }));
+        assert!(err.to_string().contains(&krane.display().to_string()));
+
+        std::fs::write(&krane, "").unwrap();
+        assert!(from_path("crane", &krane).is_ok());
+        assert!(from_path("podman", &krane).is_ok());
+        assert!(matches!(
+            from_path("skopeo", &krane),
+            Err(error::Error::Unsupported { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn options_apply_without_environment() {
+        let temp_dir = TempDir::new().unwrap();
+        let krane = temp_dir.path().join("krane");
+        std::fs::write(&krane, "").unwrap();
+        let options = CommandOptions {
+            offline: true,
+            ..CommandOptions::default()
+        };
+        let image_tool = ImageTool::from_path_with_options(
+            "crane",
+            krane,
+            RegistryClientCerts::default(),
+            RegistryCredentials::default(),
+            options,
+        )
+        .unwrap();
+
+        // The binary is empty, so reaching it at all would fail differently.
+        let err = image_tool
+            .get_digest("example.com/kit:v1")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, error::Error::Offline { .. }), "{err}");
+    }
+
+    #[tokio::test]
+    async fn dry_run_skips_pulls() {
+        let temp_dir = TempDir::new().unwrap();
+        let krane = temp_dir.path().join("krane");
+        std::fs::write(&krane, "").unwrap();
+        let options = CommandOptions {
+            dry_run: true,
+            ..CommandOptions::default()
+        };
+        let image_tool = ImageTool::from_path_with_options(
+            "crane",
+            krane,
+            RegistryClientCerts::default(),
+            RegistryCredentials::default(),
+            options,
+        )
+        .unwrap();
+        assert!(image_tool.dry_run());
+
+        let path = temp_dir.path().join("kit.tar");
+        image_tool
+            .pull_oci_image(&path, "example.com/kit:v1")
+            .await
+            .unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn image_tool_orders() {
+        assert_eq!(image_tool_order(None).unwrap(), ["krane", "podman"]);
+        assert_eq!(image_tool_order(Some(" ")).unwrap(), ["krane", "podman"]);
+        assert_eq!(
+            image_tool_order(Some("podman, crane")).unwrap(),
+            ["podman", "crane"]
+        );
+        assert!(matches!(
+            image_tool_order(Some("podman,gcrane")),
+            Err(error::Error::Unsupported { name }) if name == "gcrane"
+        ));
+        assert!(matches!(
+            image_tool_order(Some("podman,")),
+            Err(error::Error::Unsupported { .. })
+        ));
+
+        let err = ImageTool::detect(
+            &[],
+            RegistryClientCerts::default(),
+            RegistryCredentials::default(),
+            CommandOptions::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, error::Error::NoneFound { .. }));
+    }
+
+    #[tokio::test]
+    async fn shared_between_tasks() {
+        let registry = FakeRegistry {
+            tags: Mutex::new(HashMap::from([
+                ("example.com/kit:v1".to_string(), "sha256:aaaa".to_string()),
+                ("example.com/kit:v2".to_string(), "sha256:bbbb".to_string()),
+            ])),
+            ..Default::default()
+        };
+        let image_tool = Arc::new(ImageTool::new(Box::new(registry)));
+
+        let tasks: Vec<_> = ["v1", "v2"]
+            .into_iter()
+            .map(|tag| {
+                let image_tool = image_tool.clone();
+                tokio::spawn(async move {
+                    image_tool
+                        .get_digest(&format!("example.com/kit:{tag}"))
+                        .await
+                })
+            })
+            .collect();
+        let mut digests = Vec::new();
+        for task in tasks {
+            digests.push(task.await.unwrap().unwrap());
+        }
+        assert_eq!(digests, ["sha256:aaaa", "sha256:bbbb"]);
+    }
+
+    #[tokio::test]
+    async fn detect_manifest_list() {
+        let index = r#"{
+            "mediaType": "application/vnd.docker.distribution.manifest.list.v2+json",
+            "manifests": [
+                {
+                    "digest": "sha256:aaaa",
+                    "platform": { "architecture": "amd64", "os": "linux" }
+                }
+            ]
+        }"#;
+        let image = r#"{
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": { "digest": "sha256:cccc", "size": 10 },
+            "layers": []
+        }"#;
+        let registry = FakeRegistry {
+            manifests: Mutex::new(HashMap::from([
+                ("example.com/kit:v1".to_string(), index.to_string()),
+                ("example.com/kit@sha256:aaaa".to_string(), image.to_string()),
+            ])),
+            ..Default::default()
+        };
+        let image_tool = ImageTool::new(Box::new(registry));
+
+        assert!(image_tool
+            .is_manifest_list("example.com/kit:v1")
+            .await
+            .unwrap());
+        assert!(!image_tool
+            .is_manifest_list("example.com/kit@sha256:aaaa")
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn config_of_single_arch_image() {
+        let image = r#"{
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": { "digest": "sha256:cccc", "size": 10 },
+            "layers": []
+        }"#;
+        let registry = FakeRegistry {
+            manifests: Mutex::new(HashMap::from([(
+                "example.com/kit:v1".to_string(),
+                image.to_string(),
+            )])),
+            configs: HashMap::from([(
+                "example.com/kit:v1".to_string(),
+                r#"{"config":{"Labels":{"arch":"amd64"}}}"#.to_string(),
+            )]),
+            ..Default::default()
+        };
+        let image_tool = ImageTool::new(Box::new(registry));
+
+        let config = image_tool
+            .get_config("example.com/kit:v1", None)
+            .await
+            .unwrap();
+        assert_eq!(config.labels["arch"], "amd64");
+    }
+
+    #[tokio::test]
+    async fn config_of_multi_arch_image() {
+        let index = r#"{
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": [
+                {
+                    "digest": "sha256:aaaa",
+                    "platform": { "architecture": "amd64", "os": "linux" }
+                },
+                {
+                    "digest": "sha256:bbbb",
+                    "platform": { "architecture": "arm64", "os": "linux" }
+                }
+            ]
+        }"#;
+        let registry = FakeRegistry {
+            manifests: Mutex::new(HashMap::from([(
+                "example.com/kit:v1".to_string(),
+                index.to_string(),
+            )])),
+            configs: HashMap::from([
+                (
+                    "example.com/kit@sha256:aaaa".to_string(),
+                    r#"{"config":{"Labels":{"arch":"amd64"}}}"#.to_string(),
+                ),
+                (
+                    "example.com/kit@sha256:bbbb".to_string(),
+                    r#"{"config":{"Labels":{"arch":"arm64"}}}"#.to_string(),
+                ),
+            ]),
+            ..Default::default()
+        };
+        let image_tool = ImageTool::new(Box::new(registry));
+
+        let config = image_tool
+            .get_config("example.com/kit:v1", Some(&DockerArchitecture::Arm64))
+            .await
+            .unwrap();
+        assert_eq!(config.labels["arch"], "arm64");
+
+        let err = image_tool
+            .get_config("example.com/kit:v1", None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, error::Error::PlatformRequired { .. }));
+        assert_eq!(
+            err.to_string(),
+            "example.com/kit:v1 is a multi-arch image, a platform must be specified"
+        );
+    }
+
+    #[tokio::test]
+    async fn record_and_replay_resolution() {
+        let index = r#"{
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": [
+                {
+                    "digest": "sha256:aaaa",
+                    "platform": { "architecture": "amd64", "os": "linux" }
+                }
+            ]
+        }"#;
+        let registry = FakeRegistry {
+            tags: Mutex::new(HashMap::from([(
+                "example.com/kit:v1".to_string(),
+                "sha256:cccc".to_string(),
+            )])),
+            manifests: Mutex::new(HashMap::from([(
+                "example.com/kit:v1".to_string(),
+                index.to_string(),
+            )])),
+            configs: HashMap::from([(
+                "example.com/kit@sha256:aaaa".to_string(),
+                r#"{"config":{"Labels":{"arch":"amd64"}}}"#.to_string(),
+            )]),
+            ..Default::default()
+        };
+        let fixtures = TempDir::new().unwrap();
+
+        async fn resolve(image_tool: &ImageTool, dir: &Path) -> (bool, ConfigView, PathBuf) {
+            let uri = "example.com/kit:v1";
+            let is_manifest_list = image_tool.is_manifest_list(uri).await.unwrap();
+            let config = image_tool
+                .get_config(uri, Some(&DockerArchitecture::Amd64))
+                .await
+                .unwrap();
+            let archive = image_tool.pull_oci_image_into_dir(dir, uri).await.unwrap();
+            (is_manifest_list, config, archive)
+        }
+
+        let recorded_dir = TempDir::new().unwrap();
+        let recording = ImageTool::new(Box::new(registry)).record_to(fixtures.path());
+        let recorded = resolve(&recording, recorded_dir.path()).await;
+        drop(recording);
+
+        let replayed_dir = TempDir::new().unwrap();
+        let replaying = ImageTool::from_fixtures(fixtures.path());
+        let replayed = resolve(&replaying, replayed_dir.path()).await;
+
+        assert!(replayed.0);
+        assert_eq!(replayed.0, recorded.0);
+        assert_eq!(replayed.1.labels, recorded.1.labels);
+        assert_eq!(
+            replayed.2.file_name().unwrap(),
+            recorded.2.file_name().unwrap()
+        );
+        assert_eq!(
+            std::fs::read(&replayed.2).unwrap(),
+            std::fs::read(&recorded.2).unwrap()
+        );
+
+        let err = replaying
+            .get_digest("example.com/kit:v2")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, error::Error::FixtureMissing { .. }));
+        let err = replaying
+            .push_oci_archive(&replayed.2, "example.com/kit:v2")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, error::Error::ReplayUnsupported { .. }));
+    }
+
+    #[test]
+    fn architecture_spellings() {
+        for (spellings, arch) in [
+            (["x86_64", "amd64"], DockerArchitecture::Amd64),
+            (["aarch64", "arm64"], DockerArchitecture::Arm64),
+            (["armv7l", "arm"], DockerArchitecture::Arm),
+            (["i686", "386"], DockerArchitecture::I386),
+        ] {
+            for spelling in spellings {
+                assert_eq!(DockerArchitecture::try_from(spelling).unwrap(), arch);
+            }
+            // Display emits the OCI spelling, which parses back to the same architecture.
+            assert_eq!(spellings[1], arch.to_string());
+            assert_eq!(
+                serde_json::from_str::<DockerArchitecture>(&format!("\"{arch}\"")).unwrap(),
+                arch
+            );
+        }
+        assert_eq!(DockerArchitecture::Arm.variant(), Some("v7"));
+        assert_eq!(DockerArchitecture::I386.variant(), None);
+        assert!(DockerArchitecture::try_from("mips").is_err());
+    }
+
+    #[test]
+    fn architecture_serde_round_trip() {
+        for arch in [
+            DockerArchitecture::Amd64,
+            DockerArchitecture::Arm64,
+            DockerArchitecture::Arm,
+            DockerArchitecture::I386,
+            DockerArchitecture::Ppc64le,
+            DockerArchitecture::S390x,
+            DockerArchitecture::Riscv64,
+        ] {
+            // Serialization agrees with Display, and deserializes back to the same architecture.
+            let json = serde_json::to_string(&arch).unwrap();
+            assert_eq!(json, format!("\"{arch}\""));
+            assert_eq!(
+                serde_json::from_str::<DockerArchitecture>(&json).unwrap(),
+                arch
+            );
+        }
+        assert_eq!(
+            serde_json::to_string(&DockerArchitecture::Amd64).unwrap(),
+            "\"amd64\""
+        );
+    }
+
+    #[test]
+    fn index_with_32_bit_platforms() {
+        let index = br#"{
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": [
+                { "digest": "sha256:aaaa", "platform": { "architecture": "amd64", "os": "linux" } },
+                {
+                    "digest": "sha256:bbbb",
+                    "platform": { "architecture": "arm", "os": "linux", "variant": "v7" }
+                },
+                { "digest": "sha256:cccc", "platform": { "architecture": "386", "os": "linux" } }
+            ]
+        }"#;
+        let ManifestView::Index { manifests, .. } = ManifestView::from_slice(index).unwrap() else {
+            panic!("expected an image index");
+        };
+        let architectures: Vec<_> = manifests.iter().map(|m| m.architecture.clone()).collect();
+        assert_eq!(
+            architectures,
+            [
+                DockerArchitecture::Amd64,
+                DockerArchitecture::Arm,
+                DockerArchitecture::I386
+            ]
+        );
     }
 }
@@ -0,0 +1,600 @@
+//! Typed views over image manifests and image indexes.
+//!
+//! Image indexes produced by BuildKit may carry attestation manifests (e.g. SLSA provenance)
+//! alongside the platform images. These are marked with the `vnd.docker.reference.type`
+//! annotation and use an `unknown/unknown` platform, so they are exposed separately from the
+//! platform entries.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use snafu::{ensure, OptionExt, ResultExt};
+use tar::Archive as TarArchive;
+
+use crate::{document, error, DockerArchitecture, Result};
+
+/// Annotation key BuildKit uses to mark the kind of reference an index entry is.
+pub const REFERENCE_TYPE_ANNOTATION: &str = "vnd.docker.reference.type";
+/// Annotation key BuildKit uses to point an attestation at the manifest it describes.
+pub const REFERENCE_DIGEST_ANNOTATION: &str = "vnd.docker.reference.digest";
+/// Value of [`REFERENCE_TYPE_ANNOTATION`] for attestation manifests.
+pub const ATTESTATION_MANIFEST: &str = "attestation-manifest";
+
+/// Media type of the OCI image index
+pub const OCI_INDEX_MEDIA_TYPE: &str = "application/vnd.oci.image.index.v1+json";
+/// Media type of the Docker manifest list
+pub const DOCKER_MANIFEST_LIST_MEDIA_TYPE: &str =
+    "application/vnd.docker.distribution.manifest.list.v2+json";
+
+/// The kind of manifest to produce when pushing a multi-platform image
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ManifestMediaType {
+    /// An OCI image index
+    #[default]
+    OciIndex,
+    /// A Docker manifest list, for registries that do not accept OCI image indexes
+    DockerManifestList,
+}
+
+impl ManifestMediaType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::OciIndex => OCI_INDEX_MEDIA_TYPE,
+            Self::DockerManifestList => DOCKER_MANIFEST_LIST_MEDIA_TYPE,
+        }
+    }
+}
+
+/// A parsed image manifest or image index
+#[derive(Debug, Clone, PartialEq)]
+pub enum ManifestView {
+    Image {
+        media_type: Option<String>,
+        config_digest: String,
+        layers: Vec<Descriptor>,
+        annotations: HashMap<String, String>,
+    },
+    Index {
+        media_type: Option<String>,
+        manifests: Vec<PlatformDescriptor>,
+        attestations: Vec<AttestationDescriptor>,
+        annotations: HashMap<String, String>,
+    },
+}
+
+/// A content descriptor as found in the `layers` of an image manifest
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Descriptor {
+    pub media_type: Option<String>,
+    pub digest: String,
+    pub size: u64,
+}
+
+/// A platform-specific image entry in an image index
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlatformDescriptor {
+    pub architecture: DockerArchitecture,
+    pub os: String,
+    pub variant: Option<String>,
+    pub os_version: Option<String>,
+    pub digest: String,
+}
+
+/// An attestation manifest entry in an image index
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttestationDescriptor {
+    /// Digest of the attestation manifest itself
+    pub digest: String,
+    /// Digest of the platform image manifest this attestation describes
+    pub reference_digest: String,
+}
+
+/// An attestation image to include in a multi-platform manifest, attached to the platform image
+/// of the given architecture.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttestationManifest {
+    pub architecture: DockerArchitecture,
+    pub image: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct RawManifest {
+    media_type: Option<String>,
+    config: Option<Descriptor>,
+    layers: Option<Vec<Descriptor>>,
+    manifests: Option<Vec<RawIndexEntry>>,
+    #[serde(default)]
+    annotations: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct RawIndexEntry {
+    digest: String,
+    platform: Option<RawPlatform>,
+    #[serde(default)]
+    annotations: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RawPlatform {
+    architecture: String,
+    os: String,
+    variant: Option<String>,
+    #[serde(rename = "os.version")]
+    os_version: Option<String>,
+}
+
+impl ManifestView {
+    /// Parse the bytes of an image manifest or image index
+    pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+        let raw: RawManifest = document::manifest_from_slice(bytes)?;
+
+        if let Some(entries) = raw.manifests {
+            let mut manifests = Vec::new();
+            let mut attestations = Vec::new();
+            for entry in entries {
+                if entry
+                    .annotations
+                    .get(REFERENCE_TYPE_ANNOTATION)
+                    .is_some_and(|kind| kind == ATTESTATION_MANIFEST)
+                {
+                    let reference_digest = entry
+                        .annotations
+                        .get(REFERENCE_DIGEST_ANNOTATION)
+                        .context(error::MissingAttestationReferenceSnafu {
+                            digest: &entry.digest,
+                        })?
+                        .clone();
+                    attestations.push(AttestationDescriptor {
+                        digest: entry.digest,
+                        reference_digest,
+                    });
+                    continue;
+                }
+                // Entries without a platform, or for platforms we do not build, are not
+                // meaningful to us; skip them rather than failing on indexes produced by other
+                // tools.
+                let Some(platform) = entry.platform else {
+                    log::debug!("Skipping index entry '{}' with no platform", entry.digest);
+                    continue;
+                };
+                let Ok(architecture) = DockerArchitecture::try_from(platform.architecture.as_str())
+                else {
+                    log::debug!(
+                        "Skipping index entry '{}' with unsupported architecture '{}'",
+                        entry.digest,
+                        platform.architecture
+                    );
+                    continue;
+                };
+                manifests.push(PlatformDescriptor {
+                    architecture,
+                    os: platform.os,
+                    variant: platform.variant,
+                    os_version: platform.os_version,
+                    digest: entry.digest,
+                });
+            }
+            return Ok(Self::Index {
+                media_type: raw.media_type,
+                manifests,
+                attestations,
+                annotations: raw.annotations,
+            });
+        }
+
+        let config = raw.config.context(error::InvalidManifestSnafu)?;
+        Ok(Self::Image {
+            media_type: raw.media_type,
+            config_digest: config.digest,
+            layers: raw.layers.unwrap_or_default(),
+            annotations: raw.annotations,
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct LayoutIndex {
+    manifests: Vec<Descriptor>,
+}
+
+/// Whether `path` is an unpacked OCI image layout rather than an archive of one.
+pub(crate) fn is_oci_layout(path: &Path) -> bool {
+    path.join("oci-layout").is_file()
+}
+
+/// Read the digest of the image stored in an oci archive, or an unpacked OCI image layout, from
+/// its `index.json`. Returns `None` if the archive does not hold exactly one image.
+pub(crate) fn archive_manifest_digest(path: &Path) -> Result<Option<String>> {
+    if is_oci_layout(path) {
+        let index_bytes =
+            std::fs::read(path.join("index.json")).context(error::ArchiveReadSnafu)?;
+        return layout_index_digest(&index_bytes);
+    }
+
+    let oci_file = File::open(path).context(error::ArchiveReadSnafu)?;
+    let mut oci_archive = TarArchive::new(oci_file);
+    for entry in oci_archive.entries().context(error::ArchiveReadSnafu)? {
+        let mut entry = entry.context(error::ArchiveReadSnafu)?;
+        if entry.path().context(error::ArchiveReadSnafu)? != Path::new("index.json") {
+            continue;
+        }
+        let mut index_bytes = Vec::new();
+        entry
+            .read_to_end(&mut index_bytes)
+            .context(error::ArchiveReadSnafu)?;
+        return layout_index_digest(&index_bytes);
+    }
+    Ok(None)
+}
+
+fn layout_index_digest(index_bytes: &[u8]) -> Result<Option<String>> {
+    let index: LayoutIndex =
+        serde_json::from_slice(index_bytes).context(error::ArchiveIndexDeserializeSnafu)?;
+    Ok(match index.manifests.as_slice() {
+        [manifest] => Some(manifest.digest.clone()),
+        _ => None,
+    })
+}
+
+/// Check that the OCI image layout at `layout` holds the image `expected`, and that every blob
+/// the image refers to is present with the content its digest names. For an image index, every
+/// image it lists is checked as well. This turns a layout truncated by a dropped connection into
+/// an error when it is pulled, rather than when it is unpacked.
+pub(crate) fn verify_oci_layout(layout: &Path, expected: &str) -> Result<()> {
+    let index_path = layout.join("index.json");
+    let index_bytes = std::fs::read(&index_path).context(error::BlobReadSnafu {
+        path: index_path.clone(),
+    })?;
+    let actual = layout_index_digest(&index_bytes)?.unwrap_or_default();
+    ensure!(
+        actual == expected,
+        error::DigestMismatchSnafu {
+            path: index_path,
+            expected,
+            actual,
+        }
+    );
+    verify_manifest(layout, expected)
+}
+
+/// Verify the manifest or index with digest `digest`, then everything it refers to.
+fn verify_manifest(layout: &Path, digest: &str) -> Result<()> {
+    let path = verify_blob(layout, digest)?;
+    let bytes = std::fs::read(&path).context(error::BlobReadSnafu { path })?;
+    let manifest: Value = document::manifest_from_slice(&bytes)?;
+    let digests = |key: &str| -> Vec<String> {
+        let descriptors = match &manifest[key] {
+            Value::Array(descriptors) => descriptors.iter().collect(),
+            Value::Object(_) => vec![&manifest[key]],
+            _ => Vec::new(),
+        };
+        descriptors
+            .into_iter()
+            .filter_map(|d| d["digest"].as_str().map(str::to_string))
+            .collect()
+    };
+    for child in digests("manifests") {
+        verify_manifest(layout, &child)?;
+    }
+    for blob in digests("config").into_iter().chain(digests("layers")) {
+        verify_blob(layout, &blob)?;
+    }
+    Ok(())
+}
+
+/// Hash the blob with digest `digest` in `layout`, failing unless its content matches the
+/// digest. Returns the path of the blob.
+fn verify_blob(layout: &Path, digest: &str) -> Result<PathBuf> {
+    let hex = digest
+        .strip_prefix("sha256:")
+        .filter(|hex| !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .context(error::InvalidDigestSnafu {
+            uri: layout.display().to_string(),
+            digest,
+        })?;
+    let path = layout.join("blobs").join("sha256").join(hex);
+    let mut file = File::open(&path).context(error::BlobReadSnafu { path: &path })?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).context(error::BlobReadSnafu { path: &path })?;
+    let actual = format!("sha256:{:x}", hasher.finalize());
+    ensure!(
+        actual == digest,
+        error::DigestMismatchSnafu {
+            path: &path,
+            expected: digest,
+            actual,
+        }
+    );
+    Ok(path)
+}
+
+/// Merge `annotations` into the top-level annotations of a manifest or index, replacing any
+/// existing values for the same keys.
+pub(crate) fn add_annotations(
+    manifest: &mut Value,
+    annotations: &HashMap<String, String>,
+) -> Result<()> {
+    let manifest = manifest
+        .as_object_mut()
+        .context(error::InvalidManifestSnafu)?;
+    let existing = manifest
+        .entry("annotations")
+        .or_insert_with(|| json!({}))
+        .as_object_mut()
+        .context(error::InvalidManifestSnafu)?;
+    for (key, value) in annotations {
+        existing.insert(key.clone(), Value::String(value.clone()));
+    }
+    Ok(())
+}
+
+/// Mark the index entries with the given attestation digests as attestation manifests, using the
+/// annotations and `unknown/unknown` platform BuildKit uses. `attestations` maps the digest of each
+/// attestation manifest to the digest of the platform image it describes.
+pub(crate) fn annotate_attestations(
+    index: &mut Value,
+    attestations: &HashMap<String, String>,
+) -> Result<()> {
+    let entries = index
+        .get_mut("manifests")
+        .and_then(Value::as_array_mut)
+        .context(error::InvalidManifestSnafu)?;
+
+    for (attestation_digest, reference_digest) in attestations {
+        let entry = entries
+            .iter_mut()
+            .find(|entry| entry["digest"] == attestation_digest.as_str())
+            .context(error::MissingIndexEntrySnafu {
+                digest: attestation_digest,
+            })?;
+        entry["platform"] = json!({
+            "architecture": "unknown",
+            "os": "unknown",
+        });
+        entry["annotations"] = json!({
+            REFERENCE_TYPE_ANNOTATION: ATTESTATION_MANIFEST,
+            REFERENCE_DIGEST_ANNOTATION: reference_digest,
+        });
+    }
+    Ok(())
+}
+
+/// Replace the `platform` of the index entries with the given digests. `platforms` maps the digest
+/// of each platform image to its OCI platform object.
+pub(crate) fn set_platforms(index: &mut Value, platforms: &HashMap<String, Value>) -> Result<()> {
+    let entries = index
+        .get_mut("manifests")
+        .and_then(Value::as_array_mut)
+        .context(error::InvalidManifestSnafu)?;
+
+    for (digest, platform) in platforms {
+        let entry = entries
+            .iter_mut()
+            .find(|entry| entry["digest"] == digest.as_str())
+            .context(error::MissingIndexEntrySnafu { digest })?;
+        entry["platform"] = platform.clone();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const INDEX: &str = r#"{
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.oci.image.index.v1+json",
+        "manifests": [
+            {
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "digest": "sha256:aaaa",
+                "size": 100,
+                "platform": { "architecture": "amd64", "os": "linux" }
+            },
+            {
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "digest": "sha256:bbbb",
+                "size": 100,
+                "platform": { "architecture": "arm64", "os": "linux" }
+            },
+            {
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "digest": "sha256:cccc",
+                "size": 50
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn attestation_round_trip() {
+        let mut index: Value = serde_json::from_str(INDEX).unwrap();
+        let attestations = HashMap::from([("sha256:cccc".to_string(), "sha256:aaaa".to_string())]);
+        annotate_attestations(&mut index, &attestations).unwrap();
+
+        let bytes = serde_json::to_vec(&index).unwrap();
+        let ManifestView::Index {
+            manifests,
+            attestations,
+            ..
+        } = ManifestView::from_slice(&bytes).unwrap()
+        else {
+            panic!("expected an image index");
+        };
+
+        assert_eq!(manifests.len(), 2);
+        assert_eq!(manifests[0].architecture, DockerArchitecture::Amd64);
+        assert_eq!(manifests[1].architecture, DockerArchitecture::Arm64);
+        assert_eq!(
+            attestations,
+            vec![AttestationDescriptor {
+                digest: "sha256:cccc".to_string(),
+                reference_digest: "sha256:aaaa".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn image_manifest() {
+        let manifest = r#"{
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": { "digest": "sha256:1111", "size": 10 },
+            "layers": [ { "digest": "sha256:2222", "size": 20 } ]
+        }"#;
+        let view = ManifestView::from_slice(manifest.as_bytes()).unwrap();
+        assert_eq!(
+            view,
+            ManifestView::Image {
+                media_type: Some("application/vnd.oci.image.manifest.v1+json".to_string()),
+                config_digest: "sha256:1111".to_string(),
+                layers: vec![Descriptor {
+                    media_type: None,
+                    digest: "sha256:2222".to_string(),
+                    size: 20,
+                }],
+                annotations: HashMap::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn index_with_unknown_fields_and_platforms() {
+        let index = r#"{
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "artifactType": "application/vnd.example.future+json",
+            "manifests": [
+                {
+                    "digest": "sha256:aaaa",
+                    "size": 100,
+                    "platform": { "architecture": "amd64", "os": "linux", "os.features": [] },
+                    "data": "e30="
+                },
+                {
+                    "digest": "sha256:bbbb",
+                    "size": 100,
+                    "platform": { "architecture": "mips64le", "os": "linux" }
+                },
+                { "digest": "sha256:cccc", "size": 50 }
+            ],
+            "subject": { "digest": "sha256:dddd", "size": 10 }
+        }"#;
+        let ManifestView::Index { manifests, .. } =
+            ManifestView::from_slice(index.as_bytes()).unwrap()
+        else {
+            panic!("expected an image index");
+        };
+        assert_eq!(
+            manifests,
+            vec![PlatformDescriptor {
+                architecture: DockerArchitecture::Amd64,
+                os: "linux".to_string(),
+                variant: None,
+                os_version: None,
+                digest: "sha256:aaaa".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn missing_attestation_entry() {
+        let mut index: Value = serde_json::from_str(INDEX).unwrap();
+        let attestations = HashMap::from([("sha256:dddd".to_string(), "sha256:aaaa".to_string())]);
+        assert!(annotate_attestations(&mut index, &attestations).is_err());
+    }
+
+    /// Write `blob` into the OCI layout at `layout`, returning its digest.
+    fn write_blob(layout: &Path, blob: &[u8]) -> String {
+        let hex = format!("{:x}", Sha256::digest(blob));
+        std::fs::create_dir_all(layout.join("blobs/sha256")).unwrap();
+        std::fs::write(layout.join("blobs/sha256").join(&hex), blob).unwrap();
+        format!("sha256:{hex}")
+    }
+
+    /// Write an OCI layout holding an image index with one image, returning the index digest and
+    /// the layer digest.
+    fn write_layout(layout: &Path) -> (String, String) {
+        let config = write_blob(layout, br#"{"architecture":"arm64","os":"linux"}"#);
+        let layer = write_blob(layout, b"layer contents");
+        let manifest = write_blob(
+            layout,
+            json!({
+                "schemaVersion": 2,
+                "config": { "digest": config, "size": 37 },
+                "layers": [{ "digest": layer, "size": 14 }],
+            })
+            .to_string()
+            .as_bytes(),
+        );
+        let index = write_blob(
+            layout,
+            json!({
+                "schemaVersion": 2,
+                "manifests": [{
+                    "digest": manifest,
+                    "size": 1,
+                    "platform": { "architecture": "arm64", "os": "linux" },
+                }],
+            })
+            .to_string()
+            .as_bytes(),
+        );
+        std::fs::write(
+            layout.join("index.json"),
+            json!({ "schemaVersion": 2, "manifests": [{ "digest": index, "size": 1 }] })
+                .to_string(),
+        )
+        .unwrap();
+        (index, layer)
+    }
+
+    #[test]
+    fn verify_complete_layout() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let (index, _) = write_layout(temp_dir.path());
+        verify_oci_layout(temp_dir.path(), &index).unwrap();
+    }
+
+    #[test]
+    fn verify_layout_of_other_image() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let (index, _) = write_layout(temp_dir.path());
+        let expected = format!("sha256:{}", "0".repeat(64));
+        let err = verify_oci_layout(temp_dir.path(), &expected).unwrap_err();
+        let message = err.to_string();
+        assert!(matches!(err, error::Error::DigestMismatch { .. }));
+        assert!(
+            message.contains(&index) && message.contains(&expected),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn verify_truncated_layout() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let (index, layer) = write_layout(temp_dir.path());
+        let layer_path = temp_dir
+            .path()
+            .join("blobs/sha256")
+            .join(layer.trim_start_matches("sha256:"));
+
+        std::fs::write(&layer_path, b"layer con").unwrap();
+        let err = verify_oci_layout(temp_dir.path(), &index).unwrap_err();
+        assert!(
+            matches!(&err, error::Error::DigestMismatch { expected, .. } if *expected == layer),
+            "{err}"
+        );
+
+        std::fs::remove_file(&layer_path).unwrap();
+        let err = verify_oci_layout(temp_dir.path(), &index).unwrap_err();
+        assert!(matches!(err, error::Error::BlobRead { .. }), "{err}");
+    }
+}
@@ -0,0 +1,273 @@
+//! Pulling images through registry mirrors.
+//!
+//! Air-gapped builds often reach upstream registries only through an internal mirror or
+//! pull-through cache. [`RegistryMirrors`] set on an [`ImageTool`](crate::ImageTool) send the
+//! operations that read an image from its registry to the mirror of that registry, so that
+//! `Twoliter.toml` can keep referring to the upstream registries. Digests of the images being read
+//! are resolved through the mirror too. Pushes, and the digest, existence and tag lookups that
+//! decide what to push, still go to the URIs they were given.
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use async_trait::async_trait;
+use snafu::{ensure, OptionExt};
+
+use crate::manifest::{AttestationManifest, ManifestMediaType};
+use crate::{error, registry_of, ConfigView, ImageToolImpl, PlatformSpec, Result, ToolInfo};
+
+/// Environment variable holding comma-separated `registry=mirror` mappings for
+/// [`RegistryMirrors`], e.g. `public.ecr.aws=mirror.internal/public.ecr.aws`
+pub const REGISTRY_MIRRORS_ENV: &str = "TWOLITER_REGISTRY_MIRRORS";
+
+/// Maps registry hosts to the prefix images from that registry are pulled through.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegistryMirrors {
+    mirrors: BTreeMap<String, String>,
+}
+
+impl RegistryMirrors {
+    /// Pull images from `registry` through `mirror`, a registry host optionally followed by a
+    /// repository prefix.
+    pub fn with_mirror(mut self, registry: impl Into<String>, mirror: impl Into<String>) -> Self {
+        let mirror = mirror.into();
+        self.mirrors
+            .insert(registry.into(), mirror.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Parse comma-separated `registry=mirror` mappings. Empty entries are ignored.
+    pub fn from_rules(rules: &str) -> Result<Self> {
+        rules
+            .split(',')
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty())
+            .try_fold(Self::default(), |mirrors, rule| {
+                let (registry, mirror) = rule
+                    .split_once('=')
+                    .context(error::RegistryMirrorRuleSnafu { rule })?;
+                let (registry, mirror) = (registry.trim(), mirror.trim());
+                ensure!(
+                    !registry.is_empty() && !registry.contains('/') && !mirror.is_empty(),
+                    error::RegistryMirrorRuleSnafu { rule }
+                );
+                Ok(mirrors.with_mirror(registry, mirror))
+            })
+    }
+
+    /// Read the mappings from `TWOLITER_REGISTRY_MIRRORS`. There are none if it is unset.
+    pub fn from_env() -> Result<Self> {
+        match std::env::var(REGISTRY_MIRRORS_ENV) {
+            Ok(rules) => Self::from_rules(&rules),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mirrors.is_empty()
+    }
+
+    /// The URI to pull `uri` from, which is `uri` itself if its registry has no mirror.
+    pub fn mirror(&self, uri: &str) -> String {
+        let registry = registry_of(uri);
+        match self.mirrors.get(registry) {
+            Some(mirror) if registry != uri => format!("{mirror}{}", &uri[registry.len()..]),
+            _ => uri.to_string(),
+        }
+    }
+}
+
+/// Sends read operations to the mirror of the image's registry before delegating to the wrapped
+/// image tool.
+#[derive(Debug)]
+pub(crate) struct MirroringImageTool {
+    pub(crate) inner: Box<dyn ImageToolImpl>,
+    pub(crate) mirrors: RegistryMirrors,
+}
+
+#[async_trait]
+impl ImageToolImpl for MirroringImageTool {
+    async fn tool_info(&self) -> Result<ToolInfo> {
+        self.inner.tool_info().await
+    }
+
+    async fn pull_oci_image(&self, path: &Path, uri: &str) -> Result<()> {
+        self.inner
+            .pull_oci_image(path, &self.mirrors.mirror(uri))
+            .await
+    }
+
+    async fn get_config(&self, uri: &str) -> Result<ConfigView> {
+        self.inner.get_config(&self.mirrors.mirror(uri)).await
+    }
+
+    async fn get_manifest(&self, uri: &str) -> Result<Vec<u8>> {
+        self.inner.get_manifest(&self.mirrors.mirror(uri)).await
+    }
+
+    async fn get_digest(&self, uri: &str) -> Result<String> {
+        self.inner.get_digest(uri).await
+    }
+
+    async fn resolve_digest(&self, uri: &str) -> Result<String> {
+        self.inner.resolve_digest(&self.mirrors.mirror(uri)).await
+    }
+
+    async fn image_exists(&self, uri: &str) -> Result<bool> {
+        self.inner.image_exists(uri).await
+    }
+
+    async fn delete_tag(&self, uri: &str) -> Result<()> {
+        self.inner.delete_tag(uri).await
+    }
+
+    async fn list_tags(&self, repo: &str) -> Result<Vec<String>> {
+        self.inner.list_tags(repo).await
+    }
+
+    async fn tag_image(&self, uri: &str, tag: &str) -> Result<()> {
+        self.inner.tag_image(uri, tag).await
+    }
+
+    async fn push_oci_archive(&self, path: &Path, uri: &str) -> Result<()> {
+        self.inner.push_oci_archive(path, uri).await
+    }
+
+    async fn copy_image_with_annotations(
+        &self,
+        src: &str,
+        dst: &str,
+        annotations: &HashMap<String, String>,
+    ) -> Result<()> {
+        self.inner
+            .copy_image_with_annotations(&self.mirrors.mirror(src), dst, annotations)
+            .await
+    }
+
+    async fn push_multi_platform_manifest(
+        &self,
+        platform_images: Vec<(PlatformSpec, String)>,
+        uri: &str,
+        media_type: ManifestMediaType,
+        annotations: &HashMap<String, String>,
+    ) -> Result<()> {
+        self.inner
+            .push_multi_platform_manifest(platform_images, uri, media_type, annotations)
+            .await
+    }
+
+    async fn push_multi_platform_manifest_with_attestations(
+        &self,
+        platform_images: Vec<(PlatformSpec, String)>,
+        attestations: Vec<AttestationManifest>,
+        uri: &str,
+    ) -> Result<()> {
+        self.inner
+            .push_multi_platform_manifest_with_attestations(platform_images, attestations, uri)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{MockCall, MockImageTool};
+
+    const MANIFEST: &str = r#"{"schemaVersion":2,"mediaType":"application/vnd.oci.image.manifest.v1+json","config":{"mediaType":"application/vnd.oci.image.config.v1+json","digest":"sha256:aaaa","size":2},"layers":[]}"#;
+
+    #[test]
+    fn mirror_uris() {
+        let mirrors = RegistryMirrors::from_rules(
+            "public.ecr.aws=mirror.internal/public.ecr.aws/, localhost:5000=cache.internal",
+        )
+        .unwrap();
+        assert_eq!(
+            mirrors.mirror("public.ecr.aws/bottlerocket/kit:v1.0.0"),
+            "mirror.internal/public.ecr.aws/bottlerocket/kit:v1.0.0"
+        );
+        assert_eq!(
+            mirrors.mirror("localhost:5000/kit@sha256:abcd"),
+            "cache.internal/kit@sha256:abcd"
+        );
+        assert_eq!(
+            mirrors.mirror("public.ecr.aws.example.com/kit:v1.0.0"),
+            "public.ecr.aws.example.com/kit:v1.0.0"
+        );
+        assert_eq!(mirrors.mirror("public.ecr.aws"), "public.ecr.aws");
+        assert!(RegistryMirrors::from_rules("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn invalid_rules() {
+        assert!(RegistryMirrors::from_rules("public.ecr.aws").is_err());
+        assert!(RegistryMirrors::from_rules("public.ecr.aws=").is_err());
+        assert!(
+            RegistryMirrors::from_rules("public.ecr.aws/bottlerocket=mirror.internal").is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn only_reads_are_mirrored() {
+        let mock = MockImageTool::new()
+            .with_manifest("mirror.internal/ecr/kit:v1", MANIFEST)
+            .with_manifest("public.ecr.aws/kit:v1", MANIFEST)
+            .with_archive("mirror.internal/ecr/kit:v1", "archive");
+        let image_tool = mock.image_tool().registry_mirrors(
+            RegistryMirrors::default().with_mirror("public.ecr.aws", "mirror.internal/ecr"),
+        );
+
+        image_tool
+            .get_manifest("public.ecr.aws/kit:v1")
+            .await
+            .unwrap();
+        image_tool
+            .get_digest("public.ecr.aws/kit:v1")
+            .await
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        image_tool
+            .pull_oci_image(&dir.path().join("kit.tar"), "public.ecr.aws/kit:v1")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            mock.calls(),
+            [
+                ("manifest", "mirror.internal/ecr/kit:v1"),
+                ("digest", "public.ecr.aws/kit:v1"),
+                ("pull", "mirror.internal/ecr/kit:v1"),
+            ]
+            .map(|(operation, uri)| MockCall {
+                operation,
+                uri: uri.to_string(),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn digest_pinned_dependency_resolves_through_mirror() {
+        let pinned = "public.ecr.aws/kit@sha256:abcd";
+        let mirrored = "mirror.internal/ecr/kit@sha256:abcd";
+        let mock = MockImageTool::new().with_manifest(mirrored, MANIFEST);
+        let image_tool = mock.image_tool().registry_mirrors(
+            RegistryMirrors::default().with_mirror("public.ecr.aws", "mirror.internal/ecr"),
+        );
+
+        // The upstream registry is unreachable, so only the mirror can answer.
+        image_tool.get_manifest(pinned).await.unwrap();
+        let digest = image_tool.resolve_digest(pinned).await.unwrap();
+        assert_eq!(digest, image_tool.get_digest(mirrored).await.unwrap());
+
+        assert_eq!(
+            mock.calls(),
+            [
+                ("manifest", mirrored),
+                ("digest", mirrored),
+                ("digest", mirrored)
+            ]
+            .map(|(operation, uri)| MockCall {
+                operation,
+                uri: uri.to_string(),
+            })
+        );
+    }
+}
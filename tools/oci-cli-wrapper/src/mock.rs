@@ -0,0 +1,642 @@
+//! An in-memory image tool for tests.
+//!
+//! A [`MockImageTool`] serves canned manifests, configs and archives from memory rather than
+//! running an image tool against a registry, so code that resolves or publishes images can be
+//! tested quickly and deterministically. Pushes are stored alongside the canned images, so later
+//! reads see them, and every operation is recorded for tests to assert against. Other crates can
+//! use it by enabling the `testing` feature.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use snafu::{OptionExt, ResultExt};
+use tar::Archive as TarArchive;
+
+use crate::manifest::{self, AttestationManifest, ManifestMediaType};
+use crate::{
+    error, split_reference, ConfigView, ImageTool, ImageToolImpl, PlatformSpec, Result, ToolInfo,
+};
+
+/// Media type given to the image manifests listed in indexes the mock pushes
+const OCI_MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+
+/// An operation the mock image tool was asked to perform, and the image it was performed on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockCall {
+    pub operation: &'static str,
+    pub uri: String,
+}
+
+/// An image tool backed by an in-memory map of image URIs to canned images. Clones share the same
+/// images and call history, so a test can keep a clone to inspect after handing one to the code
+/// under test.
+#[derive(Debug, Clone, Default)]
+pub struct MockImageTool {
+    registry: Arc<Mutex<MockRegistry>>,
+}
+
+#[derive(Debug, Default)]
+struct MockRegistry {
+    images: HashMap<String, MockImage>,
+    calls: Vec<MockCall>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct MockImage {
+    manifest: Vec<u8>,
+    config: Option<ConfigView>,
+    archive: Option<Vec<u8>>,
+}
+
+impl MockImage {
+    fn digest(&self) -> String {
+        format!("sha256:{:x}", Sha256::digest(&self.manifest))
+    }
+}
+
+impl MockRegistry {
+    /// Find the image at `uri`, which may refer to it by tag or, within its repository, by digest.
+    fn find(&self, uri: &str) -> Option<&MockImage> {
+        if let Some(image) = self.images.get(uri) {
+            return Some(image);
+        }
+        let (repository, digest) = uri.split_once('@')?;
+        self.images
+            .iter()
+            .find(|(name, image)| split_reference(name).0 == repository && image.digest() == digest)
+            .map(|(_, image)| image)
+    }
+}
+
+impl MockImageTool {
+    /// Create a mock image tool that has no images.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve `manifest` as the manifest of the image at `uri`.
+    pub fn with_manifest(self, uri: &str, manifest: impl Into<Vec<u8>>) -> Self {
+        self.update(uri, |image| image.manifest = manifest.into());
+        self
+    }
+
+    /// Serve `config` as the config of the image at `uri`.
+    pub fn with_config(self, uri: &str, config: ConfigView) -> Self {
+        self.update(uri, |image| image.config = Some(config));
+        self
+    }
+
+    /// Serve `archive` as the oci archive pulled for the image at `uri`.
+    pub fn with_archive(self, uri: &str, archive: impl Into<Vec<u8>>) -> Self {
+        self.update(uri, |image| image.archive = Some(archive.into()));
+        self
+    }
+
+    /// Wrap the mock in an [`ImageTool`]. The mock keeps access to its images and call history.
+    pub fn image_tool(&self) -> ImageTool {
+        ImageTool::new(Box::new(self.clone()))
+    }
+
+    /// The operations performed so far, in the order they were called.
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.registry.lock().unwrap().calls.clone()
+    }
+
+    /// The URIs of the images currently held, sorted.
+    pub fn uris(&self) -> Vec<String> {
+        let mut uris: Vec<_> = self
+            .registry
+            .lock()
+            .unwrap()
+            .images
+            .keys()
+            .cloned()
+            .collect();
+        uris.sort();
+        uris
+    }
+
+    fn update(&self, uri: &str, f: impl FnOnce(&mut MockImage)) {
+        let mut registry = self.registry.lock().unwrap();
+        f(registry.images.entry(uri.to_string()).or_default());
+    }
+
+    fn insert(&self, uri: &str, image: MockImage) {
+        let mut registry = self.registry.lock().unwrap();
+        registry.images.insert(uri.to_string(), image);
+    }
+
+    fn record(&self, operation: &'static str, uri: &str) {
+        let mut registry = self.registry.lock().unwrap();
+        registry.calls.push(MockCall {
+            operation,
+            uri: uri.to_string(),
+        });
+    }
+
+    /// Record `operation` on `uri` and return the image it refers to.
+    fn get(&self, operation: &'static str, uri: &str) -> Result<MockImage> {
+        self.record(operation, uri);
+        let registry = self.registry.lock().unwrap();
+        registry
+            .find(uri)
+            .cloned()
+            .context(error::MockImageMissingSnafu { operation, uri })
+    }
+
+    /// Build an image index listing `images`, each with the platform it is given.
+    fn index(
+        &self,
+        images: Vec<(serde_json::Value, String)>,
+        media_type: ManifestMediaType,
+    ) -> Result<serde_json::Value> {
+        let mut manifests = Vec::new();
+        for (platform, image_uri) in &images {
+            let image = self.get("index entry", image_uri)?;
+            manifests.push(json!({
+                "mediaType": OCI_MANIFEST_MEDIA_TYPE,
+                "digest": image.digest(),
+                "size": image.manifest.len(),
+                "platform": platform,
+            }));
+        }
+        Ok(json!({
+            "schemaVersion": 2,
+            "mediaType": media_type.as_str(),
+            "manifests": manifests,
+        }))
+    }
+
+    fn insert_manifest(&self, uri: &str, manifest: &serde_json::Value) {
+        self.insert(
+            uri,
+            MockImage {
+                manifest: manifest.to_string().into_bytes(),
+                ..MockImage::default()
+            },
+        );
+    }
+}
+
+/// Read the manifest of the single image in the oci archive, or OCI image layout, at `path`.
+fn archive_manifest(path: &Path) -> Result<Vec<u8>> {
+    let digest = manifest::archive_manifest_digest(path)?
+        .context(error::ArchiveManifestMissingSnafu { path })?;
+    let hex = digest.trim_start_matches("sha256:");
+    let blob = PathBuf::from("blobs").join("sha256").join(hex);
+    if manifest::is_oci_layout(path) {
+        return std::fs::read(path.join(blob)).context(error::ArchiveReadSnafu);
+    }
+
+    let oci_file = File::open(path).context(error::ArchiveReadSnafu)?;
+    let mut oci_archive = TarArchive::new(oci_file);
+    for entry in oci_archive.entries().context(error::ArchiveReadSnafu)? {
+        let mut entry = entry.context(error::ArchiveReadSnafu)?;
+        if entry.path().context(error::ArchiveReadSnafu)? != blob {
+            continue;
+        }
+        let mut manifest = Vec::new();
+        entry
+            .read_to_end(&mut manifest)
+            .context(error::ArchiveReadSnafu)?;
+        return Ok(manifest);
+    }
+    error::ArchiveManifestMissingSnafu { path }.fail()
+}
+
+#[async_trait]
+impl ImageToolImpl for MockImageTool {
+    async fn tool_info(&self) -> Result<ToolInfo> {
+        Ok(ToolInfo {
+            backend: "mock".to_string(),
+            path: PathBuf::from("mock"),
+            version: None,
+        })
+    }
+
+    async fn pull_oci_image(&self, path: &Path, uri: &str) -> Result<()> {
+        let archive = self
+            .get("pull", uri)?
+            .archive
+            .context(error::MockImageMissingSnafu {
+                operation: "pull",
+                uri,
+            })?;
+        std::fs::write(path, archive).context(error::FixtureWriteSnafu { path })
+    }
+
+    async fn get_config(&self, uri: &str) -> Result<ConfigView> {
+        self.get("config", uri)?
+            .config
+            .context(error::MockImageMissingSnafu {
+                operation: "config",
+                uri,
+            })
+    }
+
+    async fn get_manifest(&self, uri: &str) -> Result<Vec<u8>> {
+        Ok(self.get("manifest", uri)?.manifest)
+    }
+
+    async fn get_digest(&self, uri: &str) -> Result<String> {
+        Ok(self.get("digest", uri)?.digest())
+    }
+
+    async fn image_exists(&self, uri: &str) -> Result<bool> {
+        self.record("exists", uri);
+        Ok(self.registry.lock().unwrap().find(uri).is_some())
+    }
+
+    async fn delete_tag(&self, uri: &str) -> Result<()> {
+        self.record("delete", uri);
+        let mut registry = self.registry.lock().unwrap();
+        registry
+            .images
+            .remove(uri)
+            .context(error::MockImageMissingSnafu {
+                operation: "delete",
+                uri,
+            })?;
+        Ok(())
+    }
+
+    async fn list_tags(&self, repo: &str) -> Result<Vec<String>> {
+        self.record("tags", repo);
+        Ok(self
+            .uris()
+            .iter()
+            .filter_map(|uri| match split_reference(uri) {
+                (repository, Some(tag)) if repository == repo => Some(tag.to_string()),
+                _ => None,
+            })
+            .collect())
+    }
+
+    async fn tag_image(&self, uri: &str, tag: &str) -> Result<()> {
+        let image = self.get("tag", uri)?;
+        let (repository, _) = split_reference(uri);
+        self.insert(&format!("{repository}:{tag}"), image);
+        Ok(())
+    }
+
+    async fn push_oci_archive(&self, path: &Path, uri: &str) -> Result<()> {
+        self.record("push", uri);
+        let manifest = archive_manifest(path)?;
+        let archive = if manifest::is_oci_layout(path) {
+            None
+        } else {
+            Some(std::fs::read(path).context(error::ArchiveReadSnafu)?)
+        };
+        self.insert(
+            uri,
+            MockImage {
+                manifest,
+                config: None,
+                archive,
+            },
+        );
+        Ok(())
+    }
+
+    async fn copy_image_with_annotations(
+        &self,
+        src: &str,
+        dst: &str,
+        annotations: &HashMap<String, String>,
+    ) -> Result<()> {
+        let mut image = self.get("copy", src)?;
+        if !annotations.is_empty() {
+            let mut manifest: serde_json::Value =
+                crate::document::manifest_from_slice(&image.manifest)?;
+            manifest::add_annotations(&mut manifest, annotations)?;
+            image.manifest = manifest.to_string().into_bytes();
+        }
+        self.insert(dst, image);
+        Ok(())
+    }
+
+    async fn push_multi_platform_manifest(
+        &self,
+        platform_images: Vec<(PlatformSpec, String)>,
+        uri: &str,
+        media_type: ManifestMediaType,
+        annotations: &HashMap<String, String>,
+    ) -> Result<()> {
+        self.record("push manifest list", uri);
+        let images = platform_images
+            .into_iter()
+            .map(|(platform, image)| (platform.to_json(), image))
+            .collect();
+        let mut index = self.index(images, media_type)?;
+        if !annotations.is_empty() {
+            manifest::add_annotations(&mut index, annotations)?;
+        }
+        self.insert_manifest(uri, &index);
+        Ok(())
+    }
+
+    async fn push_multi_platform_manifest_with_attestations(
+        &self,
+        platform_images: Vec<(PlatformSpec, String)>,
+        attestations: Vec<AttestationManifest>,
+        uri: &str,
+    ) -> Result<()> {
+        self.record("push manifest list", uri);
+        let mut references = HashMap::new();
+        for attestation in &attestations {
+            let (_, subject) = platform_images
+                .iter()
+                .find(|(platform, _)| platform.arch == attestation.architecture)
+                .context(error::MissingAttestationSubjectSnafu {
+                    architecture: attestation.architecture.clone(),
+                })?;
+            references.insert(
+                self.get("digest", &attestation.image)?.digest(),
+                self.get("digest", subject)?.digest(),
+            );
+        }
+        let images = platform_images
+            .iter()
+            .map(|(platform, image)| (platform.to_json(), image.clone()))
+            .chain(attestations.iter().map(|attestation| {
+                (
+                    PlatformSpec::new(attestation.architecture.clone()).to_json(),
+                    attestation.image.clone(),
+                )
+            }))
+            .collect();
+        // Attestation manifests are only defined for OCI image indexes.
+        let mut index = self.index(images, ManifestMediaType::OciIndex)?;
+        manifest::annotate_attestations(&mut index, &references)?;
+        self.insert_manifest(uri, &index);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{DockerArchitecture, ManifestView};
+
+    const MANIFEST: &str = r#"{"schemaVersion":2,"mediaType":"application/vnd.oci.image.manifest.v1+json","config":{"mediaType":"application/vnd.oci.image.config.v1+json","digest":"sha256:aaaa","size":2},"layers":[]}"#;
+
+    fn config() -> ConfigView {
+        ConfigView {
+            labels: HashMap::from([("org.example.kit".to_string(), "core".to_string())]),
+            ..ConfigView::default()
+        }
+    }
+
+    /// Write an oci archive holding a single image with `manifest` as its manifest.
+    fn oci_archive(dir: &Path, manifest: &str) -> PathBuf {
+        let digest = format!("sha256:{:x}", Sha256::digest(manifest));
+        let index = json!({
+            "schemaVersion": 2,
+            "manifests": [{ "digest": digest, "size": manifest.len() }],
+        })
+        .to_string();
+        let path = dir.join("kit.tar");
+        let mut builder = tar::Builder::new(File::create(&path).unwrap());
+        for (name, contents) in [
+            ("index.json".to_string(), index.as_bytes()),
+            (
+                format!("blobs/sha256/{}", digest.trim_start_matches("sha256:")),
+                manifest.as_bytes(),
+            ),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, name, contents).unwrap();
+        }
+        builder.finish().unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn serves_canned_images() {
+        let mock = MockImageTool::new()
+            .with_manifest("example.com/kit:v1", MANIFEST)
+            .with_config("example.com/kit:v1", config())
+            .with_archive("example.com/kit:v1", "archive");
+        let image_tool = mock.image_tool();
+
+        let digest = image_tool.get_digest("example.com/kit:v1").await.unwrap();
+        let by_digest = format!("example.com/kit@{digest}");
+        assert!(image_tool.image_exists(&by_digest).await.unwrap());
+        assert!(!image_tool.image_exists("example.com/kit:v2").await.unwrap());
+        assert_eq!(
+            image_tool
+                .get_config("example.com/kit:v1", None)
+                .await
+                .unwrap(),
+            config()
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("kit.tar");
+        image_tool
+            .pull_oci_image(&path, "example.com/kit:v1")
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"archive");
+
+        let err = image_tool
+            .get_manifest("example.com/kit:v2")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, error::Error::MockImageMissing { .. }));
+
+        assert_eq!(
+            mock.calls(),
+            [
+                ("digest", "example.com/kit:v1"),
+                ("exists", by_digest.as_str()),
+                ("exists", "example.com/kit:v2"),
+                ("manifest", "example.com/kit:v1"),
+                ("config", "example.com/kit:v1"),
+                ("pull", "example.com/kit:v1"),
+                ("manifest", "example.com/kit:v2"),
+            ]
+            .map(|(operation, uri)| MockCall {
+                operation,
+                uri: uri.to_string(),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn pushes_are_visible_to_reads() {
+        let mock = MockImageTool::new();
+        let image_tool = mock.image_tool();
+        let dir = tempfile::tempdir().unwrap();
+        let archive = oci_archive(dir.path(), MANIFEST);
+
+        image_tool
+            .push_all_platforms(
+                vec![(DockerArchitecture::Amd64, archive)],
+                "example.com/kit:v1",
+            )
+            .await
+            .unwrap();
+        assert!(image_tool.image_exists("example.com/kit:v1").await.unwrap());
+        let ManifestView::Index { manifests, .. } = image_tool
+            .get_manifest_parsed("example.com/kit:v1")
+            .await
+            .unwrap()
+        else {
+            panic!("expected an image index");
+        };
+        assert_eq!(manifests.len(), 1);
+        assert_eq!(manifests[0].architecture, DockerArchitecture::Amd64);
+        assert_eq!(
+            image_tool
+                .get_manifest_parsed(&format!("example.com/kit@{}", manifests[0].digest))
+                .await
+                .unwrap(),
+            ManifestView::from_slice(MANIFEST.as_bytes()).unwrap()
+        );
+        assert_eq!(
+            image_tool.list_tags("example.com/kit").await.unwrap(),
+            ["v1", "v1-amd64"]
+        );
+    }
+
+    #[tokio::test]
+    async fn push_results_match_later_lookups() {
+        let layered = |size: u64| {
+            json!({
+                "schemaVersion": 2,
+                "mediaType": OCI_MANIFEST_MEDIA_TYPE,
+                "config": { "digest": "sha256:aaaa", "size": 2 },
+                "layers": [
+                    { "digest": "sha256:bbbb", "size": size },
+                    { "digest": "sha256:cccc", "size": 100 },
+                ],
+            })
+            .to_string()
+        };
+        let mock = MockImageTool::new();
+        let image_tool = mock.image_tool();
+        let (amd64_dir, arm64_dir) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+
+        let amd64 = image_tool
+            .push_oci_archive_with_result(
+                &oci_archive(amd64_dir.path(), &layered(1000)),
+                "example.com/kit:v1-amd64",
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(amd64.size_bytes, 1100);
+        assert_eq!(
+            amd64.digest,
+            image_tool
+                .get_digest("example.com/kit:v1-amd64")
+                .await
+                .unwrap()
+        );
+
+        image_tool
+            .push_oci_archive(
+                &oci_archive(arm64_dir.path(), &layered(2000)),
+                "example.com/kit:v1-arm64",
+            )
+            .await
+            .unwrap();
+        let index = image_tool
+            .push_multi_platform_manifest_with_result(
+                vec![
+                    (
+                        DockerArchitecture::Amd64.into(),
+                        "example.com/kit:v1-amd64".to_string(),
+                    ),
+                    (
+                        DockerArchitecture::Arm64.into(),
+                        "example.com/kit:v1-arm64".to_string(),
+                    ),
+                ],
+                "example.com/kit:v1",
+                ManifestMediaType::default(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(index.size_bytes, 3200);
+        assert_eq!(
+            index.digest,
+            image_tool.get_digest("example.com/kit:v1").await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn platforms_are_pushed_whole() {
+        let windows_manifest = MANIFEST.replace("sha256:aaaa", "sha256:bbbb");
+        let mock = MockImageTool::new()
+            .with_manifest("example.com/kit:v1-arm64", MANIFEST)
+            .with_manifest("example.com/kit:v1-windows", windows_manifest);
+        let image_tool = mock.image_tool().skip_existing(true);
+        let platform_images = |variant: &str| {
+            vec![
+                (
+                    PlatformSpec::new(DockerArchitecture::Arm64).with_variant(variant),
+                    "example.com/kit:v1-arm64".to_string(),
+                ),
+                (
+                    PlatformSpec::new(DockerArchitecture::Amd64)
+                        .with_os("windows")
+                        .with_os_version("10.0.20348.2700"),
+                    "example.com/kit:v1-windows".to_string(),
+                ),
+            ]
+        };
+
+        for variant in ["v8", "v8", "v9"] {
+            image_tool
+                .push_multi_platform_manifest(
+                    platform_images(variant),
+                    "example.com/kit:v1",
+                    ManifestMediaType::default(),
+                )
+                .await
+                .unwrap();
+        }
+        let ManifestView::Index { manifests, .. } = image_tool
+            .get_manifest_parsed("example.com/kit:v1")
+            .await
+            .unwrap()
+        else {
+            panic!("expected an image index");
+        };
+        let platforms: Vec<_> = manifests
+            .iter()
+            .map(|platform| {
+                (
+                    platform.os.as_str(),
+                    platform.variant.as_deref(),
+                    platform.os_version.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            platforms,
+            [
+                ("linux", Some("v9"), None),
+                ("windows", None, Some("10.0.20348.2700")),
+            ]
+        );
+
+        // Only a change of platform needs the manifest list to be pushed again.
+        let pushes = mock
+            .calls()
+            .into_iter()
+            .filter(|call| call.operation == "push manifest list")
+            .count();
+        assert_eq!(pushes, 2);
+    }
+}
@@ -0,0 +1,490 @@
+//! An image tool backed by `podman`, for rootless environments without docker.
+//!
+//! Like docker, podman has to pull an image into local storage before it can inspect its config or
+//! digest, so it is slower than crane. Multi-arch images are assembled in a local manifest list that
+//! is pushed and then removed. Podman reads registry credentials from the docker configuration in
+//! `DOCKER_CONFIG`, and client certificates from `/etc/containers/certs.d/<host>/`.
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use snafu::{OptionExt, ResultExt};
+
+use crate::cli::{is_not_found, CommandLine};
+use crate::crane::parse_digest;
+use crate::manifest::{is_oci_layout, AttestationManifest, ManifestMediaType};
+use crate::{
+    error, null_as_default, ConfigView, ImageToolImpl, ManifestView, PlatformSpec, Result,
+    ToolInfo, DOCKER_MANIFEST_LIST_MEDIA_TYPE,
+};
+
+#[derive(Debug)]
+pub struct PodmanCLI {
+    pub(crate) cli: CommandLine,
+}
+
+/// Distinguishes the local manifest lists created by concurrent operations in this process.
+static MANIFEST_LIST_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// One image in the output of `podman image inspect`. Unlike docker, podman reports labels at the
+/// top level rather than under `Config`.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct InspectView {
+    #[serde(default, deserialize_with = "null_as_default")]
+    labels: HashMap<String, String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    config: InspectConfig,
+}
+
+/// The runtime configuration in the output of `podman image inspect`, which holds the same fields
+/// as an image config apart from the labels.
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "PascalCase")]
+struct InspectConfig {
+    #[serde(default)]
+    env: Option<Vec<String>>,
+    #[serde(default)]
+    entrypoint: Option<Vec<String>>,
+    #[serde(default)]
+    cmd: Option<Vec<String>>,
+    #[serde(default)]
+    working_dir: Option<String>,
+}
+
+impl PodmanCLI {
+    /// Pull `uri` into local storage so that it can be inspected or pushed elsewhere.
+    async fn pull(&self, uri: &str) -> Result<()> {
+        self.cli
+            .output(
+                &["pull", "--quiet", uri],
+                format!("failed to pull image {}", uri),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Push the local image `image` to `uri`.
+    async fn push(&self, image: &str, uri: &str) -> Result<()> {
+        let destination = format!("docker://{uri}");
+        let args = ["push", image, &destination];
+        if self.cli.skip_in_dry_run(&args) {
+            return Ok(());
+        }
+        self.cli
+            .spawn(&args, format!("failed to push image {}", uri))
+            .await
+    }
+
+    /// Create an empty local manifest list with a name unique to this operation.
+    async fn create_manifest_list(&self) -> Result<String> {
+        let name = format!(
+            "localhost/twoliter-manifest-{}-{}",
+            std::process::id(),
+            MANIFEST_LIST_COUNT.fetch_add(1, Ordering::SeqCst)
+        );
+        self.cli
+            .local_output(
+                &["manifest", "create", &name],
+                "failed to create manifest list".to_string(),
+            )
+            .await?;
+        Ok(name)
+    }
+
+    /// Set `annotations` on the local manifest list `name`, which will be pushed to `uri`.
+    async fn annotate_manifest_list(
+        &self,
+        name: &str,
+        uri: &str,
+        annotations: &HashMap<String, String>,
+    ) -> Result<()> {
+        if annotations.is_empty() {
+            return Ok(());
+        }
+        let mut args = vec!["manifest".to_string(), "annotate".to_string()];
+        for (key, value) in annotations {
+            args.extend(["--annotation".to_string(), format!("{key}={value}")]);
+        }
+        args.extend(["--index".to_string(), name.to_string()]);
+        self.cli
+            .local_output(
+                &args.iter().map(String::as_str).collect::<Vec<_>>(),
+                format!("could not annotate manifest at {}", uri),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Push the local manifest list `name` with all of its images to `uri`, then remove it. In
+    /// dry-run mode it is only removed.
+    async fn push_manifest_list(
+        &self,
+        name: &str,
+        uri: &str,
+        media_type: ManifestMediaType,
+    ) -> Result<()> {
+        let format = match media_type {
+            ManifestMediaType::OciIndex => "oci",
+            ManifestMediaType::DockerManifestList => "v2s2",
+        };
+        let destination = format!("docker://{uri}");
+        let args = [
+            "manifest",
+            "push",
+            "--all",
+            "--format",
+            format,
+            name,
+            &destination,
+        ];
+        let pushed = if self.cli.skip_in_dry_run(&args) {
+            Ok(())
+        } else {
+            self.cli
+                .spawn(
+                    &args,
+                    format!("could not push multi-platform manifest to {}", uri),
+                )
+                .await
+        };
+        let removed = self
+            .cli
+            .local_output(
+                &["manifest", "rm", name],
+                format!("failed to remove manifest list {}", name),
+            )
+            .await;
+        pushed?;
+        removed?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ImageToolImpl for PodmanCLI {
+    async fn tool_info(&self) -> Result<ToolInfo> {
+        let version = self
+            .cli
+            .local_output(
+                &["version", "--format", "{{.Client.Version}}"],
+                "failed to get podman version".to_string(),
+            )
+            .await
+            .map(|bytes| String::from_utf8_lossy(&bytes).trim().to_string())
+            .map_err(|e| log::debug!("{e}"))
+            .ok();
+        Ok(ToolInfo {
+            backend: "podman".to_string(),
+            path: self.cli.path.clone(),
+            version,
+        })
+    }
+
+    async fn pull_oci_image(&self, path: &Path, uri: &str) -> Result<()> {
+        let output = path.to_string_lossy();
+        let args = ["save", "--format", "oci-dir", "--output", &output, uri];
+        if self.cli.skip_in_dry_run(&args) {
+            return Ok(());
+        }
+        self.pull(uri).await?;
+        self.cli
+            .local_spawn(&args, format!("failed to save image archive of {}", uri))
+            .await
+    }
+
+    async fn get_config(&self, uri: &str) -> Result<ConfigView> {
+        self.pull(uri).await?;
+        let bytes = self
+            .cli
+            .local_output(
+                &["image", "inspect", uri],
+                format!("failed to inspect image {}", uri),
+            )
+            .await?;
+        parse_inspect(uri, &bytes)
+    }
+
+    async fn get_manifest(&self, uri: &str) -> Result<Vec<u8>> {
+        self.cli
+            .output(
+                &["manifest", "inspect", uri],
+                format!("failed to fetch manifest for resource at {}", uri),
+            )
+            .await
+    }
+
+    async fn get_digest(&self, uri: &str) -> Result<String> {
+        self.pull(uri).await?;
+        let bytes = self
+            .cli
+            .local_output(
+                &["image", "inspect", "--format", "{{.Digest}}", uri],
+                format!("failed to fetch digest for resource at {}", uri),
+            )
+            .await?;
+        parse_digest(uri, &bytes)
+    }
+
+    async fn delete_tag(&self, _: &str) -> Result<()> {
+        error::OperationUnsupportedSnafu {
+            tool: "podman",
+            operation: "delete tags",
+        }
+        .fail()
+    }
+
+    async fn list_tags(&self, _: &str) -> Result<Vec<String>> {
+        error::OperationUnsupportedSnafu {
+            tool: "podman",
+            operation: "list tags",
+        }
+        .fail()
+    }
+
+    async fn image_exists(&self, uri: &str) -> Result<bool> {
+        match self.get_manifest(uri).await {
+            Ok(_) => Ok(true),
+            Err(e) if is_not_found(&e) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn tag_image(&self, uri: &str, tag: &str) -> Result<()> {
+        self.pull(uri).await?;
+        let repository = uri
+            .split_once('@')
+            .map_or(uri, |(repository, _)| repository);
+        self.push(uri, &format!("{repository}:{tag}")).await
+    }
+
+    async fn push_oci_archive(&self, path: &Path, uri: &str) -> Result<()> {
+        let transport = if is_oci_layout(path) {
+            "oci"
+        } else {
+            "oci-archive"
+        };
+        let source = format!("{transport}:{}", path.display());
+        let image_id = self
+            .cli
+            .local_output(
+                &["pull", "--quiet", &source],
+                format!("failed to load image from {}", path.display()),
+            )
+            .await?;
+        self.push(String::from_utf8_lossy(&image_id).trim(), uri)
+            .await
+    }
+
+    async fn copy_image_with_annotations(
+        &self,
+        src: &str,
+        dst: &str,
+        annotations: &HashMap<String, String>,
+    ) -> Result<()> {
+        let ManifestView::Index { media_type, .. } =
+            ManifestView::from_slice(&self.get_manifest(src).await?)?
+        else {
+            ensure_no_annotations(annotations)?;
+            self.pull(src).await?;
+            return self.push(src, dst).await;
+        };
+
+        let name = self.create_manifest_list().await?;
+        self.cli
+            .output(
+                &[
+                    "manifest",
+                    "add",
+                    "--all",
+                    &name,
+                    &format!("docker://{src}"),
+                ],
+                format!("failed to add {} to manifest list", src),
+            )
+            .await?;
+        self.annotate_manifest_list(&name, dst, annotations).await?;
+        // Keep the media type of the source index.
+        let media_type = if media_type.as_deref() == Some(DOCKER_MANIFEST_LIST_MEDIA_TYPE) {
+            ManifestMediaType::DockerManifestList
+        } else {
+            ManifestMediaType::OciIndex
+        };
+        self.push_manifest_list(&name, dst, media_type).await
+    }
+
+    async fn push_multi_platform_manifest(
+        &self,
+        platform_images: Vec<(PlatformSpec, String)>,
+        uri: &str,
+        media_type: ManifestMediaType,
+        annotations: &HashMap<String, String>,
+    ) -> Result<()> {
+        let name = self.create_manifest_list().await?;
+        for (platform, image) in &platform_images {
+            let arch_name = platform.arch.to_string();
+            let mut args = vec![
+                "manifest",
+                "add",
+                "--arch",
+                &arch_name,
+                "--os",
+                &platform.os,
+            ];
+            if let Some(variant) = &platform.variant {
+                args.extend(["--variant", variant]);
+            }
+            if let Some(os_version) = &platform.os_version {
+                args.extend(["--os-version", os_version]);
+            }
+            let image_ref = format!("docker://{image}");
+            args.extend([name.as_str(), &image_ref]);
+            self.cli
+                .output(&args, format!("failed to add {} to manifest list", image))
+                .await?;
+        }
+        self.annotate_manifest_list(&name, uri, annotations).await?;
+        self.push_manifest_list(&name, uri, media_type).await
+    }
+
+    async fn push_multi_platform_manifest_with_attestations(
+        &self,
+        _: Vec<(PlatformSpec, String)>,
+        _: Vec<AttestationManifest>,
+        _: &str,
+    ) -> Result<()> {
+        error::OperationUnsupportedSnafu {
+            tool: "podman",
+            operation: "push attestation manifests",
+        }
+        .fail()
+    }
+}
+
+/// Podman cannot set annotations on a single image manifest it copies.
+fn ensure_no_annotations(annotations: &HashMap<String, String>) -> Result<()> {
+    snafu::ensure!(
+        annotations.is_empty(),
+        error::OperationUnsupportedSnafu {
+            tool: "podman",
+            operation: "annotate an image manifest",
+        }
+    );
+    Ok(())
+}
+
+/// Parse the output of `podman image inspect`, which is a list holding one entry per image.
+fn parse_inspect(uri: &str, bytes: &[u8]) -> Result<ConfigView> {
+    let images: Vec<InspectView> =
+        serde_json::from_slice(bytes).context(error::InspectDeserializeSnafu { uri })?;
+    let image = images
+        .into_iter()
+        .next()
+        .context(error::InspectEmptySnafu { uri })?;
+    let InspectConfig {
+        env,
+        entrypoint,
+        cmd,
+        working_dir,
+    } = image.config;
+    Ok(ConfigView {
+        labels: image.labels,
+        env,
+        entrypoint,
+        cmd,
+        working_dir,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cli::{RetryPolicy, DEFAULT_OPERATION_TIMEOUT};
+    use crate::{DockerArchitecture, ImageTool, RegistryClientCerts, RegistryCredentials};
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    #[test]
+    fn inspect_labels_are_top_level() {
+        let output = br#"[{
+            "Id": "abcd",
+            "Digest": "sha256:abcd",
+            "Labels": { "org.bottlerocket.kit.name": "core-kit" },
+            "Config": { "Cmd": ["/bin/sh"] }
+        }]"#;
+        let config = parse_inspect("example.com/kit:v1", output).unwrap();
+        assert_eq!(config.labels["org.bottlerocket.kit.name"], "core-kit");
+        assert_eq!(config.cmd, Some(vec!["/bin/sh".to_string()]));
+
+        let config = parse_inspect("example.com/kit:v1", br#"[{"Labels": null}]"#).unwrap();
+        assert!(config.labels.is_empty());
+        assert!(matches!(
+            parse_inspect("example.com/kit:v1", b"[]"),
+            Err(error::Error::InspectEmpty { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn config_of_multi_arch_image() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let index = r#"{
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": [
+                { "digest": "sha256:aaaa", "platform": { "architecture": "amd64", "os": "linux" } },
+                { "digest": "sha256:bbbb", "platform": { "architecture": "arm64", "os": "linux" } }
+            ]
+        }"#;
+        std::fs::write(dir.join("index.json"), index).unwrap();
+
+        // A stand-in for podman that serves the index and the config of each platform image.
+        let podman = dir.join("podman");
+        std::fs::write(
+            &podman,
+            format!(
+                r#"#!/bin/sh
+case "$*" in
+    "manifest inspect example.com/kit:v1") cat {index} ;;
+    "pull --quiet example.com/kit@sha256:"*) ;;
+    "image inspect example.com/kit@sha256:aaaa") echo '[{{"Labels": {{"arch": "amd64"}}}}]' ;;
+    "image inspect example.com/kit@sha256:bbbb") echo '[{{"Labels": {{"arch": "arm64"}}}}]' ;;
+    *) echo "unexpected: $*" >&2; exit 1 ;;
+esac
+"#,
+                index = dir.join("index.json").display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&podman, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let image_tool = ImageTool::new(Box::new(PodmanCLI {
+            cli: CommandLine {
+                path: podman,
+                client_certs: RegistryClientCerts::default(),
+                credentials: RegistryCredentials::default(),
+                verbose: false,
+                offline: false,
+                dry_run: false,
+                retry: RetryPolicy::none(),
+                timeout: DEFAULT_OPERATION_TIMEOUT,
+            },
+        }));
+
+        let config = image_tool
+            .get_config("example.com/kit:v1", Some(&DockerArchitecture::Arm64))
+            .await
+            .unwrap();
+        assert_eq!(config.labels["arch"], "arm64");
+        let config = image_tool
+            .get_config("example.com/kit:v1", Some(&DockerArchitecture::Amd64))
+            .await
+            .unwrap();
+        assert_eq!(config.labels["arch"], "amd64");
+        assert!(matches!(
+            image_tool.get_config("example.com/kit:v1", None).await,
+            Err(error::Error::PlatformRequired { .. })
+        ));
+    }
+}
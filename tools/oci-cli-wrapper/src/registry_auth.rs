@@ -0,0 +1,320 @@
+//! Credentials for registries that require authentication.
+//!
+//! Credentials are configured per registry host through `TWOLITER_REGISTRY_AUTH`, a
+//! comma-separated list of `host=username:password` entries, e.g.
+//! `registry.example.com=AWS:<token>`. The password is everything after the first `:`, so it may
+//! itself contain colons, but not commas.
+//!
+//! A docker `config.json` written elsewhere, e.g. by a CI system, can be named through
+//! `TWOLITER_REGISTRY_AUTH_FILE` instead of setting `DOCKER_CONFIG` for the whole process. Every
+//! invocation then uses that file, and credentials from `TWOLITER_REGISTRY_AUTH` take precedence
+//! over the file's entries for the same registry.
+//!
+//! Without configured credentials, crane uses the ambient docker configuration. Otherwise, each
+//! invocation that refers to a registry with credentials is given a docker configuration holding
+//! only the credentials for the registries it refers to, along with the auth file if there is
+//! one. The configuration is written to a private temporary directory that is removed once the
+//! command exits, and is passed through `DOCKER_CONFIG` for that command only, so the password
+//! never appears in the command's arguments.
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Formatter};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use snafu::{OptionExt, ResultExt};
+use tempfile::TempDir;
+
+use crate::{error, Result};
+
+/// Environment variable holding the per-registry credentials
+pub const REGISTRY_AUTH_ENV: &str = "TWOLITER_REGISTRY_AUTH";
+/// Environment variable holding the path of a docker `config.json` to read credentials from
+pub const REGISTRY_AUTH_FILE_ENV: &str = "TWOLITER_REGISTRY_AUTH_FILE";
+/// Environment variable through which crane receives the directory holding `config.json`
+pub(crate) const DOCKER_CONFIG_ENV: &str = "DOCKER_CONFIG";
+
+/// A username and password for a registry
+#[derive(Clone, PartialEq, Eq)]
+pub struct RegistryAuth {
+    /// The registry host, which may include a port
+    pub registry: String,
+    pub username: String,
+    pub password: String,
+}
+
+impl Debug for RegistryAuth {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RegistryAuth")
+            .field("registry", &self.registry)
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Credentials keyed by registry host, and optionally a docker configuration file holding more
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegistryCredentials {
+    auths: BTreeMap<String, RegistryAuth>,
+    auth_file: Option<PathBuf>,
+}
+
+/// Docker's `config.json`. Only `auths` is examined; other settings, such as credential helpers,
+/// are passed through as they are.
+#[derive(Default, Deserialize, Serialize)]
+struct DockerConfig {
+    #[serde(default)]
+    auths: BTreeMap<String, serde_json::Value>,
+    #[serde(flatten)]
+    other: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct DockerAuth<'a> {
+    username: &'a str,
+    password: &'a str,
+}
+
+impl RegistryCredentials {
+    /// Use `auth` for connections to its registry, replacing any credentials already set for it.
+    pub fn insert(&mut self, auth: RegistryAuth) {
+        self.auths.insert(auth.registry.clone(), auth);
+    }
+
+    /// Parse a comma-separated list of `host=username:password` entries.
+    pub fn from_spec(spec: &str) -> Result<Self> {
+        let mut credentials = Self::default();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (registry, username, password) = entry
+                .split_once('=')
+                .and_then(|(registry, login)| {
+                    let (username, password) = login.split_once(':')?;
+                    Some((registry, username, password))
+                })
+                .filter(|(registry, username, _)| !registry.is_empty() && !username.is_empty())
+                // The entry holds the password, so only the registry is included in the error.
+                .context(error::RegistryAuthSpecSnafu {
+                    registry: entry.split_once('=').map_or("", |(registry, _)| registry),
+                })?;
+            credentials.insert(RegistryAuth {
+                registry: registry.to_string(),
+                username: username.to_string(),
+                password: password.to_string(),
+            });
+        }
+        Ok(credentials)
+    }
+
+    /// Also use the credentials in the docker `config.json` at `path`. Credentials set inline take
+    /// precedence over the file's for the same registry.
+    pub fn with_auth_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.auth_file = Some(path.into());
+        self
+    }
+
+    /// Read the configuration from `TWOLITER_REGISTRY_AUTH` and `TWOLITER_REGISTRY_AUTH_FILE`,
+    /// either of which may be unset.
+    pub fn from_env() -> Result<Self> {
+        let credentials = match std::env::var(REGISTRY_AUTH_ENV) {
+            Ok(spec) => Self::from_spec(&spec)?,
+            Err(_) => Self::default(),
+        };
+        Ok(match std::env::var_os(REGISTRY_AUTH_FILE_ENV) {
+            Some(path) if !path.is_empty() => credentials.with_auth_file(path),
+            _ => credentials,
+        })
+    }
+
+    /// The credentials for the registries of the image references in `args`.
+    pub(crate) fn for_args(&self, args: &[&str]) -> Vec<&RegistryAuth> {
+        if self.auths.is_empty() {
+            return Vec::new();
+        }
+        args.iter()
+            .filter_map(|arg| self.auths.get(arg.split_once('/')?.0))
+            .collect()
+    }
+
+    /// Write a docker configuration holding the auth file, if any, and the credentials needed by
+    /// an invocation with the given arguments, returning the directory holding it. Returns `None`
+    /// if there is no auth file and the invocation refers to no registry with credentials. The
+    /// directory is removed when dropped.
+    pub(crate) fn docker_config(&self, args: &[&str]) -> Result<Option<TempDir>> {
+        let auths = self.for_args(args);
+        let mut config = match &self.auth_file {
+            Some(path) => read_auth_file(path)?,
+            None if auths.is_empty() => return Ok(None),
+            None => DockerConfig::default(),
+        };
+        for auth in auths {
+            let auth_value = serde_json::to_value(DockerAuth {
+                username: &auth.username,
+                password: &auth.password,
+            })
+            .context(error::RegistryAuthSerializeSnafu)?;
+            config.auths.insert(auth.registry.clone(), auth_value);
+        }
+        let dir = TempDir::new().context(error::RegistryAuthWriteSnafu)?;
+        write_private(
+            &dir.path().join("config.json"),
+            &serde_json::to_vec(&config).context(error::RegistryAuthSerializeSnafu)?,
+        )?;
+        Ok(Some(dir))
+    }
+}
+
+/// Read the docker configuration at `path`.
+fn read_auth_file(path: &Path) -> Result<DockerConfig> {
+    let contents = std::fs::read(path).context(error::RegistryAuthFileSnafu { path })?;
+    serde_json::from_slice(&contents).context(error::RegistryAuthFileParseSnafu { path })
+}
+
+/// Write `contents` to a new file at `path` that only the current user can read.
+fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    use std::io::Write;
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .and_then(|mut file| file.write_all(contents))
+        .context(error::RegistryAuthWriteSnafu)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_spec() {
+        let credentials = RegistryCredentials::from_spec(
+            "registry.example.com:5000=AWS:to:ken, mirror.example.com=ci:secret",
+        )
+        .unwrap();
+        let auths = credentials.for_args(&[
+            "copy",
+            "public.ecr.aws/kit:v1",
+            "registry.example.com:5000/kit:v1",
+        ]);
+        assert_eq!(auths.len(), 1);
+        assert_eq!(auths[0].username, "AWS");
+        assert_eq!(auths[0].password, "to:ken");
+        assert!(credentials
+            .for_args(&["digest", "public.ecr.aws/kit:v1"])
+            .is_empty());
+    }
+
+    #[test]
+    fn invalid_spec_hides_password() {
+        let err = RegistryCredentials::from_spec("registry.example.com=secret").unwrap_err();
+        assert!(matches!(err, error::Error::RegistryAuthSpec { .. }));
+        assert!(!err.to_string().contains("secret"), "{err}");
+        assert!(RegistryCredentials::from_spec("registry.example.com").is_err());
+        assert!(RegistryCredentials::from_spec("=user:secret").is_err());
+    }
+
+    #[test]
+    fn debug_hides_password() {
+        let credentials = RegistryCredentials::from_spec("registry.example.com=ci:secret").unwrap();
+        assert!(!format!("{credentials:?}").contains("secret"));
+    }
+
+    #[test]
+    fn docker_config_holds_referenced_registries() {
+        let credentials = RegistryCredentials::from_spec(
+            "upstream.example.com=reader:r,mirror.example.com=writer:w,other.example.com=o:o",
+        )
+        .unwrap();
+        let dir = credentials
+            .docker_config(&[
+                "copy",
+                "upstream.example.com/kit:v1",
+                "mirror.example.com/kit:v1",
+            ])
+            .unwrap()
+            .unwrap();
+        let config: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(dir.path().join("config.json")).unwrap())
+                .unwrap();
+        assert_eq!(
+            config,
+            serde_json::json!({
+                "auths": {
+                    "mirror.example.com": { "username": "writer", "password": "w" },
+                    "upstream.example.com": { "username": "reader", "password": "r" },
+                }
+            })
+        );
+
+        let path = dir.path().to_path_buf();
+        drop(dir);
+        assert!(!path.exists());
+        assert!(credentials
+            .docker_config(&["digest", "public.ecr.aws/kit:v1"])
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn auth_file_is_merged_with_inline_credentials() {
+        let dir = TempDir::new().unwrap();
+        let auth_file = dir.path().join("ci-config.json");
+        std::fs::write(
+            &auth_file,
+            r#"{
+                "auths": {
+                    "mirror.example.com": { "auth": "ZmlsZTpmaWxl" },
+                    "public.ecr.aws": { "auth": "cHVibGljOnB1YmxpYw==" }
+                },
+                "credHelpers": { "123456789012.dkr.ecr.us-west-2.amazonaws.com": "ecr-login" }
+            }"#,
+        )
+        .unwrap();
+        let credentials = RegistryCredentials::from_spec("mirror.example.com=ci:secret")
+            .unwrap()
+            .with_auth_file(&auth_file);
+
+        // Inline credentials take precedence over the file's for the same registry.
+        let config_dir = credentials
+            .docker_config(&["copy", "public.ecr.aws/kit:v1", "mirror.example.com/kit:v1"])
+            .unwrap()
+            .unwrap();
+        let config: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(config_dir.path().join("config.json")).unwrap())
+                .unwrap();
+        assert_eq!(
+            config,
+            serde_json::json!({
+                "auths": {
+                    "mirror.example.com": { "username": "ci", "password": "secret" },
+                    "public.ecr.aws": { "auth": "cHVibGljOnB1YmxpYw==" },
+                },
+                "credHelpers": { "123456789012.dkr.ecr.us-west-2.amazonaws.com": "ecr-login" }
+            })
+        );
+
+        // The auth file is used even by invocations without inline credentials.
+        assert!(credentials
+            .docker_config(&["digest", "public.ecr.aws/kit:v1"])
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn invalid_auth_file() {
+        let dir = TempDir::new().unwrap();
+        let credentials = RegistryCredentials::default().with_auth_file(dir.path().join("missing"));
+        let err = credentials.docker_config(&["digest", "public.ecr.aws/kit:v1"]);
+        assert!(matches!(err, Err(error::Error::RegistryAuthFile { .. })));
+
+        let auth_file = dir.path().join("config.json");
+        std::fs::write(&auth_file, r#"{"auths": []}"#).unwrap();
+        let credentials = RegistryCredentials::default().with_auth_file(&auth_file);
+        let err = credentials.docker_config(&["digest", "public.ecr.aws/kit:v1"]);
+        assert!(matches!(
+            err,
+            Err(error::Error::RegistryAuthFileParse { .. })
+        ));
+    }
+}
@@ -0,0 +1,295 @@
+//! Recording and replaying of registry responses.
+//!
+//! A [`RecordingImageTool`] saves the response to each read-only registry operation into a
+//! fixture directory as it passes it on. A [`ReplayingImageTool`] later serves the same operations
+//! from that directory without contacting a registry, which lets tests of image resolution run
+//! quickly and deterministically. Operations that change a registry are not recorded and cannot be
+//! replayed.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use snafu::{ensure, ResultExt};
+
+use crate::manifest::{AttestationManifest, ManifestMediaType};
+use crate::{error, ConfigView, ImageToolImpl, PlatformSpec, Result, ToolInfo};
+
+/// Environment variable naming a directory to record registry responses into
+pub const REGISTRY_RECORD_ENV: &str = "TWOLITER_REGISTRY_RECORD";
+
+/// Environment variable naming a directory of recorded registry responses to replay
+pub const REGISTRY_REPLAY_ENV: &str = "TWOLITER_REGISTRY_REPLAY";
+
+/// Where registry responses are recorded to or replayed from
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegistryFixtures {
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+impl RegistryFixtures {
+    /// Read `TWOLITER_REGISTRY_RECORD` and `TWOLITER_REGISTRY_REPLAY`, returning `None` if neither
+    /// is set. Setting both is an error.
+    pub fn from_env() -> Result<Option<Self>> {
+        let var = |name| std::env::var(name).ok().filter(|dir| !dir.is_empty());
+        match (var(REGISTRY_RECORD_ENV), var(REGISTRY_REPLAY_ENV)) {
+            (Some(_), Some(_)) => error::RegistryFixturesConflictSnafu.fail(),
+            (Some(dir), None) => Ok(Some(Self::Record(dir.into()))),
+            (None, Some(dir)) => Ok(Some(Self::Replay(dir.into()))),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+/// The file in `dir` holding the response to `operation` on `uri`. Characters that cannot appear
+/// in a file name are escaped so that distinct URIs map to distinct files.
+fn fixture_path(dir: &Path, operation: &str, uri: &str) -> PathBuf {
+    let mut name = format!("{operation}-");
+    for c in uri.chars() {
+        if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+            name.push(c);
+        } else {
+            name.push_str(&format!("_{:02x}", c as u32));
+        }
+    }
+    dir.join(name)
+}
+
+fn write_fixture(path: &Path, contents: &[u8]) -> Result<()> {
+    std::fs::write(path, contents).context(error::FixtureWriteSnafu { path })
+}
+
+fn read_fixture(dir: &Path, operation: &str, uri: &str) -> Result<Vec<u8>> {
+    let path = fixture_path(dir, operation, uri);
+    ensure!(
+        path.is_file(),
+        error::FixtureMissingSnafu {
+            operation,
+            uri,
+            dir
+        }
+    );
+    std::fs::read(&path).context(error::FixtureReadSnafu { path })
+}
+
+/// Saves the response to each read-only operation of the wrapped image tool into `dir`.
+#[derive(Debug)]
+pub(crate) struct RecordingImageTool {
+    pub(crate) inner: Box<dyn ImageToolImpl>,
+    pub(crate) dir: PathBuf,
+}
+
+impl RecordingImageTool {
+    fn record(&self, operation: &str, uri: &str, contents: &[u8]) -> Result<()> {
+        std::fs::create_dir_all(&self.dir).context(error::FixtureWriteSnafu { path: &self.dir })?;
+        write_fixture(&fixture_path(&self.dir, operation, uri), contents)
+    }
+}
+
+#[async_trait]
+impl ImageToolImpl for RecordingImageTool {
+    async fn tool_info(&self) -> Result<ToolInfo> {
+        self.inner.tool_info().await
+    }
+
+    async fn pull_oci_image(&self, path: &Path, uri: &str) -> Result<()> {
+        self.inner.pull_oci_image(path, uri).await?;
+        let archive = std::fs::read(path).context(error::FixtureReadSnafu { path })?;
+        self.record("pull", uri, &archive)
+    }
+
+    async fn get_config(&self, uri: &str) -> Result<ConfigView> {
+        let config = self.inner.get_config(uri).await?;
+        let image_config = serde_json::json!({ "config": &config });
+        self.record("config", uri, image_config.to_string().as_bytes())?;
+        Ok(config)
+    }
+
+    async fn get_manifest(&self, uri: &str) -> Result<Vec<u8>> {
+        let manifest = self.inner.get_manifest(uri).await?;
+        self.record("manifest", uri, &manifest)?;
+        Ok(manifest)
+    }
+
+    async fn get_digest(&self, uri: &str) -> Result<String> {
+        let digest = self.inner.get_digest(uri).await?;
+        self.record("digest", uri, digest.as_bytes())?;
+        Ok(digest)
+    }
+
+    async fn resolve_digest(&self, uri: &str) -> Result<String> {
+        let digest = self.inner.resolve_digest(uri).await?;
+        self.record("digest", uri, digest.as_bytes())?;
+        Ok(digest)
+    }
+
+    async fn image_exists(&self, uri: &str) -> Result<bool> {
+        let exists = self.inner.image_exists(uri).await?;
+        self.record("exists", uri, exists.to_string().as_bytes())?;
+        Ok(exists)
+    }
+
+    async fn delete_tag(&self, uri: &str) -> Result<()> {
+        self.inner.delete_tag(uri).await
+    }
+
+    async fn list_tags(&self, repo: &str) -> Result<Vec<String>> {
+        let tags = self.inner.list_tags(repo).await?;
+        self.record("tags", repo, tags.join("\n").as_bytes())?;
+        Ok(tags)
+    }
+
+    async fn tag_image(&self, uri: &str, tag: &str) -> Result<()> {
+        self.inner.tag_image(uri, tag).await
+    }
+
+    async fn push_oci_archive(&self, path: &Path, uri: &str) -> Result<()> {
+        self.inner.push_oci_archive(path, uri).await
+    }
+
+    async fn copy_image_with_annotations(
+        &self,
+        src: &str,
+        dst: &str,
+        annotations: &HashMap<String, String>,
+    ) -> Result<()> {
+        self.inner
+            .copy_image_with_annotations(src, dst, annotations)
+            .await
+    }
+
+    async fn push_multi_platform_manifest(
+        &self,
+        platform_images: Vec<(PlatformSpec, String)>,
+        uri: &str,
+        media_type: ManifestMediaType,
+        annotations: &HashMap<String, String>,
+    ) -> Result<()> {
+        self.inner
+            .push_multi_platform_manifest(platform_images, uri, media_type, annotations)
+            .await
+    }
+
+    async fn push_multi_platform_manifest_with_attestations(
+        &self,
+        platform_images: Vec<(PlatformSpec, String)>,
+        attestations: Vec<AttestationManifest>,
+        uri: &str,
+    ) -> Result<()> {
+        self.inner
+            .push_multi_platform_manifest_with_attestations(platform_images, attestations, uri)
+            .await
+    }
+}
+
+/// Serves read-only operations from responses recorded in `dir`.
+#[derive(Debug)]
+pub(crate) struct ReplayingImageTool {
+    pub(crate) dir: PathBuf,
+}
+
+impl ReplayingImageTool {
+    fn unsupported<T>(&self, operation: &str) -> Result<T> {
+        error::ReplayUnsupportedSnafu { operation }.fail()
+    }
+}
+
+#[async_trait]
+impl ImageToolImpl for ReplayingImageTool {
+    async fn tool_info(&self) -> Result<ToolInfo> {
+        Ok(ToolInfo {
+            backend: "replay".to_string(),
+            path: self.dir.clone(),
+            version: None,
+        })
+    }
+
+    async fn pull_oci_image(&self, path: &Path, uri: &str) -> Result<()> {
+        let archive = read_fixture(&self.dir, "pull", uri)?;
+        write_fixture(path, &archive)
+    }
+
+    async fn get_config(&self, uri: &str) -> Result<ConfigView> {
+        ConfigView::from_image_config(&read_fixture(&self.dir, "config", uri)?)
+    }
+
+    async fn get_manifest(&self, uri: &str) -> Result<Vec<u8>> {
+        read_fixture(&self.dir, "manifest", uri)
+    }
+
+    async fn get_digest(&self, uri: &str) -> Result<String> {
+        let digest = read_fixture(&self.dir, "digest", uri)?;
+        Ok(String::from_utf8_lossy(&digest).into_owned())
+    }
+
+    async fn image_exists(&self, uri: &str) -> Result<bool> {
+        Ok(read_fixture(&self.dir, "exists", uri)? == b"true")
+    }
+
+    async fn list_tags(&self, repo: &str) -> Result<Vec<String>> {
+        let tags = read_fixture(&self.dir, "tags", repo)?;
+        Ok(String::from_utf8_lossy(&tags)
+            .lines()
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    async fn tag_image(&self, _: &str, _: &str) -> Result<()> {
+        self.unsupported("tag")
+    }
+
+    async fn delete_tag(&self, _: &str) -> Result<()> {
+        self.unsupported("delete")
+    }
+
+    async fn push_oci_archive(&self, _: &Path, _: &str) -> Result<()> {
+        self.unsupported("push")
+    }
+
+    async fn copy_image_with_annotations(
+        &self,
+        _: &str,
+        _: &str,
+        _: &HashMap<String, String>,
+    ) -> Result<()> {
+        self.unsupported("copy")
+    }
+
+    async fn push_multi_platform_manifest(
+        &self,
+        _: Vec<(PlatformSpec, String)>,
+        _: &str,
+        _: ManifestMediaType,
+        _: &HashMap<String, String>,
+    ) -> Result<()> {
+        self.unsupported("push manifest list")
+    }
+
+    async fn push_multi_platform_manifest_with_attestations(
+        &self,
+        _: Vec<(PlatformSpec, String)>,
+        _: Vec<AttestationManifest>,
+        _: &str,
+    ) -> Result<()> {
+        self.unsupported("push manifest list")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fixture_names_are_distinct() {
+        let dir = Path::new("fixtures");
+        assert_eq!(
+            fixture_path(dir, "digest", "example.com/kit:v1"),
+            dir.join("digest-example.com_2fkit_3av1")
+        );
+        assert_ne!(
+            fixture_path(dir, "digest", "example.com/a_b"),
+            fixture_path(dir, "digest", "example.com/a/b")
+        );
+    }
+}
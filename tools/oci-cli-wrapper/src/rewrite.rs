@@ -0,0 +1,238 @@
+//! Rewriting of image URIs before they are handed to the underlying image tool.
+//!
+//! Some sites route registry traffic through path prefixes or remapped namespaces that cannot be
+//! expressed in `Twoliter.toml`. A [`UriRewriter`] set on an [`ImageTool`](crate::ImageTool) is
+//! applied to every URI passed to the image tool, including URIs derived from other URIs, such as
+//! digest references into the same repository.
+use std::collections::HashMap;
+use std::path::Path;
+
+use async_trait::async_trait;
+use regex::Regex;
+use snafu::{OptionExt, ResultExt};
+
+use crate::manifest::{AttestationManifest, ManifestMediaType};
+use crate::{error, ConfigView, ImageToolImpl, PlatformSpec, Result, ToolInfo};
+
+/// Environment variable holding a `pattern=replacement` rule for [`RegexUriRewriter`]
+pub const URI_REWRITE_ENV: &str = "TWOLITER_URI_REWRITE";
+
+/// Rewrites an image URI before a registry operation
+pub trait UriRewriter: std::fmt::Debug + Send + Sync + 'static {
+    fn rewrite(&self, uri: &str) -> String;
+}
+
+/// Leaves URIs unchanged
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdentityUriRewriter;
+
+impl UriRewriter for IdentityUriRewriter {
+    fn rewrite(&self, uri: &str) -> String {
+        uri.to_string()
+    }
+}
+
+/// Replaces every match of a regular expression in the URI. The replacement may refer to capture
+/// groups as described in [`Regex::replace_all`].
+#[derive(Debug, Clone)]
+pub struct RegexUriRewriter {
+    pattern: Regex,
+    replacement: String,
+}
+
+impl RegexUriRewriter {
+    pub fn new(pattern: &str, replacement: impl Into<String>) -> Result<Self> {
+        Ok(Self {
+            pattern: Regex::new(pattern).context(error::UriRewritePatternSnafu { pattern })?,
+            replacement: replacement.into(),
+        })
+    }
+
+    /// Parse a `pattern=replacement` rule. The rule is split at the first `=`.
+    pub fn from_rule(rule: &str) -> Result<Self> {
+        let (pattern, replacement) = rule
+            .split_once('=')
+            .context(error::UriRewriteRuleSnafu { rule })?;
+        Self::new(pattern, replacement)
+    }
+
+    /// Read the rule from `TWOLITER_URI_REWRITE`, returning `None` if it is unset or empty.
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var(URI_REWRITE_ENV) {
+            Ok(rule) if !rule.is_empty() => Self::from_rule(&rule).map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl UriRewriter for RegexUriRewriter {
+    fn rewrite(&self, uri: &str) -> String {
+        self.pattern
+            .replace_all(uri, self.replacement.as_str())
+            .into_owned()
+    }
+}
+
+/// The rewriter configured through `TWOLITER_URI_REWRITE`, or the identity rewriter if unset.
+pub fn uri_rewriter_from_env() -> Result<Box<dyn UriRewriter>> {
+    Ok(match RegexUriRewriter::from_env()? {
+        Some(rewriter) => Box::new(rewriter),
+        None => Box::new(IdentityUriRewriter),
+    })
+}
+
+/// Applies a [`UriRewriter`] to each URI before delegating to the wrapped image tool.
+#[derive(Debug)]
+pub(crate) struct RewritingImageTool {
+    pub(crate) inner: Box<dyn ImageToolImpl>,
+    pub(crate) rewriter: Box<dyn UriRewriter>,
+}
+
+impl RewritingImageTool {
+    fn rewrite_platform_images(
+        &self,
+        platform_images: Vec<(PlatformSpec, String)>,
+    ) -> Vec<(PlatformSpec, String)> {
+        platform_images
+            .into_iter()
+            .map(|(platform, image)| (platform, self.rewriter.rewrite(&image)))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl ImageToolImpl for RewritingImageTool {
+    async fn tool_info(&self) -> Result<ToolInfo> {
+        self.inner.tool_info().await
+    }
+
+    async fn pull_oci_image(&self, path: &Path, uri: &str) -> Result<()> {
+        self.inner
+            .pull_oci_image(path, &self.rewriter.rewrite(uri))
+            .await
+    }
+
+    async fn get_config(&self, uri: &str) -> Result<ConfigView> {
+        self.inner.get_config(&self.rewriter.rewrite(uri)).await
+    }
+
+    async fn get_manifest(&self, uri: &str) -> Result<Vec<u8>> {
+        self.inner.get_manifest(&self.rewriter.rewrite(uri)).await
+    }
+
+    async fn get_digest(&self, uri: &str) -> Result<String> {
+        self.inner.get_digest(&self.rewriter.rewrite(uri)).await
+    }
+
+    async fn resolve_digest(&self, uri: &str) -> Result<String> {
+        self.inner.resolve_digest(&self.rewriter.rewrite(uri)).await
+    }
+
+    async fn image_exists(&self, uri: &str) -> Result<bool> {
+        self.inner.image_exists(&self.rewriter.rewrite(uri)).await
+    }
+
+    async fn delete_tag(&self, uri: &str) -> Result<()> {
+        self.inner.delete_tag(&self.rewriter.rewrite(uri)).await
+    }
+
+    async fn list_tags(&self, repo: &str) -> Result<Vec<String>> {
+        self.inner.list_tags(&self.rewriter.rewrite(repo)).await
+    }
+
+    async fn tag_image(&self, uri: &str, tag: &str) -> Result<()> {
+        self.inner.tag_image(&self.rewriter.rewrite(uri), tag).await
+    }
+
+    async fn push_oci_archive(&self, path: &Path, uri: &str) -> Result<()> {
+        self.inner
+            .push_oci_archive(path, &self.rewriter.rewrite(uri))
+            .await
+    }
+
+    async fn copy_image_with_annotations(
+        &self,
+        src: &str,
+        dst: &str,
+        annotations: &HashMap<String, String>,
+    ) -> Result<()> {
+        self.inner
+            .copy_image_with_annotations(
+                &self.rewriter.rewrite(src),
+                &self.rewriter.rewrite(dst),
+                annotations,
+            )
+            .await
+    }
+
+    async fn push_multi_platform_manifest(
+        &self,
+        platform_images: Vec<(PlatformSpec, String)>,
+        uri: &str,
+        media_type: ManifestMediaType,
+        annotations: &HashMap<String, String>,
+    ) -> Result<()> {
+        self.inner
+            .push_multi_platform_manifest(
+                self.rewrite_platform_images(platform_images),
+                &self.rewriter.rewrite(uri),
+                media_type,
+                annotations,
+            )
+            .await
+    }
+
+    async fn push_multi_platform_manifest_with_attestations(
+        &self,
+        platform_images: Vec<(PlatformSpec, String)>,
+        attestations: Vec<AttestationManifest>,
+        uri: &str,
+    ) -> Result<()> {
+        let attestations = attestations
+            .into_iter()
+            .map(|attestation| AttestationManifest {
+                image: self.rewriter.rewrite(&attestation.image),
+                ..attestation
+            })
+            .collect();
+        self.inner
+            .push_multi_platform_manifest_with_attestations(
+                self.rewrite_platform_images(platform_images),
+                attestations,
+                &self.rewriter.rewrite(uri),
+            )
+            .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn identity_rewriter() {
+        let uri = "public.ecr.aws/bottlerocket/kit:v1.0.0";
+        assert_eq!(IdentityUriRewriter.rewrite(uri), uri);
+    }
+
+    #[test]
+    fn regex_rewriter() {
+        let rewriter =
+            RegexUriRewriter::from_rule(r"^public\.ecr\.aws/(.*)=mirror.example.com/ecr/$1")
+                .unwrap();
+        assert_eq!(
+            rewriter.rewrite("public.ecr.aws/bottlerocket/kit:v1.0.0"),
+            "mirror.example.com/ecr/bottlerocket/kit:v1.0.0"
+        );
+        assert_eq!(
+            rewriter.rewrite("example.com/bottlerocket/kit:v1.0.0"),
+            "example.com/bottlerocket/kit:v1.0.0"
+        );
+    }
+
+    #[test]
+    fn invalid_rule() {
+        assert!(RegexUriRewriter::from_rule("no-separator").is_err());
+        assert!(RegexUriRewriter::from_rule("(unclosed=replacement").is_err());
+    }
+}
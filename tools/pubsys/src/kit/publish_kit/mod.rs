@@ -1,7 +1,11 @@
 use crate::Args;
+use buildsys::manifest::SupportedArch;
 use clap::Parser;
 use log::{debug, info, trace};
-use oci_cli_wrapper::{DockerArchitecture, ImageTool};
+use oci_cli_wrapper::{
+    uri_rewriter_from_env, CommandOptions, DockerArchitecture, ImageTool, ImageToolSelection,
+    ManifestMediaType, PlatformSpec, RegistryClientCerts, RegistryCredentials,
+};
 use pubsys_config::InfraConfig;
 use snafu::{ensure, OptionExt, ResultExt};
 use std::path::PathBuf;
@@ -28,10 +32,25 @@ pub(crate) struct PublishKitArgs {
     /// The build id of the kit that should be published
     #[arg(long)]
     build_id: String,
+
+    /// Optionally push the per-architecture images to a different repository name than the kit,
+    /// such as a sub-path of it. It must be in the same registry.
+    #[arg(long)]
+    platform_repo: Option<String>,
+
+    /// Skip uploading images that already exist in the registry
+    #[arg(long)]
+    skip_existing: bool,
 }
 
 pub(crate) async fn run(args: &Args, publish_kit_args: &PublishKitArgs) -> Result<()> {
-    let image_tool = ImageTool::from_builtin_krane();
+    let credentials = RegistryCredentials::from_env().context(error::CredentialsSnafu)?;
+    let image_tool = image_tool(
+        ImageToolSelection::from_env(),
+        credentials,
+        CommandOptions::from_env(),
+    )?
+    .skip_existing(publish_kit_args.skip_existing);
 
     // If a lock file exists, use that, otherwise use Infra.toml
     let infra_config = InfraConfig::from_path_or_lock(&args.infra_config_path, false)
@@ -41,6 +60,22 @@ pub(crate) async fn run(args: &Args, publish_kit_args: &PublishKitArgs) -> Resul
     publish_kit(infra_config, publish_kit_args, &image_tool).await
 }
 
+/// The image tool to publish with, set up the way twoliter sets up its own: `selection` chooses
+/// the tool, as `TWOLITER_KIT_IMAGE_TOOL`, `TWOLITER_KIT_IMAGE_TOOL_ORDER` and
+/// `TWOLITER_KIT_IMAGE_TOOL_PATH` do, `options` are the offline, dry-run, retry and timeout
+/// settings twoliter passes down, and it applies the client certificates and URI rewrite rule
+/// configured in the environment along with `credentials`.
+fn image_tool(
+    selection: ImageToolSelection,
+    credentials: RegistryCredentials,
+    options: CommandOptions,
+) -> Result<ImageTool> {
+    let client_certs = RegistryClientCerts::from_env().context(error::ClientCertsSnafu)?;
+    let image_tool = ImageTool::from_selection(selection, client_certs, credentials, options)
+        .context(error::ImageToolSnafu)?;
+    Ok(image_tool.uri_rewriter(uri_rewriter_from_env().context(error::UriRewriteSnafu)?))
+}
+
 async fn publish_kit(
     infra_config: InfraConfig,
     publish_kit_args: &PublishKitArgs,
@@ -75,11 +110,13 @@ async fn publish_kit(
         None => kit_name.to_string(),
     };
 
-    let mut platform_images = Vec::new();
-    for arch in ["aarch64", "x86_64"] {
-        let docker_arch =
-            DockerArchitecture::try_from(arch).context(error::InvalidArchitectureSnafu { arch })?;
+    let platform_repository_target = publish_kit_args
+        .platform_repo
+        .as_ref()
+        .unwrap_or(&repository_target);
 
+    let mut platform_images = Vec::new();
+    for arch in [SupportedArch::Aarch64, SupportedArch::X86_64] {
         let kit_filename = format!("{}-{}-{}-{}.tar", &kit_name, &kit_version, &build_id, arch);
         let path = kit_path.join(&kit_filename);
 
@@ -90,7 +127,7 @@ async fn publish_kit(
 
         let arch_specific_target_uri = format!(
             "{}/{}:{}-{}-{}",
-            vendor_registry_uri, repository_target, &kit_version, &build_id, arch
+            vendor_registry_uri, platform_repository_target, &kit_version, &build_id, arch
         );
 
         info!(
@@ -103,7 +140,10 @@ async fn publish_kit(
             .await
             .context(error::PublishKitSnafu)?;
 
-        platform_images.push((docker_arch, arch_specific_target_uri.clone()));
+        platform_images.push((
+            PlatformSpec::new(docker_arch(arch)),
+            arch_specific_target_uri.clone(),
+        ));
     }
     ensure!(
         !platform_images.is_empty(),
@@ -117,16 +157,143 @@ async fn publish_kit(
 
     info!("Pushing kit to {}", &target_uri);
 
-    image_tool
-        .push_multi_platform_manifest(platform_images, &target_uri)
+    let pushed = image_tool
+        .push_multi_platform_manifest_with_result(
+            platform_images,
+            &target_uri,
+            ManifestMediaType::default(),
+        )
         .await
         .context(error::PublishKitSnafu)?;
 
-    info!("Successfully published kit to {}", target_uri);
+    match pushed {
+        Some(pushed) => info!(
+            "Successfully published kit to {} ({}, {} bytes)",
+            target_uri, pushed.digest, pushed.size_bytes
+        ),
+        None => info!("Dry run, kit was not published to {}", target_uri),
+    }
 
     Ok(())
 }
 
+/// Map a buildsys architecture into the architecture of its platform image.
+fn docker_arch(arch: SupportedArch) -> DockerArchitecture {
+    match arch {
+        SupportedArch::X86_64 => DockerArchitecture::Amd64,
+        SupportedArch::Aarch64 => DockerArchitecture::Arm64,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    const DIGEST: &str = "sha256:5b0f5c5e4d6a3e0b7c2b0b4f1b9d7c1e2a3f4b5c6d7e8f90a1b2c3d4e5f60718";
+
+    #[test]
+    fn test_docker_arch() {
+        assert_eq!(
+            docker_arch(SupportedArch::X86_64),
+            DockerArchitecture::Amd64
+        );
+        assert_eq!(
+            docker_arch(SupportedArch::Aarch64),
+            DockerArchitecture::Arm64
+        );
+    }
+
+    #[tokio::test]
+    async fn test_image_tool_uses_registry_auth_file() {
+        let dir = TempDir::new().unwrap();
+        let auth_file = dir.path().join("auth.json");
+        std::fs::write(
+            &auth_file,
+            r#"{"auths":{"registry.example.com":{"auth":"dXNlcjpwYXNz"}}}"#,
+        )
+        .unwrap();
+        // A crane that only knows the image's digest when it is given the credentials.
+        let crane = dir.path().join("crane");
+        std::fs::write(
+            &crane,
+            format!(
+                "#!/bin/sh\ngrep -q dXNlcjpwYXNz \"$DOCKER_CONFIG/config.json\" && echo {DIGEST}\n"
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&crane, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let selection = ImageToolSelection {
+            tool: Some("crane".to_string()),
+            path: Some(crane),
+            ..Default::default()
+        };
+        let credentials = RegistryCredentials::default().with_auth_file(&auth_file);
+        let digest = image_tool(selection, credentials, CommandOptions::default())
+            .unwrap()
+            .get_digest("registry.example.com/my-kit:v1.0.0")
+            .await
+            .unwrap();
+        assert_eq!(digest, DIGEST);
+    }
+
+    #[tokio::test]
+    async fn test_image_tool_uses_command_options() {
+        let dir = TempDir::new().unwrap();
+        // A crane that counts its runs and always fails with an error that is normally retried.
+        let runs = dir.path().join("runs");
+        let crane = dir.path().join("crane");
+        std::fs::write(
+            &crane,
+            format!(
+                "#!/bin/sh
+echo run >> {}
+echo 'status code 503' >&2
+exit 1
+",
+                runs.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&crane, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let image_tool = |options| {
+            let selection = ImageToolSelection {
+                tool: Some("crane".to_string()),
+                path: Some(crane.clone()),
+                ..Default::default()
+            };
+            image_tool(selection, RegistryCredentials::default(), options).unwrap()
+        };
+        let uri = "registry.example.com/my-kit:v1.0.0";
+
+        let offline = image_tool(CommandOptions {
+            offline: true,
+            ..CommandOptions::default()
+        });
+        let err = offline.get_digest(uri).await.unwrap_err();
+        assert!(
+            matches!(err, oci_cli_wrapper::error::Error::Offline { .. }),
+            "{err}"
+        );
+        assert!(!runs.exists());
+
+        let dry_run = image_tool(CommandOptions {
+            dry_run: true,
+            ..CommandOptions::default()
+        });
+        assert!(dry_run.dry_run());
+
+        let no_retries = image_tool(CommandOptions {
+            retries: 0,
+            ..CommandOptions::default()
+        });
+        no_retries.get_digest(uri).await.unwrap_err();
+        assert_eq!(std::fs::read_to_string(&runs).unwrap().lines().count(), 1);
+    }
+}
+
 mod error {
     use snafu::Snafu;
     use std::path::PathBuf;
@@ -134,13 +301,22 @@ mod error {
     #[derive(Debug, Snafu)]
     #[snafu(visibility(pub(super)))]
     pub(crate) enum Error {
+        #[snafu(display("Invalid registry client certificate configuration: {}", source))]
+        ClientCerts {
+            source: oci_cli_wrapper::error::Error,
+        },
+
         #[snafu(display("Error reading config: {}", source))]
         Config { source: pubsys_config::Error },
 
-        #[snafu(display("Could not convert {} to docker architecture: {}", arch, source))]
-        InvalidArchitecture {
+        #[snafu(display("Invalid registry credential configuration: {}", source))]
+        Credentials {
+            source: oci_cli_wrapper::error::Error,
+        },
+
+        #[snafu(display("Failed to set up image tool: {}", source))]
+        ImageTool {
             source: oci_cli_wrapper::error::Error,
-            arch: String,
         },
 
         #[snafu(display("Failed not get kit name from path {}", path.display()))]
@@ -157,6 +333,11 @@ mod error {
             source: oci_cli_wrapper::error::Error,
         },
 
+        #[snafu(display("Invalid image URI rewrite rule: {}", source))]
+        UriRewrite {
+            source: oci_cli_wrapper::error::Error,
+        },
+
         #[snafu(display("Vendor '{}' not specified in Infra.toml", name))]
         VendorNotFound { name: String },
     }
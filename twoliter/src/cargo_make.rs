@@ -1,4 +1,4 @@
-use crate::common::{exec_log, BUILDSYS_OUTPUT_GENERATION_ID};
+use crate::common::{exec_log, GlobalOptions, BUILDSYS_OUTPUT_GENERATION_ID};
 use anyhow::{bail, Result};
 use std::path::PathBuf;
 use tokio::process::Command;
@@ -48,12 +48,16 @@ pub struct CargoMake {
 
 impl CargoMake {
     /// Create a new `cargo make` command. The sdk environment variable will be set based on the
-    /// definition in `Twoliter.toml`.
+    /// definition in `Twoliter.toml`, and the global options for this run are passed on to the
+    /// tasks.
     pub(crate) fn new(sdk: &str) -> Result<Self> {
-        Ok(Self::default().env("TLPRIVATE_SDK_IMAGE", sdk).env(
-            "BUILDSYS_OUTPUT_GENERATION_ID",
-            BUILDSYS_OUTPUT_GENERATION_ID.to_string(),
-        ))
+        Ok(Self::default()
+            .env("TLPRIVATE_SDK_IMAGE", sdk)
+            .env(
+                "BUILDSYS_OUTPUT_GENERATION_ID",
+                BUILDSYS_OUTPUT_GENERATION_ID.to_string(),
+            )
+            .envs(GlobalOptions::get().envs().into_iter()))
     }
 
     /// Specify the path to the `Makefile.toml` for the `cargo make` command
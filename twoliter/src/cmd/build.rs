@@ -1,10 +1,12 @@
 use super::build_clean::BuildClean;
 use crate::cargo_make::CargoMake;
 use crate::common::fs;
+use crate::preflight::check_build_arch;
 use crate::project::{self, Locked};
 use crate::tools::install_tools;
-use anyhow::{Context, Result};
+use anyhow::{ensure, Context, Result};
 use clap::Parser;
+use globset::{Glob, GlobSetBuilder};
 use std::path::PathBuf;
 use tempfile::TempDir;
 
@@ -12,6 +14,7 @@ use tempfile::TempDir;
 pub(crate) enum BuildCommand {
     Clean(BuildClean),
     Kit(BuildKit),
+    Package(BuildPackage),
     Variant(BuildVariant),
 }
 
@@ -20,11 +23,94 @@ impl BuildCommand {
         match self {
             BuildCommand::Clean(command) => command.run().await,
             BuildCommand::Kit(command) => command.run().await,
+            BuildCommand::Package(command) => command.run().await,
             BuildCommand::Variant(command) => command.run().await,
         }
     }
 }
 
+/// Build the packages whose names match a filter, along with the packages they depend on.
+#[derive(Debug, Parser)]
+pub(crate) struct BuildPackage {
+    /// Path to Twoliter.toml. Will search for Twoliter.toml when absent.
+    #[clap(long = "project-path")]
+    pub(crate) project_path: Option<PathBuf>,
+
+    /// The architecture to build for.
+    #[clap(long = "arch", default_value = "x86_64")]
+    pub(crate) arch: String,
+
+    /// A glob matched against package names, e.g. `kernel-*`. May be given more than once to
+    /// build the packages matching any of the filters.
+    #[clap(long = "package", required = true)]
+    pub(crate) package: Vec<String>,
+
+    /// The URL to the lookaside cache where sources are stored to avoid pulling them from upstream.
+    /// Defaults to https://cache.bottlerocket.aws
+    #[clap(long = "lookaside-cache")]
+    pub(crate) lookaside_cache: Option<String>,
+
+    /// If sources are not found in the lookaside cache, this flag will cause buildsys to pull them
+    /// from the upstream URL found in a package's `Cargo.toml`.
+    #[clap(long = "upstream-source-fallback")]
+    pub(crate) upstream_source_fallback: bool,
+}
+
+impl BuildPackage {
+    pub(super) async fn run(&self) -> Result<()> {
+        let project = project::load_or_find_project(self.project_path.clone()).await?;
+        let packages = select_packages(&project.find_packages().await?, &self.package)?;
+        check_build_arch(&self.arch, None);
+        let project = project.load_lock::<Locked>().await?;
+        let toolsdir = project.project_dir().join("build/tools");
+        install_tools(&toolsdir).await?;
+        let makefile_path = toolsdir.join("Makefile.toml");
+
+        let mut optional_envs = Vec::new();
+
+        if let Some(lookaside_cache) = &self.lookaside_cache {
+            optional_envs.push(("BUILDSYS_LOOKASIDE_CACHE", lookaside_cache))
+        }
+
+        CargoMake::new(&project.sdk_image().project_image_uri().to_string())?
+            .env("TWOLITER_TOOLS_DIR", toolsdir.display().to_string())
+            .env("BUILDSYS_ARCH", &self.arch)
+            .env("PACKAGE", packages.join(" "))
+            .env("BUILDSYS_VERSION_IMAGE", project.release_version())
+            .env("GO_MODULES", project.find_go_modules().await?.join(" "))
+            .env(
+                "BUILDSYS_UPSTREAM_SOURCE_FALLBACK",
+                self.upstream_source_fallback.to_string(),
+            )
+            .envs(optional_envs.into_iter())
+            .makefile(makefile_path)
+            .project_dir(project.project_dir())
+            .exec("build-package")
+            .await
+    }
+}
+
+/// Returns the packages whose names match any of the glob `filters`, failing if none match.
+fn select_packages(packages: &[String], filters: &[String]) -> Result<Vec<String>> {
+    let mut builder = GlobSetBuilder::new();
+    for filter in filters {
+        builder.add(Glob::new(filter).context(format!("Invalid package filter '{filter}'"))?);
+    }
+    let globs = builder.build().context("Unable to build package filters")?;
+    let selected: Vec<String> = packages
+        .iter()
+        .filter(|package| globs.is_match(package.as_str()))
+        .cloned()
+        .collect();
+    ensure!(
+        !selected.is_empty(),
+        "No packages match the filter(s) '{}'. Available packages: {}",
+        filters.join("', '"),
+        packages.join(", ")
+    );
+    Ok(selected)
+}
+
 /// Build a Bottlerocket variant image.
 #[derive(Debug, Parser)]
 pub(crate) struct BuildKit {
@@ -52,6 +138,7 @@ pub(crate) struct BuildKit {
 impl BuildKit {
     pub(super) async fn run(&self) -> Result<()> {
         let project = project::load_or_find_project(self.project_path.clone()).await?;
+        check_build_arch(&self.arch, None);
         let project = project.load_lock::<Locked>().await?;
         let toolsdir = project.project_dir().join("build/tools");
         install_tools(&toolsdir).await?;
@@ -112,6 +199,10 @@ pub(crate) struct BuildVariant {
 impl BuildVariant {
     pub(super) async fn run(&self) -> Result<()> {
         let project = project::load_or_find_project(self.project_path.clone()).await?;
+        check_build_arch(
+            &self.arch,
+            Some(&project.project_dir().join("variants").join(&self.variant)),
+        );
         let project = project.load_lock::<Locked>().await?;
         let toolsdir = project.project_dir().join("build/tools");
         install_tools(&toolsdir).await?;
@@ -152,3 +243,33 @@ impl BuildVariant {
             .await
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn packages() -> Vec<String> {
+        ["kernel-5_10", "kernel-6_1", "glibc", "kmod-6_1-nvidia"]
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_select_packages() {
+        assert_eq!(
+            select_packages(&packages(), &["kernel-*".to_string()]).unwrap(),
+            ["kernel-5_10", "kernel-6_1"]
+        );
+        assert_eq!(
+            select_packages(&packages(), &["glibc".to_string(), "*nvidia".to_string()]).unwrap(),
+            ["glibc", "kmod-6_1-nvidia"]
+        );
+    }
+
+    #[test]
+    fn test_select_packages_no_match() {
+        let err = select_packages(&packages(), &["systemd*".to_string()]).unwrap_err();
+        assert!(err.to_string().contains("systemd*"));
+    }
+}
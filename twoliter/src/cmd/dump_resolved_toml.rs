@@ -0,0 +1,24 @@
+use crate::project;
+use anyhow::{Context, Result};
+use clap::Parser;
+use oci_cli_wrapper::uri_rewriter_from_env;
+use std::path::PathBuf;
+
+/// Print the fully resolved project configuration as TOML: image URIs with Twoliter.override and
+/// TWOLITER_URI_REWRITE applied, and the versions and digests locked in Twoliter.lock. Nothing is
+/// written and no registry is contacted.
+#[derive(Debug, Parser)]
+pub(crate) struct DumpResolvedToml {
+    /// Path to Twoliter.toml. Will search for Twoliter.toml when absent
+    #[clap(long = "project-path")]
+    pub(crate) project_path: Option<PathBuf>,
+}
+
+impl DumpResolvedToml {
+    pub(super) async fn run(&self) -> Result<()> {
+        let project = project::load_or_find_project(self.project_path.clone()).await?;
+        let rewriter = uri_rewriter_from_env().context("failed to read image URI rewrite rule")?;
+        print!("{}", project.resolved_toml(rewriter.as_ref()).await?);
+        Ok(())
+    }
+}
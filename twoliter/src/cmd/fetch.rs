@@ -1,8 +1,12 @@
 use crate::project::{self, Locked};
 use anyhow::Result;
 use clap::Parser;
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
 
+/// How many kits are fetched at a time when `--jobs` is not given
+const DEFAULT_FETCH_JOBS: usize = 4;
+
 #[derive(Debug, Parser)]
 pub(crate) struct Fetch {
     /// Path to Twoliter.toml. Will search for Twoliter.toml when absent
@@ -12,13 +16,17 @@ pub(crate) struct Fetch {
     /// Architecture of images to fetch
     #[clap(long = "arch", default_value = "x86_64")]
     pub(crate) arch: String,
+
+    /// How many kits to fetch at a time
+    #[clap(long = "jobs", env = "TWOLITER_FETCH_JOBS", default_value_t = NonZeroUsize::new(DEFAULT_FETCH_JOBS).unwrap())]
+    pub(crate) jobs: NonZeroUsize,
 }
 
 impl Fetch {
     pub(super) async fn run(&self) -> Result<()> {
         let project = project::load_or_find_project(self.project_path.clone()).await?;
         let project = project.load_lock::<Locked>().await?;
-        project.fetch(self.arch.as_str()).await?;
+        project.fetch(self.arch.as_str(), self.jobs).await?;
         Ok(())
     }
 }
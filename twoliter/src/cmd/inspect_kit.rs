@@ -0,0 +1,90 @@
+use crate::project::image_tool;
+use anyhow::{ensure, Context, Result};
+use clap::Parser;
+use oci_cli_wrapper::{ConfigView, DockerArchitecture};
+use std::collections::BTreeMap;
+
+/// Print the labels of a kit image, such as its name and version, by fetching only the image
+/// config from the registry.
+#[derive(Debug, Parser)]
+pub(crate) struct InspectKit {
+    /// The kit image, e.g. public.ecr.aws/bottlerocket/bottlerocket-core-kit:v1.0.0
+    uri: String,
+
+    /// For a multi-arch kit, the architecture whose image labels are printed.
+    #[clap(long = "arch", default_value = "x86_64")]
+    arch: String,
+
+    /// Print the labels as a JSON object
+    #[clap(long)]
+    json: bool,
+}
+
+impl InspectKit {
+    pub(super) async fn run(&self) -> Result<()> {
+        let arch = DockerArchitecture::try_from(self.arch.as_str())
+            .with_context(|| format!("Unsupported architecture '{}'", self.arch))?;
+        let config = image_tool()?
+            .get_config(&self.uri, Some(&arch))
+            .await
+            .with_context(|| format!("Failed to fetch the image config of '{}'", self.uri))?;
+        let labels = sorted_labels(&self.uri, &config)?;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&labels)?);
+        } else {
+            for (label, value) in &labels {
+                println!("{label}={value}");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The labels in `config`, sorted by name.
+fn sorted_labels<'a>(uri: &str, config: &'a ConfigView) -> Result<BTreeMap<&'a str, &'a str>> {
+    ensure!(!config.labels.is_empty(), "'{uri}' has no labels");
+    Ok(config
+        .labels
+        .iter()
+        .map(|(label, value)| (label.as_str(), value.as_str()))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_labels_are_sorted() {
+        let config = ConfigView {
+            labels: HashMap::from([
+                (
+                    "org.bottlerocket.kit.version".to_string(),
+                    "2.0.0".to_string(),
+                ),
+                (
+                    "org.bottlerocket.kit.name".to_string(),
+                    "core-kit".to_string(),
+                ),
+            ]),
+            ..Default::default()
+        };
+        let labels = sorted_labels("example.com/core-kit:v2.0.0", &config).unwrap();
+        assert_eq!(
+            labels.into_iter().collect::<Vec<_>>(),
+            [
+                ("org.bottlerocket.kit.name", "core-kit"),
+                ("org.bottlerocket.kit.version", "2.0.0"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_labels() {
+        let err = sorted_labels("example.com/core-kit:v2.0.0", &ConfigView::default())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "'example.com/core-kit:v2.0.0' has no labels");
+    }
+}
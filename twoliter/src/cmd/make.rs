@@ -1,4 +1,5 @@
 use crate::cargo_make::CargoMake;
+use crate::preflight::check_build_arch;
 use crate::project::{self, Locked, SDKLocked, Unlocked};
 use crate::tools::install_tools;
 use anyhow::Result;
@@ -43,6 +44,11 @@ pub(crate) struct Make {
     #[clap(long, env = "BUILDSYS_ARCH")]
     arch: String,
 
+    /// Remove the SDK image from the docker daemon if it is already present and pull it again.
+    /// This is useful for recovering from a suspected corrupt SDK load.
+    #[clap(long)]
+    force_sdk_refresh: bool,
+
     /// Cargo make task. E.g. the word "build" if we want to execute `cargo make build`.
     makefile_task: String,
 
@@ -54,6 +60,12 @@ pub(crate) struct Make {
 impl Make {
     pub(super) async fn run(&self) -> Result<()> {
         let project = project::load_or_find_project(self.project_path.clone()).await?;
+        // The variant, if any, is passed through to cargo make from the environment.
+        let variant_dir = std::env::var("BUILDSYS_VARIANT")
+            .ok()
+            .filter(|variant| !variant.is_empty())
+            .map(|variant| project.project_dir().join("variants").join(variant));
+        check_build_arch(&self.arch, variant_dir.as_deref());
         let sdk_source = self.locked_sdk(&project).await?;
         let toolsdir = project.project_dir().join("build/tools");
         install_tools(&toolsdir).await?;
@@ -62,6 +74,10 @@ impl Make {
             .env("CARGO_HOME", self.cargo_home.display().to_string())
             .env("TWOLITER_TOOLS_DIR", toolsdir.display().to_string())
             .env("BUILDSYS_VERSION_IMAGE", project.release_version())
+            .env(
+                "TLPRIVATE_FORCE_SDK_REFRESH",
+                self.force_sdk_refresh.to_string(),
+            )
             .makefile(makefile_path)
             .project_dir(project.project_dir())
             .exec_with_args(&self.makefile_task, self.additional_args.clone())
@@ -197,6 +213,9 @@ mod test {
     async fn twoliter_update(project_path: &Path) {
         let command = Update {
             project_path: Some(project_path.to_path_buf()),
+            prune: false,
+            only: Vec::new(),
+            keep_going: false,
         };
         command.run().await.unwrap();
     }
@@ -205,6 +224,15 @@ mod test {
         target: &str,
         project_dir: &Path,
         delete_verifier_tags: bool,
+    ) -> Result<()> {
+        run_makefile_target_with_env(target, project_dir, delete_verifier_tags, &[]).await
+    }
+
+    async fn run_makefile_target_with_env(
+        target: &str,
+        project_dir: &Path,
+        delete_verifier_tags: bool,
+        env: &[(&str, &str)],
     ) -> Result<()> {
         let project_path = project_dir.join("Twoliter.toml");
 
@@ -227,11 +255,15 @@ mod test {
         install_tools(&toolsdir).await.unwrap();
         let makefile_path = toolsdir.join("Makefile.toml");
 
-        CargoMake::new(&sdk_source)
+        let mut cargo_make = CargoMake::new(&sdk_source)
             .unwrap()
             .env("CARGO_HOME", project_dir.display().to_string())
             .env("TWOLITER_TOOLS_DIR", toolsdir.display().to_string())
-            .env("BUILDSYS_VERSION_IMAGE", project.release_version())
+            .env("BUILDSYS_VERSION_IMAGE", project.release_version());
+        for (key, value) in env {
+            cargo_make = cargo_make.env(*key, *value);
+        }
+        cargo_make
             .makefile(makefile_path)
             .project_dir(project.project_dir())
             .exec_with_args(target, Vec::<&'static str>::new())
@@ -250,6 +282,7 @@ mod test {
             project_path: Some(project_path),
             cargo_home: project_dir.to_owned(),
             arch: "x86_64".to_string(),
+            force_sdk_refresh: false,
             makefile_task: target_name.to_string(),
             additional_args: Vec::new(),
         };
@@ -275,6 +308,113 @@ mod test {
             .is_ok());
     }
 
+    #[test]
+    fn test_force_sdk_refresh_arg() {
+        let args = Make::try_parse_from([
+            "make",
+            "--cargo-home",
+            "/tmp/foo",
+            "--arch",
+            "x86_64",
+            "--force-sdk-refresh",
+            "fetch-sdk",
+        ])
+        .unwrap();
+
+        assert!(args.force_sdk_refresh);
+        assert_eq!(args.makefile_task, "fetch-sdk");
+    }
+
+    async fn sdk_last_tag_time(project_dir: &Path) -> String {
+        let project = project::load_or_find_project(Some(project_dir.join("Twoliter.toml")))
+            .await
+            .unwrap();
+        let project = project.load_lock::<SDKLocked>().await.unwrap();
+        let sdk = project.sdk_image().project_image_uri().to_string();
+        let output = tokio::process::Command::new("docker")
+            .args([
+                "image",
+                "inspect",
+                "--format",
+                "{{.Metadata.LastTagTime}}",
+                &sdk,
+            ])
+            .output()
+            .await
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).to_string()
+    }
+
+    #[tokio::test]
+    #[ignore] // integration test
+    async fn test_fetch_sdk_force_refresh_pulls_cached_sdk() {
+        let temp_dir = crate::test::copy_project_to_temp_dir(PROJECT);
+        let project_dir = temp_dir.path();
+        run_makefile_target("fetch-sdk", project_dir, false)
+            .await
+            .unwrap();
+        let cached = sdk_last_tag_time(project_dir).await;
+
+        run_makefile_target_with_env(
+            "fetch-sdk",
+            project_dir,
+            false,
+            &[("TLPRIVATE_FORCE_SDK_REFRESH", "true")],
+        )
+        .await
+        .unwrap();
+
+        // The SDK is loaded into docker again, which updates the time it was last tagged.
+        assert_ne!(cached, sdk_last_tag_time(project_dir).await);
+    }
+
+    #[tokio::test]
+    #[ignore] // integration test
+    async fn test_fetch_sdk_compressed_archive_loads() {
+        let temp_dir = crate::test::copy_project_to_temp_dir(PROJECT);
+        let project_dir = temp_dir.path();
+        for compression in ["gzip", "zstd"] {
+            run_makefile_target_with_env(
+                "fetch-sdk",
+                project_dir,
+                false,
+                &[
+                    ("TLPRIVATE_FORCE_SDK_REFRESH", "true"),
+                    ("BUILDSYS_SDK_ARCHIVE_COMPRESSION", compression),
+                ],
+            )
+            .await
+            .unwrap();
+            // The SDK was loaded into docker from the compressed archive.
+            sdk_last_tag_time(project_dir).await;
+        }
+    }
+
+    #[tokio::test]
+    #[ignore] // integration test
+    async fn test_fetch_sdk_keeps_complete_archive() {
+        let temp_dir = crate::test::copy_project_to_temp_dir(PROJECT);
+        let project_dir = temp_dir.path();
+        run_makefile_target_with_env(
+            "fetch-sdk",
+            project_dir,
+            false,
+            &[("TLPRIVATE_FORCE_SDK_REFRESH", "true")],
+        )
+        .await
+        .unwrap();
+
+        // Only the complete archive and its marker are left behind for the next run to reuse.
+        let archives = std::fs::read_dir(project_dir.join("build/external-sdk-archives"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(archives.len(), 2, "{archives:?}");
+        assert!(archives.iter().any(|name| name.ends_with(".tar.complete")));
+        assert!(!archives.iter().any(|name| name.contains(".partial")));
+    }
+
     #[tokio::test]
     #[ignore] // integration test
     async fn test_fetch_sdk_fails_when_nothing_verified() {
@@ -1,21 +1,38 @@
 mod build;
 mod build_clean;
 mod debug;
+mod dump_resolved_toml;
 mod fetch;
+mod inspect_kit;
 mod make;
+mod preflight;
 mod publish_kit;
 mod update;
+mod validate;
+mod validate_kit_labels;
+mod verify_release;
+mod warm_cache;
+mod which_tool;
 
 use self::build::BuildCommand;
 use crate::cmd::debug::DebugAction;
+use crate::cmd::dump_resolved_toml::DumpResolvedToml;
 use crate::cmd::fetch::Fetch;
+use crate::cmd::inspect_kit::InspectKit;
 use crate::cmd::make::Make;
+use crate::cmd::preflight::Preflight;
 use crate::cmd::publish_kit::PublishCommand;
 use crate::cmd::update::Update;
+use crate::cmd::validate::Validate;
+use crate::cmd::validate_kit_labels::ValidateKitLabels;
+use crate::cmd::verify_release::VerifyRelease;
+use crate::cmd::warm_cache::WarmCache;
+use crate::cmd::which_tool::WhichTool;
 use anyhow::Result;
 use clap::Parser;
 use env_logger::Builder;
 use log::LevelFilter;
+use std::path::PathBuf;
 
 const DEFAULT_LEVEL_FILTER: LevelFilter = LevelFilter::Info;
 
@@ -29,6 +46,29 @@ pub(crate) struct Args {
     #[clap(long = "log-level")]
     pub(crate) log_level: Option<LevelFilter>,
 
+    /// Echo the output of the image tool commands twoliter runs, such as crane, as they run. This
+    /// is the same as setting TWOLITER_VERBOSE_SUBPROCESS=true.
+    #[clap(long = "verbose-subprocess")]
+    pub(crate) verbose_subprocess: bool,
+
+    /// Fail instead of accessing the network, naming the operation that tried to. Kits, the SDK
+    /// and sources must already have been fetched. This is the same as setting
+    /// TWOLITER_OFFLINE=true.
+    #[clap(long = "offline")]
+    pub(crate) offline: bool,
+
+    /// Log the image tool commands that would push, tag, delete or pull images instead of running
+    /// them. Commands that only read from a registry still run. This is the same as setting
+    /// TWOLITER_DRY_RUN=true.
+    #[clap(long = "dry-run")]
+    pub(crate) dry_run: bool,
+
+    /// A docker config.json to read registry credentials from, instead of setting DOCKER_CONFIG.
+    /// Credentials in TWOLITER_REGISTRY_AUTH take precedence over the file's for the same
+    /// registry. This is the same as setting TWOLITER_REGISTRY_AUTH_FILE.
+    #[clap(long = "registry-auth-file")]
+    pub(crate) registry_auth_file: Option<PathBuf>,
+
     #[clap(subcommand)]
     pub(crate) subcommand: Subcommand,
 }
@@ -41,6 +81,8 @@ pub(crate) enum Subcommand {
 
     Fetch(Fetch),
 
+    InspectKit(InspectKit),
+
     Make(Make),
 
     /// Update Twoliter.lock
@@ -53,6 +95,20 @@ pub(crate) enum Subcommand {
     /// Commands that are used for checking and troubleshooting Twoliter's internals.
     #[clap(subcommand)]
     Debug(DebugAction),
+
+    DumpResolvedToml(DumpResolvedToml),
+
+    Preflight(Preflight),
+
+    Validate(Validate),
+
+    ValidateKitLabels(ValidateKitLabels),
+
+    VerifyRelease(VerifyRelease),
+
+    WarmCache(WarmCache),
+
+    WhichTool(WhichTool),
 }
 
 /// Entrypoint for the `twoliter` command line program.
@@ -60,10 +116,20 @@ pub(super) async fn run(args: Args) -> Result<()> {
     match args.subcommand {
         Subcommand::Build(build_command) => build_command.run().await,
         Subcommand::Fetch(fetch_args) => fetch_args.run().await,
+        Subcommand::InspectKit(inspect_kit_args) => inspect_kit_args.run().await,
         Subcommand::Make(make_args) => make_args.run().await,
         Subcommand::Update(update_args) => update_args.run().await,
         Subcommand::Publish(publish_command) => publish_command.run().await,
         Subcommand::Debug(debug_action) => debug_action.run().await,
+        Subcommand::DumpResolvedToml(dump_args) => dump_args.run().await,
+        Subcommand::Preflight(preflight_args) => preflight_args.run().await,
+        Subcommand::Validate(validate_args) => validate_args.run().await,
+        Subcommand::ValidateKitLabels(validate_kit_labels_args) => {
+            validate_kit_labels_args.run().await
+        }
+        Subcommand::VerifyRelease(verify_release_args) => verify_release_args.run().await,
+        Subcommand::WarmCache(warm_cache_args) => warm_cache_args.run().await,
+        Subcommand::WhichTool(which_tool_args) => which_tool_args.run().await,
     }
 }
 
@@ -92,10 +158,11 @@ pub(super) fn init_logger(level: Option<LevelFilter>) {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::cmd::build::BuildKit;
+    use crate::cmd::build::{BuildKit, BuildPackage};
     use async_walkdir::WalkDir;
     use futures::stream::StreamExt;
     use std::collections::HashSet;
+    use std::num::NonZeroUsize;
     use std::path::Path;
 
     const PROJECT: &str = "local-kit";
@@ -134,6 +201,9 @@ mod test {
     async fn twoliter_update(project_path: &Path) {
         let command = Update {
             project_path: Some(project_path.to_path_buf()),
+            prune: false,
+            only: Vec::new(),
+            keep_going: false,
         };
         command.run().await.unwrap();
     }
@@ -142,10 +212,41 @@ mod test {
         let command = Fetch {
             project_path: Some(project_path.to_path_buf()),
             arch: arch.into(),
+            jobs: NonZeroUsize::new(2).unwrap(),
         };
         command.run().await.unwrap()
     }
 
+    #[tokio::test]
+    #[ignore] // integration test
+    async fn build_filtered_package() {
+        let arch = "x86_64";
+        let temp_dir = crate::test::copy_project_to_temp_dir(PROJECT);
+        let project_dir = temp_dir.path();
+        let project_path = project_dir.join("Twoliter.toml");
+        twoliter_update(&project_path).await;
+        twoliter_fetch(&project_path, arch).await;
+
+        let command = BuildPackage {
+            project_path: Some(project_path),
+            arch: arch.to_string(),
+            package: vec!["pkg-a*".to_string()],
+            lookaside_cache: None,
+            upstream_source_fallback: false,
+        };
+
+        command.run().await.unwrap();
+
+        let mut built = Vec::new();
+        let mut entries = tokio::fs::read_dir(project_dir.join("build/rpms"))
+            .await
+            .unwrap();
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            built.push(entry.file_name().to_string_lossy().to_string());
+        }
+        assert_eq!(built, ["pkg-a"]);
+    }
+
     #[tokio::test]
     #[ignore] // integration test
     async fn build_core_kit() {
@@ -0,0 +1,32 @@
+use crate::preflight::run_checks;
+use anyhow::{ensure, Result};
+use clap::Parser;
+
+/// Run the environment checks that twoliter performs at startup and report the result of each.
+#[derive(Debug, Parser)]
+pub(crate) struct Preflight {
+    /// Print the results as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+impl Preflight {
+    pub(super) async fn run(&self) -> Result<()> {
+        let results = run_checks().await;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&results)?);
+        } else {
+            for result in &results {
+                match (&result.error, &result.warning) {
+                    (Some(e), _) => println!("FAIL  {}: {}", result.name, e),
+                    (None, Some(w)) => println!("WARN  {}: {}", result.name, w),
+                    (None, None) => println!("PASS  {}", result.name),
+                }
+            }
+        }
+
+        let failed = results.iter().filter(|result| !result.passed).count();
+        ensure!(failed == 0, "{failed} preflight check(s) failed");
+        Ok(())
+    }
+}
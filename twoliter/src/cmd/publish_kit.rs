@@ -34,6 +34,15 @@ pub(crate) struct PublishKit {
 
     /// Publish kit image to a different repository than the kit's name
     kit_repo: Option<String>,
+
+    /// Push the per-architecture images to this repository, in the same registry, instead of the
+    /// kit's repository
+    #[clap(long = "platform-repo")]
+    platform_repo: Option<String>,
+
+    /// Skip uploading kit images that already exist in the registry
+    #[clap(long = "skip-existing")]
+    skip_existing: bool,
 }
 
 impl PublishKit {
@@ -54,6 +63,14 @@ impl PublishKit {
             .env("BUILDSYS_VERSION_IMAGE", project.release_version())
             .env("PUBLISH_VENDOR", &self.vendor)
             .env("PUBLISH_KIT_REPO", publish_kit_repo)
+            .env(
+                "PUBLISH_KIT_PLATFORM_REPO",
+                self.platform_repo.as_deref().unwrap_or_default(),
+            )
+            .env(
+                "PUBLISH_SKIP_EXISTING",
+                if self.skip_existing { "true" } else { "" },
+            )
             .makefile(makefile_path)
             .project_dir(project.project_dir())
             .exec("publish-kit")
@@ -1,4 +1,4 @@
-use crate::project;
+use crate::project::{self, DependencyFilter};
 use anyhow::Result;
 use clap::Parser;
 use std::path::PathBuf;
@@ -8,12 +8,29 @@ pub(crate) struct Update {
     /// Path to Twoliter.toml. Will search for Twoliter.toml when absent
     #[clap(long = "project-path")]
     pub(crate) project_path: Option<PathBuf>,
+
+    /// Remove entries from Twoliter.lock whose vendor is no longer defined in Twoliter.toml.
+    #[clap(long = "prune")]
+    pub(crate) prune: bool,
+
+    /// Only update the given dependency, as `name` or `name@vendor`, leaving all others at their
+    /// locked versions. May be given more than once.
+    #[clap(long = "only", value_name = "NAME[@VENDOR]")]
+    pub(crate) only: Vec<DependencyFilter>,
+
+    /// Keep resolving the remaining dependencies when one fails to resolve, and write those that
+    /// succeeded to Twoliter.lock. The update still fails, listing the dependencies that could
+    /// not be resolved.
+    #[clap(long = "keep-going")]
+    pub(crate) keep_going: bool,
 }
 
 impl Update {
     pub(super) async fn run(&self) -> Result<()> {
         let project = project::load_or_find_project(self.project_path.clone()).await?;
-        project.create_lock().await?;
+        project
+            .create_lock(self.prune, &self.only, self.keep_going)
+            .await?;
         Ok(())
     }
 }
@@ -0,0 +1,35 @@
+use crate::project::{find_problems, find_project_file};
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use std::path::PathBuf;
+
+/// Check Twoliter.toml and Twoliter.lock for problems without contacting a registry, reporting
+/// every problem found.
+#[derive(Debug, Parser)]
+pub(crate) struct Validate {
+    /// Path to Twoliter.toml. Will search for Twoliter.toml when absent
+    #[clap(long = "project-path")]
+    pub(crate) project_path: Option<PathBuf>,
+}
+
+impl Validate {
+    pub(super) async fn run(&self) -> Result<()> {
+        let path = match &self.project_path {
+            Some(path) => path.clone(),
+            None => find_project_file(
+                &std::env::current_dir().context("Unable to get the current directory")?,
+            )?,
+        };
+        let problems = find_problems(&path).await?;
+        if !problems.is_empty() {
+            bail!(
+                "Found {} problem(s) in the project at '{}':\n  {}",
+                problems.len(),
+                path.display(),
+                problems.join("\n  ")
+            );
+        }
+        println!("No problems found in the project at '{}'", path.display());
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+use crate::project::image_tool;
+use anyhow::{ensure, Context, Result};
+use clap::Parser;
+use oci_cli_wrapper::{ConfigView, DockerArchitecture};
+use regex::Regex;
+use std::str::FromStr;
+
+/// Check that a kit image carries the labels it needs before depending on it, reporting every
+/// missing or malformed label.
+#[derive(Debug, Parser)]
+pub(crate) struct ValidateKitLabels {
+    /// The kit image, e.g. public.ecr.aws/bottlerocket/bottlerocket-core-kit:v1.0.0
+    uri: String,
+
+    /// A label that must be present, e.g. org.bottlerocket.kit.name. Use `<label>=<pattern>` to
+    /// also require that the whole value matches a regular expression, e.g.
+    /// 'org.bottlerocket.kit.version=\d+\.\d+\.\d+'.
+    #[clap(long = "require", required = true)]
+    requirements: Vec<LabelRequirement>,
+
+    /// For a multi-arch kit, the architecture whose image labels are checked.
+    #[clap(long = "arch", default_value = "x86_64")]
+    arch: String,
+}
+
+impl ValidateKitLabels {
+    pub(super) async fn run(&self) -> Result<()> {
+        let arch = DockerArchitecture::try_from(self.arch.as_str())
+            .with_context(|| format!("Unsupported architecture '{}'", self.arch))?;
+        let config = image_tool()?
+            .get_config(&self.uri, Some(&arch))
+            .await
+            .with_context(|| format!("Failed to fetch the image config of '{}'", self.uri))?;
+        let problems = label_problems(&config, &self.requirements);
+        for problem in &problems {
+            eprintln!("{problem}");
+        }
+        ensure!(
+            problems.is_empty(),
+            "'{}' has {} label problem(s)",
+            self.uri,
+            problems.len()
+        );
+        println!("'{}' has every required label", self.uri);
+        Ok(())
+    }
+}
+
+/// A label that must be present, and optionally a pattern its value must match.
+#[derive(Debug, Clone)]
+struct LabelRequirement {
+    label: String,
+    pattern: Option<Regex>,
+}
+
+impl FromStr for LabelRequirement {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (label, pattern) = match s.split_once('=') {
+            Some((label, pattern)) => {
+                // Anchor the pattern so that it must match the whole value.
+                let pattern = Regex::new(&format!("^(?:{pattern})$"))
+                    .with_context(|| format!("Invalid pattern for label '{label}'"))?;
+                (label, Some(pattern))
+            }
+            None => (s, None),
+        };
+        ensure!(!label.is_empty(), "Label name must not be empty in '{s}'");
+        Ok(Self {
+            label: label.to_string(),
+            pattern,
+        })
+    }
+}
+
+/// Describes each of `requirements` that the labels in `config` do not meet.
+fn label_problems(config: &ConfigView, requirements: &[LabelRequirement]) -> Vec<String> {
+    let mut problems = Vec::new();
+    for requirement in requirements {
+        match config.labels.get(&requirement.label) {
+            None => problems.push(format!("Missing label '{}'", requirement.label)),
+            Some(value) => {
+                if let Some(pattern) = &requirement.pattern {
+                    if !pattern.is_match(value) {
+                        problems.push(format!(
+                            "Label '{}' has value '{value}', which does not match '{}'",
+                            requirement.label,
+                            pattern.as_str()
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn local_kit() -> ConfigView {
+        ConfigView {
+            labels: HashMap::from([
+                (
+                    "org.bottlerocket.kit.name".to_string(),
+                    "core-kit".to_string(),
+                ),
+                (
+                    "org.bottlerocket.kit.version".to_string(),
+                    "2.0.0".to_string(),
+                ),
+            ]),
+            ..Default::default()
+        }
+    }
+
+    fn requirements(requirements: &[&str]) -> Vec<LabelRequirement> {
+        requirements.iter().map(|r| r.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_labels_present() {
+        let requirements = requirements(&[
+            "org.bottlerocket.kit.name",
+            r"org.bottlerocket.kit.version=\d+\.\d+\.\d+",
+        ]);
+        assert!(label_problems(&local_kit(), &requirements).is_empty());
+    }
+
+    #[test]
+    fn test_all_failures_reported() {
+        let requirements = requirements(&[
+            "org.bottlerocket.kit.vendor",
+            r"org.bottlerocket.kit.version=\d+",
+            "org.bottlerocket.kit.name",
+        ]);
+        let problems = label_problems(&local_kit(), &requirements);
+        assert_eq!(
+            problems,
+            [
+                "Missing label 'org.bottlerocket.kit.vendor'",
+                r"Label 'org.bottlerocket.kit.version' has value '2.0.0', which does not match '^(?:\d+)$'",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_requirement() {
+        assert!("org.bottlerocket.kit.version=("
+            .parse::<LabelRequirement>()
+            .is_err());
+        assert!("=.*".parse::<LabelRequirement>().is_err());
+    }
+}
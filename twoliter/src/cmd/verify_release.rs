@@ -0,0 +1,183 @@
+use crate::preflight::SUPPORTED_ARCHES;
+use crate::project::image_tool;
+use anyhow::{bail, ensure, Context, Result};
+use buildsys::manifest::ManifestInfo;
+use clap::Parser;
+use oci_cli_wrapper::{DockerArchitecture, ManifestView};
+use std::path::{Path, PathBuf};
+
+/// Check that a published multi-arch image, such as a kit, has an image for every architecture
+/// before announcing the release.
+#[derive(Debug, Parser)]
+pub(crate) struct VerifyRelease {
+    /// The published image, e.g. public.ecr.aws/bottlerocket/bottlerocket-core-kit:v1.0.0
+    uri: String,
+
+    /// An architecture that must have an image. Defaults to the `supported-arches` of the kit
+    /// manifest, or to every supported architecture if it lists none.
+    #[clap(long = "arch")]
+    arches: Vec<String>,
+
+    /// Path to the Cargo.toml of the kit that was published
+    #[clap(long = "kit-manifest")]
+    kit_manifest: Option<PathBuf>,
+}
+
+impl VerifyRelease {
+    pub(super) async fn run(&self) -> Result<()> {
+        let arches = if self.arches.is_empty() {
+            default_arches(self.kit_manifest.as_deref())?
+        } else {
+            self.arches.clone()
+        };
+        let manifest = image_tool()?
+            .get_manifest_parsed(&self.uri)
+            .await
+            .with_context(|| format!("Failed to fetch the manifest of '{}'", self.uri))?;
+        let missing = missing_arches(&self.uri, &manifest, &arches)?;
+        ensure!(
+            missing.is_empty(),
+            "'{}' has no image for {}",
+            self.uri,
+            missing.join(", ")
+        );
+        println!("'{}' has images for {}", self.uri, arches.join(", "));
+        Ok(())
+    }
+}
+
+/// The architectures a kit declares in the `supported-arches` of its manifest, sorted. Without a
+/// manifest, or if it lists none, every architecture twoliter supports is returned.
+fn default_arches(kit_manifest: Option<&Path>) -> Result<Vec<String>> {
+    let mut arches = Vec::new();
+    if let Some(path) = kit_manifest {
+        let manifest = ManifestInfo::new(path)
+            .with_context(|| format!("Failed to read kit manifest '{}'", path.display()))?;
+        arches.extend(
+            manifest
+                .supported_arches()
+                .into_iter()
+                .flatten()
+                .map(|arch| arch.to_string()),
+        );
+        arches.sort();
+    }
+    if arches.is_empty() {
+        arches.extend(SUPPORTED_ARCHES.iter().map(|arch| arch.to_string()));
+    }
+    Ok(arches)
+}
+
+/// The architectures in `arches` without an image in the image index `manifest`.
+fn missing_arches<'a>(
+    uri: &str,
+    manifest: &ManifestView,
+    arches: &'a [String],
+) -> Result<Vec<&'a str>> {
+    let ManifestView::Index { manifests, .. } = manifest else {
+        bail!("'{uri}' is not a multi-arch image");
+    };
+    let mut missing = Vec::new();
+    for arch in arches {
+        let docker_arch = DockerArchitecture::try_from(arch.as_str())
+            .with_context(|| format!("Unsupported architecture '{arch}'"))?;
+        if !manifests
+            .iter()
+            .any(|manifest| manifest.architecture == docker_arch)
+        {
+            missing.push(arch.as_str());
+        }
+    }
+    Ok(missing)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use oci_cli_wrapper::PlatformDescriptor;
+    use std::collections::HashMap;
+
+    const URI: &str = "example.com/my-kit:v1.0.0";
+
+    fn index(architectures: &[DockerArchitecture]) -> ManifestView {
+        ManifestView::Index {
+            media_type: None,
+            manifests: architectures
+                .iter()
+                .map(|architecture| PlatformDescriptor {
+                    architecture: architecture.clone(),
+                    os: "linux".to_string(),
+                    variant: None,
+                    os_version: None,
+                    digest: "sha256:aaaa".to_string(),
+                })
+                .collect(),
+            attestations: Vec::new(),
+            annotations: HashMap::new(),
+        }
+    }
+
+    fn arches() -> Vec<String> {
+        SUPPORTED_ARCHES
+            .iter()
+            .map(|arch| arch.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_release_with_every_arch() {
+        let manifest = index(&[DockerArchitecture::Amd64, DockerArchitecture::Arm64]);
+        assert!(missing_arches(URI, &manifest, &arches())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_release_missing_an_arch() {
+        let manifest = index(&[DockerArchitecture::Amd64]);
+        assert_eq!(
+            missing_arches(URI, &manifest, &arches()).unwrap(),
+            ["aarch64"]
+        );
+    }
+
+    #[test]
+    fn test_release_of_single_arch_image() {
+        let manifest = ManifestView::Image {
+            media_type: None,
+            config_digest: "sha256:cccc".to_string(),
+            layers: Vec::new(),
+            annotations: HashMap::new(),
+        };
+        assert!(missing_arches(URI, &manifest, &arches()).is_err());
+    }
+
+    #[test]
+    fn test_default_arches_from_kit_manifest() {
+        assert_eq!(default_arches(None).unwrap(), arches());
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let manifest = dir.path().join("Cargo.toml");
+        let kit = |metadata: &str| {
+            format!(
+                r#"
+[package]
+name = "my-kit"
+version = "0.1.0"
+
+[package.metadata.build-kit]
+vendor = "my-vendor"
+{metadata}
+"#
+            )
+        };
+        std::fs::write(&manifest, kit(r#"supported-arches = ["aarch64"]"#)).unwrap();
+        assert_eq!(default_arches(Some(&manifest)).unwrap(), ["aarch64"]);
+
+        // A manifest without supported arches falls back to every supported architecture.
+        std::fs::write(&manifest, kit("")).unwrap();
+        assert_eq!(default_arches(Some(&manifest)).unwrap(), arches());
+
+        assert!(default_arches(Some(&dir.path().join("missing.toml"))).is_err());
+    }
+}
@@ -0,0 +1,298 @@
+use crate::docker::Docker;
+use crate::project::{self, Locked};
+use anyhow::{ensure, Context, Result};
+use async_trait::async_trait;
+use clap::Parser;
+use oci_cli_wrapper::{DockerArchitecture, ImageTool};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{debug, info};
+
+/// The first major version of Docker that can `docker load` an OCI image layout
+const MINIMUM_OCI_LOAD_DOCKER_VERSION: u64 = 25;
+
+/// Pull every image the project depends on, its SDK and kits, into the docker daemon so that
+/// builds can run without network access. Images the daemon already has are skipped.
+#[derive(Debug, Parser)]
+pub(crate) struct WarmCache {
+    /// Path to Twoliter.toml. Will search for Twoliter.toml when absent
+    #[clap(long = "project-path")]
+    project_path: Option<PathBuf>,
+}
+
+impl WarmCache {
+    pub(super) async fn run(&self) -> Result<()> {
+        let project = project::load_or_find_project(self.project_path.clone()).await?;
+        let project = project.load_lock::<Locked>().await?;
+        let uris: Vec<String> = project
+            .all_images()
+            .iter()
+            .map(|image| image.project_image_uri().to_string())
+            .collect();
+
+        let daemon = DockerDaemon::new().await?;
+        let report = warm_cache(&uris, &daemon).await?;
+        for uri in &report.loaded {
+            if daemon.image_tool.dry_run() {
+                println!("loaded   {uri} (dry run, not pulled)");
+            } else {
+                println!("loaded   {uri}");
+            }
+        }
+        for uri in &report.skipped {
+            println!("skipped  {uri} (already cached)");
+        }
+        Ok(())
+    }
+}
+
+/// An image cache that images can be loaded into.
+#[async_trait]
+trait ImageCache {
+    async fn contains(&self, uri: &str) -> Result<bool>;
+    async fn load(&self, uri: &str) -> Result<()>;
+}
+
+/// The docker daemon's image cache. Images are pulled for the daemon's platform through the
+/// project's image tool, so credentials, mirrors and URI rewrites apply as they do for other pulls,
+/// then loaded with `docker load`. In dry-run mode, images are only reported as loaded.
+struct DockerDaemon {
+    image_tool: Arc<ImageTool>,
+    arch: DockerArchitecture,
+}
+
+impl DockerDaemon {
+    async fn new() -> Result<Self> {
+        let version = Docker::server_version().await?;
+        ensure!(
+            version.major >= MINIMUM_OCI_LOAD_DOCKER_VERSION,
+            "Docker {version} cannot load OCI images; warm-cache requires Docker \
+            {MINIMUM_OCI_LOAD_DOCKER_VERSION}.0.0 or later"
+        );
+        let platform = Docker::server_platform().await?;
+        let arch = platform
+            .split('/')
+            .nth(1)
+            .and_then(|arch| DockerArchitecture::try_from(arch).ok())
+            .with_context(|| format!("Unsupported docker platform '{platform}'"))?;
+        Ok(Self {
+            image_tool: project::image_tool()?,
+            arch,
+        })
+    }
+
+    /// Pulls the image for the daemon's architecture from `uri`, which may be an image index, into
+    /// an OCI image layout at `layout`.
+    async fn pull(&self, uri: &str, layout: &Path) -> Result<()> {
+        let image = self
+            .image_tool
+            .get_platform_image(uri, Some(&self.arch))
+            .await
+            .with_context(|| format!("Failed to find the {} image of '{uri}'", self.arch))?;
+        self.image_tool
+            .pull_oci_image(layout, &image)
+            .await
+            .with_context(|| format!("Failed to pull '{image}'"))
+    }
+}
+
+#[async_trait]
+impl ImageCache for DockerDaemon {
+    async fn contains(&self, uri: &str) -> Result<bool> {
+        Docker::image_exists(&daemon_reference(uri)).await
+    }
+
+    async fn load(&self, uri: &str) -> Result<()> {
+        let temp_dir = tempfile::tempdir().context("Failed to create directory for image")?;
+        let layout = temp_dir.path().join("image");
+        self.pull(uri, &layout).await?;
+        if self.image_tool.dry_run() {
+            return Ok(());
+        }
+        let archive = temp_dir.path().join("image.tar");
+        let mut builder = tar::Builder::new(
+            std::fs::File::create(&archive).context("Failed to create image archive")?,
+        );
+        builder
+            .append_dir_all(".", &layout)
+            .and_then(|()| builder.finish())
+            .with_context(|| format!("Failed to archive the image pulled from '{uri}'"))?;
+        if let Some(image) = Docker::load(&archive).await? {
+            let reference = daemon_reference(uri);
+            debug!("Loaded '{uri}' into docker as '{image}', tagging it '{reference}'");
+            Docker::tag(&image, &reference).await?;
+        }
+        Ok(())
+    }
+}
+
+/// The name an image is cached under in the docker daemon. Docker cannot tag an image with a
+/// digest, so an image pinned as `<repository>@sha256:<hex>` is tagged
+/// `<repository>:sha256-<hex>` instead. Since the digest is part of the tag, a cached image only
+/// matches while the project pins that same digest.
+fn daemon_reference(uri: &str) -> String {
+    let Some((name, digest)) = uri.split_once('@') else {
+        return uri.to_string();
+    };
+    let repository = match name.rsplit_once(':') {
+        Some((repository, tag)) if !tag.contains('/') => repository,
+        _ => name,
+    };
+    format!("{repository}:{}", digest.replace(':', "-"))
+}
+
+/// The images `warm_cache` loaded, and those that were already cached.
+#[derive(Debug, Default, PartialEq)]
+struct WarmCacheReport {
+    loaded: Vec<String>,
+    skipped: Vec<String>,
+}
+
+async fn warm_cache(uris: &[String], cache: &impl ImageCache) -> Result<WarmCacheReport> {
+    let mut report = WarmCacheReport::default();
+    for uri in uris {
+        if cache.contains(uri).await? {
+            report.skipped.push(uri.clone());
+            continue;
+        }
+        info!("Loading '{uri}' into the image cache");
+        cache.load(uri).await?;
+        report.loaded.push(uri.clone());
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use oci_cli_wrapper::MockImageTool;
+    use serde_json::json;
+    use sha2::{Digest, Sha256};
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+
+    /// A daemon that records the images loaded into it.
+    #[derive(Default)]
+    struct StubDaemon {
+        images: Mutex<HashSet<String>>,
+    }
+
+    #[async_trait]
+    impl ImageCache for StubDaemon {
+        async fn contains(&self, uri: &str) -> Result<bool> {
+            Ok(self.images.lock().unwrap().contains(uri))
+        }
+
+        async fn load(&self, uri: &str) -> Result<()> {
+            self.images.lock().unwrap().insert(uri.to_string());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_warm_cache_loads_every_image() {
+        let uris = vec![
+            "a.com/b/my-bottlerocket-sdk:v1.2.3".to_string(),
+            "a.com/b/my-core-kit:v1.2.3".to_string(),
+            "a.com/b/my-extra-kit:v1.0.0".to_string(),
+        ];
+        let daemon = StubDaemon::default();
+        daemon.images.lock().unwrap().insert(uris[0].clone());
+
+        let report = warm_cache(&uris, &daemon).await.unwrap();
+        assert_eq!(
+            report,
+            WarmCacheReport {
+                loaded: uris[1..].to_vec(),
+                skipped: uris[..1].to_vec(),
+            }
+        );
+        assert_eq!(
+            *daemon.images.lock().unwrap(),
+            uris.iter().cloned().collect::<HashSet<_>>()
+        );
+
+        let report = warm_cache(&uris, &daemon).await.unwrap();
+        assert!(report.loaded.is_empty());
+        assert_eq!(report.skipped, uris);
+    }
+
+    #[tokio::test]
+    async fn test_docker_daemon_pulls_platform_image_through_image_tool() {
+        let uri = "a.com/b/my-core-kit:v1.2.3";
+        let manifest = json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": {
+                "mediaType": "application/vnd.oci.image.config.v1+json",
+                "digest": "sha256:aaaa",
+                "size": 2,
+            },
+            "layers": [],
+        })
+        .to_string();
+        let digest = format!("sha256:{:x}", Sha256::digest(manifest.as_bytes()));
+        let index = json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": [
+                {
+                    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                    "digest": "sha256:bbbb",
+                    "size": 2,
+                    "platform": { "architecture": "amd64", "os": "linux" },
+                },
+                {
+                    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                    "digest": digest,
+                    "size": manifest.len(),
+                    "platform": { "architecture": "arm64", "os": "linux" },
+                },
+            ],
+        })
+        .to_string();
+        let mock = MockImageTool::new()
+            .with_manifest(uri, index)
+            .with_manifest("a.com/b/my-core-kit:v1.2.3-arm64", manifest)
+            .with_archive("a.com/b/my-core-kit:v1.2.3-arm64", "layout");
+        let daemon = DockerDaemon {
+            image_tool: Arc::new(mock.image_tool()),
+            arch: DockerArchitecture::Arm64,
+        };
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        daemon
+            .pull(uri, &temp_dir.path().join("image"))
+            .await
+            .unwrap();
+        let pulls: Vec<_> = mock
+            .calls()
+            .into_iter()
+            .filter(|call| call.operation == "pull")
+            .map(|call| call.uri)
+            .collect();
+        assert_eq!(pulls, vec![format!("a.com/b/my-core-kit@{digest}")]);
+    }
+
+    #[test]
+    fn test_daemon_reference_tags_digest_pinned_images_by_digest() {
+        let digest = "sha256:5b0f5c5e4d6a3e0b7c2b0b4f1b9d7c1e2a3f4b5c6d7e8f90a1b2c3d4e5f60718";
+        let tag = "a.com/b/my-core-kit:sha256-5b0f5c5e4d6a3e0b7c2b0b4f1b9d7c1e2a3f4b5c6d7e8f90a1b2c3d4e5f60718";
+        assert_eq!(
+            daemon_reference("a.com/b/my-core-kit:v1.2.3"),
+            "a.com/b/my-core-kit:v1.2.3"
+        );
+        assert_eq!(
+            daemon_reference(&format!("a.com/b/my-core-kit@{digest}")),
+            tag
+        );
+        assert_eq!(
+            daemon_reference(&format!("a.com/b/my-core-kit:v1.2.3@{digest}")),
+            tag
+        );
+        assert_eq!(
+            daemon_reference(&format!("localhost:5000/my-core-kit@{digest}")),
+            format!("localhost:5000/my-core-kit:{}", digest.replace(':', "-"))
+        );
+    }
+}
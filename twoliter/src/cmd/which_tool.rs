@@ -0,0 +1,51 @@
+use crate::project::image_tool_with_selection;
+use anyhow::{Context, Result};
+use clap::Parser;
+use oci_cli_wrapper::{ImageToolSelection, ToolInfo};
+
+/// Report which container image tool twoliter uses for registry operations, where its binary is
+/// and which version it is.
+#[derive(Debug, Parser)]
+pub(crate) struct WhichTool {
+    /// Print the result as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+impl WhichTool {
+    pub(super) async fn run(&self) -> Result<()> {
+        let info = tool_info(ImageToolSelection::from_env()).await?;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&info)?);
+        } else {
+            println!("tool: {}", info.backend);
+            println!("path: {}", info.path.display());
+            println!("version: {}", info.version.as_deref().unwrap_or("unknown"));
+        }
+        Ok(())
+    }
+}
+
+async fn tool_info(selection: ImageToolSelection) -> Result<ToolInfo> {
+    image_tool_with_selection(selection)?
+        .tool_info()
+        .await
+        .context("Unable to determine the container image tool")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reports_crane() {
+        // Requesting crane selects the builtin krane.
+        let selection = ImageToolSelection {
+            tool: Some("crane".to_string()),
+            ..Default::default()
+        };
+        let info = tool_info(selection).await.unwrap();
+        assert_eq!(info.backend, "crane");
+        assert!(info.path.is_file());
+    }
+}
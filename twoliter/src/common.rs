@@ -1,5 +1,9 @@
 use anyhow::{ensure, Context, Result};
 use log::{self, LevelFilter};
+use oci_cli_wrapper::CommandOptions;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
 use tokio::process::Command;
 use tracing::{debug, instrument};
 
@@ -9,6 +13,119 @@ use tracing::{debug, instrument};
 /// Twoliter.
 pub(crate) const BUILDSYS_OUTPUT_GENERATION_ID: u32 = 1;
 
+/// Settings from the global command line flags, each of which can also be given by its
+/// environment variable, and the image tool settings that only have an environment variable.
+/// These are set once, before any subcommand runs, rather than exported into our own environment;
+/// child processes that need them receive them through [`GlobalOptions::envs`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct GlobalOptions {
+    /// `--verbose-subprocess` or `TWOLITER_VERBOSE_SUBPROCESS`
+    pub(crate) verbose_subprocess: bool,
+    /// `--offline` or `TWOLITER_OFFLINE`
+    pub(crate) offline: bool,
+    /// `--dry-run` or `TWOLITER_DRY_RUN`
+    pub(crate) dry_run: bool,
+    /// `TWOLITER_REGISTRY_RETRIES`
+    pub(crate) registry_retries: u32,
+    /// `TWOLITER_OPERATION_TIMEOUT`
+    pub(crate) operation_timeout: Duration,
+    /// `--registry-auth-file` or `TWOLITER_REGISTRY_AUTH_FILE`
+    pub(crate) registry_auth_file: Option<PathBuf>,
+}
+
+static GLOBAL_OPTIONS: OnceLock<GlobalOptions> = OnceLock::new();
+
+impl GlobalOptions {
+    /// Combines the flags given on the command line with the environment variables they stand in
+    /// for. A flag that is given always wins.
+    pub(crate) fn new(
+        verbose_subprocess: bool,
+        offline: bool,
+        dry_run: bool,
+        registry_auth_file: Option<PathBuf>,
+    ) -> Self {
+        let env = CommandOptions::from_env();
+        Self {
+            verbose_subprocess: verbose_subprocess || env.verbose,
+            offline: offline || env.offline,
+            dry_run: dry_run || env.dry_run,
+            registry_retries: env.retries,
+            operation_timeout: env.timeout,
+            registry_auth_file: registry_auth_file.or_else(|| {
+                std::env::var_os(oci_cli_wrapper::REGISTRY_AUTH_FILE_ENV)
+                    .filter(|path| !path.is_empty())
+                    .map(PathBuf::from)
+            }),
+        }
+    }
+
+    /// Makes these the options for the rest of this run. Only the first call has any effect.
+    pub(crate) fn install(self) {
+        if GLOBAL_OPTIONS.set(self).is_err() {
+            debug!("Global options were already set");
+        }
+    }
+
+    /// The options for this run, which are read from the environment alone if none were
+    /// installed, as happens in tests.
+    pub(crate) fn get() -> &'static GlobalOptions {
+        GLOBAL_OPTIONS.get_or_init(|| Self::new(false, false, false, None))
+    }
+
+    /// How the image tool should run its commands.
+    pub(crate) fn command_options(&self) -> CommandOptions {
+        CommandOptions {
+            verbose: self.verbose_subprocess,
+            offline: self.offline,
+            dry_run: self.dry_run,
+            retries: self.registry_retries,
+            timeout: self.operation_timeout,
+        }
+    }
+
+    /// The environment variables that pass these options on to a child process. Image tool
+    /// commands run by pubsys read the `TWOLITER_*` variables, and buildsys reads
+    /// `BUILDSYS_OFFLINE`.
+    pub(crate) fn envs(&self) -> Vec<(&'static str, String)> {
+        let mut envs = Vec::new();
+        if self.verbose_subprocess {
+            envs.push((oci_cli_wrapper::VERBOSE_SUBPROCESS_ENV, "true".to_string()));
+        }
+        if self.offline {
+            envs.push((oci_cli_wrapper::OFFLINE_ENV, "true".to_string()));
+            envs.push(("BUILDSYS_OFFLINE", "true".to_string()));
+        }
+        if self.dry_run {
+            envs.push((oci_cli_wrapper::DRY_RUN_ENV, "true".to_string()));
+        }
+        envs.push((
+            oci_cli_wrapper::REGISTRY_RETRIES_ENV,
+            self.registry_retries.to_string(),
+        ));
+        envs.push((
+            oci_cli_wrapper::OPERATION_TIMEOUT_ENV,
+            self.operation_timeout.as_secs().to_string(),
+        ));
+        if let Some(auth_file) = &self.registry_auth_file {
+            envs.push((
+                oci_cli_wrapper::REGISTRY_AUTH_FILE_ENV,
+                auth_file.display().to_string(),
+            ));
+        }
+        envs
+    }
+}
+
+/// Whether network access is disabled for this run, by `--offline` or `TWOLITER_OFFLINE`.
+pub(crate) fn offline() -> bool {
+    GlobalOptions::get().offline
+}
+
+/// Whether this run only logs what it would push or pull, by `--dry-run` or `TWOLITER_DRY_RUN`.
+pub(crate) fn dry_run() -> bool {
+    GlobalOptions::get().dry_run
+}
+
 /// Run a `tokio::process::Command` and return a `Result` letting us know whether or not it worked.
 /// Pipes stdout/stderr when logging `LevelFilter` is more verbose than `Warn`.
 #[instrument(level = "trace", skip(cmd))]
@@ -1,7 +1,15 @@
-use crate::common::exec;
+use crate::common::{dry_run, exec};
 use anyhow::{Context, Result};
 use semver::Version;
+use std::future::Future;
+use std::path::Path;
+use std::process::Stdio;
 use tokio::process::Command;
+use tokio::sync::OnceCell;
+use tracing::info;
+
+/// The platform of the docker daemon, once it has been fetched
+static SERVER_PLATFORM: OnceCell<String> = OnceCell::const_new();
 
 pub(crate) struct Docker;
 
@@ -21,4 +29,163 @@ impl Docker {
 
         Version::parse(&version_str).context("Failed to parse docker version as semver")
     }
+
+    /// Fetches the platform of the docker daemon, e.g. `linux/amd64`. The platform cannot change
+    /// while twoliter runs, so docker is only asked once it has answered successfully.
+    pub(crate) async fn server_platform() -> Result<String> {
+        cached(&SERVER_PLATFORM, Self::fetch_server_platform).await
+    }
+
+    async fn fetch_server_platform() -> Result<String> {
+        exec(
+            Command::new("docker").args(["version", "--format", "{{.Server.Os}}/{{.Server.Arch}}"]),
+            true,
+        )
+        .await
+        .ok()
+        .flatten()
+        .map(|s| s.trim().to_string())
+        .context("Failed to fetch docker platform")
+    }
+
+    /// Checks whether the docker daemon already has the image `uri`
+    pub(crate) async fn image_exists(uri: &str) -> Result<bool> {
+        let status = Command::new("docker")
+            .args(["image", "inspect", uri])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await
+            .context("Unable to start docker")?;
+        Ok(status.success())
+    }
+
+    /// Loads the image archive at `path` into the docker daemon, returning the loaded image by
+    /// name if the archive tags it, or by ID otherwise. In dry-run mode nothing is loaded and
+    /// `None` is returned.
+    pub(crate) async fn load(path: &Path) -> Result<Option<String>> {
+        if dry_run() {
+            info!("Dry run, skipping [docker load --input {}]", path.display());
+            return Ok(None);
+        }
+        let stdout = exec(
+            Command::new("docker").arg("load").arg("--input").arg(path),
+            true,
+        )
+        .await
+        .with_context(|| format!("Failed to load '{}' into docker", path.display()))?
+        .unwrap_or_default();
+        parse_loaded_image(&stdout)
+            .map(Some)
+            .with_context(|| format!("Failed to load '{}' into docker", path.display()))
+    }
+
+    /// Tags the image `source`, a name or ID, as `target` in the docker daemon. In dry-run mode
+    /// nothing is tagged.
+    pub(crate) async fn tag(source: &str, target: &str) -> Result<()> {
+        if dry_run() {
+            info!("Dry run, skipping [docker tag {source} {target}]");
+            return Ok(());
+        }
+        exec(Command::new("docker").args(["tag", source, target]), true)
+            .await
+            .with_context(|| format!("Failed to tag '{source}' as '{target}'"))?;
+        Ok(())
+    }
+}
+
+/// Returns the value in `cache`, running `probe` to fill it if it is empty. A failed probe leaves
+/// the cache empty, so that the next call tries again.
+async fn cached<F, Fut>(cache: &OnceCell<String>, probe: F) -> Result<String>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<String>>,
+{
+    cache.get_or_try_init(probe).await.cloned()
+}
+
+/// Finds the image in the output of `docker load`, which prints `Loaded image: <name>:<tag>` for
+/// each tag in the archive, or `Loaded image ID: sha256:<id>` for an archive without tags.
+fn parse_loaded_image(stdout: &str) -> Result<String> {
+    stdout
+        .lines()
+        .find_map(|line| {
+            let line = line.trim();
+            line.strip_prefix("Loaded image ID:")
+                .or_else(|| line.strip_prefix("Loaded image:"))
+                .map(str::trim)
+                .filter(|image| !image.is_empty())
+        })
+        .map(str::to_string)
+        .with_context(|| format!("No image found in the output of docker load:\n{stdout}"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::anyhow;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_server_platform_failures_are_not_cached() {
+        let cache = OnceCell::new();
+        let probes = AtomicUsize::new(0);
+        let probe = |result: Result<String>| {
+            probes.fetch_add(1, Ordering::SeqCst);
+            async { result }
+        };
+
+        assert!(
+            cached(&cache, || probe(Err(anyhow!("docker is not running"))))
+                .await
+                .is_err()
+        );
+        assert!(cache.get().is_none());
+
+        let platform = cached(&cache, || probe(Ok("linux/amd64".to_string())))
+            .await
+            .unwrap();
+        assert_eq!(platform, "linux/amd64");
+        assert_eq!(probes.load(Ordering::SeqCst), 2);
+
+        // The successful answer is kept, so docker is not asked again.
+        let platform = cached(&cache, || probe(Ok("linux/arm64".to_string())))
+            .await
+            .unwrap();
+        assert_eq!(platform, "linux/amd64");
+        assert_eq!(probes.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_parse_loaded_image_from_docker_archive() {
+        let stdout = "Loaded image: public.ecr.aws/bottlerocket/bottlerocket-sdk:v0.50.0\n";
+        assert_eq!(
+            parse_loaded_image(stdout).unwrap(),
+            "public.ecr.aws/bottlerocket/bottlerocket-sdk:v0.50.0"
+        );
+
+        let stdout =
+            "Loaded image: localhost/core-kit:v2.0.0\nLoaded image: localhost/core-kit:latest\n";
+        assert_eq!(
+            parse_loaded_image(stdout).unwrap(),
+            "localhost/core-kit:v2.0.0"
+        );
+    }
+
+    #[test]
+    fn test_parse_loaded_image_from_untagged_oci_archive() {
+        let stdout = "Loaded image ID: sha256:5b0f5c5e4d6a3e0b7c2b0b4f1b9d7c1e2a3f4b5c6d7e8f90a1b2c3d4e5f60718\n";
+        assert_eq!(
+            parse_loaded_image(stdout).unwrap(),
+            "sha256:5b0f5c5e4d6a3e0b7c2b0b4f1b9d7c1e2a3f4b5c6d7e8f90a1b2c3d4e5f60718"
+        );
+    }
+
+    #[test]
+    fn test_parse_loaded_image_reports_output() {
+        let stdout = "open /var/lib/docker/tmp/docker-import-1234/repositories: no such file\n";
+        let err = parse_loaded_image(stdout).unwrap_err().to_string();
+        assert!(err.contains("docker-import-1234"), "{err}");
+        assert!(parse_loaded_image("").is_err());
+    }
 }
@@ -1,17 +1,32 @@
+use anyhow::{ensure, Error};
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The registry of image references that do not name one, as with `docker pull`.
+const DEFAULT_REGISTRY: &str = "docker.io";
+
+/// The namespace of single-component Docker Hub repositories, e.g. `docker.io/library/alpine`.
+const DEFAULT_NAMESPACE: &str = "library";
+
+/// The tag of image references that name neither a tag nor a digest.
+const DEFAULT_TAG: &str = "latest";
 
 /// Represents a docker image URI such as `public.ecr.aws/myregistry/myrepo:v0.1.0`. The registry is
 /// optional as it is when using `docker`. That is, it will be looked for locally first, then at
-/// `dockerhub.io` when the registry is absent.
+/// `dockerhub.io` when the registry is absent. When a digest is given, the image is referred to by
+/// the digest instead of the tag, e.g. `public.ecr.aws/myregistry/myrepo@sha256:...`.
 #[derive(Debug, Default, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Deserialize, Serialize)]
 pub(crate) struct ImageUri {
     /// e.g. public.ecr.aws/bottlerocket
     pub(crate) registry: Option<String>,
     /// e.g. my-repo
     pub(crate) repo: String,
-    /// e.g. v0.31.0
+    /// e.g. v0.31.0, or empty for an image pinned only by digest
     pub(crate) tag: String,
+    /// e.g. sha256:3f8a2c64...
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) digest: Option<String>,
 }
 
 impl ImageUri {
@@ -26,15 +41,97 @@ impl ImageUri {
             registry,
             repo: repo.as_ref().into(),
             tag: tag.as_ref().into(),
+            digest: None,
         }
     }
 
     /// Returns the `ImageUri` for use with docker, e.g. `public.ecr.aws/myregistry/myrepo:v0.1.0`
     pub(crate) fn uri(&self) -> String {
+        let reference = match &self.digest {
+            None => format!(":{}", self.tag),
+            Some(digest) => format!("@{digest}"),
+        };
         match &self.registry {
-            None => format!("{}:{}", self.repo, self.tag),
-            Some(registry) => format!("{}/{}:{}", registry, self.repo, self.tag),
+            None => format!("{}{}", self.repo, reference),
+            Some(registry) => format!("{}/{}{}", registry, self.repo, reference),
+        }
+    }
+}
+
+/// Parses a full image reference such as `localhost:5000/my-repo:v0.1.0` or
+/// `public.ecr.aws/myregistry/myrepo@sha256:...`. The first path component is the registry if it
+/// looks like a host, that is it contains a `.` or a port or is `localhost`; otherwise the
+/// registry is Docker Hub. The tag defaults to `latest`. A reference may have both a tag and a
+/// digest, e.g. `myrepo:v0.1.0@sha256:...`, in which case the digest takes precedence and the tag
+/// is kept only for reference. Digests must be `sha256:` followed by 64 lowercase hex digits.
+impl FromStr for ImageUri {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, digest) = match s.split_once('@') {
+            Some((name, digest)) => (name, Some(digest)),
+            None => (s, None),
+        };
+        // A colon after the last slash separates the tag; one before it is a registry's port.
+        let (name, tag) = match name.rsplit_once(':') {
+            Some((repo, tag)) if !tag.contains('/') => (repo, Some(tag)),
+            _ => (name, None),
+        };
+        let (registry, repo) = match name.split_once('/') {
+            Some((host, repo))
+                if host.contains('.') || host.contains(':') || host == "localhost" =>
+            {
+                (host.to_string(), repo.to_string())
+            }
+            _ if !name.contains('/') => (
+                DEFAULT_REGISTRY.to_string(),
+                format!("{DEFAULT_NAMESPACE}/{name}"),
+            ),
+            _ => (DEFAULT_REGISTRY.to_string(), name.to_string()),
+        };
+        ensure!(
+            !registry.is_empty() && !registry.ends_with(':'),
+            "Image reference '{s}' has an invalid registry '{registry}'"
+        );
+        ensure!(
+            !repo.is_empty()
+                && repo.split('/').all(|component| {
+                    !component.is_empty()
+                        && component.chars().all(|c| {
+                            c.is_ascii_lowercase() || c.is_ascii_digit() || "._-".contains(c)
+                        })
+                }),
+            "Image reference '{s}' has an invalid repository '{repo}'"
+        );
+
+        let tag = tag.unwrap_or(DEFAULT_TAG);
+        ensure!(
+            !tag.is_empty()
+                && tag.len() <= 128
+                && tag
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c)),
+            "Image reference '{s}' has an invalid tag '{tag}'"
+        );
+        if let Some(digest) = digest {
+            ensure!(
+                digest.strip_prefix("sha256:").is_some_and(|hex| {
+                    hex.len() == 64
+                        && hex
+                            .chars()
+                            .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+                }),
+                "Image reference '{s}' has an invalid digest '{digest}', expected 'sha256:' \
+                followed by 64 lowercase hex digits"
+            );
         }
+
+        Ok(Self {
+            registry: Some(registry),
+            repo,
+            tag: tag.to_string(),
+            digest: digest.map(str::to_string),
+        })
     }
 }
 
@@ -65,3 +162,129 @@ fn image_uri_with_registry() {
     let expected = "example.com/a/b/c/foo:v1.2.3";
     assert_eq!(expected, formatted);
 }
+
+#[test]
+fn image_uri_with_digest() {
+    let uri = ImageUri {
+        digest: Some(
+            "sha256:3f8a2c64c2c1e0cb3ee34a43ef6c0dc3ac6c2e4c0d1b5e1c3a1f2f1d0b0c0e0f".into(),
+        ),
+        ..ImageUri::new(Some("example.com/a/b/c".to_string()), "foo", "v1.2.3")
+    };
+    let formatted = uri.uri();
+    let expected = "example.com/a/b/c/foo@sha256:3f8a2c64c2c1e0cb3ee34a43ef6c0dc3ac6c2e4c0d1b5e1c3a1f2f1d0b0c0e0f";
+    assert_eq!(expected, formatted);
+}
+
+#[test]
+fn image_uri_parse() {
+    let cases = [
+        (
+            "public.ecr.aws/bottlerocket/bottlerocket-sdk:v0.50.0",
+            ("public.ecr.aws", "bottlerocket/bottlerocket-sdk", "v0.50.0"),
+        ),
+        ("example.com/foo", ("example.com", "foo", "latest")),
+        ("alpine", ("docker.io", "library/alpine", "latest")),
+        ("alpine:3.19", ("docker.io", "library/alpine", "3.19")),
+        (
+            "bottlerocket/sdk:v1",
+            ("docker.io", "bottlerocket/sdk", "v1"),
+        ),
+        ("localhost/foo:v1", ("localhost", "foo", "v1")),
+    ];
+    for (reference, (registry, repo, tag)) in cases {
+        let uri: ImageUri = reference.parse().unwrap();
+        assert_eq!(
+            uri,
+            ImageUri::new(Some(registry.to_string()), repo, tag),
+            "{reference}"
+        );
+    }
+}
+
+#[test]
+fn image_uri_parse_port_or_tag() {
+    // A port belongs to the registry, which is only there if a path follows it.
+    let uri: ImageUri = "localhost:5000/foo".parse().unwrap();
+    assert_eq!(uri.registry.as_deref(), Some("localhost:5000"));
+    assert_eq!((uri.repo.as_str(), uri.tag.as_str()), ("foo", "latest"));
+
+    let uri: ImageUri = "localhost:5000/a/foo:v1.2.3".parse().unwrap();
+    assert_eq!(uri.registry.as_deref(), Some("localhost:5000"));
+    assert_eq!((uri.repo.as_str(), uri.tag.as_str()), ("a/foo", "v1.2.3"));
+
+    // Without a path, the number after the colon is a tag.
+    let uri: ImageUri = "foo:5000".parse().unwrap();
+    assert_eq!(uri.registry.as_deref(), Some("docker.io"));
+    assert_eq!(
+        (uri.repo.as_str(), uri.tag.as_str()),
+        ("library/foo", "5000")
+    );
+
+    let uri: ImageUri = "registry:5000/foo:5000".parse().unwrap();
+    assert_eq!(uri.registry.as_deref(), Some("registry:5000"));
+    assert_eq!((uri.repo.as_str(), uri.tag.as_str()), ("foo", "5000"));
+}
+
+#[test]
+fn image_uri_parse_digest() {
+    let digest = "sha256:3f8a2c64c2c1e0cb3ee34a43ef6c0dc3ac6c2e4c0d1b5e1c3a1f2f1d0b0c0e0f";
+    let uri: ImageUri = format!("localhost:5000/foo@{digest}").parse().unwrap();
+    assert_eq!(uri.registry.as_deref(), Some("localhost:5000"));
+    assert_eq!(uri.repo, "foo");
+    assert_eq!(uri.digest.as_deref(), Some(digest));
+    assert_eq!(uri.uri(), format!("localhost:5000/foo@{digest}"));
+
+    // The digest takes precedence over a tag given with it.
+    let uri: ImageUri = format!("localhost:5000/foo:v1@{digest}").parse().unwrap();
+    assert_eq!(uri.tag, "v1");
+    assert_eq!(uri.digest.as_deref(), Some(digest));
+    assert_eq!(uri.uri(), format!("localhost:5000/foo@{digest}"));
+
+    for invalid in [
+        "sha256".to_string(),
+        "sha256:not-hex".to_string(),
+        "sha256:abcd".to_string(),
+        digest.to_uppercase().replace("SHA256", "sha256"),
+        digest.replace("sha256", "sha512"),
+        format!("{digest}0"),
+    ] {
+        assert!(
+            format!("foo@{invalid}").parse::<ImageUri>().is_err(),
+            "'{invalid}' should not be a valid digest"
+        );
+    }
+}
+
+#[test]
+fn image_uri_parse_invalid() {
+    for reference in [
+        "",
+        ":v1",
+        "foo:",
+        "Foo:v1",
+        "example.com/",
+        "example.com//foo",
+        "example.com/foo:bad/tag",
+        "example.com:/foo",
+    ] {
+        assert!(
+            reference.parse::<ImageUri>().is_err(),
+            "'{reference}' should not parse"
+        );
+    }
+}
+
+#[test]
+fn image_uri_parse_round_trips() {
+    for reference in [
+        "public.ecr.aws/bottlerocket/bottlerocket-core-kit:v2.0.0",
+        "localhost:5000/foo:5000",
+        "docker.io/library/alpine:latest",
+        "example.com/a/b/c/foo@sha256:3f8a2c64c2c1e0cb3ee34a43ef6c0dc3ac6c2e4c0d1b5e1c3a1f2f1d0b0c0e0f",
+    ] {
+        let uri: ImageUri = reference.parse().unwrap();
+        assert_eq!(uri.uri(), reference);
+        assert_eq!(uri.uri().parse::<ImageUri>().unwrap(), uri);
+    }
+}
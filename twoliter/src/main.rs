@@ -1,4 +1,5 @@
-use crate::cmd::{init_logger, Args};
+use crate::cmd::{init_logger, Args, Subcommand};
+use crate::common::GlobalOptions;
 use anyhow::Result;
 use clap::Parser;
 
@@ -21,6 +22,20 @@ mod tools;
 async fn main() -> Result<()> {
     let args = Args::parse();
     init_logger(args.log_level);
-    preflight::preflight().await?;
+    GlobalOptions::new(
+        args.verbose_subprocess,
+        args.offline,
+        args.dry_run,
+        args.registry_auth_file.clone(),
+    )
+    .install();
+    // The preflight subcommand reports on the same checks, and validate and which-tool do not
+    // depend on them, so let these run even if the checks fail.
+    if !matches!(
+        args.subcommand,
+        Subcommand::Preflight(_) | Subcommand::Validate(_) | Subcommand::WhichTool(_)
+    ) {
+        preflight::preflight().await?;
+    }
     cmd::run(args).await
 }
@@ -1,13 +1,35 @@
 //! This module performs checks that the current environment is compatible with twoliter, as well
 //! as any other "global" setup that must occur before the build process begins.
-use anyhow::{ensure, Result};
+use anyhow::{ensure, Context, Result};
 use lazy_static::lazy_static;
-use semver::{Comparator, Op, Prerelease, VersionReq};
+use semver::{Comparator, Op, Prerelease, Version, VersionReq};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::Path;
+use tracing::warn;
 use which::which_global;
 
 use crate::docker::Docker;
+use buildsys::manifest::{ManifestInfo, SupportedArch};
+use krane_bundle::KRANE;
+use oci_cli_wrapper::IMAGE_TOOL_ENV;
 
-const REQUIRED_TOOLS: &[&str] = &["docker", "gzip", "lz4"];
+/// Tools that must be found in PATH, each given with the alternatives that can stand in for it.
+const REQUIRED_TOOLS: &[&[&str]] = &[&["docker"], &["gzip", "pigz"], &["lz4"]];
+
+/// The oldest versions of the required tools whose command line the build relies on. Tools not
+/// listed here are not version checked.
+const MINIMUM_TOOL_VERSIONS: &[(&str, Version)] = &[
+    ("gzip", Version::new(1, 6, 0)),
+    ("pigz", Version::new(2, 3, 0)),
+    ("lz4", Version::new(1, 9, 0)),
+];
+
+/// The architectures twoliter projects can be built for.
+pub(crate) const SUPPORTED_ARCHES: &[&str] = &["x86_64", "aarch64"];
+
+/// Where the kernel exposes the registered binfmt handlers used to run foreign binaries.
+const BINFMT_MISC_DIR: &str = "/proc/sys/fs/binfmt_misc";
 
 lazy_static! {
     // Twoliter relies on minimum Dockerfile syntax 1.4.3, which is shipped in Docker 23.0.0 by default
@@ -28,36 +50,279 @@ lazy_static! {
 /// Runs all common setup required for twoliter.
 ///
 /// * Ensures that any required system tools are installed an accessible.
-/// * Sets up interrupt handler to cleanup on SIGINT
+///
+/// Whether this host can build for an architecture is only checked when a build for it is
+/// requested, by [`check_build_arch`], or when `twoliter preflight` lists every check.
 pub(crate) async fn preflight() -> Result<()> {
     check_environment().await?;
 
     Ok(())
 }
 
+/// Warns if this host cannot run `arch` binaries, which a build for `arch` needs. A variant that
+/// lists its supported architectures is never built for any other, so there is nothing to warn
+/// about if the variant in `variant_dir` does not support `arch`.
+pub(crate) fn check_build_arch(arch: &str, variant_dir: Option<&Path>) {
+    if variant_dir.is_some_and(|dir| !variant_supports_arch(dir, arch)) {
+        return;
+    }
+    let available = available_arches(std::env::consts::ARCH, Path::new(BINFMT_MISC_DIR));
+    if let Some(warning) = unsupported_arch_warning(&available, arch) {
+        warn!("{warning}");
+    }
+}
+
+/// Whether the variant in `variant_dir` can be built for `arch`, according to the
+/// `supported-arches` in its `Cargo.toml`. A variant that does not list them, or whose manifest
+/// cannot be read, is assumed to support every architecture.
+fn variant_supports_arch(variant_dir: &Path, arch: &str) -> bool {
+    let Ok(manifest) = ManifestInfo::new(variant_dir.join("Cargo.toml")) else {
+        return true;
+    };
+    match (manifest.supported_arches(), arch.parse::<SupportedArch>()) {
+        (Some(supported), Ok(arch)) => supported.contains(&arch),
+        _ => true,
+    }
+}
+
 pub(crate) async fn check_environment() -> Result<()> {
-    check_for_required_tools()?;
-    check_docker_version().await?;
+    let docker_required = docker_required_by_env();
+    check_for_required_tools(docker_required)?;
+    if docker_required {
+        check_docker_version().await?;
+    }
 
     Ok(())
 }
 
-fn check_for_required_tools() -> Result<()> {
-    for tool in REQUIRED_TOOLS {
-        ensure!(
-            which_global(tool).is_ok(),
-            "Failed to find required tool `{tool}` in PATH"
-        );
+/// The outcome of a single environment check.
+#[derive(Debug, Serialize)]
+pub(crate) struct CheckResult {
+    pub(crate) name: String,
+    pub(crate) passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+    /// A problem that does not prevent twoliter from running, such as being unable to build for
+    /// one of the supported architectures.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) warning: Option<String>,
+}
+
+impl CheckResult {
+    fn new(name: impl Into<String>, result: Result<()>) -> Self {
+        Self {
+            name: name.into(),
+            passed: result.is_ok(),
+            error: result.err().map(|e| format!("{e:#}")),
+            warning: None,
+        }
+    }
+
+    fn warning(name: impl Into<String>, warning: Option<String>) -> Self {
+        Self {
+            name: name.into(),
+            passed: true,
+            error: None,
+            warning,
+        }
+    }
+}
+
+/// Runs the same checks as `check_environment`, but reports the outcome of each one instead of
+/// stopping at the first failure.
+pub(crate) async fn run_checks() -> Vec<CheckResult> {
+    let docker_required = docker_required_by_env();
+    let mut results: Vec<_> = required_tools(docker_required)
+        .map(|tools| {
+            CheckResult::new(
+                format!("required tool {}", tool_names(tools)),
+                check_for_tool(tools),
+            )
+        })
+        .collect();
+    if docker_required {
+        results.push(CheckResult::new(
+            "docker version",
+            check_docker_version().await,
+        ));
+    }
+    let available = available_arches(std::env::consts::ARCH, Path::new(BINFMT_MISC_DIR));
+    results.extend(SUPPORTED_ARCHES.iter().map(|arch| {
+        CheckResult::warning(
+            format!("build architecture `{arch}`"),
+            unsupported_arch_warning(&available, arch),
+        )
+    }));
+    results
+}
+
+/// Returns the architectures this host can run binaries for: its own, plus any that have an
+/// enabled binfmt handler, e.g. one registered for QEMU user emulation by `tonistiigi/binfmt`.
+fn available_arches(native: &str, binfmt_dir: &Path) -> BTreeSet<String> {
+    let mut arches = BTreeSet::from([native.to_string()]);
+    let enabled = |path: &Path| {
+        std::fs::read_to_string(path)
+            .is_ok_and(|contents| contents.lines().next() == Some("enabled"))
+    };
+    if !enabled(&binfmt_dir.join("status")) {
+        return arches;
+    }
+    let Ok(entries) = std::fs::read_dir(binfmt_dir) else {
+        return arches;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Some(target) = name.strip_prefix("qemu-") else {
+            continue;
+        };
+        // Handlers are named for the QEMU target, which may not match our architecture names.
+        let arch = match target {
+            "arm64" => "aarch64",
+            "amd64" => "x86_64",
+            target => target,
+        };
+        if enabled(&entry.path()) {
+            arches.insert(arch.to_string());
+        }
+    }
+    arches
+}
+
+fn unsupported_arch_warning(available: &BTreeSet<String>, arch: &str) -> Option<String> {
+    (!available.contains(arch)).then(|| {
+        format!(
+            "this host cannot run {arch} binaries, so builds for {arch} will fail; register a \
+            QEMU binfmt handler, e.g. with `docker run --privileged --rm tonistiigi/binfmt \
+            --install {arch}`"
+        )
+    })
+}
+
+/// Whether docker is needed. Registry operations only need docker if crane is not selected as
+/// the image tool with `TWOLITER_KIT_IMAGE_TOOL`, or the builtin krane cannot be found.
+fn docker_required(image_tool: Option<&str>, krane_found: bool) -> bool {
+    !(matches!(image_tool, Some("crane" | "krane")) && krane_found)
+}
+
+fn docker_required_by_env() -> bool {
+    let image_tool = std::env::var(IMAGE_TOOL_ENV).ok();
+    // Only extract the builtin krane if it would be used.
+    let crane_selected = matches!(image_tool.as_deref(), Some("crane" | "krane"));
+    docker_required(
+        image_tool.as_deref(),
+        crane_selected && KRANE.path().is_file(),
+    )
+}
+
+fn required_tools(docker_required: bool) -> impl Iterator<Item = &'static [&'static str]> {
+    REQUIRED_TOOLS
+        .iter()
+        .copied()
+        .filter(move |tools| docker_required || *tools != ["docker"])
+}
+
+fn check_for_required_tools(docker_required: bool) -> Result<()> {
+    for tools in required_tools(docker_required) {
+        check_for_tool(tools)?;
+    }
+    Ok(())
+}
+
+/// Check that at least one of the alternatives `tools` is in PATH, and that the first one found
+/// meets its minimum version.
+fn check_for_tool(tools: &[&str]) -> Result<()> {
+    let tool = tools
+        .iter()
+        .find(|tool| which_global(tool).is_ok())
+        .with_context(|| format!("Failed to find required tool {} in PATH", tool_names(tools)))?;
+    check_tool_version(tool)
+}
+
+/// Check that `tool` is at least its minimum version. A tool whose version cannot be determined
+/// is assumed to be new enough, since some builds print no version at all.
+fn check_tool_version(tool: &str) -> Result<()> {
+    let Some((_, minimum)) = MINIMUM_TOOL_VERSIONS.iter().find(|(name, _)| *name == tool) else {
+        return Ok(());
+    };
+    let output = std::process::Command::new(tool)
+        .arg("--version")
+        .output()
+        .with_context(|| format!("Failed to run `{tool} --version`"))?;
+    // Older releases of lz4 and pigz print their version to stderr.
+    let output = format!(
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    match parse_tool_version(&output) {
+        Some(version) => ensure_minimum_tool_version(tool, &version, minimum),
+        None => {
+            warn!("Unable to determine the version of `{tool}`, assuming it is at least {minimum}");
+            Ok(())
+        }
     }
+}
+
+fn ensure_minimum_tool_version(tool: &str, version: &Version, minimum: &Version) -> Result<()> {
+    ensure!(
+        version >= minimum,
+        "`{tool}` version {version} is older than the minimum version required by twoliter: \
+        {minimum}"
+    );
     Ok(())
 }
 
+/// Finds the first version number in the output of `--version`, such as `gzip 1.12` or
+/// `*** LZ4 command line interface 64-bits v1.9.4, by Yann Collet ***`. Missing minor and patch
+/// numbers are taken to be zero. Releases of lz4 before 1.7.0 were numbered like `r131` instead;
+/// these are taken to be `0.131.0`, so that they are older than any minimum version.
+fn parse_tool_version(output: &str) -> Option<Version> {
+    let words = || {
+        output
+            .split_whitespace()
+            .map(|word| word.trim_end_matches(|c: char| !c.is_ascii_digit()))
+    };
+    words()
+        .find_map(|word| {
+            let word = word.strip_prefix('v').unwrap_or(word);
+            if !word.contains('.') {
+                return None;
+            }
+            let mut parts = word.split('.').map(|part| part.parse::<u64>().ok());
+            let major = parts.next()??;
+            let minor = parts.next().unwrap_or(Some(0))?;
+            let patch = parts.next().unwrap_or(Some(0))?;
+            parts
+                .next()
+                .is_none()
+                .then(|| Version::new(major, minor, patch))
+        })
+        .or_else(|| {
+            words().find_map(|word| {
+                let release = word.strip_prefix('r')?.parse::<u64>().ok()?;
+                Some(Version::new(0, release, 0))
+            })
+        })
+}
+
+/// Names the alternatives `tools`, e.g. "`gzip` or `pigz`".
+fn tool_names(tools: &[&str]) -> String {
+    tools
+        .iter()
+        .map(|tool| format!("`{tool}`"))
+        .collect::<Vec<_>>()
+        .join(" or ")
+}
+
 async fn check_docker_version() -> Result<()> {
-    let docker_version = Docker::server_version().await?;
+    ensure_supported_docker_version(&Docker::server_version().await?)
+}
 
+fn ensure_supported_docker_version(docker_version: &Version) -> Result<()> {
     ensure!(
-        MINIMUM_DOCKER_VERSION.matches(&docker_version),
-        "docker found in PATH does not meet the minimum version requirements for twoliter: {}",
+        MINIMUM_DOCKER_VERSION.matches(docker_version),
+        "docker daemon version {docker_version} does not meet the minimum version requirements \
+        for twoliter: {}",
         MINIMUM_DOCKER_VERSION.to_string(),
     );
 
@@ -67,7 +332,6 @@ async fn check_docker_version() -> Result<()> {
 #[cfg(test)]
 mod test {
     use super::*;
-    use semver::Version;
     use test_case::test_case;
 
     #[test_case(Version::parse("25.0.5").unwrap(), true; "25.0.5 passes")]
@@ -75,6 +339,146 @@ mod test {
     #[test_case(Version::parse("18.0.9").unwrap(), false; "18.0.9 fails")]
     #[test_case(Version::parse("20.10.27").unwrap(), false)]
     fn test_docker_version_req(version: Version, is_ok: bool) {
-        assert_eq!(MINIMUM_DOCKER_VERSION.matches(&version), is_ok)
+        assert_eq!(MINIMUM_DOCKER_VERSION.matches(&version), is_ok);
+        assert_eq!(ensure_supported_docker_version(&version).is_ok(), is_ok);
+    }
+
+    #[test_case(None, true, true; "default tool needs docker")]
+    #[test_case(Some("podman"), true, true; "podman needs docker")]
+    #[test_case(Some("crane"), true, false; "crane does not need docker")]
+    #[test_case(Some("krane"), true, false; "krane does not need docker")]
+    #[test_case(Some("crane"), false, true; "missing crane needs docker")]
+    fn test_docker_required(image_tool: Option<&str>, krane_found: bool, required: bool) {
+        assert_eq!(docker_required(image_tool, krane_found), required);
+        let tools: Vec<_> = required_tools(docker_required(image_tool, krane_found)).collect();
+        assert_eq!(tools.contains(&&["docker"][..]), required);
+        assert!(tools.contains(&&["gzip", "pigz"][..]));
+        assert!(tools.contains(&&["lz4"][..]));
+    }
+
+    #[test]
+    fn test_alternative_tools() {
+        assert!(check_for_tool(&["twoliter-test-missing-tool", "sh"]).is_ok());
+        let err = check_for_tool(&["twoliter-test-missing-tool", "twoliter-test-missing-alt"])
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("`twoliter-test-missing-tool` or `twoliter-test-missing-alt`"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_docker_version_error_names_version() {
+        let err = ensure_supported_docker_version(&Version::parse("20.10.27").unwrap())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("20.10.27"), "{err}");
+        assert!(err.contains(">=23"), "{err}");
+    }
+
+    fn binfmt_dir(handlers: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("status"), "enabled\n").unwrap();
+        std::fs::write(dir.path().join("register"), "").unwrap();
+        for (name, status) in handlers {
+            std::fs::write(
+                dir.path().join(name),
+                format!("{status}\ninterpreter /usr/bin/{name}\nflags: F\n"),
+            )
+            .unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_emulated_arch_is_available() {
+        let dir = binfmt_dir(&[("qemu-aarch64", "enabled"), ("qemu-riscv64", "enabled")]);
+        let available = available_arches("x86_64", dir.path());
+        assert_eq!(
+            available,
+            BTreeSet::from(["aarch64".into(), "riscv64".into(), "x86_64".into()])
+        );
+        assert!(SUPPORTED_ARCHES
+            .iter()
+            .all(|arch| unsupported_arch_warning(&available, arch).is_none()));
+    }
+
+    #[test]
+    fn test_variant_supports_arch() {
+        let dir = tempfile::TempDir::new().unwrap();
+        // Without a readable manifest, every architecture is assumed to be supported.
+        assert!(variant_supports_arch(dir.path(), "aarch64"));
+
+        std::fs::write(
+            dir.path().join("Cargo.toml"),
+            r#"
+[package]
+name = "my-variant"
+version = "0.1.0"
+
+[package.metadata.build-variant]
+supported-arches = ["x86_64"]
+"#,
+        )
+        .unwrap();
+        assert!(variant_supports_arch(dir.path(), "x86_64"));
+        assert!(!variant_supports_arch(dir.path(), "aarch64"));
+    }
+
+    #[test]
+    fn test_warns_for_disabled_emulated_arch() {
+        let dir = binfmt_dir(&[("qemu-aarch64", "disabled")]);
+        let available = available_arches("x86_64", dir.path());
+        assert!(unsupported_arch_warning(&available, "x86_64").is_none());
+        let warning = unsupported_arch_warning(&available, "aarch64").unwrap();
+        assert!(warning.contains("aarch64"), "{warning}");
+
+        std::fs::write(dir.path().join("status"), "disabled\n").unwrap();
+        std::fs::write(dir.path().join("qemu-aarch64"), "enabled\n").unwrap();
+        let available = available_arches("x86_64", dir.path());
+        assert_eq!(available, BTreeSet::from(["x86_64".into()]));
+    }
+
+    #[test_case("gzip 1.12\nCopyright (C) 2018 Free Software Foundation, Inc.\n", Some((1, 12, 0)); "gnu gzip")]
+    #[test_case("pigz 2.8\n", Some((2, 8, 0)); "pigz")]
+    #[test_case("*** LZ4 command line interface 64-bits v1.9.4, by Yann Collet ***\n", Some((1, 9, 4)); "lz4")]
+    #[test_case("*** LZ4 command line interface 64-bits r131, by Yann Collet ***\n", Some((0, 131, 0)); "lz4 release number")]
+    #[test_case("lz4 version unknown\n", None; "no version")]
+    #[test_case("", None; "empty")]
+    fn test_parse_tool_version(output: &str, version: Option<(u64, u64, u64)>) {
+        assert_eq!(
+            parse_tool_version(output),
+            version.map(|(major, minor, patch)| Version::new(major, minor, patch))
+        );
+    }
+
+    #[test]
+    fn test_minimum_tool_version() {
+        let minimum = Version::new(1, 9, 0);
+        assert!(ensure_minimum_tool_version("lz4", &Version::new(1, 9, 4), &minimum).is_ok());
+        assert!(ensure_minimum_tool_version("lz4", &minimum, &minimum).is_ok());
+        let err = ensure_minimum_tool_version("lz4", &Version::new(1, 8, 3), &minimum)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("1.8.3") && err.contains("1.9.0"), "{err}");
+
+        let r131 =
+            parse_tool_version("*** LZ4 command line interface 64-bits r131, by Yann Collet ***")
+                .unwrap();
+        assert!(ensure_minimum_tool_version("lz4", &r131, &minimum).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_checks_reports_required_tools() {
+        let results = run_checks().await;
+        for tools in required_tools(docker_required_by_env()) {
+            let result = results
+                .iter()
+                .find(|result| result.name == format!("required tool {}", tool_names(tools)))
+                .unwrap();
+            assert_eq!(result.passed, check_for_tool(tools).is_ok());
+            assert_eq!(result.passed, result.error.is_none());
+        }
     }
 }
@@ -36,8 +36,10 @@ impl OCIArchive {
         format!("{}/{}@{}", self.registry, self.repository, self.digest)
     }
 
+    /// Pulls the image into the cache unless it is already there. Returns `false` if the image
+    /// tool is in dry-run mode and skipped the pull, so there is no archive to unpack.
     #[instrument(level = "trace", skip_all, fields(registry = %self.registry, repository = %self.repository, digest = %self.digest))]
-    pub async fn pull_image(&self, image_tool: &ImageTool) -> Result<()> {
+    pub async fn pull_image(&self, image_tool: &ImageTool) -> Result<bool> {
         let digest_uri = self.uri();
         debug!("Pulling image '{}'", digest_uri);
         let oci_archive_path = self.archive_path();
@@ -46,13 +48,25 @@ impl OCIArchive {
             image_tool
                 .pull_oci_image(oci_archive_path.as_path(), digest_uri.as_str())
                 .await?;
+            if image_tool.dry_run() {
+                // Nothing was written, so do not leave an empty archive to be mistaken for a
+                // complete one.
+                remove_dir_all(&oci_archive_path).await?;
+                return Ok(false);
+            }
+            if let Err(e) = image_tool.verify_oci_image(&oci_archive_path, &self.digest) {
+                // Remove the incomplete image so that the next run pulls it again.
+                remove_dir_all(&oci_archive_path).await?;
+                return Err(e)
+                    .with_context(|| format!("image pulled from '{digest_uri}' is incomplete"));
+            }
         } else {
             debug!(
                 "Image from '{}' already present -- no need to pull.",
                 digest_uri
             );
         }
-        Ok(())
+        Ok(true)
     }
 
     #[instrument(
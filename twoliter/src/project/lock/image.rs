@@ -1,13 +1,15 @@
 use super::archive::OCIArchive;
-use super::views::ManifestListView;
 use crate::common::fs::create_dir_all;
+use crate::common::offline;
 use crate::compatibility::SUPPORTED_KIT_METADATA_VERSION;
 use crate::project::{Image, ProjectImage, ValidIdentifier, VendedArtifact};
-use anyhow::{bail, Context, Result};
+use anyhow::{bail, ensure, Context, Result};
 use base64::Engine;
 use futures::{pin_mut, stream, StreamExt, TryStreamExt};
 use log::trace;
-use oci_cli_wrapper::{ConfigView, DockerArchitecture, ImageTool};
+use oci_cli_wrapper::{
+    ConfigView, DockerArchitecture, ImageTool, ManifestView, PlatformDescriptor,
+};
 use semver::Version;
 use serde::{Deserialize, Serialize};
 use sha2::Digest;
@@ -63,8 +65,8 @@ impl VendedArtifact for LockedImage {
         &self.vendor
     }
 
-    fn version(&self) -> &Version {
-        &self.version
+    fn version(&self) -> Option<&Version> {
+        Some(&self.version)
     }
 }
 
@@ -75,7 +77,6 @@ pub(crate) struct ImageMetadata {
     #[expect(dead_code)]
     pub name: String,
     /// The version of the kit
-    #[expect(dead_code)]
     pub version: Version,
     /// The required sdk of the kit,
     pub sdk: Image,
@@ -103,7 +104,11 @@ impl EncodedKitMetadata {
     #[instrument(level = "trace")]
     async fn try_from_image(image_uri: &str, image_tool: &ImageTool) -> Result<Self> {
         tracing::trace!(image_uri, "Extracting kit metadata from OCI image config");
-        let config = image_tool.get_config(image_uri).await?;
+        // Every architecture of a kit carries the same metadata, so read it from the amd64 image as
+        // crane would by default.
+        let config = image_tool
+            .get_config(image_uri, Some(&DockerArchitecture::Amd64))
+            .await?;
         let kit_metadata = EncodedKitMetadata(Self::extract_encoded_kit_metadata(&config)?);
 
         tracing::trace!(
@@ -232,12 +237,19 @@ impl ImageResolver {
         level = "trace",
         fields(image = %self.image, uri = %self.image.project_image_uri())
     )]
-    async fn get_manifest(&self, image_tool: &ImageTool) -> Result<ManifestListView> {
+    /// Fetch the manifest list of the image, returning its platform images
+    async fn get_platform_manifests(
+        &self,
+        image_tool: &ImageTool,
+    ) -> Result<Vec<PlatformDescriptor>> {
         let uri = self.image.project_image_uri().to_string();
         debug!(image=%self.image, uri, "Fetching image manifest.");
-        let manifest_bytes = image_tool.get_manifest(uri.as_str()).await?;
-        serde_json::from_slice(manifest_bytes.as_slice())
-            .context("failed to deserialize manifest list")
+        match image_tool.get_manifest_parsed(uri.as_str()).await? {
+            ManifestView::Index { manifests, .. } => Ok(manifests),
+            ManifestView::Image { .. } => {
+                bail!("expected a manifest list at '{uri}' but found a single image manifest")
+            }
+        }
     }
 
     #[instrument(
@@ -252,27 +264,36 @@ impl ImageResolver {
         let uri = self.image.project_image_uri();
         info!("Resolving dependency image dependency '{}'.", self.image);
 
-        let manifest_list = self.get_manifest(image_tool).await?;
+        let manifests = self.get_platform_manifests(image_tool).await?;
+        if let Some(expected) = self.image.expected_digest() {
+            let actual = image_tool.resolve_digest(uri.to_string().as_str()).await?;
+            verify_expected_digest(&self.image, expected, &actual)?;
+        }
         let registry = uri
             .registry
             .as_ref()
             .context("no registry found for image")?;
 
-        let locked_image = LockedImage {
+        let digest = self.calculate_digest(image_tool).await?;
+        let lock = |version: Version| LockedImage {
             name: self.image.name().to_owned(),
-            version: self.image.version().to_owned(),
+            version,
             vendor: self.image.vendor_name().to_owned(),
             // The source is the image uri without the tag, which is the digest
             source: self.image.original_source_uri().to_string(),
-            digest: self.calculate_digest(image_tool).await?,
+            digest,
         };
 
         if self.skip_metadata_retrieval {
-            return Ok((locked_image, None));
+            let version = self.image.version().cloned().context(format!(
+                "'{}' is pinned by digest, but has no metadata to read its version from",
+                self.image
+            ))?;
+            return Ok((lock(version), None));
         }
 
         debug!("Extracting kit metadata from OCI image");
-        let embedded_kit_metadata = stream::iter(manifest_list.manifests).then(|manifest| {
+        let embedded_kit_metadata = stream::iter(manifests).then(|manifest| {
             let registry = registry.clone();
             let repo = uri.repo.clone();
             async move {
@@ -298,11 +319,17 @@ impl ImageResolver {
                 bail!("Metadata does not match between images in manifest list");
             }
         }
-        let metadata = canonical_metadata
+        let metadata: ImageMetadata = canonical_metadata
             .try_into()
             .context("Failed to decode and parse kit metadata")?;
 
-        Ok((locked_image, Some(metadata)))
+        // A kit pinned only by digest takes its version from its metadata.
+        let version = self
+            .image
+            .version()
+            .cloned()
+            .unwrap_or_else(|| metadata.version.clone());
+        Ok((lock(version), Some(metadata)))
     }
 
     #[instrument(
@@ -323,19 +350,27 @@ impl ImageResolver {
             self.image.vendor_name(),
             self.image.name()
         ));
+        if offline() {
+            // Without the registry we cannot tell which image is current, so trust the last fetch.
+            ensure!(
+                target_path.join("digest").is_file(),
+                "kit '{}' has not been fetched for {arch} and network access is disabled, run \
+                 `twoliter fetch` before building offline",
+                self.image
+            );
+            return Ok(());
+        }
         let cache_path = path.as_ref().join("cache");
         create_dir_all(&target_path).await?;
         create_dir_all(&cache_path).await?;
 
         // First get the manifest for the specific requested architecture
         let uri = self.image.project_image_uri();
-        let manifest_list = self.get_manifest(image_tool).await?;
+        let manifests = self.get_platform_manifests(image_tool).await?;
         let docker_arch = DockerArchitecture::try_from(arch)?;
-        let manifest = manifest_list
-            .manifests
-            .iter()
-            .find(|x| x.platform.as_ref().unwrap().architecture == docker_arch)
-            .cloned()
+        let manifest = manifests
+            .into_iter()
+            .find(|x| x.architecture == docker_arch)
             .context(format!(
                 "could not find image for architecture '{}' at {}",
                 docker_arch, uri
@@ -350,7 +385,10 @@ impl ImageResolver {
         )?;
 
         // Checks for the saved image locally, or else pulls and saves it
-        oci_archive.pull_image(image_tool).await?;
+        if !oci_archive.pull_image(image_tool).await? {
+            info!("Dry run, not extracting kit '{}'", self.image.name());
+            return Ok(());
+        }
 
         // Checks if this archive has already been extracted by checking a digest file
         // otherwise cleans up the path and unpacks the archive
@@ -360,11 +398,50 @@ impl ImageResolver {
     }
 }
 
+/// Ensures an overridden image is the one the override pins it to, so that builds against an
+/// override cannot silently drift from what was published.
+fn verify_expected_digest(image: &ProjectImage, expected: &str, actual: &str) -> Result<()> {
+    if expected != actual {
+        error!(
+            %image,
+            expected,
+            actual,
+            "Overridden image does not match the digest in Twoliter.override"
+        );
+        bail!(
+            "overridden image '{}' has digest '{actual}' but Twoliter.override expects             '{expected}'; the override no longer points at the published image",
+            image.project_image_uri(),
+        );
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::project::Project;
+    use crate::test::data_dir;
     use std::collections::HashMap;
 
+    const OVERRIDE_DIGEST: &str =
+        "sha256:3f8a2c64c2c1e0cb3ee34a43ef6c0dc3ac6c2e4c0d1b5e1c3a1f2f1d0b0c0e0f";
+
+    #[tokio::test]
+    async fn test_verify_override_digest() {
+        let path = data_dir().join("override-digest/Twoliter.toml");
+        let project = Project::load(path).await.unwrap();
+        let sdk = project.direct_sdk_image_dep().unwrap().unwrap();
+        assert_eq!(sdk.expected_digest(), Some(OVERRIDE_DIGEST));
+
+        verify_expected_digest(&sdk, OVERRIDE_DIGEST, OVERRIDE_DIGEST).unwrap();
+
+        let err = verify_expected_digest(&sdk, OVERRIDE_DIGEST, "sha256:0000")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("sha256:0000") && err.contains(OVERRIDE_DIGEST));
+        assert!(err.contains("c.com/d/my-bottlerocket-sdk:v1.2.3"));
+    }
+
     #[test]
     fn test_try_debug_image_metadata_succeeds() {
         // Given a valid encoded metadata string,
@@ -393,6 +470,7 @@ mod test {
     fn test_extract_encoded_kit_metadata_fails_no_label() {
         EncodedKitMetadata::extract_encoded_kit_metadata(&ConfigView {
             labels: HashMap::from([("foo".to_string(), "bar".to_string())]),
+            ..Default::default()
         })
         .expect_err("no label");
     }
@@ -401,6 +479,7 @@ mod test {
     fn test_extract_encoded_kit_metadata_fails_older_metadata() {
         let err = EncodedKitMetadata::extract_encoded_kit_metadata(&ConfigView {
             labels: HashMap::from([(format!("{KIT_METADATA_LABEL_PREFIX}v0"), "bar".to_string())]),
+            ..Default::default()
         })
         .expect_err("too old")
         .to_string();
@@ -415,6 +494,7 @@ mod test {
                 format!("{KIT_METADATA_LABEL_PREFIX}v9999"),
                 "bar".to_string(),
             )]),
+            ..Default::default()
         })
         .expect_err("too new")
         .to_string();
@@ -429,6 +509,7 @@ mod test {
                 format!("{KIT_METADATA_LABEL_PREFIX}notaversion"),
                 "foo".to_string(),
             )]),
+            ..Default::default()
         })
         .expect_err("not a version")
         .to_string();
@@ -444,6 +525,7 @@ mod test {
                     format!("{KIT_METADATA_LABEL_PREFIX}{SUPPORTED_KIT_METADATA_VERSION}"),
                     "bar".to_string(),
                 )]),
+                ..Default::default()
             })
             .unwrap(),
             "bar".to_string()
@@ -12,27 +12,36 @@ mod verification;
 /// Implements view models of common OCI manifest and configuration types
 mod views;
 
+pub(crate) use self::image::LockedImage;
 pub(crate) use self::verification::VerificationTagger;
 
 use crate::common::fs::{create_dir_all, read, write};
-use crate::project::{Project, ValidIdentifier};
+use crate::common::{offline, GlobalOptions};
+use crate::project::{Project, ProjectImage, ValidIdentifier};
 use crate::schema_version::SchemaVersion;
 use anyhow::{bail, ensure, Context, Result};
-use image::{ImageResolver, LockedImage};
-use oci_cli_wrapper::ImageTool;
+use futures::stream::{self, StreamExt};
+use image::{ImageMetadata, ImageResolver};
+use oci_cli_wrapper::{
+    uri_rewriter_from_env, ImageTool, ImageToolSelection, RegistryClientCerts, RegistryCredentials,
+    RegistryFixtures, RegistryMirrors,
+};
 use olpc_cjson::CanonicalFormatter as CanonicalJsonFormatter;
 use semver::Version;
 use serde::{Deserialize, Serialize};
 use std::cmp::PartialEq;
 use std::collections::{HashMap, HashSet};
-use std::fmt::Debug;
+use std::fmt::{Debug, Display, Formatter};
 use std::mem::take;
+use std::num::NonZeroUsize;
+use std::str::FromStr;
+use std::sync::Arc;
 use tokio::fs::read_to_string;
 use tracing::{debug, error, info, instrument};
 
 use super::{Locked, ProjectLock, Unlocked};
 
-const TWOLITER_LOCK: &str = "Twoliter.lock";
+pub(super) const TWOLITER_LOCK: &str = "Twoliter.lock";
 
 #[derive(Serialize, Debug)]
 struct ExternalKitMetadata {
@@ -46,6 +55,10 @@ struct ExternalKitMetadata {
 pub(crate) struct Override {
     pub name: Option<String>,
     pub registry: Option<String>,
+    /// The manifest digest, e.g. `sha256:...`, the overridden image is expected to have. Resolving
+    /// the image fails if the registry serves anything else.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
 }
 
 /// A resolved and locked project SDK, typically from the Twoliter.lock file for a project.
@@ -67,6 +80,10 @@ impl LockedSDK {
         info!("Resolving SDK project reference to check against lock file");
 
         let current_lock = Lock::current_lock_state(project).await?;
+        if offline() {
+            info!("Network access is disabled, using the SDK in Twoliter.lock without checking it");
+            return Ok(Self(current_lock.sdk));
+        }
         let resolved_lock = Self::resolve_sdk(project)
             .await?
             .context("Project does not have explicit SDK image.")?;
@@ -103,7 +120,7 @@ impl LockedSDK {
         };
 
         debug!(?sdk, "Resolving workspace SDK");
-        let image_tool = ImageTool::from_builtin_krane();
+        let image_tool = image_tool()?;
         ImageResolver::from_image(&sdk)?
             .skip_metadata_retrieval() // SDKs don't have metadata
             .resolve(&image_tool)
@@ -134,18 +151,56 @@ impl PartialEq for Lock {
 
 #[allow(dead_code)]
 impl Lock {
+    /// Resolves the project's dependencies and writes them to the lockfile.
+    ///
+    /// If the existing lockfile references vendors that have been removed from `Twoliter.toml`,
+    /// those entries are dropped when `prune` is set; otherwise this is an error.
+    ///
+    /// If `only` is not empty, only the dependencies it matches are resolved and all others keep
+    /// the entry they have in the existing lockfile, without contacting their registries.
+    ///
+    /// If `keep_going` is set, kits that fail to resolve are left out of the lockfile instead of
+    /// stopping the update, and are reported in the returned error once the lockfile is written.
     #[instrument(level = "trace", skip(project))]
-    pub(super) async fn create(project: &Project<Unlocked>) -> Result<Self> {
+    pub(super) async fn create(
+        project: &Project<Unlocked>,
+        prune: bool,
+        only: &[DependencyFilter],
+        keep_going: bool,
+    ) -> Result<Self> {
+        Self::create_with(project, image_tool()?.as_ref(), prune, only, keep_going).await
+    }
+
+    /// Like [`Lock::create`], but resolves dependencies with `image_tool`.
+    async fn create_with(
+        project: &Project<Unlocked>,
+        image_tool: &ImageTool,
+        prune: bool,
+        only: &[DependencyFilter],
+        keep_going: bool,
+    ) -> Result<Self> {
         let lock_file_path = project.project_dir().join(TWOLITER_LOCK);
+        Self::prune_orphaned(project, prune).await?;
+
+        let existing = if only.is_empty() {
+            None
+        } else {
+            let existing = Self::read_lock_file(project).await?;
+            existing.ensure_filters_match(project, only)?;
+            Some(existing)
+        };
+        let pins = existing.as_ref().map(|existing| Pins { existing, only });
 
         info!("Resolving project references to create lock file");
-        let lock_state = Self::resolve(project).await?;
+        let mut failures = ResolveFailures::new(keep_going);
+        let lock_state = Self::resolve(project, image_tool, pins.as_ref(), &mut failures).await?;
         let lock_str = toml::to_string(&lock_state).context("failed to serialize lock file")?;
 
         debug!("Writing new lock file to '{}'", lock_file_path.display());
         write(&lock_file_path, lock_str)
             .await
             .context("failed to write lock file")?;
+        failures.into_result()?;
         Ok(lock_state)
     }
 
@@ -158,7 +213,17 @@ impl Lock {
         info!("Resolving project references to check against lock file");
 
         let current_lock = Self::current_lock_state(project).await?;
-        let resolved_lock = Self::resolve(project).await?;
+        if offline() {
+            info!("Network access is disabled, using Twoliter.lock without checking it");
+            return Ok(current_lock);
+        }
+        let resolved_lock = Self::resolve(
+            project,
+            image_tool()?.as_ref(),
+            None,
+            &mut ResolveFailures::new(false),
+        )
+        .await?;
 
         debug!(
             current_lock=?current_lock,
@@ -177,8 +242,41 @@ impl Lock {
         Ok(resolved_lock)
     }
 
+    /// Resolves a kit image and the metadata describing its dependencies.
+    async fn resolve_kit(
+        image: &ProjectImage,
+        image_tool: &ImageTool,
+    ) -> Result<(LockedImage, ImageMetadata)> {
+        let image_resolver = ImageResolver::from_image(image)?;
+        let (locked_image, metadata) = image_resolver.resolve(image_tool).await?;
+        let metadata = metadata.context(format!(
+            "failed to validate kit image with name {} from vendor {}",
+            locked_image.name, locked_image.vendor
+        ))?;
+        Ok((locked_image, metadata))
+    }
+
     /// Returns the state of the lockfile for the given `Project`
     async fn current_lock_state<L: ProjectLock>(project: &Project<L>) -> Result<Self> {
+        let lock = Self::read_lock_file(project).await?;
+        let orphaned = lock.orphaned_images(project);
+        ensure!(orphaned.is_empty(), orphaned_images_message(&orphaned));
+        Ok(lock)
+    }
+
+    /// Reads the lockfile for the given `Project` if there is one, without validating it against
+    /// the project or any registry
+    pub(super) async fn read_existing<L: ProjectLock>(
+        project: &Project<L>,
+    ) -> Result<Option<Self>> {
+        if !project.project_dir().join(TWOLITER_LOCK).exists() {
+            return Ok(None);
+        }
+        Self::read_lock_file(project).await.map(Some)
+    }
+
+    /// Reads the lockfile for the given `Project` without validating it against the project
+    async fn read_lock_file<L: ProjectLock>(project: &Project<L>) -> Result<Self> {
         let lock_file_path = project.project_dir().join(TWOLITER_LOCK);
         ensure!(
             lock_file_path.exists(),
@@ -188,9 +286,93 @@ impl Lock {
         let lock_str = read_to_string(&lock_file_path)
             .await
             .context("failed to read lockfile")?;
-        let lock: Self =
-            toml::from_str(lock_str.as_str()).context("failed to deserialize lockfile")?;
-        Ok(lock)
+        toml::from_str(lock_str.as_str())
+            .map(Self::canonicalize)
+            .context("failed to deserialize lockfile")
+    }
+
+    /// Puts the kits in canonical order, sorted by vendor and then name, so that the lockfile and
+    /// the metadata derived from it do not depend on the order in which kits were resolved.
+    fn canonicalize(mut self) -> Self {
+        self.kit.sort_by(|left, right| {
+            (&left.vendor, &left.name, &left.version).cmp(&(
+                &right.vendor,
+                &right.name,
+                &right.version,
+            ))
+        });
+        self
+    }
+
+    /// Ensures that each of `only` matches the SDK or a kit, either in this lockfile or among the
+    /// project's direct dependencies.
+    fn ensure_filters_match<L: ProjectLock>(
+        &self,
+        project: &Project<L>,
+        only: &[DependencyFilter],
+    ) -> Result<()> {
+        let direct = project
+            .sdk
+            .iter()
+            .chain(&project.kit)
+            .map(|image| (&image.name, &image.vendor));
+        let locked = std::iter::once(&self.sdk)
+            .chain(&self.kit)
+            .map(|image| (&image.name, &image.vendor));
+        let known: Vec<_> = direct.chain(locked).collect();
+        for filter in only {
+            ensure!(
+                known
+                    .iter()
+                    .any(|(name, vendor)| filter.matches(name, vendor)),
+                "'{filter}' does not match the SDK or any kit the project depends on"
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns the locked images whose vendor is no longer defined in the project.
+    fn orphaned_images<L: ProjectLock>(&self, project: &Project<L>) -> Vec<&LockedImage> {
+        std::iter::once(&self.sdk)
+            .chain(&self.kit)
+            .filter(|image| !project.vendor.contains_key(&image.vendor))
+            .collect()
+    }
+
+    /// Checks the existing lockfile, if any, for images from vendors that are no longer defined in
+    /// the project. These are reported and dropped if `prune` is set, and are an error otherwise.
+    /// Returns the pruned images.
+    async fn prune_orphaned<L: ProjectLock>(
+        project: &Project<L>,
+        prune: bool,
+    ) -> Result<Vec<LockedImage>> {
+        let lock_file_path = project.project_dir().join(TWOLITER_LOCK);
+        if !lock_file_path.exists() {
+            return Ok(Vec::new());
+        }
+        let lock_str = read_to_string(&lock_file_path)
+            .await
+            .context("failed to read lockfile")?;
+        let Ok(lock) = toml::from_str::<Self>(lock_str.as_str()) else {
+            // The lockfile is replaced entirely, so there is nothing to reconcile.
+            debug!(
+                "Existing lockfile could not be deserialized, not checking for orphaned vendors"
+            );
+            return Ok(Vec::new());
+        };
+
+        let orphaned = lock.orphaned_images(project);
+        ensure!(
+            prune || orphaned.is_empty(),
+            orphaned_images_message(&orphaned)
+        );
+        for image in &orphaned {
+            info!(
+                "Pruning '{image}' from Twoliter.lock, vendor '{}' is no longer defined in Twoliter.toml",
+                image.vendor
+            );
+        }
+        Ok(orphaned.into_iter().cloned().collect())
     }
 
     fn external_kit_metadata(&self) -> ExternalKitMetadata {
@@ -200,10 +382,17 @@ impl Lock {
         }
     }
 
-    /// Fetches all external kits defined in a Twoliter.lock to the build directory
+    /// Fetches all external kits defined in a Twoliter.lock to the build directory, up to `jobs`
+    /// of them at a time. Every kit is attempted even if others fail, and all failures are
+    /// reported together.
     #[instrument(level = "trace", skip_all)]
-    pub(crate) async fn fetch(&self, project: &Project<Locked>, arch: &str) -> Result<()> {
-        let image_tool = ImageTool::from_builtin_krane();
+    pub(crate) async fn fetch(
+        &self,
+        project: &Project<Locked>,
+        arch: &str,
+        jobs: NonZeroUsize,
+    ) -> Result<()> {
+        let image_tool = image_tool()?;
         let target_dir = project.external_kits_dir();
         create_dir_all(&target_dir).await.context(format!(
             "failed to create external-kits directory at {}",
@@ -212,14 +401,40 @@ impl Lock {
 
         info!(
             dependencies = ?self.kit.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            jobs,
             "Extracting kit dependencies."
         );
-        for image in self.kit.iter() {
-            let image = project.as_project_image(image)?;
-            let resolver = ImageResolver::from_image(&image)?;
-            resolver
-                .extract(&image_tool, &project.external_kits_dir(), arch)
-                .await?;
+        let failures = stream::iter(self.kit.iter())
+            .map(|image| {
+                let image_tool = &image_tool;
+                let target_dir = &target_dir;
+                async move {
+                    let result = async {
+                        let image = project.as_project_image(image)?;
+                        ImageResolver::from_image(&image)?
+                            .extract(image_tool, target_dir, arch)
+                            .await
+                    }
+                    .await;
+                    (image, result)
+                }
+            })
+            .buffer_unordered(jobs.get())
+            .filter_map(|(image, result)| async move {
+                let e = result.err()?;
+                error!("Failed to fetch '{image}': {e:#}");
+                Some((image, e))
+            })
+            .collect::<Vec<_>>()
+            .await;
+        if !failures.is_empty() {
+            let count = failures.len();
+            let failures = failures
+                .iter()
+                .map(|(image, e)| format!("  {image}: {e:#}"))
+                .collect::<Vec<_>>()
+                .join("\n");
+            bail!("{count} kit(s) failed to fetch:\n{failures}");
         }
 
         self.synchronize_metadata(project).await
@@ -253,11 +468,19 @@ impl Lock {
         Ok(())
     }
 
-    #[instrument(level = "trace", skip(project))]
-    async fn resolve(project: &Project<Unlocked>) -> Result<Self> {
-        let mut known: HashMap<(ValidIdentifier, ValidIdentifier), Version> = HashMap::new();
+    /// Resolves the project's dependencies with `image_tool`. Dependencies that `pins` keeps at
+    /// their existing lock entry are not resolved, and neither are the kits they depend on, which
+    /// keep their existing entries too.
+    #[instrument(level = "trace", skip(project, image_tool, pins))]
+    async fn resolve(
+        project: &Project<Unlocked>,
+        image_tool: &ImageTool,
+        pins: Option<&Pins<'_>>,
+        failures: &mut ResolveFailures,
+    ) -> Result<Self> {
+        let mut known: HashMap<(ValidIdentifier, ValidIdentifier), Option<Version>> =
+            HashMap::new();
         let mut locked: Vec<LockedImage> = Vec::new();
-        let image_tool = ImageTool::from_builtin_krane();
         let mut remaining = project.direct_kit_deps()?;
 
         let mut sdk_set = HashSet::new();
@@ -272,14 +495,16 @@ impl Lock {
                 if let Some(version) =
                     known.get(&(image.name().clone(), image.vendor_name().clone()))
                 {
-                    let name = image.name().clone();
-                    let left_version = image.version().clone();
-                    let vendor = image.vendor_name().clone();
-                    ensure!(
-                        image.version() == version,
-                        "cannot have multiple versions of the same kit ({name}-{left_version}@{vendor} \
-                        != {name}-{version}@{vendor}",
-                    );
+                    // A kit pinned only by digest has no version to compare until it is locked.
+                    if let (Some(left_version), Some(version)) = (image.version(), version) {
+                        let name = image.name().clone();
+                        let vendor = image.vendor_name().clone();
+                        ensure!(
+                            left_version == version,
+                            "cannot have multiple versions of the same kit \
+                            ({name}-{left_version}@{vendor} != {name}-{version}@{vendor}",
+                        );
+                    }
                     debug!(
                         ?image,
                         "Skipping kit '{}' as it has already been resolved",
@@ -289,14 +514,17 @@ impl Lock {
                 }
                 known.insert(
                     (image.name().clone(), image.vendor_name().clone()),
-                    image.version().clone(),
+                    image.version().cloned(),
                 );
-                let image_resolver = ImageResolver::from_image(image)?;
-                let (locked_image, metadata) = image_resolver.resolve(&image_tool).await?;
-                let metadata = metadata.context(format!(
-                    "failed to validate kit image with name {} from vendor {}",
-                    locked_image.name, locked_image.vendor
-                ))?;
+                if let Some(pinned) = pins.and_then(|pins| pins.pinned(image)) {
+                    debug!(%pinned, "Keeping locked image");
+                    locked.push(pinned.clone());
+                    continue;
+                }
+                let resolved = Self::resolve_kit(image, image_tool).await;
+                let Some((locked_image, metadata)) = failures.record(image, resolved)? else {
+                    continue;
+                };
                 locked.push(locked_image);
                 sdk_set.insert(project.as_project_image(&metadata.sdk)?);
                 for dep in metadata.kits {
@@ -304,6 +532,17 @@ impl Lock {
                 }
             }
         }
+        if let Some(pins) = pins {
+            // The dependencies of pinned kits were not read, so keep the kits they were locked with.
+            for kit in &pins.existing.kit {
+                if !known.contains_key(&(kit.name.clone(), kit.vendor.clone()))
+                    && project.vendor.contains_key(&kit.vendor)
+                {
+                    debug!(%kit, "Keeping locked image");
+                    locked.push(kit.clone());
+                }
+            }
+        }
         debug!(?sdk_set, "Resolving workspace SDK");
         ensure!(
             sdk_set.len() <= 1,
@@ -314,21 +553,565 @@ impl Lock {
                 .collect::<Vec<_>>()
                 .join(", ")
         );
-        let sdk = sdk_set
-            .iter()
-            .next()
-            .context("no sdk was found for use, please specify a sdk in Twoliter.toml")?;
-
-        debug!(?sdk, "Resolving workspace SDK");
-        let (sdk, _metadata) = ImageResolver::from_image(sdk)?
-            .skip_metadata_retrieval() // SDKs don't have metadata
-            .resolve(&image_tool)
-            .await?;
+        let sdk = match (sdk_set.iter().next(), pins) {
+            (Some(sdk), pins) => match pins.and_then(|pins| pins.pinned(sdk)) {
+                Some(pinned) => {
+                    debug!(%pinned, "Keeping locked SDK");
+                    pinned.clone()
+                }
+                None => {
+                    debug!(?sdk, "Resolving workspace SDK");
+                    let (sdk, _metadata) = ImageResolver::from_image(sdk)?
+                        .skip_metadata_retrieval() // SDKs don't have metadata
+                        .resolve(image_tool)
+                        .await?;
+                    sdk
+                }
+            },
+            // Only pinned kits name the SDK, so it is the one they were locked with.
+            (None, Some(pins)) => pins.existing.sdk.clone(),
+            (None, None) => {
+                bail!("no sdk was found for use, please specify a sdk in Twoliter.toml")
+            }
+        };
 
         Ok(Self {
             schema_version: project.schema_version(),
             kit: locked,
             sdk,
+        }
+        .canonicalize())
+    }
+}
+
+/// The existing lock entries kept for the dependencies that an update does not select.
+#[derive(Debug)]
+struct Pins<'a> {
+    existing: &'a Lock,
+    only: &'a [DependencyFilter],
+}
+
+impl Pins<'_> {
+    /// Returns the existing lock entry to keep for `image`, or `None` if `image` is selected for
+    /// update, or cannot be pinned because it is new or its version or digest has changed.
+    fn pinned(&self, image: &ProjectImage) -> Option<&LockedImage> {
+        if self
+            .only
+            .iter()
+            .any(|filter| filter.matches(image.name(), image.vendor_name()))
+        {
+            return None;
+        }
+        std::iter::once(&self.existing.sdk)
+            .chain(&self.existing.kit)
+            .find(|locked| {
+                &locked.name == image.name()
+                    && &locked.vendor == image.vendor_name()
+                    && image
+                        .version()
+                        .into_iter()
+                        .all(|version| &locked.version == version)
+                    && (image.original_source_uri().digest.is_none()
+                        || locked.source == image.original_source_uri().to_string())
+            })
+    }
+}
+
+/// Decides what happens when an artifact fails to resolve. By default the first failure is
+/// returned as is; with `keep_going`, failures are collected so the remaining artifacts can still
+/// be resolved, and are reported together at the end.
+#[derive(Debug)]
+struct ResolveFailures {
+    keep_going: bool,
+    failures: Vec<(String, anyhow::Error)>,
+}
+
+impl ResolveFailures {
+    fn new(keep_going: bool) -> Self {
+        Self {
+            keep_going,
+            failures: Vec::new(),
+        }
+    }
+
+    /// Returns the resolved value, or `None` if resolution failed and the failure was recorded.
+    fn record<T>(&mut self, artifact: impl Display, result: Result<T>) -> Result<Option<T>> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if self.keep_going => {
+                error!("Failed to resolve '{artifact}': {e:#}");
+                self.failures.push((artifact.to_string(), e));
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns an error describing every recorded failure, if there were any.
+    fn into_result(self) -> Result<()> {
+        if self.failures.is_empty() {
+            return Ok(());
+        }
+        let failures = self
+            .failures
+            .iter()
+            .map(|(artifact, e)| format!("  {artifact}: {e:#}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        bail!(
+            "{} artifact(s) failed to resolve and were left out of Twoliter.lock:\n{failures}",
+            self.failures.len()
+        )
+    }
+}
+
+/// Selects dependencies by name, and optionally vendor, in the form `name[@vendor]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DependencyFilter {
+    name: ValidIdentifier,
+    vendor: Option<ValidIdentifier>,
+}
+
+impl DependencyFilter {
+    fn matches(&self, name: &ValidIdentifier, vendor: &ValidIdentifier) -> bool {
+        &self.name == name && self.vendor.iter().all(|filter| filter == vendor)
+    }
+}
+
+impl FromStr for DependencyFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, vendor) = match s.split_once('@') {
+            Some((name, vendor)) => (name, Some(vendor.parse()?)),
+            None => (s, None),
+        };
+        Ok(Self {
+            name: name.parse()?,
+            vendor,
+        })
+    }
+}
+
+impl Display for DependencyFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.vendor {
+            Some(vendor) => write!(f, "{}@{}", self.name, vendor),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// The image tool used to resolve and fetch images, which can be shared between tasks so that one
+/// tool serves every concurrent registry operation.
+///
+/// The tool is the builtin krane unless `TWOLITER_KIT_IMAGE_TOOL` selects another, and it runs
+/// with the [`GlobalOptions`] for this run. It applies any URI rewrite rule configured in
+/// `TWOLITER_URI_REWRITE`, any client certificates configured in `TWOLITER_REGISTRY_CLIENT_CERTS`,
+/// and any credentials configured in `TWOLITER_REGISTRY_AUTH` or in the docker config file named
+/// by `--registry-auth-file` or `TWOLITER_REGISTRY_AUTH_FILE`. Images are read through the
+/// registry mirrors in `TWOLITER_REGISTRY_MIRRORS`. Registry responses are recorded to, or
+/// replayed from, the directory in `TWOLITER_REGISTRY_RECORD` or `TWOLITER_REGISTRY_REPLAY` if
+/// either is set; recorded responses are keyed by the unmirrored URIs.
+pub(crate) fn image_tool() -> Result<Arc<ImageTool>> {
+    image_tool_with_selection(ImageToolSelection::from_env())
+}
+
+/// Like [`image_tool`], but uses the image tool chosen by `selection` instead of the one named in
+/// `TWOLITER_KIT_IMAGE_TOOL`.
+pub(crate) fn image_tool_with_selection(selection: ImageToolSelection) -> Result<Arc<ImageTool>> {
+    let rewriter = uri_rewriter_from_env().context("failed to read image URI rewrite rule")?;
+    let client_certs = RegistryClientCerts::from_env()
+        .context("failed to read registry client certificate configuration")?;
+    let options = GlobalOptions::get();
+    let mut credentials =
+        RegistryCredentials::from_env().context("failed to read registry credentials")?;
+    if let Some(auth_file) = &options.registry_auth_file {
+        credentials = credentials.with_auth_file(auth_file);
+    }
+    let mirrors =
+        RegistryMirrors::from_env().context("failed to read registry mirror configuration")?;
+    let fixtures =
+        RegistryFixtures::from_env().context("failed to read registry fixture configuration")?;
+    let image_tool = match fixtures {
+        Some(RegistryFixtures::Replay(dir)) => ImageTool::from_fixtures(dir),
+        Some(RegistryFixtures::Record(dir)) => ImageTool::from_selection(
+            selection,
+            client_certs,
+            credentials,
+            options.command_options(),
+        )?
+        .registry_mirrors(mirrors)
+        .record_to(dir),
+        None => ImageTool::from_selection(
+            selection,
+            client_certs,
+            credentials,
+            options.command_options(),
+        )?
+        .registry_mirrors(mirrors),
+    };
+    Ok(Arc::new(image_tool.uri_rewriter(rewriter)))
+}
+
+fn orphaned_images_message(orphaned: &[&LockedImage]) -> String {
+    let images = orphaned
+        .iter()
+        .map(|image| format!("'{image}' (vendor '{}')", image.vendor))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "Twoliter.lock references vendors that are no longer defined in Twoliter.toml: {images}. \
+        Run `twoliter update --prune` to remove them from the lock."
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::fs;
+    use base64::Engine;
+    use image::supported_kit_metadata_label;
+    use oci_cli_wrapper::{ConfigView, MockImageTool};
+    use serde_json::json;
+    use sha2::Digest;
+    use tempfile::TempDir;
+
+    const TWOLITER_TOML: &str = r#"
+schema-version = 1
+release-version = "1.0.0"
+
+[sdk]
+name = "my-bottlerocket-sdk"
+version = "1.2.3"
+vendor = "my-vendor"
+
+[vendor.my-vendor]
+registry = "a.com/b"
+"#;
+
+    const TWOLITER_LOCK_WITH_ORPHAN: &str = r#"
+schema-version = 1
+
+[sdk]
+name = "my-bottlerocket-sdk"
+version = "1.2.3"
+vendor = "my-vendor"
+source = "a.com/b/my-bottlerocket-sdk:v1.2.3"
+digest = "abc="
+
+[[kit]]
+name = "my-core-kit"
+version = "1.2.3"
+vendor = "removed-vendor"
+source = "c.com/d/my-core-kit:v1.2.3"
+digest = "def="
+"#;
+
+    async fn project_with_orphaned_lock() -> (TempDir, Project<Unlocked>) {
+        let temp_dir = TempDir::new().unwrap();
+        let project_path = temp_dir.path().join("Twoliter.toml");
+        fs::write(&project_path, TWOLITER_TOML).await.unwrap();
+        fs::write(
+            temp_dir.path().join(TWOLITER_LOCK),
+            TWOLITER_LOCK_WITH_ORPHAN,
+        )
+        .await
+        .unwrap();
+        let project = Project::load(&project_path).await.unwrap();
+        (temp_dir, project)
+    }
+
+    #[tokio::test]
+    async fn test_orphaned_vendor_is_an_error() {
+        let (_temp_dir, project) = project_with_orphaned_lock().await;
+
+        let err = Lock::current_lock_state(&project).await.unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("removed-vendor"), "{message}");
+        assert!(message.contains("twoliter update --prune"), "{message}");
+
+        assert!(Lock::prune_orphaned(&project, false).await.is_err());
+    }
+
+    fn locked(name: &str, version: &str, vendor: &str, digest: &str) -> LockedImage {
+        LockedImage {
+            name: name.parse().unwrap(),
+            version: version.parse().unwrap(),
+            vendor: vendor.parse().unwrap(),
+            source: format!("a.com/b/{name}:v{version}"),
+            digest: digest.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_lock_is_written_in_canonical_order() {
+        let kits = [
+            locked("my-extra-kit", "1.0.0", "my-vendor", "abc="),
+            locked("my-core-kit", "1.2.3", "other-vendor", "abc="),
+            locked("my-core-kit", "1.2.3", "my-vendor", "abc="),
+        ];
+        let lock_from = |kit: Vec<LockedImage>| {
+            let lock = Lock {
+                schema_version: SchemaVersion,
+                sdk: locked("my-bottlerocket-sdk", "1.2.3", "my-vendor", "abc="),
+                kit,
+            };
+            toml::to_string(&lock.canonicalize()).unwrap()
+        };
+
+        let forward = lock_from(kits.to_vec());
+        let reversed = lock_from(kits.iter().rev().cloned().collect());
+        assert_eq!(forward, reversed);
+
+        let lock: Lock = toml::from_str(&forward).unwrap();
+        let order: Vec<_> = lock
+            .kit
+            .iter()
+            .map(|kit| format!("{}@{}", kit.name, kit.vendor))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                "my-core-kit@my-vendor",
+                "my-extra-kit@my-vendor",
+                "my-core-kit@other-vendor"
+            ]
+        );
+    }
+
+    const TWO_VENDOR_TOML: &str = r#"
+schema-version = 1
+release-version = "1.0.0"
+
+[sdk]
+name = "my-bottlerocket-sdk"
+version = "1.2.3"
+vendor = "vendor-a"
+
+[vendor.vendor-a]
+registry = "a.com/a"
+
+[vendor.vendor-b]
+registry = "unreachable.example.com/b"
+
+[[kit]]
+name = "my-core-kit"
+version = "1.2.3"
+vendor = "vendor-a"
+
+[[kit]]
+name = "my-extra-kit"
+version = "1.0.0"
+vendor = "vendor-b"
+"#;
+
+    /// Serves an image index at `uri` whose amd64 image config has `labels`.
+    fn with_index(mock: MockImageTool, uri: &str, labels: &[(&str, String)]) -> MockImageTool {
+        let manifest = json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": {
+                "mediaType": "application/vnd.oci.image.config.v1+json",
+                "digest": "sha256:aaaa",
+                "size": 2,
+            },
+            "layers": [],
+            // Gives each image its own digest.
+            "annotations": { "source": uri },
         })
+        .to_string();
+        let digest = format!("sha256:{:x}", sha2::Sha256::digest(manifest.as_bytes()));
+        let index = json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": [{
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "digest": digest,
+                "size": manifest.len(),
+                "platform": { "architecture": "amd64", "os": "linux" },
+            }],
+        })
+        .to_string();
+        let config = ConfigView {
+            labels: labels
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.clone()))
+                .collect(),
+            ..ConfigView::default()
+        };
+        mock.with_manifest(&format!("{uri}-amd64"), manifest)
+            .with_config(&format!("{uri}-amd64"), config)
+            .with_manifest(uri, index)
+    }
+
+    /// Serves a kit with no kit dependencies that uses the project's SDK.
+    fn with_kit(mock: MockImageTool, uri: &str, name: &str, version: &str) -> MockImageTool {
+        let metadata = json!({
+            "name": name,
+            "version": version,
+            "sdk": { "name": "my-bottlerocket-sdk", "version": "1.2.3", "vendor": "vendor-a" },
+            "kit": [],
+        });
+        let label = base64::engine::general_purpose::STANDARD.encode(metadata.to_string());
+        with_index(mock, uri, &[(&supported_kit_metadata_label(), label)])
+    }
+
+    fn two_vendor_lock(digest: &str) -> Lock {
+        let locked = |name: &str, version: &str, vendor: &str, registry: &str| LockedImage {
+            source: format!("{registry}/{name}:v{version}"),
+            ..locked(name, version, vendor, digest)
+        };
+        Lock {
+            schema_version: SchemaVersion,
+            sdk: locked("my-bottlerocket-sdk", "1.2.3", "vendor-a", "a.com/a"),
+            kit: vec![
+                locked("my-core-kit", "1.2.3", "vendor-a", "a.com/a"),
+                locked(
+                    "my-extra-kit",
+                    "1.0.0",
+                    "vendor-b",
+                    "unreachable.example.com/b",
+                ),
+            ],
+        }
+    }
+
+    async fn two_vendor_project(lock: Option<&Lock>) -> (TempDir, Project<Unlocked>) {
+        let temp_dir = TempDir::new().unwrap();
+        let project_path = temp_dir.path().join("Twoliter.toml");
+        fs::write(&project_path, TWO_VENDOR_TOML).await.unwrap();
+        if let Some(lock) = lock {
+            fs::write(
+                temp_dir.path().join(TWOLITER_LOCK),
+                toml::to_string(lock).unwrap(),
+            )
+            .await
+            .unwrap();
+        }
+        let project = Project::load(&project_path).await.unwrap();
+        (temp_dir, project)
+    }
+
+    #[tokio::test]
+    async fn test_update_only_does_not_contact_unselected_registries() {
+        let existing = two_vendor_lock("old=");
+        let (_temp_dir, project) = two_vendor_project(Some(&existing)).await;
+        // Nothing is served from vendor-b's registry, so any request to it fails.
+        let mock = with_kit(
+            MockImageTool::new(),
+            "a.com/a/my-core-kit:v1.2.3",
+            "my-core-kit",
+            "1.2.3",
+        );
+        let only = ["my-core-kit@vendor-a".parse().unwrap()];
+
+        let updated = Lock::create_with(&project, &mock.image_tool(), false, &only, false)
+            .await
+            .unwrap();
+
+        assert_ne!(updated.kit[0].digest, "old=");
+        assert_eq!(updated.kit[1], existing.kit[1]);
+        assert_eq!(updated.sdk, existing.sdk);
+        for call in mock.calls() {
+            assert!(
+                call.uri.starts_with("a.com/a/my-core-kit"),
+                "unexpected registry access: {call:?}"
+            );
+        }
+        assert_eq!(Lock::read_lock_file(&project).await.unwrap(), updated);
+    }
+
+    #[tokio::test]
+    async fn test_update_only_unknown_dependency() {
+        let (_temp_dir, project) = two_vendor_project(Some(&two_vendor_lock("old="))).await;
+        let mock = MockImageTool::new();
+        for only in ["my-missing-kit", "my-core-kit@vendor-b"] {
+            let only = [only.parse().unwrap()];
+            let err = Lock::create_with(&project, &mock.image_tool(), false, &only, false)
+                .await
+                .unwrap_err();
+            assert!(err.to_string().contains(&only[0].to_string()), "{err}");
+        }
+        assert!(mock.calls().is_empty());
+    }
+
+    /// Serves the SDK and the vendor-a kit, and the vendor-b kit if `serve_vendor_b` is set.
+    fn two_vendor_registry(serve_vendor_b: bool) -> MockImageTool {
+        let mock = with_index(
+            MockImageTool::new(),
+            "a.com/a/my-bottlerocket-sdk:v1.2.3",
+            &[],
+        );
+        let mock = with_kit(mock, "a.com/a/my-core-kit:v1.2.3", "my-core-kit", "1.2.3");
+        if !serve_vendor_b {
+            return mock;
+        }
+        with_kit(
+            mock,
+            "unreachable.example.com/b/my-extra-kit:v1.0.0",
+            "my-extra-kit",
+            "1.0.0",
+        )
+    }
+
+    fn kit_names(lock: &Lock) -> Vec<String> {
+        lock.kit.iter().map(|kit| kit.name.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_resolve_all_succeed() {
+        for keep_going in [false, true] {
+            let (_temp_dir, project) = two_vendor_project(None).await;
+            let image_tool = two_vendor_registry(true).image_tool();
+
+            let lock = Lock::create_with(&project, &image_tool, false, &[], keep_going)
+                .await
+                .unwrap();
+            assert_eq!(kit_names(&lock), ["my-core-kit", "my-extra-kit"]);
+            assert_eq!(Lock::read_lock_file(&project).await.unwrap(), lock);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_failure_aborts_by_default() {
+        let (_temp_dir, project) = two_vendor_project(None).await;
+        let image_tool = two_vendor_registry(false).image_tool();
+
+        let err = Lock::create_with(&project, &image_tool, false, &[], false)
+            .await
+            .unwrap_err();
+        assert!(
+            format!("{err:#}").contains("unreachable.example.com/b/my-extra-kit"),
+            "{err:#}"
+        );
+        assert!(!project.project_dir().join(TWOLITER_LOCK).exists());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_failure_keep_going() {
+        let (_temp_dir, project) = two_vendor_project(None).await;
+        let image_tool = two_vendor_registry(false).image_tool();
+
+        let err = Lock::create_with(&project, &image_tool, false, &[], true)
+            .await
+            .unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("1 artifact(s) failed"), "{message}");
+        assert!(message.contains("my-extra-kit-1.0.0@"), "{message}");
+
+        let lock = Lock::read_lock_file(&project).await.unwrap();
+        assert_eq!(kit_names(&lock), ["my-core-kit"]);
+        assert_eq!(lock.sdk.name.to_string(), "my-bottlerocket-sdk");
+    }
+
+    #[tokio::test]
+    async fn test_orphaned_vendor_is_pruned() {
+        let (_temp_dir, project) = project_with_orphaned_lock().await;
+
+        let pruned = Lock::prune_orphaned(&project, true).await.unwrap();
+        assert_eq!(pruned.len(), 1);
+        assert_eq!(pruned[0].name.to_string(), "my-core-kit");
+        assert_eq!(pruned[0].vendor.to_string(), "removed-vendor");
     }
 }
@@ -1,22 +1,10 @@
-use oci_cli_wrapper::DockerArchitecture;
 use serde::de::Error;
 use serde::{Deserialize, Deserializer};
 use std::fmt::{Display, Formatter};
 
-#[derive(Deserialize, Debug)]
-pub(crate) struct ManifestListView {
-    pub manifests: Vec<ManifestView>,
-}
-
 #[derive(Deserialize, Debug, Clone)]
 pub(crate) struct ManifestView {
     pub digest: String,
-    pub platform: Option<Platform>,
-}
-
-#[derive(Deserialize, Debug, Clone)]
-pub(crate) struct Platform {
-    pub architecture: DockerArchitecture,
 }
 
 #[derive(Deserialize, Debug)]
@@ -1,11 +1,16 @@
 mod lock;
+mod validate;
 pub(crate) mod vendor;
 
 pub(crate) use self::vendor::ArtifactVendor;
-pub(crate) use lock::VerificationTagger;
+pub(crate) use lock::{
+    image_tool, image_tool_with_selection, DependencyFilter, VerificationTagger,
+};
 use path_absolutize::Absolutize;
+pub(crate) use validate::{find_problems, find_project_file};
 
-use self::lock::{Lock, LockedSDK, Override};
+use self::lock::{Lock, LockedImage, LockedSDK, Override};
+use self::validate::is_sha256_digest;
 use crate::common::fs::{self, read_to_string};
 use crate::compatibility::SUPPORTED_TWOLITER_PROJECT_SCHEMA_VERSION;
 use crate::docker::ImageUri;
@@ -16,6 +21,7 @@ use async_trait::async_trait;
 use async_walkdir::WalkDir;
 use buildsys_config::{EXTERNAL_KIT_DIRECTORY, EXTERNAL_KIT_METADATA};
 use futures::stream::StreamExt;
+use oci_cli_wrapper::UriRewriter;
 use semver::Version;
 use serde::de::Error;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
@@ -23,6 +29,7 @@ use std::collections::BTreeMap;
 use std::ffi::OsStr;
 use std::fmt::{Debug, Display, Formatter};
 use std::hash::Hash;
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use toml::Table;
@@ -124,8 +131,17 @@ impl Project<Unlocked> {
         Self::find_and_load(parent).await
     }
 
-    pub(crate) async fn create_lock(self) -> Result<Project<Locked>> {
-        let lock = Lock::create(&self).await?;
+    /// Resolves dependencies and writes `Twoliter.lock`. If `prune` is set, lock entries for
+    /// vendors that are no longer defined in `Twoliter.toml` are dropped instead of being an error.
+    /// If `only` is not empty, dependencies it does not match keep their existing lock entries. If
+    /// `keep_going` is set, kits that fail to resolve are reported after writing the others.
+    pub(crate) async fn create_lock(
+        self,
+        prune: bool,
+        only: &[DependencyFilter],
+        keep_going: bool,
+    ) -> Result<Project<Locked>> {
+        let lock = Lock::create(&self, prune, only, keep_going).await?;
         Ok(self.with_new_lock(lock))
     }
 
@@ -273,6 +289,117 @@ impl<L: ProjectLock> Project<L> {
         modules.sort();
         Ok(modules)
     }
+
+    /// Returns the names of the packages found in the `packages` directory, as declared in each
+    /// package's `Cargo.toml`.
+    pub(crate) async fn find_packages(&self) -> Result<Vec<String>> {
+        let root = self.project_dir.join("packages");
+        if !root.exists() {
+            return Ok(Vec::new());
+        }
+        let mut entries = tokio::fs::read_dir(&root)
+            .await
+            .context(format!("Unable to read directory '{}'", root.display()))?;
+        let mut packages = Vec::new();
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .context(format!("Unable to read directory '{}'", root.display()))?
+        {
+            let manifest_path = entry.path().join("Cargo.toml");
+            if !manifest_path.is_file() {
+                continue;
+            }
+            let manifest: Table =
+                toml::from_str(&read_to_string(&manifest_path).await?).context(format!(
+                    "Unable to deserialize package manifest '{}'",
+                    manifest_path.display()
+                ))?;
+            let name = manifest
+                .get("package")
+                .and_then(|package| package.get("name"))
+                .and_then(|name| name.as_str())
+                .context(format!(
+                    "Expected a package name in '{}'",
+                    manifest_path.display()
+                ))?;
+            packages.push(name.to_string());
+        }
+        // Provide a predictable ordering.
+        packages.sort();
+        Ok(packages)
+    }
+}
+
+impl Project<Unlocked> {
+    /// Serializes the project as Twoliter uses it: with overrides from `Twoliter.override` and
+    /// `rewriter` applied to image URIs and, if `Twoliter.lock` exists, the locked versions and
+    /// digests. Neither the lockfile nor any registry is touched.
+    pub(crate) async fn resolved_toml(&self, rewriter: &dyn UriRewriter) -> Result<String> {
+        let lock = Lock::read_existing(self).await?;
+        let locked: Vec<&LockedImage> = lock
+            .iter()
+            .flat_map(|lock| std::iter::once(&lock.sdk).chain(&lock.kit))
+            .collect();
+        let resolve = |image: &Image| -> Result<ResolvedImage> {
+            let project_image = self.as_project_image(image)?;
+            let uri = rewriter.rewrite(&project_image.project_image_uri().to_string());
+            let source_uri = project_image.original_source_uri().to_string();
+            let locked = locked
+                .iter()
+                .find(|locked| locked.name == image.name && locked.vendor == image.vendor);
+            Ok(ResolvedImage {
+                name: image.name.clone(),
+                version: locked
+                    .map(|locked| &locked.version)
+                    .or(image.version.as_ref())
+                    .cloned(),
+                vendor: image.vendor.clone(),
+                overridden_from: (source_uri != uri).then_some(source_uri),
+                uri,
+                digest: locked.map(|locked| locked.digest.clone()),
+            })
+        };
+
+        let resolved = ResolvedProject {
+            schema_version: self.schema_version,
+            release_version: self.release_version.clone(),
+            vendor: self.vendor.clone(),
+            sdk: self.sdk.as_ref().map(resolve).transpose()?,
+            kit: self.kit.iter().map(resolve).collect::<Result<_>>()?,
+        };
+        toml::to_string(&resolved).context("Unable to serialize resolved project")
+    }
+}
+
+/// The fully resolved form of a project, see [`Project::resolved_toml`].
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct ResolvedProject {
+    schema_version: SchemaVersion<1>,
+    release_version: String,
+    vendor: BTreeMap<ValidIdentifier, Vendor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sdk: Option<ResolvedImage>,
+    kit: Vec<ResolvedImage>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct ResolvedImage {
+    name: ValidIdentifier,
+    /// The locked version, or the project's if the image has not been locked. An image pinned only
+    /// by digest has no version until it is locked.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<Version>,
+    vendor: ValidIdentifier,
+    /// The image URI the project will use
+    uri: String,
+    /// The vendor's image URI, if an override or URI rewrite changed it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    overridden_from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    digest: Option<String>,
 }
 
 impl Project<SDKLocked> {
@@ -284,13 +411,13 @@ impl Project<SDKLocked> {
 }
 
 impl Project<Locked> {
-    /// Fetches all external kits defined in a Twoliter.lock to the build directory
-    pub(crate) async fn fetch(&self, arch: &str) -> Result<()> {
+    /// Fetches all external kits defined in a Twoliter.lock to the build directory, up to `jobs`
+    /// of them at a time
+    pub(crate) async fn fetch(&self, arch: &str, jobs: NonZeroUsize) -> Result<()> {
         let Locked(lock) = &self.lock;
-        lock.fetch(self, arch).await
+        lock.fetch(self, arch, jobs).await
     }
 
-    #[expect(dead_code)]
     pub(crate) fn kits(&self) -> Vec<ProjectImage> {
         let Locked(lock) = &self.lock;
         lock.kit
@@ -305,6 +432,13 @@ impl Project<Locked> {
         self.as_project_image(&lock.sdk)
             .expect("Could not find SDK vendor despite lock resolution succeeding?")
     }
+
+    /// Every image the project depends on: the SDK followed by the kits.
+    pub(crate) fn all_images(&self) -> Vec<ProjectImage> {
+        std::iter::once(self.sdk_image())
+            .chain(self.kits())
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -318,17 +452,17 @@ impl Display for ProjectImage {
         match self.vendor {
             ArtifactVendor::Overridden(_) => write!(
                 f,
-                "{}-{}@{} (overridden-to: {})",
+                "{}{}@{} (overridden-to: {})",
                 self.name(),
-                self.version(),
+                version_suffix(self.version()),
                 self.original_source_uri(),
                 self.project_image_uri(),
             ),
             ArtifactVendor::Verbatim(_) => write!(
                 f,
-                "{}-{}@{}",
+                "{}{}@{}",
                 self.name(),
-                self.version(),
+                version_suffix(self.version()),
                 self.original_source_uri()
             ),
         }
@@ -340,7 +474,7 @@ impl ProjectImage {
         &self.image.name
     }
 
-    pub(crate) fn version(&self) -> &Version {
+    pub(crate) fn version(&self) -> Option<&Version> {
         self.image.version()
     }
 
@@ -348,6 +482,11 @@ impl ProjectImage {
         self.vendor.vendor_name()
     }
 
+    /// Returns the manifest digest that an override requires this image to have, if any.
+    pub(crate) fn expected_digest(&self) -> Option<&str> {
+        self.vendor.expected_digest()
+    }
+
     /// Returns the URI for the original vendor.
     pub(crate) fn original_source_uri(&self) -> ImageUri {
         match &self.vendor {
@@ -366,7 +505,8 @@ impl ProjectImage {
         ImageUri {
             registry: Some(self.vendor.registry().to_string()),
             repo: self.vendor.repo_for(&self.image).to_string(),
-            tag: format!("v{}", self.image.version()),
+            tag: self.image.version_tag(),
+            digest: self.image.digest.clone(),
         }
     }
 }
@@ -377,9 +517,37 @@ impl ProjectImage {
 pub(crate) trait VendedArtifact: std::fmt::Debug {
     fn artifact_name(&self) -> &ValidIdentifier;
     fn vendor_name(&self) -> &ValidIdentifier;
-    fn version(&self) -> &Version;
+    /// The version of the artifact, which an image pinned by digest in Twoliter.toml may leave
+    /// out.
+    fn version(&self) -> Option<&Version>;
+
+    /// The digest the artifact is pinned to in Twoliter.toml, if any.
+    fn pinned_digest(&self) -> Option<&str> {
+        None
+    }
+
+    /// The tag the artifact is published under, `v<version>`. An artifact without a version is
+    /// pinned by digest, which image URIs refer to instead, so its tag is empty.
+    fn version_tag(&self) -> String {
+        self.version()
+            .map(|version| format!("v{version}"))
+            .unwrap_or_default()
+    }
 }
 
+/// Formats `version` to follow an artifact name, e.g. `-1.2.3`, or nothing without a version.
+fn version_suffix(version: Option<&Version>) -> String {
+    version
+        .map(|version| format!("-{version}"))
+        .unwrap_or_default()
+}
+
+/// The longest identifier allowed, which keeps names usable as image repository path segments and
+/// tags.
+const MAX_IDENTIFIER_LEN: usize = 128;
+
+/// A name for a vendor or artifact: 1 to 128 letters, digits, underscores or hyphens, starting with
+/// a letter or digit.
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub(crate) struct ValidIdentifier(pub(crate) String);
 
@@ -400,6 +568,11 @@ impl FromStr for ValidIdentifier {
             !input.is_empty(),
             "cannot define an identifier as an empty string",
         );
+        ensure!(
+            input.len() <= MAX_IDENTIFIER_LEN,
+            "identifier '{input}' is {} characters long, the maximum is {MAX_IDENTIFIER_LEN}",
+            input.len()
+        );
 
         // Check if the input contains any invalid characters
         for c in input.chars() {
@@ -410,6 +583,13 @@ impl FromStr for ValidIdentifier {
             );
         }
 
+        // Identifiers become repository path segments, which must start with a letter or digit.
+        let first = input.chars().next().unwrap_or_default();
+        ensure!(
+            first.is_ascii_alphanumeric(),
+            "identifier '{input}' must start with a letter or digit, not '{first}'"
+        );
+
         Ok(Self(input.to_string()))
     }
 }
@@ -458,8 +638,14 @@ pub(crate) struct Vendor {
 #[serde(rename_all = "kebab-case")]
 pub(crate) struct Image {
     pub name: ValidIdentifier,
-    pub version: Version,
+    /// May be left out when `digest` is set, in which case the version is read from the kit's
+    /// metadata when the image is locked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<Version>,
     pub vendor: ValidIdentifier,
+    /// Pins the image to a manifest digest, e.g. `sha256:...`, instead of its mutable version tag
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
 }
 
 impl Image {
@@ -467,14 +653,25 @@ impl Image {
         Self {
             name: artifact.artifact_name().clone(),
             vendor: artifact.vendor_name().clone(),
-            version: artifact.version().clone(),
+            version: artifact.version().cloned(),
+            digest: artifact.pinned_digest().map(str::to_string),
         }
     }
 }
 
 impl Display for Image {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}-{}@{}", self.name, self.version, self.vendor)
+        write!(
+            f,
+            "{}{}@{}",
+            self.name,
+            version_suffix(self.version.as_ref()),
+            self.vendor
+        )?;
+        match (&self.version, &self.digest) {
+            (None, Some(digest)) => write!(f, " ({digest})"),
+            _ => Ok(()),
+        }
     }
 }
 
@@ -487,8 +684,12 @@ impl VendedArtifact for Image {
         &self.vendor
     }
 
-    fn version(&self) -> &Version {
-        &self.version
+    fn version(&self) -> Option<&Version> {
+        self.version.as_ref()
+    }
+
+    fn pinned_digest(&self) -> Option<&str> {
+        self.digest.as_deref()
     }
 }
 
@@ -519,6 +720,7 @@ impl UnvalidatedProject {
             .to_path_buf();
 
         self.check_vendor_availability().await?;
+        self.check_image_references()?;
         self.check_release_toml(&project_dir).await?;
         let overrides = self.check_and_load_overrides(&project_dir).await?;
 
@@ -575,6 +777,32 @@ impl UnvalidatedProject {
         Ok(())
     }
 
+    /// Errors if a dependency gives neither a version nor a digest, or a digest that is not a
+    /// sha256 digest. The SDK has no kit metadata to read a version from, so it must always give
+    /// one.
+    fn check_image_references(&self) -> Result<()> {
+        if let Some(sdk) = &self.sdk {
+            ensure!(
+                sdk.version.is_some(),
+                "the sdk '{sdk}' must specify a version in Twoliter.toml"
+            );
+        }
+        for image in self.sdk.iter().chain(self.kit.iter().flatten()) {
+            if let Some(digest) = &image.digest {
+                ensure!(
+                    is_sha256_digest(digest),
+                    "the digest of '{image}', '{digest}', is not a sha256 digest, expected \
+                    'sha256:' followed by 64 lowercase hex digits"
+                );
+            }
+            ensure!(
+                image.version.is_some() || image.digest.is_some(),
+                "'{image}' must specify a version, a digest, or both in Twoliter.toml"
+            );
+        }
+        Ok(())
+    }
+
     /// Issues a warning if `Release.toml` is found and, if so, ensures that it contains the same
     /// version (i.e. `release-version`) as the `Twoliter.toml` project file.
     async fn check_release_toml(&self, project_dir: &Path) -> Result<()> {
@@ -700,6 +928,7 @@ mod test {
     use super::*;
     use crate::common::fs;
     use crate::test::{data_dir, projects_dir};
+    use oci_cli_wrapper::{IdentityUriRewriter, RegexUriRewriter};
     use tempfile::TempDir;
 
     /// Ensure that `Twoliter.toml` can be deserialized.
@@ -725,15 +954,133 @@ mod test {
 
         let sdk = deserialized.sdk.unwrap();
         assert_eq!("my-bottlerocket-sdk", sdk.name.to_string());
-        assert_eq!(Version::new(1, 2, 3), sdk.version);
+        assert_eq!(Some(Version::new(1, 2, 3)), sdk.version);
         assert_eq!("my-vendor", sdk.vendor.to_string());
 
         assert_eq!(1, deserialized.kit.len());
         assert_eq!("my-core-kit", deserialized.kit[0].name.to_string());
-        assert_eq!(Version::new(1, 2, 3), deserialized.kit[0].version);
+        assert_eq!(Some(Version::new(1, 2, 3)), deserialized.kit[0].version);
         assert_eq!("my-vendor", deserialized.kit[0].vendor.to_string());
     }
 
+    /// Ensure that an image pinned to a digest is referred to by the digest rather than its tag.
+    #[tokio::test]
+    async fn deserialize_digest_pinned_kit() {
+        let path = data_dir().join("Twoliter-digest.toml");
+        let project = Project::load(path).await.unwrap();
+
+        let sdk = project.direct_sdk_image_dep().unwrap().unwrap();
+        assert_eq!(
+            sdk.project_image_uri().to_string(),
+            "a.com/b/my-bottlerocket-sdk:v1.2.3"
+        );
+
+        let kit = &project.direct_kit_deps().unwrap()[0];
+        let digest = "sha256:3f8a2c64c2c1e0cb3ee34a43ef6c0dc3ac6c2e4c0d1b5e1c3a1f2f1d0b0c0e0f";
+        assert_eq!(
+            kit.project_image_uri().to_string(),
+            format!("a.com/b/my-core-kit@{digest}")
+        );
+        assert_eq!(
+            kit.original_source_uri().to_string(),
+            format!("a.com/b/my-core-kit@{digest}")
+        );
+    }
+
+    /// Ensure that a kit can be pinned by a digest instead of a version.
+    #[test]
+    fn deserialize_digest_without_version() {
+        let image = toml::from_str::<Image>(
+            r#"
+            name = "my-core-kit"
+            vendor = "my-vendor"
+            digest = "sha256:3f8a2c64c2c1e0cb3ee34a43ef6c0dc3ac6c2e4c0d1b5e1c3a1f2f1d0b0c0e0f"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(image.version, None);
+        assert_eq!(image.version_tag(), "");
+        assert_eq!(
+            image.to_string(),
+            "my-core-kit@my-vendor \
+            (sha256:3f8a2c64c2c1e0cb3ee34a43ef6c0dc3ac6c2e4c0d1b5e1c3a1f2f1d0b0c0e0f)"
+        );
+    }
+
+    /// Ensure that loading a project checks how each image is referenced.
+    #[tokio::test]
+    async fn load_checks_image_references() {
+        let digest = "sha256:3f8a2c64c2c1e0cb3ee34a43ef6c0dc3ac6c2e4c0d1b5e1c3a1f2f1d0b0c0e0f";
+        let twoliter_toml = |sdk: &str, kit: &str| {
+            format!(
+                r#"
+schema-version = 1
+release-version = "1.0.0"
+
+[vendor.my-vendor]
+registry = "a.com/b"
+
+[sdk]
+name = "my-bottlerocket-sdk"
+vendor = "my-vendor"
+{sdk}
+
+[[kit]]
+name = "my-core-kit"
+vendor = "my-vendor"
+{kit}
+"#
+            )
+        };
+        let load = |contents: String| async move {
+            let dir = TempDir::new().unwrap();
+            let path = dir.path().join("Twoliter.toml");
+            fs::write(&path, contents).await.unwrap();
+            Project::load(&path).await
+        };
+
+        let project = load(twoliter_toml(
+            r#"version = "1.2.3""#,
+            &format!(r#"digest = "{digest}""#),
+        ))
+        .await
+        .unwrap();
+        let kit = &project.direct_kit_deps().unwrap()[0];
+        assert_eq!(kit.version(), None);
+        assert_eq!(
+            kit.project_image_uri().to_string(),
+            format!("a.com/b/my-core-kit@{digest}")
+        );
+
+        let err = load(twoliter_toml(
+            r#"version = "1.2.3""#,
+            r#"digest = "sha256:1234""#,
+        ))
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("is not a sha256 digest"), "{err}");
+
+        let err = load(twoliter_toml(r#"version = "1.2.3""#, ""))
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("must specify a version, a digest"),
+            "{err}"
+        );
+
+        let err = load(twoliter_toml(
+            &format!(r#"digest = "{digest}""#),
+            r#"version = "1.2.3""#,
+        ))
+        .await
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("sdk 'my-bottlerocket-sdk@my-vendor"),
+            "{err}"
+        );
+    }
+
     /// Ensure that a `Twoliter.toml` cannot be serialized if the `schema_version` is incorrect.
     #[tokio::test]
     async fn deserialize_invalid_version() {
@@ -815,6 +1162,7 @@ mod test {
                 Override {
                     name: Some("my-overridden-sdk".parse().unwrap()),
                     registry: Some("c.com/d".parse().unwrap()),
+                    digest: None,
                 },
             )
         );
@@ -825,10 +1173,80 @@ mod test {
                 registry: Some("c.com/d".into()),
                 repo: "my-overridden-sdk".into(),
                 tag: "v1.2.3".into(),
+                digest: None,
             }
         )
     }
 
+    #[tokio::test]
+    async fn test_resolved_toml_applies_overrides() {
+        let path = data_dir().join("override/Twoliter-override-1.toml");
+        let project = Project::load(path).await.unwrap();
+
+        let resolved = project.resolved_toml(&IdentityUriRewriter).await.unwrap();
+        let resolved: Table = toml::from_str(&resolved).unwrap();
+        let sdk = resolved["sdk"].as_table().unwrap();
+        assert_eq!(
+            sdk["uri"].as_str(),
+            Some("c.com/d/my-overridden-sdk:v1.2.3")
+        );
+        assert_eq!(
+            sdk["overridden-from"].as_str(),
+            Some("a.com/b/my-bottlerocket-sdk:v1.2.3")
+        );
+        let kit = resolved["kit"][0].as_table().unwrap();
+        assert_eq!(kit["uri"].as_str(), Some("a.com/b/my-core-kit:v1.2.3"));
+        assert!(!kit.contains_key("overridden-from"));
+    }
+
+    /// Ensure that the dump shows URIs as rewritten by a `TWOLITER_URI_REWRITE` rule and the
+    /// versions and digests from an existing Twoliter.lock, which is left as it was.
+    #[tokio::test]
+    async fn test_resolved_toml_applies_rewrite_and_lock() {
+        let tempdir = TempDir::new().unwrap();
+        let p = tempdir.path();
+        fs::copy(data_dir().join("Twoliter-1.toml"), p.join("Twoliter.toml"))
+            .await
+            .unwrap();
+        let lock = r#"schema-version = 1
+
+[sdk]
+name = "my-bottlerocket-sdk"
+version = "1.2.3"
+vendor = "my-vendor"
+source = "a.com/b/my-bottlerocket-sdk:v1.2.3"
+digest = "sdk-digest"
+
+[[kit]]
+name = "my-core-kit"
+version = "1.2.4"
+vendor = "my-vendor"
+source = "a.com/b/my-core-kit:v1.2.4"
+digest = "kit-digest"
+"#;
+        fs::write(p.join("Twoliter.lock"), lock).await.unwrap();
+        let project = Project::load(p.join("Twoliter.toml")).await.unwrap();
+
+        let rewriter = RegexUriRewriter::from_rule("^a.com/b/=mirror.example.com/b/").unwrap();
+        let resolved = project.resolved_toml(&rewriter).await.unwrap();
+        let resolved: Table = toml::from_str(&resolved).unwrap();
+        let kit = resolved["kit"][0].as_table().unwrap();
+        assert_eq!(
+            kit["uri"].as_str(),
+            Some("mirror.example.com/b/my-core-kit:v1.2.3")
+        );
+        assert_eq!(
+            kit["overridden-from"].as_str(),
+            Some("a.com/b/my-core-kit:v1.2.3")
+        );
+        assert_eq!(kit["version"].as_str(), Some("1.2.4"));
+        assert_eq!(kit["digest"].as_str(), Some("kit-digest"));
+        assert_eq!(
+            fs::read_to_string(p.join("Twoliter.lock")).await.unwrap(),
+            lock
+        );
+    }
+
     #[tokio::test]
     async fn test_vendor_specifications() {
         let project = UnvalidatedProject {
@@ -836,8 +1254,9 @@ mod test {
             release_version: "1.0.0".into(),
             sdk: Some(Image {
                 name: ValidIdentifier("bottlerocket-sdk".into()),
-                version: Version::new(1, 41, 1),
+                version: Some(Version::new(1, 41, 1)),
                 vendor: ValidIdentifier("bottlerocket".into()),
+                digest: None,
             }),
             vendor: Some(BTreeMap::from([(
                 ValidIdentifier("not-bottlerocket".into()),
@@ -847,8 +1266,9 @@ mod test {
             )])),
             kit: Some(vec![Image {
                 name: ValidIdentifier("bottlerocket-core-kit".into()),
-                version: Version::new(1, 20, 0),
+                version: Some(Version::new(1, 20, 0)),
                 vendor: ValidIdentifier("not-bottlerocket".into()),
+                digest: None,
             }]),
         };
         assert!(project.check_vendor_availability().await.is_err());
@@ -874,6 +1294,25 @@ mod test {
         Project::find_and_load(p).await.unwrap();
     }
 
+    #[tokio::test]
+    async fn find_packages() {
+        let twoliter_toml_path = projects_dir().join("local-kit").join("Twoliter.toml");
+        let project = Project::load(twoliter_toml_path).await.unwrap();
+        let packages = project.find_packages().await.unwrap();
+        assert_eq!(
+            packages,
+            [
+                "pkg-a-1_27",
+                "pkg-b",
+                "pkg-c",
+                "pkg-d",
+                "pkg-e",
+                "pkg-f",
+                "pkg-g"
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn find_go_modules() {
         let twoliter_toml_path = projects_dir().join("project1").join("Twoliter.toml");
@@ -882,4 +1321,42 @@ mod test {
         assert_eq!(go_modules.len(), 1, "Expected to find 1 go module");
         assert_eq!(go_modules.first().unwrap(), "hello-go");
     }
+
+    #[test]
+    fn test_valid_identifiers() {
+        for id in [
+            "core-kit",
+            "a",
+            "0-kit",
+            "my_vendor",
+            &"a".repeat(MAX_IDENTIFIER_LEN),
+        ] {
+            assert_eq!(id.parse::<ValidIdentifier>().unwrap().as_ref(), id);
+        }
+    }
+
+    #[test]
+    fn test_invalid_identifiers() {
+        let err = |id: &str| id.parse::<ValidIdentifier>().unwrap_err().to_string();
+        assert_eq!(err(""), "cannot define an identifier as an empty string");
+        assert_eq!(
+            err(&"a".repeat(MAX_IDENTIFIER_LEN + 1)),
+            format!(
+                "identifier '{}' is 129 characters long, the maximum is 128",
+                "a".repeat(MAX_IDENTIFIER_LEN + 1)
+            )
+        );
+        assert_eq!(
+            err("core.kit"),
+            "invalid character '.' found in identifier name"
+        );
+        assert_eq!(
+            err("-core-kit"),
+            "identifier '-core-kit' must start with a letter or digit, not '-'"
+        );
+        assert_eq!(
+            err("_core-kit"),
+            "identifier '_core-kit' must start with a letter or digit, not '_'"
+        );
+    }
 }
@@ -0,0 +1,360 @@
+//! Checks of a project's `Twoliter.toml` and `Twoliter.lock` that do not contact a registry.
+//!
+//! Loading a project stops at the first problem it finds. These checks instead read both files
+//! leniently and report every problem at once, which makes them useful as a fast CI gate.
+use super::lock::{LockedImage, TWOLITER_LOCK};
+use super::{Image, ValidIdentifier, Vendor};
+use crate::common::fs::read_to_string;
+use crate::compatibility::SUPPORTED_TWOLITER_PROJECT_SCHEMA_VERSION;
+use crate::schema_version::SchemaVersion;
+use anyhow::{Context, Result};
+use semver::Version;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// Search for `Twoliter.toml` in `dir` and then each of its parents.
+pub(crate) fn find_project_file(dir: &Path) -> Result<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join("Twoliter.toml"))
+        .find(|path| path.is_file())
+        .context(format!(
+            "Unable to find a Twoliter.toml file in '{}' or its parents",
+            dir.display()
+        ))
+}
+
+/// Check the project file at `path`, and the lockfile next to it if there is one, returning every
+/// problem found.
+pub(crate) async fn find_problems(path: &Path) -> Result<Vec<String>> {
+    let mut problems = Problems::default();
+    let project_str = read_to_string(path).await?;
+    let Some(project) = problems.parse(&project_str, "Twoliter.toml") else {
+        return Ok(problems.0);
+    };
+    let (vendors, images) = check_project(&project, &mut problems);
+
+    let lock_path = path
+        .parent()
+        .context(format!(
+            "Unable to find the parent directory of '{}'",
+            path.display()
+        ))?
+        .join(TWOLITER_LOCK);
+    if lock_path.is_file() {
+        let lock_str = read_to_string(&lock_path).await?;
+        if let Some(lock) = problems.parse(&lock_str, TWOLITER_LOCK) {
+            check_lock(&lock, &vendors, &images, &mut problems);
+        }
+    }
+    Ok(problems.0)
+}
+
+/// Checks the fields of `Twoliter.toml`, returning the names of the vendors it defines and the
+/// images it depends on that are valid.
+fn check_project(project: &Table, problems: &mut Problems) -> (BTreeSet<String>, Vec<Image>) {
+    let context = "Twoliter.toml";
+    problems.required::<SchemaVersion<SUPPORTED_TWOLITER_PROJECT_SCHEMA_VERSION>>(
+        project,
+        "schema-version",
+        context,
+    );
+    problems.required::<String>(project, "release-version", context);
+
+    let mut vendors = BTreeSet::new();
+    if let Some(vendor_table) = problems.optional::<Table>(project, "vendor", context) {
+        for (name, vendor) in vendor_table {
+            let context = format!("Twoliter.toml: vendor '{name}'");
+            problems.check(name.parse::<ValidIdentifier>(), &context);
+            problems.convert::<Vendor>(&vendor, &context);
+            vendors.insert(name);
+        }
+    }
+
+    let mut images = Vec::new();
+    if let Some(sdk) = project.get("sdk") {
+        images.extend(check_image(
+            sdk,
+            true,
+            "Twoliter.toml: sdk",
+            &vendors,
+            problems,
+        ));
+    }
+    if let Some(kits) = problems.optional::<Vec<Value>>(project, "kit", context) {
+        for (i, kit) in kits.iter().enumerate() {
+            let context = format!("Twoliter.toml: kit #{}", i + 1);
+            images.extend(check_image(kit, false, &context, &vendors, problems));
+        }
+    }
+    (vendors, images)
+}
+
+/// Checks a dependency on an image, returning it if it is valid.
+fn check_image(
+    image: &Value,
+    is_sdk: bool,
+    context: &str,
+    vendors: &BTreeSet<String>,
+    problems: &mut Problems,
+) -> Option<Image> {
+    let Some(table) = image.as_table() else {
+        problems.push(context, "expected a table");
+        return None;
+    };
+    let name = problems.required::<ValidIdentifier>(table, "name", context);
+    let digest = problems.optional::<String>(table, "digest", context);
+    // A kit pinned by digest may leave out its version, which is read from the kit's metadata when
+    // it is locked. The SDK has no metadata, so it always needs one.
+    let version = if table.contains_key("version") || digest.is_none() || is_sdk {
+        problems
+            .required::<Version>(table, "version", context)
+            .map(Some)
+    } else {
+        Some(None)
+    };
+    let vendor = problems.required::<ValidIdentifier>(table, "vendor", context)?;
+    if let Some(digest) = digest.as_deref().filter(|digest| !is_sha256_digest(digest)) {
+        problems.push(context, format!("'{digest}' is not a sha256 digest"));
+    }
+    if !vendors.contains(vendor.as_ref()) {
+        problems.push(
+            context,
+            format!("vendor '{vendor}' is not defined in Twoliter.toml"),
+        );
+        return None;
+    }
+    Some(Image {
+        name: name?,
+        version: version?,
+        vendor,
+        digest,
+    })
+}
+
+/// Checks that `Twoliter.lock` is well formed and agrees with the project's vendors and images.
+fn check_lock(lock: &Table, vendors: &BTreeSet<String>, images: &[Image], problems: &mut Problems) {
+    let context = TWOLITER_LOCK;
+    problems.required::<SchemaVersion<1>>(lock, "schema-version", context);
+
+    let mut locked = Vec::new();
+    if let Some(sdk) = lock.get("sdk") {
+        locked.extend(problems.convert::<LockedImage>(sdk, "Twoliter.lock: sdk"));
+    } else {
+        problems.push(context, "missing 'sdk'");
+    }
+    if let Some(kits) = problems.optional::<Vec<Value>>(lock, "kit", context) {
+        for (i, kit) in kits.iter().enumerate() {
+            let context = format!("Twoliter.lock: kit #{}", i + 1);
+            locked.extend(problems.convert::<LockedImage>(kit, &context));
+        }
+    }
+
+    for image in &locked {
+        let context = format!("Twoliter.lock: {image}");
+        if !vendors.contains(image.vendor.as_ref()) {
+            problems.push(
+                &context,
+                format!("vendor '{}' is not defined in Twoliter.toml", image.vendor),
+            );
+        }
+        if !is_sha256_digest(&image.digest) {
+            problems.push(
+                &context,
+                format!("'{}' is not a sha256 digest", image.digest),
+            );
+        }
+    }
+    for image in images {
+        let is_locked = locked.iter().any(|locked| {
+            locked.name == image.name
+                && locked.vendor == image.vendor
+                && image
+                    .version
+                    .iter()
+                    .all(|version| &locked.version == version)
+                && image
+                    .digest
+                    .iter()
+                    .all(|digest| locked.source.ends_with(&format!("@{digest}")))
+        });
+        if !is_locked {
+            problems.push(
+                context,
+                format!("'{image}' is not locked, run `twoliter update`"),
+            );
+        }
+    }
+}
+
+pub(super) fn is_sha256_digest(digest: &str) -> bool {
+    digest.strip_prefix("sha256:").is_some_and(|hex| {
+        hex.len() == 64 && hex.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f'))
+    })
+}
+
+/// The problems found so far, each prefixed with where it was found.
+#[derive(Debug, Default)]
+struct Problems(Vec<String>);
+
+impl Problems {
+    fn push(&mut self, context: &str, problem: impl AsRef<str>) {
+        self.0.push(format!("{context}: {}", problem.as_ref()));
+    }
+
+    fn check<T, E: std::fmt::Display>(&mut self, result: Result<T, E>, context: &str) -> Option<T> {
+        result
+            .map_err(|e| self.push(context, e.to_string().trim_end()))
+            .ok()
+    }
+
+    fn parse(&mut self, toml_str: &str, context: &str) -> Option<Table> {
+        self.check(toml::from_str(toml_str), context)
+    }
+
+    fn convert<T: DeserializeOwned>(&mut self, value: &Value, context: &str) -> Option<T> {
+        self.check(value.clone().try_into(), context)
+    }
+
+    fn optional<T: DeserializeOwned>(
+        &mut self,
+        table: &Table,
+        key: &str,
+        context: &str,
+    ) -> Option<T> {
+        let value = table.get(key)?;
+        self.check(value.clone().try_into(), &format!("{context}: '{key}'"))
+    }
+
+    fn required<T: DeserializeOwned>(
+        &mut self,
+        table: &Table,
+        key: &str,
+        context: &str,
+    ) -> Option<T> {
+        if !table.contains_key(key) {
+            self.push(context, format!("missing '{key}'"));
+        }
+        self.optional(table, key, context)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::TempDir;
+
+    const PROJECT: &str = r#"
+schema-version = 1
+release-version = "1.0.0"
+
+[vendor.bottlerocket]
+registry = "public.ecr.aws/bottlerocket"
+
+[sdk]
+name = "bottlerocket-sdk"
+vendor = "bottlerocket"
+version = "0.50.0"
+
+[[kit]]
+name = "core-kit"
+vendor = "bottlerocket"
+version = "2.0.0"
+"#;
+
+    fn lock(sdk_digest: &str, kit_vendor: &str) -> String {
+        format!(
+            r#"
+schema-version = 1
+
+[sdk]
+name = "bottlerocket-sdk"
+version = "0.50.0"
+vendor = "bottlerocket"
+source = "public.ecr.aws/bottlerocket/bottlerocket-sdk:v0.50.0"
+digest = "{sdk_digest}"
+
+[[kit]]
+name = "core-kit"
+version = "2.0.0"
+vendor = "{kit_vendor}"
+source = "public.ecr.aws/bottlerocket/core-kit:v2.0.0"
+digest = "sha256:{}"
+"#,
+            "b".repeat(64)
+        )
+    }
+
+    async fn problems(project: &str, lock: Option<&str>) -> Vec<String> {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("Twoliter.toml");
+        std::fs::write(&path, project).unwrap();
+        if let Some(lock) = lock {
+            std::fs::write(temp_dir.path().join(TWOLITER_LOCK), lock).unwrap();
+        }
+        find_problems(&path).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_valid_project() {
+        let digest = format!("sha256:{}", "a".repeat(64));
+        assert!(problems(PROJECT, None).await.is_empty());
+        assert!(problems(PROJECT, Some(&lock(&digest, "bottlerocket")))
+            .await
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_all_problems_are_reported() {
+        let project = PROJECT
+            .replace("schema-version = 1", "schema-version = 2")
+            .replace("name = \"core-kit\"", "name = \"core.kit\"")
+            .replace(
+                "vendor = \"bottlerocket\"\nversion = \"0.50.0\"",
+                "vendor = \"missing\"\nversion = \"0.50.0\"",
+            );
+        let problems = problems(&project, Some(&lock("sha256:abc", "missing"))).await;
+        assert_eq!(problems.len(), 5, "{problems:#?}");
+        assert!(problems[0].starts_with("Twoliter.toml: 'schema-version'"));
+        assert!(problems[1].starts_with("Twoliter.toml: sdk: vendor 'missing'"));
+        assert!(problems[2].starts_with("Twoliter.toml: kit #1: 'name'"));
+        assert!(problems[3].contains("'sha256:abc' is not a sha256 digest"));
+        assert!(problems[4].contains("vendor 'missing' is not defined"));
+    }
+
+    #[tokio::test]
+    async fn test_digest_instead_of_version() {
+        let digest = format!("sha256:{}", "c".repeat(64));
+        let project = PROJECT.replace("version = \"2.0.0\"", &format!("digest = \"{digest}\""));
+        let lock =
+            lock(&digest, "bottlerocket").replace("core-kit:v2.0.0", &format!("core-kit@{digest}"));
+        assert!(problems(&project, Some(&lock)).await.is_empty());
+
+        let problems = problems(
+            &project.replace("version = \"0.50.0\"", &format!("digest = \"{digest}\"")),
+            None,
+        )
+        .await;
+        assert_eq!(problems, ["Twoliter.toml: sdk: missing 'version'"]);
+    }
+
+    #[tokio::test]
+    async fn test_unparseable_project() {
+        let problems = problems("schema-version = ", None).await;
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("Twoliter.toml: "));
+    }
+
+    #[test]
+    fn test_find_project_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let nested = temp_dir.path().join("packages/hello");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(temp_dir.path().join("Twoliter.toml"), PROJECT).unwrap();
+        assert_eq!(
+            find_project_file(&nested).unwrap(),
+            temp_dir.path().join("Twoliter.toml")
+        );
+    }
+}
@@ -34,7 +34,16 @@ impl ArtifactVendor {
         ImageUri {
             registry: Some(self.registry().to_string()),
             repo: self.repo_for(image).to_string(),
-            tag: format!("v{}", image.version()),
+            tag: image.version_tag(),
+            digest: image.pinned_digest().map(str::to_string),
+        }
+    }
+
+    /// The manifest digest the overridden image must have, if the override specifies one.
+    pub(crate) fn expected_digest(&self) -> Option<&str> {
+        match self {
+            ArtifactVendor::Verbatim(_) => None,
+            ArtifactVendor::Overridden(vendor) => vendor.override_.digest.as_deref(),
         }
     }
 
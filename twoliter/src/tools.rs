@@ -141,3 +141,67 @@ async fn test_install_tools() {
 
     assert_eq!(dockerfile_mtime, buildsys_mtime);
 }
+
+/// Run the embedded docker-go script with a stand-in `docker` that prints its arguments, returning
+/// the arguments docker-go passed to `docker run`.
+#[cfg(test)]
+fn docker_go_run_args(extra_args: &[&str]) -> Vec<String> {
+    use std::os::unix::fs::PermissionsExt;
+    let tempdir = tempfile::TempDir::new().unwrap();
+    let bin_dir = tempdir.path().join("bin");
+    let go_mod_cache = tempdir.path().join("gopath/pkg/mod");
+    std::fs::create_dir_all(&bin_dir).unwrap();
+    std::fs::create_dir_all(&go_mod_cache).unwrap();
+    let docker = bin_dir.join("docker");
+    std::fs::write(&docker, "#!/bin/sh\nprintf '%s\\n' \"$@\"\n").unwrap();
+    std::fs::set_permissions(&docker, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let script = Path::new(env!("CARGO_MANIFEST_DIR")).join("embedded/docker-go");
+    let output = std::process::Command::new("bash")
+        .arg(script)
+        .args(["--module-path", "/src/hello-go", "--sdk-image", "sdk:v1"])
+        .arg("--go-mod-cache")
+        .arg(&go_mod_cache)
+        .args(extra_args)
+        .args(["--command", "go mod vendor"])
+        .env(
+            "PATH",
+            format!("{}:{}", bin_dir.display(), std::env::var("PATH").unwrap()),
+        )
+        .env("GOPROXY", "https://proxy.example.com")
+        // docker-go decides the network itself rather than trusting the caller's environment.
+        .env("DOCKER_RUN_ARGS", "--network=host")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn test_docker_go_uses_host_network() {
+    let args = docker_go_run_args(&[]);
+    assert!(args.contains(&"--network=host".to_string()), "{args:?}");
+    assert!(!args.contains(&"--env=GOPROXY=off".to_string()), "{args:?}");
+    assert_eq!(args.last().unwrap(), "go mod vendor");
+}
+
+#[test]
+fn test_docker_go_offline_has_no_network() {
+    let args = docker_go_run_args(&["--offline"]);
+    assert!(args.contains(&"--network=none".to_string()), "{args:?}");
+    assert!(!args.iter().any(|arg| arg == "--network=host"), "{args:?}");
+    assert!(args.contains(&"--env=GOPROXY=off".to_string()), "{args:?}");
+    assert!(
+        !args.iter().any(|arg| arg.starts_with("--env=GOPROXY=http")),
+        "{args:?}"
+    );
+    assert_eq!(args.last().unwrap(), "go mod vendor");
+}